@@ -0,0 +1,217 @@
+//! Generation of DSSAT batch files (`DSSBatch.vXX`), listing the treatments of the rendered experiment files so
+//! DSSAT can be invoked in batch mode.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Width of the `FILEX` column. DSSAT truncates anything longer.
+const FILEX_WIDTH: usize = 92;
+
+/// Lines of [`BatchConfig::header`].
+const HEADER_LINES: usize = 3;
+
+#[derive(Debug, Error)]
+pub enum BatchError {
+    #[error("No treatments found in the *TREATMENTS section of {0}")]
+    NoTreatments(PathBuf),
+    #[error(
+        "Path {0} is longer than the {width} characters DSSAT allows in batch files",
+        width = FILEX_WIDTH
+    )]
+    PathTooLong(String),
+}
+
+/// Where the batch files of a run are written.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchScope {
+    /// One batch file in every context directory, listing its own experiment file.
+    #[default]
+    Context,
+    /// One batch file in the run directory, listing the experiment files of every context of the run.
+    Run,
+}
+
+/// Batch file of a run, listing every treatment of the rendered template (the experiment file).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct BatchConfig {
+    /// DSSAT version, used in the file name (`DSSBatch.v48`).
+    #[serde(default = "default_version")]
+    pub version: String,
+
+    /// Name in the `$BATCH(...)` header.
+    #[serde(default = "default_name")]
+    pub name: String,
+
+    #[serde(default)]
+    pub scope: BatchScope,
+}
+
+fn default_version() -> String {
+    "48".to_string()
+}
+
+fn default_name() -> String {
+    "PYTHIA".to_string()
+}
+
+impl BatchConfig {
+    pub fn file_name(&self) -> String {
+        format!("DSSBatch.v{}", self.version)
+    }
+
+    pub fn header(&self) -> String {
+        format!(
+            "$BATCH({})\n!\n{:<width$}{:>7}{:>7}{:>7}{:>7}{:>7}\n",
+            self.name,
+            "@FILEX",
+            "TRTNO",
+            "RP",
+            "SQ",
+            "OP",
+            "CO",
+            width = FILEX_WIDTH
+        )
+    }
+}
+
+/// A treatment of the `*TREATMENTS` section of an experiment file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Treatment {
+    /// Treatment number (`N`).
+    pub number: u32,
+    /// Rotation component (`R`).
+    pub rotation: u32,
+    /// Rotation option (`O`).
+    pub option: u32,
+    /// Crop component (`C`).
+    pub component: u32,
+}
+
+/// Parses the treatments of the `*TREATMENTS` section of the contents of an experiment file.
+/// Line endings are expected to be normalized to LF.
+pub fn treatments(xfile: &str) -> Vec<Treatment> {
+    xfile
+        .lines()
+        .skip_while(|line| !line.starts_with("*TREATMENTS"))
+        .skip(1)
+        .take_while(|line| !line.starts_with('*'))
+        .filter(|line| !line.starts_with('@') && !line.starts_with('!'))
+        .filter_map(|line| {
+            let mut columns = line.split_whitespace().map(|c| c.parse::<u32>().ok());
+            Some(Treatment {
+                number: columns.next()??,
+                rotation: columns.next()??,
+                option: columns.next()??,
+                component: columns.next()??,
+            })
+        })
+        .collect()
+}
+
+/// Formats the batch file lines running every treatment of the experiment file at `filex`. Sequential (crop rotation)
+/// experiments run every treatment as the component of the rotation given by its `R` column, see [`crate::simulation`].
+pub fn lines(
+    filex: &Path,
+    treatments: &[Treatment],
+    sequential: bool,
+) -> Result<String, BatchError> {
+    let filex = filex.to_string_lossy();
+    if filex.len() > FILEX_WIDTH {
+        return Err(BatchError::PathTooLong(filex.to_string()));
+    }
+
+    Ok(treatments
+        .iter()
+        .map(|t| {
+            let (sq, op, co) = if sequential {
+                (t.rotation, 1, t.component)
+            } else {
+                (0, 1, 0)
+            };
+            format!(
+                "{:<FILEX_WIDTH$}{:>7}{:>7}{:>7}{:>7}{:>7}\n",
+                filex, t.number, 1, sq, op, co
+            )
+        })
+        .collect())
+}
+
+/// Puts the entries of the batch file at `path` in a canonical order (by experiment file, then treatment), whatever
+/// order the workers appended them in. See `--deterministic`.
+pub fn sort_entries(path: &Path) -> std::io::Result<()> {
+    let contents = std::fs::read_to_string(path)?;
+    let mut lines: Vec<&str> = contents.split_inclusive('\n').collect();
+    let header = HEADER_LINES.min(lines.len());
+    lines[header..].sort_unstable();
+    std::fs::write(path, lines.concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XFILE: &str = "*EXP.DETAILS: PYTH0001MZ\n\
+        \n\
+        *TREATMENTS                        -------------FACTOR LEVELS------------\n\
+        @N R O C TNAME.................... CU FL SA IC MP MI MF MR MC MT ME MH SM\n \
+        1 1 0 0 Rainfed                    1  1  0  1  1  0  1  0  0  0  0  1  1\n \
+        2 1 0 0 Irrigated                  1  1  0  1  1  1  1  0  0  0  0  1  1\n\
+        \n\
+        *CULTIVARS\n\
+        @C CR INGENO CNAME\n \
+        1 MZ IB0001 CUSTOM\n";
+
+    #[test]
+    fn test_treatments() {
+        let treatments = treatments(XFILE);
+        assert_eq!(treatments.len(), 2);
+        assert_eq!(treatments[1].number, 2);
+        assert_eq!(treatments[1].rotation, 1);
+        assert!(super::treatments("*CULTIVARS\n 1 MZ IB0001 CUSTOM\n").is_empty());
+    }
+
+    #[test]
+    fn test_lines() {
+        let lines = lines(Path::new("PYTH0001.MZX"), &treatments(XFILE), false).unwrap();
+        let lines: Vec<&str> = lines.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].len(), FILEX_WIDTH + 35);
+        assert!(lines[0].starts_with("PYTH0001.MZX "));
+        assert!(lines[1].ends_with("      2      1      0      1      0"));
+
+        let long = "a".repeat(FILEX_WIDTH + 1);
+        assert!(super::lines(Path::new(&long), &treatments(XFILE), false).is_err());
+
+        let sequential = super::lines(Path::new("PYTH0001.SQX"), &treatments(XFILE), true).unwrap();
+        assert!(sequential.ends_with("      2      1      1      1      0\n"));
+    }
+
+    #[test]
+    fn test_header() {
+        let config: BatchConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config.file_name(), "DSSBatch.v48");
+        let header = config.header();
+        assert!(header.starts_with("$BATCH(PYTHIA)\n!\n@FILEX "));
+        assert!(header.ends_with("  TRTNO     RP     SQ     OP     CO\n"));
+    }
+
+    #[test]
+    fn test_sort_entries() {
+        let config: BatchConfig = serde_json::from_str("{}").unwrap();
+        let entries = |filex: &str| lines(Path::new(filex), &treatments(XFILE), false).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(config.file_name());
+        let contents = config.header() + &entries("B/PYTH0001.MZX") + &entries("A/PYTH0001.MZX");
+        std::fs::write(&path, contents.replace('\n', "\r\n")).unwrap();
+
+        sort_entries(&path).unwrap();
+        let expected = config.header() + &entries("A/PYTH0001.MZX") + &entries("B/PYTH0001.MZX");
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            expected.replace('\n', "\r\n")
+        );
+    }
+}
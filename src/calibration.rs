@@ -0,0 +1,644 @@
+//! The experimental calibration mode (see [`crate::config::Command::Calibrate`]): every candidate parameter set of
+//! the search is set on the runs, the pipeline renders, executes and harvests a subset of the sites with it, and the
+//! harvested outputs are scored against observations by an objective function.
+
+use crate::config::{Args, Config};
+use crate::harvest::daily::DailyRecord;
+use crate::harvest::summary::SummaryRecord;
+use crate::processing::context::{ContextValue, PrimitiveContextValue};
+use crate::processing::ProcessingBuilder;
+use crate::provenance::Manifest;
+use crate::utils::rng::Rng;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Name of the file the iterations are logged to, in the root of the working directory.
+pub const CALIBRATION_LOG_FILE_NAME: &str = "calibration.csv";
+
+#[derive(Debug, Error)]
+pub enum CalibrationError {
+    #[error("Failed to read observations {0}: {1}")]
+    Csv(PathBuf, csv::Error),
+    #[error("Observations {0} have no column named \"{1}\"")]
+    MissingColumn(PathBuf, String),
+    #[error("Observations {path}, row {row}: invalid {column} \"{value}\"")]
+    InvalidValue {
+        path: PathBuf,
+        row: usize,
+        column: String,
+        value: String,
+    },
+    #[error("Failed to write the calibration log: {0}")]
+    Log(csv::Error),
+    #[error("Failed to read the harvested table {0}: {1}")]
+    Harvested(PathBuf, csv::Error),
+}
+
+/// A calibrated parameter, exposed to templates under its name.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ParameterRange {
+    pub name: String,
+    pub min: f64,
+    pub max: f64,
+
+    /// Number of values of the parameter in a grid search, evenly spaced from `min` to `max`.
+    #[serde(default = "default_steps")]
+    pub steps: usize,
+}
+
+fn default_steps() -> usize {
+    5
+}
+
+/// How candidate parameter sets are drawn.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "method", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Search {
+    /// Every combination of the values of the parameters.
+    Grid,
+    /// `samples` parameter sets drawn uniformly from the ranges (GLUE-style).
+    Random {
+        samples: usize,
+        #[serde(default)]
+        seed: u64,
+    },
+}
+
+/// Observed values (e.g. yields) of a CSV file, one per row. Every observation is of a season (the DSSAT run number,
+/// as in the harvested tables) of a run at a site.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ObservationsConfig {
+    pub file: PathBuf,
+
+    #[serde(default = "default_site_column")]
+    pub site_column: String,
+
+    #[serde(default = "default_run_column")]
+    pub run_column: String,
+
+    #[serde(default = "default_season_column")]
+    pub season_column: String,
+
+    #[serde(default = "default_variable_column")]
+    pub variable_column: String,
+
+    #[serde(default = "default_value_column")]
+    pub value_column: String,
+}
+
+fn default_site_column() -> String {
+    "site".to_string()
+}
+
+fn default_run_column() -> String {
+    "run".to_string()
+}
+
+fn default_season_column() -> String {
+    "season".to_string()
+}
+
+fn default_variable_column() -> String {
+    "variable".to_string()
+}
+
+fn default_value_column() -> String {
+    "value".to_string()
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Objective {
+    /// Root mean square error.
+    #[default]
+    Rmse,
+    /// Mean absolute error.
+    Mae,
+}
+
+/// Tables the candidates are scored on, as written by the `std:collect` stage of the pipeline into the directory of
+/// every iteration.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct HarvestedTables {
+    #[serde(default = "default_summary_table")]
+    pub summary: PathBuf,
+
+    /// The daily outputs, if harvested.
+    #[serde(default)]
+    pub daily: Option<PathBuf>,
+}
+
+fn default_summary_table() -> PathBuf {
+    PathBuf::from("summary.csv")
+}
+
+impl Default for HarvestedTables {
+    fn default() -> Self {
+        Self {
+            summary: default_summary_table(),
+            daily: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CalibrationConfig {
+    pub parameters: Vec<ParameterRange>,
+    pub search: Search,
+    pub observations: ObservationsConfig,
+    #[serde(default)]
+    pub objective: Objective,
+
+    /// Number of sites simulated for each candidate.
+    #[serde(default)]
+    pub sample_size: Option<usize>,
+
+    #[serde(default)]
+    pub harvested: HarvestedTables,
+}
+
+/// Values of the calibrated parameters, by name.
+pub type ParameterSet = HashMap<String, f64>;
+
+/// Site ID, run name, season and variable of a value.
+pub type ValueKey = (i32, String, u32, String);
+
+/// Simulated values, keyed by site, run, season and variable.
+pub type Simulated = HashMap<ValueKey, f64>;
+
+impl CalibrationConfig {
+    /// Every candidate parameter set of the search, in the order they are evaluated.
+    pub fn candidates(&self) -> Vec<ParameterSet> {
+        match &self.search {
+            Search::Grid => {
+                let mut candidates = vec![ParameterSet::new()];
+                for p in &self.parameters {
+                    let steps = p.steps.max(1);
+                    let values: Vec<f64> = (0..steps)
+                        .map(|i| match steps {
+                            1 => p.min,
+                            _ => p.min + (p.max - p.min) * i as f64 / (steps - 1) as f64,
+                        })
+                        .collect();
+                    candidates = candidates
+                        .into_iter()
+                        .flat_map(|c| {
+                            values.iter().map(move |v| {
+                                let mut c = c.clone();
+                                c.insert(p.name.clone(), *v);
+                                c
+                            })
+                        })
+                        .collect();
+                }
+                candidates
+            }
+            Search::Random { samples, seed } => {
+                let mut rng = Rng::new(*seed);
+                (0..*samples)
+                    .map(|_| {
+                        self.parameters
+                            .iter()
+                            .map(|p| (p.name.clone(), p.min + (p.max - p.min) * rng.next_f64()))
+                            .collect()
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// `config` with the parameters of `candidate` set on every run, and its sites cut down to
+    /// [`CalibrationConfig::sample_size`].
+    pub fn apply(&self, config: &Config, candidate: &ParameterSet) -> Config {
+        let mut config = config.clone();
+        if let Some(size) = self.sample_size {
+            let sample_size = config.sites.sample_size.map_or(size, |s| s.min(size));
+            config.sites.sample_size = Some(sample_size);
+        }
+        for run in &mut config.runs {
+            for (name, value) in candidate {
+                let value = ContextValue::Prim(PrimitiveContextValue::Float(*value));
+                run.extra.insert(name.clone(), value);
+            }
+        }
+        config
+    }
+}
+
+impl HarvestedTables {
+    /// Final simulated value of every variable of every season harvested into `dir`: the one of the last day of the
+    /// daily outputs, taken over by the end of season outputs. Values that aren't numbers are left out.
+    pub fn read(&self, dir: &Path) -> Result<Simulated, CalibrationError> {
+        let reader = |table: &Path| {
+            let path = dir.join(table);
+            csv::Reader::from_path(&path).map_err(|e| CalibrationError::Harvested(path, e))
+        };
+
+        let mut last: HashMap<ValueKey, (NaiveDate, f64)> = HashMap::new();
+        if let Some(daily) = &self.daily {
+            for record in reader(daily)?.deserialize() {
+                let record: DailyRecord =
+                    record.map_err(|e| CalibrationError::Harvested(dir.join(daily), e))?;
+                let value = (record.date, record.value);
+                last.entry((record.site, record.run, record.season, record.variable))
+                    .and_modify(|v| {
+                        if value.0 >= v.0 {
+                            *v = value
+                        }
+                    })
+                    .or_insert(value);
+            }
+        }
+
+        let mut simulated: Simulated = last.into_iter().map(|(k, (_, v))| (k, v)).collect();
+        for record in reader(&self.summary)?.deserialize() {
+            let record: SummaryRecord =
+                record.map_err(|e| CalibrationError::Harvested(dir.join(&self.summary), e))?;
+            if let Ok(value) = record.value.parse() {
+                let key = (record.site, record.run, record.season, record.variable);
+                simulated.insert(key, value);
+            }
+        }
+        Ok(simulated)
+    }
+}
+
+/// Observed values, keyed by site, run, season and variable.
+pub struct Observations(HashMap<ValueKey, f64>);
+
+impl Observations {
+    pub fn load(config: &ObservationsConfig) -> Result<Self, CalibrationError> {
+        let path = config.file.as_path();
+        let csv_err = |e| CalibrationError::Csv(path.to_path_buf(), e);
+        let mut reader = csv::Reader::from_path(path).map_err(csv_err)?;
+
+        let headers = reader.headers().map_err(csv_err)?.clone();
+        let column = |name: &str| {
+            headers.iter().position(|h| h == name).ok_or_else(|| {
+                CalibrationError::MissingColumn(path.to_path_buf(), name.to_string())
+            })
+        };
+        let site_idx = column(&config.site_column)?;
+        let run_idx = column(&config.run_column)?;
+        let season_idx = column(&config.season_column)?;
+        let variable_idx = column(&config.variable_column)?;
+        let value_idx = column(&config.value_column)?;
+
+        let mut observations = HashMap::new();
+        for (row, record) in reader.records().enumerate() {
+            let record = record.map_err(csv_err)?;
+            let parse = |idx: usize, column: &str| {
+                let value = record.get(idx).unwrap_or_default().trim();
+                value
+                    .parse::<f64>()
+                    .map_err(|_| CalibrationError::InvalidValue {
+                        path: path.to_path_buf(),
+                        row,
+                        column: column.to_string(),
+                        value: value.to_string(),
+                    })
+            };
+            let site = parse(site_idx, &config.site_column)? as i32;
+            let season = parse(season_idx, &config.season_column)? as u32;
+            let value = parse(value_idx, &config.value_column)?;
+            let text = |idx: usize| record.get(idx).unwrap_or_default().trim().to_string();
+            observations.insert((site, text(run_idx), season, text(variable_idx)), value);
+        }
+        Ok(Self(observations))
+    }
+
+    /// Share of the observations `simulated` has a value for, from 0 to 1.
+    pub fn coverage(&self, simulated: &Simulated) -> f64 {
+        let covered = self.0.keys().filter(|key| simulated.contains_key(*key));
+        match self.0.len() {
+            0 => 1.0,
+            n => covered.count() as f64 / n as f64,
+        }
+    }
+}
+
+impl Objective {
+    /// Scores the simulated value of each observed variable against the observation. Lower is better.
+    /// `None` unless every observation was simulated, as a candidate scored on fewer of them isn't comparable to the
+    /// others (see [`Observations::coverage`]).
+    pub fn evaluate(&self, observations: &Observations, simulated: &Simulated) -> Option<f64> {
+        let errors = observations
+            .0
+            .iter()
+            .map(|(key, observed)| Some(simulated.get(key)? - observed))
+            .collect::<Option<Vec<f64>>>()?;
+        if errors.is_empty() {
+            return None;
+        }
+
+        let n = errors.len() as f64;
+        Some(match self {
+            Objective::Rmse => (errors.iter().map(|e| e * e).sum::<f64>() / n).sqrt(),
+            Objective::Mae => errors.iter().map(|e| e.abs()).sum::<f64>() / n,
+        })
+    }
+}
+
+/// Logs the parameters and score of every iteration of the calibration to a CSV file, one row per iteration, along
+/// with how many of its contexts failed and the share of the observations it simulated.
+pub struct IterationLog<W: Write> {
+    writer: csv::Writer<W>,
+    parameters: Vec<String>,
+}
+
+impl IterationLog<std::fs::File> {
+    pub fn create(path: &Path, parameters: &[ParameterRange]) -> Result<Self, CalibrationError> {
+        let writer = csv::Writer::from_path(path).map_err(CalibrationError::Log)?;
+        Self::new(writer, parameters)
+    }
+}
+
+impl<W: Write> IterationLog<W> {
+    fn new(
+        mut writer: csv::Writer<W>,
+        parameters: &[ParameterRange],
+    ) -> Result<Self, CalibrationError> {
+        let parameters: Vec<String> = parameters.iter().map(|p| p.name.clone()).collect();
+        let mut header = vec!["iteration".to_string()];
+        header.extend(parameters.iter().cloned());
+        header.extend(["failed", "coverage", "objective"].map(String::from));
+        writer
+            .write_record(&header)
+            .map_err(CalibrationError::Log)?;
+        Ok(Self { writer, parameters })
+    }
+
+    pub fn log(
+        &mut self,
+        iteration: usize,
+        candidate: &ParameterSet,
+        failed: usize,
+        coverage: f64,
+        objective: Option<f64>,
+    ) -> Result<(), CalibrationError> {
+        let mut row = vec![iteration.to_string()];
+        for name in &self.parameters {
+            row.push(candidate.get(name).map(f64::to_string).unwrap_or_default());
+        }
+        row.push(failed.to_string());
+        row.push(coverage.to_string());
+        row.push(objective.map(|o| o.to_string()).unwrap_or_default());
+        self.writer
+            .write_record(&row)
+            .map_err(CalibrationError::Log)?;
+        // Flushed on every iteration, so the log is usable while the calibration runs.
+        self.writer
+            .flush()
+            .map_err(|e| CalibrationError::Log(e.into()))
+    }
+}
+
+/// Runs the calibration loop of the config, in `workdir`: the pipeline is run for every candidate in the
+/// `iteration-<n>` directory, and the iterations are logged to [`CALIBRATION_LOG_FILE_NAME`]. Candidates some
+/// contexts failed for, or that didn't simulate every observation, aren't scored.
+pub fn calibrate(
+    config: &Config,
+    args: &Args,
+    workdir: &Path,
+    manifest: &Manifest,
+) -> Result<(), Box<dyn Error>> {
+    let calibration = config
+        .calibration
+        .as_ref()
+        .ok_or("The config has no calibration section")?;
+    let observations = Observations::load(&calibration.observations)?;
+    let log_path = workdir.join(CALIBRATION_LOG_FILE_NAME);
+    let mut log = IterationLog::create(&log_path, &calibration.parameters)?;
+
+    let candidates = calibration.candidates();
+    let mut best: Option<(usize, f64)> = None;
+    for (i, candidate) in candidates.iter().enumerate() {
+        println!("Calibration iteration {} of {}", i + 1, candidates.len());
+        let config = calibration.apply(config, candidate);
+        let dir = workdir.join(format!("iteration-{}", i));
+        std::fs::create_dir_all(&dir)?;
+        let failed = ProcessingBuilder {
+            config: &config,
+            args,
+            workdir: dir.clone(),
+            manifest,
+        }
+        .build()?
+        .start()?;
+
+        let simulated = calibration.harvested.read(&dir)?;
+        let coverage = observations.coverage(&simulated);
+        let objective = match failed {
+            0 => calibration.objective.evaluate(&observations, &simulated),
+            _ => None,
+        };
+        println!(
+            "Iteration {}: {} context(s) failed, {:.1}% of the observations simulated",
+            i,
+            failed,
+            coverage * 100.0
+        );
+        log.log(i, candidate, failed, coverage, objective)?;
+        if let Some(objective) = objective {
+            if best.is_none_or(|(_, b)| objective < b) {
+                best = Some((i, objective));
+            }
+        }
+    }
+
+    match best {
+        Some((i, objective)) => println!(
+            "Best candidate is iteration {} ({:?} of {}), see {}",
+            i,
+            calibration.objective,
+            objective,
+            log_path.display()
+        ),
+        None => println!(
+            "No candidate simulated every observation, see {}",
+            log_path.display()
+        ),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(search: Search) -> CalibrationConfig {
+        CalibrationConfig {
+            parameters: vec![
+                ParameterRange {
+                    name: "p1".to_string(),
+                    min: 0.0,
+                    max: 1.0,
+                    steps: 3,
+                },
+                ParameterRange {
+                    name: "g2".to_string(),
+                    min: 500.0,
+                    max: 900.0,
+                    steps: 2,
+                },
+            ],
+            search,
+            observations: serde_json::from_str(r#"{ "file": "obs.csv" }"#).unwrap(),
+            objective: Objective::Rmse,
+            sample_size: None,
+            harvested: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_candidates() {
+        let grid = config(Search::Grid).candidates();
+        assert_eq!(grid.len(), 6);
+        assert_eq!(grid[1]["p1"], 0.0);
+        assert_eq!(grid[1]["g2"], 900.0);
+        assert_eq!(grid[2]["p1"], 0.5);
+
+        let random = config(Search::Random {
+            samples: 10,
+            seed: 42,
+        });
+        let candidates = random.candidates();
+        assert_eq!(candidates.len(), 10);
+        assert!(candidates
+            .iter()
+            .all(|c| (500.0..900.0).contains(&c["g2"]) && (0.0..1.0).contains(&c["p1"])));
+        assert_eq!(candidates, random.candidates());
+    }
+
+    #[test]
+    fn test_read() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("daily.csv"),
+            "site,run,season,date,variable,value\n\
+            1,maize,1,2020-01-02,CWAD,100.0\n\
+            1,maize,1,2020-01-01,CWAD,50.0\n\
+            1,maize,1,2020-01-02,HWAM,900.0\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("summary.csv"),
+            "site,run,season,variable,value\n\
+            1,maize,1,TNAM,Rainfed\n\
+            1,maize,1,HWAM,1000\n\
+            1,maize,2,HWAM,1100\n\
+            1,irrigated,1,HWAM,2000\n",
+        )
+        .unwrap();
+
+        let tables = HarvestedTables {
+            daily: Some(PathBuf::from("daily.csv")),
+            ..Default::default()
+        };
+        let simulated = tables.read(dir.path()).unwrap();
+        assert_eq!(simulated.len(), 4);
+        let key =
+            |run: &str, season, variable: &str| (1, run.to_string(), season, variable.to_string());
+        assert_eq!(simulated[&key("maize", 1, "CWAD")], 100.0);
+        assert_eq!(simulated[&key("maize", 1, "HWAM")], 1000.0);
+        assert_eq!(simulated[&key("maize", 2, "HWAM")], 1100.0);
+        assert_eq!(simulated[&key("irrigated", 1, "HWAM")], 2000.0);
+
+        let missing = HarvestedTables {
+            summary: PathBuf::from("missing.csv"),
+            daily: None,
+        };
+        assert!(missing.read(dir.path()).is_err());
+    }
+
+    #[test]
+    fn test_observations() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("obs.csv");
+        std::fs::write(
+            &file,
+            "site,run,season,variable,value\n1,maize,2,HWAM,1300\n",
+        )
+        .unwrap();
+        let config = ObservationsConfig {
+            file,
+            ..serde_json::from_str(r#"{ "file": "obs.csv" }"#).unwrap()
+        };
+        let observations = Observations::load(&config).unwrap();
+        assert_eq!(
+            observations.0,
+            HashMap::from([((1, "maize".to_string(), 2, "HWAM".to_string()), 1300.0)])
+        );
+
+        let config = ObservationsConfig {
+            season_column: "year".to_string(),
+            ..config
+        };
+        assert!(matches!(
+            Observations::load(&config),
+            Err(CalibrationError::MissingColumn(_, column)) if column == "year"
+        ));
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let key = |site, run: &str| (site, run.to_string(), 1, "HWAM".to_string());
+        let simulated: Simulated = [
+            (key(1, "maize"), 1000.0),
+            (key(2, "maize"), 3000.0),
+            (key(1, "irrigated"), 9000.0),
+        ]
+        .into_iter()
+        .collect();
+        let observations = Observations(
+            [(key(1, "maize"), 1300.0), (key(2, "maize"), 2600.0)]
+                .into_iter()
+                .collect(),
+        );
+
+        assert_eq!(
+            Objective::Mae.evaluate(&observations, &simulated),
+            Some(350.0)
+        );
+        assert_eq!(
+            Objective::Rmse.evaluate(&observations, &simulated),
+            Some(((300.0f64.powi(2) + 400.0f64.powi(2)) / 2.0).sqrt())
+        );
+        assert_eq!(observations.coverage(&simulated), 1.0);
+
+        // A candidate that didn't simulate site 2 isn't scored on site 1 alone.
+        let partial: Simulated = [(key(1, "maize"), 1300.0)].into_iter().collect();
+        assert_eq!(observations.coverage(&partial), 0.5);
+        assert_eq!(Objective::Mae.evaluate(&observations, &partial), None);
+        assert_eq!(
+            Objective::Rmse.evaluate(&observations, &Simulated::new()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_log() {
+        let config = config(Search::Grid);
+        let mut out = Vec::new();
+        {
+            let mut log =
+                IterationLog::new(csv::Writer::from_writer(&mut out), &config.parameters).unwrap();
+            log.log(0, &config.candidates()[0], 0, 1.0, Some(1.5))
+                .unwrap();
+            log.log(1, &config.candidates()[1], 2, 0.5, None).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "iteration,p1,g2,failed,coverage,objective\n0,0,500,0,1,1.5\n1,0,900,2,0.5,\n"
+        );
+    }
+}
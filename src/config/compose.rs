@@ -0,0 +1,362 @@
+//! Composition of config files, so suites of experiments can share their site source and run parameters:
+//!
+//! - `include`: config files (relative to the one including them) deep merged in order under the one including them.
+//!   Objects are merged key by key, anything else (arrays included) is replaced by the value of the latter file, except
+//!   for `runs`, which are concatenated in the order the files are merged.
+//! - `run_defaults`: an object deep merged under every run, for the parameters shared by all of them.
+//! - `default_setup`: like the one of the original Pythia, an object whose keys are set on every run that doesn't set
+//!   them itself. Unlike `run_defaults`, a key set by the run replaces the default as a whole.
+//! - `extends` (of a run): the name of another run deep merged under it, so scenarios only declare what differs from
+//!   their baseline. Runs may extend runs that extend others, as long as none ends up extending itself.
+//!
+//! Every file is upgraded to the current layout of the config on its own, from the `version` it declares (see
+//! [`super::migrate`]), before it is merged with the others.
+//!
+//! Paths within the files are left as they are, relative to the working directory.
+
+use super::diagnostics::SourceMap;
+use super::migrate::{migrate, MigrationError, MIGRATIONS};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ComposeError {
+    #[error("Failed to read config file {path}: {source}")]
+    IOError {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to parse config file {path}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("Config file {0} must be an object")]
+    NotAnObject(PathBuf),
+    #[error("`include` of config file {0} must be a list of paths")]
+    InvalidInclude(PathBuf),
+    #[error("`{0}` must be an object")]
+    InvalidRunDefaults(&'static str),
+    #[error("Config file {0} includes itself")]
+    Cycle(PathBuf),
+    #[error("Failed to migrate config file {path}: {source}")]
+    Migration {
+        path: PathBuf,
+        source: MigrationError,
+    },
+    #[error("`extends` of run {0} must be the name of a run")]
+    InvalidExtends(String),
+    #[error("Run {run} extends run {extends}, which doesn't exist")]
+    UnknownExtends { run: String, extends: String },
+    #[error("Run {0} extends itself")]
+    ExtendsCycle(String),
+}
+
+/// Merges `top` into `base`: objects key by key, recursively. Anything else in `top` replaces what is in `base`.
+fn merge(base: &mut Value, top: Value) {
+    match (base, top) {
+        (Value::Object(base), Value::Object(top)) => {
+            for (k, v) in top {
+                match base.get_mut(&k) {
+                    Some(existing) => merge(existing, v),
+                    None => {
+                        base.insert(k, v);
+                    }
+                }
+            }
+        }
+        (base, top) => *base = top,
+    }
+}
+
+/// Merges the config `file` into `composed`, the files merged before it, appending its `runs` to theirs.
+fn merge_file(composed: &mut Value, mut file: Value) {
+    if let (Some(Value::Array(runs)), Some(Value::Array(more))) =
+        (composed.get_mut("runs"), file.get_mut("runs"))
+    {
+        more.splice(0..0, std::mem::take(runs));
+    }
+    merge(composed, file);
+}
+
+/// Reads the config file at `path` along with the ones it includes, indexing them into `sources` in the order they are
+/// merged.
+fn read(
+    path: &Path,
+    including: &mut Vec<PathBuf>,
+    sources: &mut SourceMap,
+) -> Result<Value, ComposeError> {
+    let io_err = |source| ComposeError::IOError {
+        path: path.to_path_buf(),
+        source,
+    };
+    let canonical = path.canonicalize().map_err(io_err)?;
+    if including.contains(&canonical) {
+        return Err(ComposeError::Cycle(path.to_path_buf()));
+    }
+
+    let json = std::fs::read_to_string(path).map_err(io_err)?;
+    let value = serde_json::from_str(&json).map_err(|source| ComposeError::Parse {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let Value::Object(mut file) = value else {
+        return Err(ComposeError::NotAnObject(path.to_path_buf()));
+    };
+    let changes = migrate(&mut file, MIGRATIONS).map_err(|source| ComposeError::Migration {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    for change in changes {
+        eprintln!("Migrated config file {} from {}", path.display(), change);
+    }
+    let Some(include) = file.remove("include") else {
+        sources.add(path, &json);
+        return Ok(Value::Object(file));
+    };
+
+    let invalid = || ComposeError::InvalidInclude(path.to_path_buf());
+    let Value::Array(include) = include else {
+        return Err(invalid());
+    };
+    let dir = path.parent().unwrap_or(Path::new(""));
+    including.push(canonical);
+    let mut composed = Value::Object(Map::new());
+    for included in include {
+        let included = included.as_str().ok_or_else(invalid)?;
+        let included = read(&dir.join(included), including, sources)?;
+        merge_file(&mut composed, included);
+    }
+    including.pop();
+    sources.add(path, &json);
+
+    merge_file(&mut composed, Value::Object(file));
+    Ok(composed)
+}
+
+/// Run `i` of `runs` merged over the runs it extends, if any. `extending` are the runs whose `extends` are being
+/// resolved.
+fn extended(
+    runs: &[Value],
+    i: usize,
+    names: &HashMap<&str, usize>,
+    extending: &mut Vec<usize>,
+) -> Result<Value, ComposeError> {
+    let run = &runs[i];
+    let Some(extends) = run.get("extends") else {
+        return Ok(run.clone());
+    };
+    let name = || run["name"].as_str().unwrap_or_default().to_string();
+    let extends = extends
+        .as_str()
+        .ok_or_else(|| ComposeError::InvalidExtends(name()))?;
+    let &parent = names
+        .get(extends)
+        .ok_or_else(|| ComposeError::UnknownExtends {
+            run: name(),
+            extends: extends.to_string(),
+        })?;
+
+    extending.push(i);
+    if extending.contains(&parent) {
+        return Err(ComposeError::ExtendsCycle(name()));
+    }
+    let mut base = extended(runs, parent, names, extending)?;
+    extending.pop();
+
+    let mut run = run.clone();
+    if let Value::Object(run) = &mut run {
+        run.remove("extends");
+    }
+    merge(&mut base, run);
+    Ok(base)
+}
+
+/// Replaces every run of `runs` extending another by the merge of both.
+fn resolve_extends(runs: &[Value]) -> Result<Vec<Value>, ComposeError> {
+    let mut names = HashMap::new();
+    for (i, run) in runs.iter().enumerate() {
+        if let Some(name) = run.get("name").and_then(Value::as_str) {
+            names.entry(name).or_insert(i);
+        }
+    }
+    (0..runs.len())
+        .map(|i| extended(runs, i, &names, &mut Vec::new()))
+        .collect()
+}
+
+/// Removes the `key` object of the defaults of the runs from `config`.
+fn take_defaults(config: &mut Value, key: &'static str) -> Result<Option<Value>, ComposeError> {
+    match config.as_object_mut().and_then(|c| c.remove(key)) {
+        Some(defaults) if !defaults.is_object() => Err(ComposeError::InvalidRunDefaults(key)),
+        defaults => Ok(defaults),
+    }
+}
+
+/// Reads the config file at `path`, along with the ones it includes, into a single config. Also returns where its
+/// properties are declared, see [`SourceMap`].
+pub fn load(path: &Path) -> Result<(Value, SourceMap), ComposeError> {
+    let mut sources = SourceMap::default();
+    let mut config = read(path, &mut Vec::new(), &mut sources)?;
+    let run_defaults = take_defaults(&mut config, "run_defaults")?;
+    let default_setup = take_defaults(&mut config, "default_setup")?;
+
+    let Some(Value::Array(runs)) = config.get_mut("runs") else {
+        return Ok((config, sources));
+    };
+    *runs = resolve_extends(runs)?;
+    for run in runs.iter_mut().filter(|run| run.is_object()) {
+        if let Some(defaults) = &run_defaults {
+            let mut defaulted = defaults.clone();
+            merge(&mut defaulted, std::mem::take(run));
+            *run = defaulted;
+        }
+        if let (Some(Value::Object(defaults)), Value::Object(run)) = (&default_setup, run) {
+            for (k, v) in defaults {
+                run.entry(k).or_insert_with(|| v.clone());
+            }
+        }
+    }
+    Ok((config, sources))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, value: Value| {
+            let path = dir.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, value.to_string()).unwrap();
+            path
+        };
+        write(
+            "common.json",
+            json!({
+                "version": 1,
+                "sites": { "type": "std:raster", "file": "sites.tif" },
+                "run_defaults": { "template": "x.SNX", "soil": { "copy": true, "file": "a.SOL" } },
+                "default_setup": { "cultivar": { "file": "a.CUL" }, "wth_dir": "weather" },
+                "runs": [{ "name": "base" }]
+            }),
+        );
+        write(
+            "runs/maize.json",
+            json!({ "runs": [
+                { "name": "maize", "soil": { "file": "b.SOL" }, "cultivar": { "id": "IB0001" } },
+                { "name": "rice" },
+                { "name": "maize_late", "extends": "maize", "pdate": "06-01" }
+            ] }),
+        );
+        let path = write(
+            "config.json",
+            json!({
+                "include": ["common.json", "runs/maize.json"],
+                "sites": { "file": "other.tif" }
+            }),
+        );
+
+        let expected = json!({
+            "sites": { "type": "std:raster", "file": "other.tif" },
+            "runs": [
+                {
+                    "name": "base",
+                    "template": "x.SNX",
+                    "soil": { "copy": true, "file": "a.SOL" },
+                    "cultivar": { "file": "a.CUL" },
+                    "wth_dir": "weather"
+                },
+                {
+                    "name": "maize",
+                    "template": "x.SNX",
+                    "soil": { "copy": true, "file": "b.SOL" },
+                    "cultivar": { "id": "IB0001" },
+                    "wth_dir": "weather"
+                },
+                {
+                    "name": "rice",
+                    "template": "x.SNX",
+                    "soil": { "copy": true, "file": "a.SOL" },
+                    "cultivar": { "file": "a.CUL" },
+                    "wth_dir": "weather"
+                },
+                {
+                    "name": "maize_late",
+                    "template": "x.SNX",
+                    "soil": { "copy": true, "file": "b.SOL" },
+                    "cultivar": { "id": "IB0001" },
+                    "wth_dir": "weather",
+                    "pdate": "06-01"
+                }
+            ]
+        });
+        let (config, sources) = load(&path).unwrap();
+        assert_eq!(config, expected);
+        let located = sources.locate(&["sites".into(), "file".into()]).unwrap();
+        assert_eq!(located.file, dir.path().join("config.json"));
+
+        let newer = write("newer.json", json!({ "version": 1000 }));
+        assert!(matches!(load(&newer), Err(ComposeError::Migration { .. })));
+        let cycle = write("cycle.json", json!({ "include": ["cycle.json"] }));
+        assert!(matches!(load(&cycle), Err(ComposeError::Cycle(_))));
+        let invalid = write("invalid.json", json!({ "include": "common.json" }));
+        assert!(matches!(
+            load(&invalid),
+            Err(ComposeError::InvalidInclude(_))
+        ));
+        let extends = write(
+            "extends.json",
+            json!({ "runs": [{ "name": "a", "extends": "b" }, { "name": "b", "extends": "a" }] }),
+        );
+        assert!(matches!(load(&extends), Err(ComposeError::ExtendsCycle(_))));
+        let extends = write(
+            "extends.json",
+            json!({ "runs": [{ "name": "a", "extends": "missing" }] }),
+        );
+        assert!(matches!(
+            load(&extends),
+            Err(ComposeError::UnknownExtends { .. })
+        ));
+        assert!(matches!(
+            load(&dir.path().join("missing.json")),
+            Err(ComposeError::IOError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_include_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, value: Value| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, value.to_string()).unwrap();
+            path
+        };
+        write(
+            "a.json",
+            json!({ "runs": [{ "name": "a1" }, { "name": "a2" }] }),
+        );
+        write(
+            "b.json",
+            json!({ "runs": [{ "name": "b1", "extends": "a1" }] }),
+        );
+        let path = write(
+            "config.json",
+            json!({ "include": ["a.json", "b.json"], "runs": [{ "name": "c1" }] }),
+        );
+
+        let (config, _) = load(&path).unwrap();
+        let names: Vec<_> = config["runs"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|run| run["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["a1", "a2", "b1", "c1"]);
+    }
+}
@@ -0,0 +1,412 @@
+//! Diagnostics of invalid configs, telling every problem along with its path within the config (e.g.
+//! `runs[1].template`) and, as far as the config files tell, the file, line and column it is declared at.
+//!
+//! The path of a problem found while deserializing travels within its message (see [`at`]), as serde errors can't
+//! carry anything else.
+
+use regex::Regex;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use validator::{ValidationErrors, ValidationErrorsKind};
+
+static AT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)^at `([^`]*)`: (.*)$").unwrap());
+static UNKNOWN_FIELD: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^unknown field `([^`]*)`").unwrap());
+static IDENT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").unwrap());
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+impl From<&str> for Segment {
+    fn from(key: &str) -> Self {
+        Segment::Key(key.to_string())
+    }
+}
+
+impl From<usize> for Segment {
+    fn from(index: usize) -> Self {
+        Segment::Index(index)
+    }
+}
+
+/// `path` as written in diagnostics, e.g. `runs[1].template` or `inputs["data/soil.tif"]`.
+pub fn display(path: &[Segment]) -> String {
+    let mut displayed = String::new();
+    for segment in path {
+        match segment {
+            Segment::Index(i) => displayed.push_str(&format!("[{}]", i)),
+            Segment::Key(key) if IDENT.is_match(key) => {
+                if !displayed.is_empty() {
+                    displayed.push('.');
+                }
+                displayed.push_str(key);
+            }
+            Segment::Key(key) => displayed.push_str(&format!("[{}]", Value::from(key.as_str()))),
+        }
+    }
+    displayed
+}
+
+/// The path [`display`] wrote as `displayed`.
+fn parse(displayed: &str) -> Vec<Segment> {
+    let mut path = Vec::new();
+    let mut rest = displayed;
+    while let Some(c) = rest.chars().next() {
+        if c == '.' {
+            rest = &rest[1..];
+        } else if let Some(quoted) = rest.strip_prefix("[\"") {
+            let mut escaped = false;
+            let end = quoted
+                .char_indices()
+                .find(|&(_, c)| {
+                    let end = c == '"' && !escaped;
+                    escaped = c == '\\' && !escaped;
+                    end
+                })
+                .map_or(quoted.len(), |(i, _)| i);
+            let key = serde_json::from_str(&rest[1..end + 3]).unwrap_or_default();
+            path.push(Segment::Key(key));
+            rest = rest.get(end + 4..).unwrap_or_default();
+        } else if let Some(index) = rest.strip_prefix('[') {
+            let end = index.find(']').unwrap_or(index.len());
+            path.push(Segment::Index(index[..end].parse().unwrap_or_default()));
+            rest = index.get(end + 1..).unwrap_or_default();
+        } else {
+            let end = rest.find(['.', '[']).unwrap_or(rest.len());
+            path.push(Segment::Key(rest[..end].to_string()));
+            rest = &rest[end..];
+        }
+    }
+    path
+}
+
+/// Prefixes the path of `error` with `path`, for the error to tell where it comes from. See [`split`].
+pub fn at<E: serde::de::Error>(path: &[Segment], error: impl fmt::Display) -> E {
+    let (inner, message) = split(&error.to_string());
+    E::custom(format!(
+        "at `{}`: {}",
+        display(&[path, inner.as_slice()].concat()),
+        message
+    ))
+}
+
+/// Path and message of an error passed through [`at`]. The path is empty if it wasn't.
+pub fn split(error: &str) -> (Vec<Segment>, String) {
+    match AT.captures(error) {
+        Some(captures) => (parse(&captures[1]), captures[2].to_string()),
+        None => (Vec::new(), error.to_string()),
+    }
+}
+
+/// Where something is declared in the config files.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file.display(), self.line, self.column)
+    }
+}
+
+/// Line and column of the properties of a config file, by path.
+type Positions = HashMap<Vec<Segment>, (usize, usize)>;
+
+/// Where the properties of the config files are declared: their key for the members of objects, the value itself for
+/// the items of arrays.
+#[derive(Default)]
+pub struct SourceMap {
+    /// Positions by path of every file, in the order they are merged into the config.
+    files: Vec<(PathBuf, Positions)>,
+}
+
+impl SourceMap {
+    /// Indexes the config file `file` of contents `json`, merged over the ones added before. `json` is expected to be
+    /// valid.
+    pub fn add(&mut self, file: &Path, json: &str) {
+        let mut scanner = Scanner {
+            bytes: json.as_bytes(),
+            json,
+            pos: 0,
+            line: 1,
+            column: 1,
+            positions: HashMap::new(),
+        };
+        scanner.value(&mut Vec::new());
+        self.files.push((file.to_path_buf(), scanner.positions));
+    }
+
+    /// Location of `path`, or else of its closest parent found, in the last file declaring it.
+    pub fn locate(&self, path: &[Segment]) -> Option<Location> {
+        (1..=path.len()).rev().find_map(|len| {
+            self.files.iter().rev().find_map(|(file, positions)| {
+                let &(line, column) = positions.get(&path[..len])?;
+                Some(Location {
+                    file: file.clone(),
+                    line,
+                    column,
+                })
+            })
+        })
+    }
+}
+
+struct Scanner<'a> {
+    json: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+    line: usize,
+    column: usize,
+    positions: Positions,
+}
+
+impl Scanner<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn advance(&mut self) {
+        match self.peek() {
+            Some(b'\n') => {
+                self.line += 1;
+                self.column = 1;
+            }
+            // Continuation bytes of UTF-8 characters, counted along with their first byte.
+            Some(b) if b & 0xC0 == 0x80 => {}
+            Some(_) => self.column += 1,
+            None => return,
+        }
+        self.pos += 1;
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|b| b.is_ascii_whitespace()) {
+            self.advance();
+        }
+    }
+
+    fn string(&mut self) -> String {
+        let start = self.pos;
+        self.advance();
+        let mut escaped = false;
+        while let Some(b) = self.peek() {
+            self.advance();
+            match b {
+                b'"' if !escaped => break,
+                b'\\' => escaped = !escaped,
+                _ => escaped = false,
+            }
+        }
+        serde_json::from_str(&self.json[start..self.pos]).unwrap_or_default()
+    }
+
+    fn value(&mut self, path: &mut Vec<Segment>) {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => {
+                self.advance();
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() != Some(b'"') {
+                        break;
+                    }
+                    let position = (self.line, self.column);
+                    let key = self.string();
+                    self.skip_whitespace();
+                    self.advance(); // :
+                    path.push(Segment::Key(key));
+                    self.positions.insert(path.clone(), position);
+                    self.value(path);
+                    path.pop();
+                    self.skip_whitespace();
+                    if self.peek() == Some(b',') {
+                        self.advance();
+                    }
+                }
+                self.advance(); // }
+            }
+            Some(b'[') => {
+                self.advance();
+                let mut index = 0;
+                loop {
+                    self.skip_whitespace();
+                    if matches!(self.peek(), Some(b']') | None) {
+                        break;
+                    }
+                    path.push(Segment::Index(index));
+                    self.positions
+                        .insert(path.clone(), (self.line, self.column));
+                    self.value(path);
+                    path.pop();
+                    index += 1;
+                    self.skip_whitespace();
+                    if self.peek() == Some(b',') {
+                        self.advance();
+                    }
+                }
+                self.advance(); // ]
+            }
+            Some(b'"') => {
+                self.string();
+            }
+            _ => {
+                while self
+                    .peek()
+                    .is_some_and(|b| !b",]}".contains(&b) && !b.is_ascii_whitespace())
+                {
+                    self.advance();
+                }
+            }
+        }
+    }
+}
+
+/// A problem of the config.
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub path: Vec<Segment>,
+    pub message: String,
+    pub location: Option<Location>,
+}
+
+impl Diagnostic {
+    /// Diagnostic of `error`, raised while deserializing the config, located with `sources`.
+    pub fn deserialization(error: &str, sources: &SourceMap) -> Self {
+        let (mut path, message) = split(error);
+        // Unknown fields are better located by their key than by the object they are in.
+        let mut located = path.clone();
+        if let Some(captures) = UNKNOWN_FIELD.captures(&message) {
+            located.push(Segment::Key(captures[1].to_string()));
+            path = located.clone();
+        }
+        Diagnostic {
+            location: sources.locate(&located),
+            path,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(location) = &self.location {
+            write!(f, "{}: ", location)?;
+        }
+        if !self.path.is_empty() {
+            write!(f, "{}: ", display(&self.path))?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Every error of `errors` along with its path, sorted by path. Errors of whole structs are reported at their path.
+pub fn validation_errors(errors: &ValidationErrors) -> Vec<(Vec<Segment>, String)> {
+    fn walk(
+        errors: &ValidationErrors,
+        path: &mut Vec<Segment>,
+        found: &mut Vec<(Vec<Segment>, String)>,
+    ) {
+        for (field, kind) in errors.errors() {
+            let len = path.len();
+            if field != "__all__" {
+                path.push(Segment::Key(field.to_string()));
+            }
+            match kind {
+                ValidationErrorsKind::Field(errors) => {
+                    found.extend(errors.iter().map(|e| (path.clone(), e.to_string())))
+                }
+                ValidationErrorsKind::Struct(errors) => walk(errors, path, found),
+                ValidationErrorsKind::List(errors) => {
+                    for (i, errors) in errors {
+                        path.push(Segment::Index(*i));
+                        walk(errors, path, found);
+                        path.pop();
+                    }
+                }
+            }
+            path.truncate(len);
+        }
+    }
+
+    let mut found = Vec::new();
+    walk(errors, &mut Vec::new(), &mut found);
+    found.sort_by_key(|(path, _)| display(path));
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paths() {
+        let path = vec![
+            Segment::from("inputs"),
+            Segment::from("data/soil.tif"),
+            Segment::from("runs"),
+            Segment::from(2),
+            Segment::from("name"),
+        ];
+        let displayed = display(&path);
+        assert_eq!(displayed, r#"inputs["data/soil.tif"].runs[2].name"#);
+        assert_eq!(parse(&displayed), path);
+
+        let error: serde_json::Error = at(&["sites".into()], "at `include.file`: invalid type");
+        let (path, message) = split(&error.to_string());
+        assert_eq!(display(&path), "sites.include.file");
+        assert_eq!(message, "invalid type");
+        assert_eq!(
+            split("invalid type"),
+            (Vec::new(), "invalid type".to_string())
+        );
+    }
+
+    #[test]
+    fn test_source_map() {
+        let mut sources = SourceMap::default();
+        sources.add(
+            Path::new("common.json"),
+            "{\n  \"sites\": { \"type\": \"std:raster\" },\n  \"runs\": [{ \"name\": \"base\" }]\n}",
+        );
+        sources.add(
+            Path::new("config.json"),
+            "{\n  \"runs\": [\n    { \"name\": \"maïze\", \"template\": \"x.SNX\" },\n    {\n      \"name\": \"r\\\"ice\",\n      \"unknown\": [1, { \"a\": 2 }]\n    }\n  ]\n}",
+        );
+
+        let locate = |path: &str| sources.locate(&parse(path)).map(|l| l.to_string());
+        assert_eq!(locate("sites.type").as_deref(), Some("common.json:2:14"));
+        assert_eq!(
+            locate("runs[0].template").as_deref(),
+            Some("config.json:3:24")
+        );
+        assert_eq!(locate("runs[1].name").as_deref(), Some("config.json:5:7"));
+        assert_eq!(
+            locate("runs[1].unknown[1].a").as_deref(),
+            Some("config.json:6:24")
+        );
+        // Located at the closest parent declared.
+        assert_eq!(
+            locate("runs[1].soil.file").as_deref(),
+            Some("config.json:4:5")
+        );
+        assert_eq!(locate("pipeline"), None);
+
+        let diagnostic = Diagnostic::deserialization(
+            "at `runs[1]`: unknown field `unknown`, expected `name`",
+            &sources,
+        );
+        assert_eq!(
+            diagnostic.to_string(),
+            "config.json:6:7: runs[1].unknown: unknown field `unknown`, expected `name`"
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use crate::processing::template::drivers::DRIVER_TERA;
+use crate::processing::template::{Engine, EngineDriver, TemplateSources};
+use crate::registry::resources::TemplateEngineDriverResource;
+use crate::registry::ResourceSeed;
+use serde::de::{DeserializeSeed, MapAccess, Visitor};
+use serde_json::Map;
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+
+/// The template engine of a run: a registered [`EngineDriver`] along with its options.
+#[derive(Clone)]
+pub struct TemplateEngineConfig {
+    /// Identifier of the driver as written in the config, for the logs.
+    pub name: String,
+    pub driver: EngineDriver<Box<dyn Engine>, Box<dyn Any>>,
+    args: serde_json::Value,
+}
+
+impl TemplateEngineConfig {
+    pub fn build(
+        &self,
+        sources: &TemplateSources,
+    ) -> Result<Box<dyn Engine>, Box<dyn Error + Send + Sync>> {
+        let config = (self.driver.config_deserializer)(self.args.clone())?;
+        (self.driver.create)(config, sources)
+    }
+}
+
+/// Tera, for the runs that don't set an `engine`.
+impl Default for TemplateEngineConfig {
+    fn default() -> Self {
+        Self {
+            name: "std:tera".to_string(),
+            driver: DRIVER_TERA.clone().coerce_to_dynamic(),
+            args: serde_json::Value::Object(Map::new()),
+        }
+    }
+}
+
+/// Deserializes the template engine of a run, either as the identifier of its driver (e.g. `"std:tera"`) or as an
+/// object with the identifier under `type` along with the options of the driver.
+#[derive(Clone)]
+pub struct TemplateEngineConfigSeed<'a> {
+    pub resource_seed: ResourceSeed<'a, TemplateEngineDriverResource>,
+}
+
+impl<'de> DeserializeSeed<'de> for TemplateEngineConfigSeed<'de> {
+    type Value = TemplateEngineConfig;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(TemplateEngineConfigVisitor { seed: self })
+    }
+}
+
+struct TemplateEngineConfigVisitor<'a> {
+    seed: TemplateEngineConfigSeed<'a>,
+}
+
+impl<'a> TemplateEngineConfigVisitor<'a> {
+    /// Checks the options against the driver, so they are reported along with the other config errors.
+    fn engine_config<E: serde::de::Error>(
+        self,
+        name: &str,
+        args: Map<String, serde_json::Value>,
+    ) -> Result<TemplateEngineConfig, E> {
+        let resource = self
+            .seed
+            .resource_seed
+            .deserialize(serde::de::value::StrDeserializer::<E>::new(name))?;
+        let args = serde_json::Value::Object(args);
+        (resource.0.config_deserializer)(args.clone()).map_err(E::custom)?;
+        Ok(TemplateEngineConfig {
+            name: name.to_string(),
+            driver: resource.0.clone(),
+            args,
+        })
+    }
+}
+
+impl<'de> Visitor<'de> for TemplateEngineConfigVisitor<'de> {
+    type Value = TemplateEngineConfig;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a template engine ID or a TemplateEngineConfig struct")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        self.engine_config(v, Map::new())
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut name: Option<String> = None;
+        let mut args: Map<String, serde_json::Value> = Map::new();
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "type" => name = Some(map.next_value()?),
+                _ => {
+                    args.insert(key.to_string(), map.next_value()?);
+                }
+            }
+        }
+
+        let name = name.ok_or_else(|| serde::de::Error::missing_field("type"))?;
+        self.engine_config(&name, args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::itself::init_itself;
+    use crate::registry::{PublicIdentifierSeed, Registries};
+    use serde_json::json;
+
+    #[test]
+    fn test_template_engine_seed() {
+        let mut registries = Registries::new();
+        init_itself(&mut registries).unwrap();
+        let seed = TemplateEngineConfigSeed {
+            resource_seed: ResourceSeed {
+                registry: registries.reg_template_engines(),
+                id_seed: PublicIdentifierSeed {
+                    default_namespace: "std".to_string(),
+                },
+            },
+        };
+        let parse = |json: serde_json::Value| seed.clone().deserialize(json);
+
+        assert_eq!(parse(json!("std:tera")).unwrap().name, "std:tera");
+        assert_eq!(parse(json!({ "type": "tera" })).unwrap().name, "tera");
+        assert!(parse(json!({ "type": "std:tera", "unknown": 1 })).is_err());
+        assert!(parse(json!("std:jinja")).is_err());
+
+        let engine = parse(json!("std:tera")).unwrap();
+        let sources = TemplateSources {
+            run: "r1",
+            template: "{% extends \"missing.tpl\" %}",
+            partials: &[],
+            undefined: Default::default(),
+            extensions: &[],
+        };
+        assert!(engine.build(&sources).is_err());
+    }
+}
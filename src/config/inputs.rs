@@ -0,0 +1,25 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+use validator::Validate;
+
+static RE_SHA256: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[a-fA-F0-9]{64}$").unwrap());
+static RE_URL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(https?|s3)://.+").unwrap());
+
+/// Expectations about an input dataset, verified before processing starts.
+/// Protects a campaign against datasets being swapped or modified between executions.
+#[derive(Validate, Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct InputConfig {
+    /// Expected SHA-256 digest of the file, hex-encoded.
+    #[validate(regex(path = *RE_SHA256, message = "sha256 must be a 64 characters long hex string"))]
+    pub sha256: Option<String>,
+
+    /// Expected size of the file, in bytes.
+    pub size: Option<u64>,
+
+    /// Where to download the file from if it doesn't exist (`http://`, `https://` or `s3://` for public buckets).
+    /// Downloads are kept in the input cache (see `--input-cache-dir`), so each file is only downloaded once.
+    #[validate(regex(path = *RE_URL, message = "url must start with http://, https:// or s3://"))]
+    pub url: Option<String>,
+}
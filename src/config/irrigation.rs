@@ -0,0 +1,190 @@
+//! Irrigation scenarios, expanding a single run declaration into rainfed and irrigated runs.
+
+use super::runs::RunConfig;
+use crate::processing::context::{ContextValue, PrimitiveContextValue};
+use serde::{Deserialize, Serialize};
+
+/// Irrigation scenarios of a run. The run is replaced by one run per scenario, named `<run>_rainfed` and
+/// `<run>_<scenario>`, with the DSSAT irrigation management variables set for templates: `irrig` (`N` or `A`),
+/// `imdep`, `ithrl`, `ithru`, `iroff`, `imeth`, `iramt`, `ireff` and `irrigation_scenario` (the scenario name).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct IrrigationConfig {
+    /// Generates the rainfed run.
+    #[serde(default = "default_rainfed")]
+    pub rainfed: bool,
+
+    /// Automatic irrigation scenarios, each generating a run.
+    #[serde(default)]
+    pub automatic: Vec<AutoIrrigation>,
+}
+
+fn default_rainfed() -> bool {
+    true
+}
+
+/// Automatic irrigation when the available soil water falls below a threshold.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AutoIrrigation {
+    #[serde(default = "default_name")]
+    pub name: String,
+
+    /// Management depth, cm (`IMDEP`).
+    #[serde(default = "default_depth_cm")]
+    pub depth_cm: f64,
+
+    /// Threshold, % of the maximum available water triggering irrigation (`ITHRL`).
+    #[serde(default = "default_threshold_pct")]
+    pub threshold_pct: f64,
+
+    /// End point, % of the maximum available water (`ITHRU`).
+    #[serde(default = "default_target_pct")]
+    pub target_pct: f64,
+
+    /// Growth stage irrigation stops at (`IROFF`).
+    #[serde(default = "default_end_stage")]
+    pub end_stage: String,
+
+    /// Irrigation method code (`IMETH`).
+    #[serde(default = "default_method")]
+    pub method: String,
+
+    /// Amount per irrigation, mm (`IRAMT`).
+    #[serde(default = "default_amount_mm")]
+    pub amount_mm: f64,
+
+    /// Irrigation efficiency, fraction (`IREFF`).
+    #[serde(default = "default_efficiency")]
+    pub efficiency: f64,
+}
+
+fn default_name() -> String {
+    "irrigated".to_string()
+}
+
+fn default_depth_cm() -> f64 {
+    30.0
+}
+
+fn default_threshold_pct() -> f64 {
+    50.0
+}
+
+fn default_target_pct() -> f64 {
+    100.0
+}
+
+fn default_end_stage() -> String {
+    "GS000".to_string()
+}
+
+fn default_method() -> String {
+    "IR001".to_string()
+}
+
+fn default_amount_mm() -> f64 {
+    10.0
+}
+
+fn default_efficiency() -> f64 {
+    1.0
+}
+
+fn variant(run: &RunConfig, suffix: &str, values: Vec<(&str, PrimitiveContextValue)>) -> RunConfig {
+    let mut variant = run.clone();
+    variant.name = format!("{}_{}", run.name, suffix);
+    variant.irrigation = None;
+    for (k, v) in values {
+        variant.extra.insert(k.to_string(), ContextValue::Prim(v));
+    }
+    variant
+}
+
+/// Replaces every run declaring irrigation scenarios with one run per scenario. Other runs are kept as is.
+pub fn expand_irrigation(runs: Vec<RunConfig>) -> Vec<RunConfig> {
+    use PrimitiveContextValue::{Float, String as Str};
+
+    let mut expanded = Vec::with_capacity(runs.len());
+    for run in runs {
+        let Some(irrigation) = &run.irrigation else {
+            expanded.push(run);
+            continue;
+        };
+
+        if irrigation.rainfed {
+            expanded.push(variant(
+                &run,
+                "rainfed",
+                vec![
+                    ("irrig", Str("N".to_string())),
+                    ("irrigation_scenario", Str("rainfed".to_string())),
+                ],
+            ));
+        }
+
+        for auto in &irrigation.automatic {
+            expanded.push(variant(
+                &run,
+                &auto.name,
+                vec![
+                    ("irrig", Str("A".to_string())),
+                    ("irrigation_scenario", Str(auto.name.clone())),
+                    ("imdep", Float(auto.depth_cm)),
+                    ("ithrl", Float(auto.threshold_pct)),
+                    ("ithru", Float(auto.target_pct)),
+                    ("iroff", Str(auto.end_stage.clone())),
+                    ("imeth", Str(auto.method.clone())),
+                    ("iramt", Float(auto.amount_mm)),
+                    ("ireff", Float(auto.efficiency)),
+                ],
+            ));
+        }
+    }
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_irrigation() {
+        let irrigation: IrrigationConfig =
+            serde_json::from_str(r#"{ "automatic": [{ "threshold_pct": 40 }] }"#).unwrap();
+        let runs = vec![
+            RunConfig {
+                name: "maize".to_string(),
+                irrigation: Some(irrigation),
+                ..Default::default()
+            },
+            RunConfig {
+                name: "wheat".to_string(),
+                ..Default::default()
+            },
+        ];
+
+        let runs = expand_irrigation(runs);
+        let names: Vec<&str> = runs.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["maize_rainfed", "maize_irrigated", "wheat"]);
+
+        let get = |run: &RunConfig, k: &str| match run.extra.get(k) {
+            Some(ContextValue::Prim(v)) => Some(v.clone()),
+            _ => None,
+        };
+        assert_eq!(
+            get(&runs[0], "irrig"),
+            Some(PrimitiveContextValue::String("N".to_string()))
+        );
+        assert_eq!(get(&runs[0], "ithrl"), None);
+        assert_eq!(
+            get(&runs[1], "irrig"),
+            Some(PrimitiveContextValue::String("A".to_string()))
+        );
+        assert_eq!(
+            get(&runs[1], "ithrl"),
+            Some(PrimitiveContextValue::Float(40.0))
+        );
+        assert!(runs[1].irrigation.is_none());
+    }
+}
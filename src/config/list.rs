@@ -0,0 +1,100 @@
+//! Identifiers of the registered drivers along with their description, for config authors to discover the values the
+//! `type` of the drivers can take, plugins included. See the `list` subcommand.
+
+use crate::registry::{PublicIdentifier, Registries, Registry, Resource};
+use clap::ValueEnum;
+
+/// Kind of the drivers listed by the `list` subcommand.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum ListKind {
+    /// Site generator drivers, the `type` of `sites`.
+    Drivers,
+    /// Processors and stages of the `pipeline`.
+    Processors,
+    /// Sinks of the `sinks` of the runs.
+    Sinks,
+    /// Namespaces of the identifiers, along with the plugin that claimed each.
+    Namespaces,
+}
+
+fn entries<T: Resource>(registry: &Registry<T>) -> Vec<(PublicIdentifier, Option<&str>)> {
+    let ids = registry.ids().into_iter();
+    ids.map(|id| {
+        let description = registry.description(&id);
+        (id, description)
+    })
+    .collect()
+}
+
+/// Pads the first column of `rows` to the widest one, trimming the lines of the rows without a second column.
+fn columns(rows: Vec<(String, String)>) -> Vec<String> {
+    let width = rows.iter().map(|(first, _)| first.len()).max().unwrap_or(0);
+    rows.into_iter()
+        .map(|(first, second)| {
+            let line = format!("{:width$}  {}", first, second);
+            line.trim_end().to_string()
+        })
+        .collect()
+}
+
+/// The drivers of `kind` registered in `registries`, one line each sorted by identifier: the identifier with its
+/// namespace (e.g. `std:csv`), followed by its description if it was registered with one.
+///
+/// For [`ListKind::Namespaces`], the claimed namespaces instead, each followed by the name, version and description of
+/// the plugin that claimed it.
+pub fn list(registries: &Registries, kind: ListKind) -> Vec<String> {
+    let mut entries = match kind {
+        ListKind::Drivers => entries(registries.reg_sitegen_drivers()),
+        ListKind::Processors => {
+            let mut processors = entries(registries.reg_processor_drivers());
+            processors.extend(entries(registries.reg_pipeline_stages()));
+            processors
+        }
+        ListKind::Sinks => entries(registries.reg_sinks()),
+        ListKind::Namespaces => {
+            let namespaces = registries.namespaces().into_iter();
+            let rows = namespaces.map(|namespace| {
+                let claimant = namespace.claimant();
+                let about = format!("{}  {}", claimant, claimant.description);
+                (namespace.to_string(), about)
+            });
+            return columns(rows.collect());
+        }
+    };
+    entries.sort_by_key(|(id, _)| id.to_string());
+
+    let rows = entries
+        .into_iter()
+        .map(|(id, description)| (id.to_string(), description.unwrap_or("").to_string()));
+    columns(rows.collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::itself::init_itself;
+
+    #[test]
+    fn test_list() {
+        let mut registries = Registries::new();
+        init_itself(&mut registries).unwrap();
+
+        let drivers = list(&registries, ListKind::Drivers);
+        assert_eq!(drivers.len(), 4);
+        assert!(drivers[0].starts_with("std:csv      Sites at the rows"));
+        let processors = list(&registries, ListKind::Processors);
+        assert!(processors
+            .iter()
+            .any(|line| line.starts_with("std:render ")));
+        let sinks = list(&registries, ListKind::Sinks);
+        assert!(sinks[0].starts_with("std:csv  Writes"));
+        let namespaces = list(&registries, ListKind::Namespaces);
+        assert_eq!(
+            namespaces,
+            vec![format!(
+                "std  pythia-rs {}  Standard library of drivers embedded in the application",
+                env!("CARGO_PKG_VERSION")
+            )]
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! Upgrades of config files written for older layouts of the config, so they keep working across releases.
+//!
+//! Every config file may declare the `version` of the layout it is written for, taken as 1 (the layout of the
+//! releases before versions were introduced) if it doesn't. Whenever a release changes the layout in a way older files
+//! no longer load with, it bumps [`VERSION`] by adding a [`Migration`] to [`MIGRATIONS`] rewriting files of the
+//! previous version into the new layout.
+
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+/// Rewrite of config files of a version into the layout of the next one.
+pub struct Migration {
+    /// What is rewritten, reported when the migration applies.
+    pub description: &'static str,
+    pub apply: fn(&mut Map<String, Value>),
+}
+
+/// Migrations of the layouts, the one at `i` upgrading files of version `i + 1`.
+pub const MIGRATIONS: &[Migration] = &[];
+
+/// Version of the current layout of the config.
+pub const VERSION: u64 = 1 + MIGRATIONS.len() as u64;
+
+#[derive(Debug, Error)]
+pub enum MigrationError {
+    #[error("`version` must be a positive integer")]
+    InvalidVersion,
+    #[error("version {version} is newer than the version {current} this release supports")]
+    Newer { version: u64, current: u64 },
+}
+
+/// Removes the `version` of `file` and rewrites it into the current layout with `migrations` (see [`MIGRATIONS`]).
+/// Returns what was rewritten, if anything.
+pub fn migrate(
+    file: &mut Map<String, Value>,
+    migrations: &[Migration],
+) -> Result<Vec<String>, MigrationError> {
+    let current = 1 + migrations.len() as u64;
+    let version = match file.remove("version") {
+        None => 1,
+        Some(version) => match version.as_u64() {
+            Some(version) if version >= 1 => version,
+            _ => return Err(MigrationError::InvalidVersion),
+        },
+    };
+    if version > current {
+        return Err(MigrationError::Newer { version, current });
+    }
+
+    let pending = &migrations[version as usize - 1..];
+    Ok(pending
+        .iter()
+        .zip(version..)
+        .map(|(migration, from)| {
+            (migration.apply)(file);
+            format!(
+                "version {} to {}: {}",
+                from,
+                from + 1,
+                migration.description
+            )
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_migrate() {
+        let migrations = [
+            Migration {
+                description: "renamed `templateDir` to `template_dir`",
+                apply: |file| {
+                    if let Some(dir) = file.remove("templateDir") {
+                        file.insert("template_dir".to_string(), dir);
+                    }
+                },
+            },
+            Migration {
+                description: "moved `sinks` into every run",
+                apply: |file| {
+                    let sinks = file.remove("sinks").unwrap_or(json!([]));
+                    for run in file["runs"].as_array_mut().unwrap() {
+                        run["sinks"] = sinks.clone();
+                    }
+                },
+            },
+        ];
+        let object = |value: Value| value.as_object().unwrap().clone();
+
+        let mut file = object(json!({
+            "templateDir": "templates",
+            "sinks": ["std:csv"],
+            "runs": [{ "name": "maize" }]
+        }));
+        let changes = migrate(&mut file, &migrations).unwrap();
+        assert_eq!(changes.len(), 2);
+        assert!(changes[0].starts_with("version 1 to 2"));
+        assert_eq!(
+            Value::Object(file),
+            json!({ "template_dir": "templates", "runs": [{ "name": "maize", "sinks": ["std:csv"] }] })
+        );
+
+        let mut file = object(json!({ "version": 3, "templateDir": "templates" }));
+        assert!(migrate(&mut file, &migrations).unwrap().is_empty());
+        assert!(file.contains_key("templateDir"));
+
+        let mut file = object(json!({ "version": 4 }));
+        assert!(matches!(
+            migrate(&mut file, &migrations),
+            Err(MigrationError::Newer { version: 4, .. })
+        ));
+        let mut file = object(json!({ "version": "1" }));
+        assert!(matches!(
+            migrate(&mut file, &migrations),
+            Err(MigrationError::InvalidVersion)
+        ));
+        assert!(migrate(&mut Map::new(), MIGRATIONS).unwrap().is_empty());
+    }
+}
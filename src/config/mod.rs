@@ -1,13 +1,49 @@
+pub mod compose;
+pub mod diagnostics;
+pub mod engines;
+pub mod inputs;
+pub mod irrigation;
+pub mod list;
+pub mod migrate;
+pub mod pipeline;
+pub mod plugins;
+pub mod providers;
+pub mod references;
 pub mod runs;
+pub mod schema;
+pub mod secrets;
+pub mod sinks;
 pub mod sites;
-
+pub mod suggest;
+pub mod sweep;
+
+use crate::calibration::CalibrationConfig;
+use crate::config::diagnostics::{at, validation_errors, Diagnostic, Segment, SourceMap};
+use crate::config::engines::{TemplateEngineConfig, TemplateEngineConfigSeed};
+use crate::config::inputs::InputConfig;
+use crate::config::irrigation::expand_irrigation;
+use crate::config::list::ListKind;
+use crate::config::pipeline::{
+    default_pipeline, PipelineConfigSeed, ProcessorConfig, ProcessorConfigSeed,
+};
+use crate::config::plugins::PluginConfig;
+use crate::config::providers::{ContextProviderConfig, ContextProviderConfigSeed};
+use crate::config::references::resolve_references;
+use crate::config::secrets::Secrets;
+use crate::config::sinks::{SinkConfig, SinkConfigSeed};
 use crate::config::sites::{SiteSourceConfig, SiteSourceConfigSeed};
-use crate::registry::{PublicIdentifierSeed, Registries, ResourceSeed};
-use clap::Parser;
+use crate::config::sweep::expand_sweeps;
+use crate::fetch::{fetch_inputs, FetchError, InputCache};
+use crate::processing::context::ContextValue;
+use crate::processing::template::extensions::{template_name, TemplateExtension};
+use crate::registry::resources::TemplateExtensionResource;
+use crate::registry::{PublicIdentifierSeed, Registries, Registry, ResourceSeed};
+use clap::{Parser, Subcommand};
 use runs::*;
 use serde::de::{DeserializeSeed, MapAccess, Visitor};
 use serde_inline_default::serde_inline_default;
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
 use std::path::PathBuf;
@@ -30,9 +66,25 @@ fn validate_workdir_is_directory(path: &PathBuf) -> Result<(), ValidationError>
     Ok(())
 }
 
+static ERRCODE_TEMPLATE_DIR_NOT_DIR: &str = "ERRCODE_TEMPLATE_DIR_NOT_DIR";
+
+fn validate_template_dir(path: &PathBuf) -> Result<(), ValidationError> {
+    if !path.is_dir() {
+        return Err(
+            ValidationError::new(ERRCODE_TEMPLATE_DIR_NOT_DIR).with_message(Cow::from(format!(
+                "Template directory {} is not a directory.",
+                path.display()
+            ))),
+        );
+    }
+
+    Ok(())
+}
+
 fn validate_workdir_overrides(args: &Args) -> Result<(), ValidationError> {
     if let Some(path) = &args.workdir {
-        if !args.clear_workdir {
+        // Resuming a run needs the working directory it left behind.
+        if !args.clear_workdir && !args.resume {
             match path.read_dir() {
                 Ok(entries) => {
                     if entries.count() > 0 {
@@ -54,9 +106,35 @@ fn validate_workdir_overrides(args: &Args) -> Result<(), ValidationError> {
     Ok(())
 }
 
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Prints the JSON Schema of the config file, with the identifiers of the drivers of Pythia itself, for editors to
+    /// complete and validate configs with. No config file is read, so the drivers of plugins are left out.
+    Schema,
+    /// Loads the config file and runs the preflight checks on it (site sources, templates, inputs), then exits
+    /// without creating a working directory or running the pipeline. Exits with 1 if anything is wrong.
+    Validate,
+    /// Prints the identifiers of the registered drivers of a kind (those of the plugins of `--config-file` included, if
+    /// given), with their namespace and a short description, e.g. the values the `type` of `sites` can take for
+    /// `list drivers`. `list namespaces` prints the claimed namespaces instead, along with the name, version and
+    /// description of the plugin that claimed each.
+    List {
+        #[arg(value_enum)]
+        kind: ListKind,
+    },
+    /// Runs the calibration loop of the `calibration` section of the config (experimental): the pipeline is run on a
+    /// subset of the sites for every candidate parameter set, each in its own directory of the working directory, and
+    /// the outputs it harvested are scored against the observations. Scores are logged to `calibration.csv`.
+    Calibrate,
+}
+
 #[derive(Validate, Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct Args {
+    /// Runs the pipeline of the config file if not given.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Path to the JSON configuration file.
     #[arg(short, long, default_value = "config.json")]
     pub config_file: String,
@@ -65,10 +143,41 @@ pub struct Args {
     #[arg(short, long, default_value_t = 0)]
     pub workers: usize,
 
+    /// Number of times a crashed worker thread is restarted before giving up on it. Defaults to 0 (never restart).
+    /// The remaining workers keep processing the run regardless.
+    #[arg(long, default_value_t = 0)]
+    pub worker_restarts: usize,
+
+    /// Memory the contexts of a run may take together, in MB, given the `memory_hint` of the run. Unlimited if not
+    /// specified.
+    #[arg(long)]
+    pub memory_budget: Option<u64>,
+
     /// Size of the buffer between each step of the processing pipeline. Defaults to 128.
     #[arg(short, long, default_value_t = 128)]
     pub pipeline_buffer_size: usize,
 
+    /// Parks the contexts the pipeline can't keep up with in a temporary file of the working directory instead of
+    /// holding the context generator back. Meant for huge site lists on machines with little memory, along with a small
+    /// --pipeline-buffer-size.
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value_t = false)]
+    pub spill_to_disk: bool,
+
+    /// How many contexts are read ahead of the pipeline to pass on the ones of the runs with the highest priority first.
+    /// Only used when the runs have different priorities. Defaults to 10000.
+    #[arg(long, default_value_t = 10_000)]
+    pub priority_window: usize,
+
+    /// Writes the checkpoint, failed contexts and run-wide batch files in a canonical (run, site) order once the
+    /// processing is done, instead of in the order the workers got to the contexts, so they can be diffed across runs.
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value_t = false)]
+    pub deterministic: bool,
+
+    /// Fails the preflight checks when the templates use variables their contexts don't have, instead of warning about
+    /// them. Those may still be covariates or attributes of the sites, which aren't known until they are read.
+    #[arg(long, action = clap::ArgAction::SetTrue, default_value_t = false)]
+    pub strict_templates: bool,
+
     /// Specify the working directory, created recursively if needed. If not specified, a temporary one will be created.
     /// Check --keep-workdir and --clear-workdir to control the behavior of the working directory.
     /// By default, the program will halt execution if the specified --workdir is not empty, unless --clear-workdir is specified.
@@ -85,6 +194,26 @@ pub struct Args {
     /// Overrides the working directory if it isn't already empty. This option has NO effect if not combined with --workdir (directory will always be kep).
     /// By default, the program will halt execution if the specified --workdir is not empty, unless --clear-workdir is specified.
     pub clear_workdir: bool,
+
+    /// Directory where inputs declared with a URL are downloaded to. May be shared between campaigns.
+    #[arg(long, default_value = ".pythia-cache/inputs")]
+    pub input_cache_dir: PathBuf,
+
+    /// JSON file of the secrets referenced as `${secret:NAME}` by the site sources and sinks of the config, by name.
+    /// Environment variables take precedence over it. See [`secrets`].
+    #[arg(long)]
+    pub secrets_file: Option<PathBuf>,
+
+    /// Resumes an interrupted run in --workdir, skipping the contexts its checkpoint file records as completed.
+    /// The configuration is expected to be the same as the one of the interrupted run.
+    #[arg(
+        long,
+        action = clap::ArgAction::SetTrue,
+        default_value_t = false,
+        requires = "workdir",
+        conflicts_with = "clear_workdir"
+    )]
+    pub resume: bool,
 }
 
 #[serde_inline_default]
@@ -96,6 +225,35 @@ pub struct Config {
     #[validate(nested)]
     #[validate(custom(function = "validate_unique_run_names"))]
     pub runs: Vec<RunConfig>,
+
+    /// Input datasets to be verified before processing, keyed by path.
+    #[validate(nested)]
+    pub inputs: HashMap<PathBuf, InputConfig>,
+
+    /// Processors every context goes through, in order (e.g. `["std:render"]`). Defaults to rendering only, and can't
+    /// be empty.
+    pub pipeline: Vec<ProcessorConfig>,
+
+    /// Sinks of every run, by run name. See [`RunConfig::sinks`].
+    pub sinks: HashMap<String, Vec<SinkConfig>>,
+
+    /// Template engine of every run, by run name. See [`RunConfig::engine`].
+    pub engines: HashMap<String, TemplateEngineConfig>,
+
+    /// Providers of the [`crate::processing::context::ProviderValue`] variables of every run, by run name and variable.
+    pub providers: HashMap<String, HashMap<String, ContextProviderConfig>>,
+
+    /// Functions and filters of the templates registered by plugins, by their name in the templates. See
+    /// [`crate::processing::template::extensions`].
+    pub template_extensions: Vec<(String, TemplateExtension)>,
+
+    /// Directory of the templates the run templates `{% include %}` or `{% extends %}`, by their path relative to it
+    /// (e.g. `{% include "common/fields.tpl" %}`).
+    #[validate(custom(function = "validate_template_dir"))]
+    pub template_dir: Option<PathBuf>,
+
+    /// What the `calibrate` command searches, see [`crate::calibration`].
+    pub calibration: Option<CalibrationConfig>,
 }
 
 #[derive(Debug, Error)]
@@ -135,24 +293,70 @@ impl<'a> ConfigSeedBuilder<'a> {
         let registries = self
             .registries
             .ok_or(ConfigSeedBuilderError::MissingRegistries)?;
+        let default_namespace = self
+            .default_namespace
+            .ok_or(ConfigSeedBuilderError::MissingDefaultNamespace)?;
 
         Ok(ConfigSeed {
             sites_seed: SiteSourceConfigSeed {
                 resource_seed: ResourceSeed {
                     registry: registries.reg_sitegen_drivers(),
                     id_seed: PublicIdentifierSeed {
-                        default_namespace: self
-                            .default_namespace
-                            .ok_or(ConfigSeedBuilderError::MissingDefaultNamespace)?,
+                        default_namespace: default_namespace.clone(),
+                    },
+                },
+            },
+            pipeline_seed: PipelineConfigSeed {
+                processor_seed: ProcessorConfigSeed {
+                    resource_seed: ResourceSeed {
+                        registry: registries.reg_processor_drivers(),
+                        id_seed: PublicIdentifierSeed {
+                            default_namespace: default_namespace.clone(),
+                        },
+                    },
+                    stage_seed: ResourceSeed {
+                        registry: registries.reg_pipeline_stages(),
+                        id_seed: PublicIdentifierSeed {
+                            default_namespace: default_namespace.clone(),
+                        },
+                    },
+                },
+            },
+            sinks_seed: SinkConfigSeed {
+                resource_seed: ResourceSeed {
+                    registry: registries.reg_sinks(),
+                    id_seed: PublicIdentifierSeed {
+                        default_namespace: default_namespace.clone(),
+                    },
+                },
+            },
+            engines_seed: TemplateEngineConfigSeed {
+                resource_seed: ResourceSeed {
+                    registry: registries.reg_template_engines(),
+                    id_seed: PublicIdentifierSeed {
+                        default_namespace: default_namespace.clone(),
                     },
                 },
             },
+            providers_seed: ContextProviderConfigSeed {
+                resource_seed: ResourceSeed {
+                    registry: registries.reg_context_providers(),
+                    id_seed: PublicIdentifierSeed { default_namespace },
+                },
+            },
+            template_extensions: registries.reg_template_extensions(),
         })
     }
 }
 
 pub struct ConfigSeed<'a> {
     pub sites_seed: SiteSourceConfigSeed<'a>,
+    pub pipeline_seed: PipelineConfigSeed<'a>,
+    pub sinks_seed: SinkConfigSeed<'a>,
+    pub engines_seed: TemplateEngineConfigSeed<'a>,
+    pub providers_seed: ContextProviderConfigSeed<'a>,
+    /// Every template extension is installed, so they are taken from the registry as is.
+    pub template_extensions: &'a Registry<TemplateExtensionResource>,
 }
 
 impl<'de> DeserializeSeed<'de> for ConfigSeed<'de> {
@@ -166,6 +370,50 @@ impl<'de> DeserializeSeed<'de> for ConfigSeed<'de> {
     }
 }
 
+/// Reads a run. Its properties other than [`RUN_FIELDS`] are its variables, which can't be objects other than the ones
+/// of context values (e.g. raster values): the ones that are and look like a field are reported as a misspelling of it.
+/// Lists are only reported when they look like one of [`RUN_LIST_FIELDS`], as variables can be lists too.
+fn run(value: serde_json::Value) -> Result<RunConfig, serde_json::Error> {
+    let misspelled = value.as_object().and_then(|run| {
+        run.iter()
+            .filter(|(k, _)| !RUN_FIELDS.contains(&k.as_str()))
+            .find_map(|(k, v)| {
+                let fields = match v {
+                    serde_json::Value::Array(_) => RUN_LIST_FIELDS,
+                    serde_json::Value::Object(_)
+                        if serde_json::from_value::<ContextValue>(v.clone()).is_err() =>
+                    {
+                        RUN_FIELDS
+                    }
+                    _ => return None,
+                };
+                suggest::closest(k, fields).map(|_| k.clone())
+            })
+    });
+    match misspelled {
+        Some(field) => Err(suggest::unknown_field(&field, RUN_FIELDS)),
+        None => serde_json::from_value(value),
+    }
+}
+
+/// Index of the run named `name` among the runs of the config file (`names`), before their sweeps and irrigation
+/// scenarios are expanded: the run itself, or else the one it was expanded from.
+fn source_run(names: &[Option<String>], name: &str) -> Option<usize> {
+    let expanded_from = |source: &str| {
+        name.strip_prefix(source)
+            .is_some_and(|suffix| suffix.starts_with('_'))
+    };
+    let exact = names.iter().position(|n| n.as_deref() == Some(name));
+    exact.or_else(|| {
+        names
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| n.as_deref().is_some_and(expanded_from))
+            .max_by_key(|(_, n)| n.as_ref().map_or(0, String::len))
+            .map(|(i, _)| i)
+    })
+}
+
 struct ConfigVisitor<'a> {
     pub seed: ConfigSeed<'a>,
 }
@@ -183,33 +431,182 @@ impl<'de> Visitor<'de> for ConfigVisitor<'de> {
     {
         let mut sites = None;
         let mut runs = None;
+        let mut inputs = None;
+        let mut pipeline = None;
+        let mut template_dir = None;
+        let mut calibration = None;
 
         while let Some(key) = map.next_key::<String>()? {
+            let at_key = |e: A::Error| -> A::Error { at(&[key.as_str().into()], e) };
             match key.as_str() {
-                "sites" => sites = Some(map.next_value_seed(self.seed.sites_seed.clone())?),
-                "runs" => runs = Some(map.next_value()?),
-                _ => return Err(serde::de::Error::unknown_field(&key, &["sites"])),
+                "sites" => {
+                    let seed = self.seed.sites_seed.clone();
+                    sites = Some(map.next_value_seed(seed).map_err(at_key)?)
+                }
+                "runs" => {
+                    let values: Vec<serde_json::Value> = map.next_value().map_err(at_key)?;
+                    let parsed = values
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, value)| {
+                            run(value).map_err(|e| at::<A::Error>(&["runs".into(), i.into()], e))
+                        })
+                        .collect::<Result<Vec<_>, _>>();
+                    runs = Some(parsed?)
+                }
+                "inputs" => inputs = Some(map.next_value().map_err(at_key)?),
+                "pipeline" => {
+                    let seed = self.seed.pipeline_seed.clone();
+                    let stages: Vec<ProcessorConfig> = map.next_value_seed(seed).map_err(at_key)?;
+                    if stages.is_empty() {
+                        let error =
+                            serde::de::Error::custom("At least one pipeline stage is required");
+                        return Err(at_key(error));
+                    }
+                    pipeline = Some(stages)
+                }
+                "template_dir" => template_dir = Some(map.next_value().map_err(at_key)?),
+                "calibration" => calibration = Some(map.next_value().map_err(at_key)?),
+                // Loaded before the config is deserialized, see `init_plugins`.
+                "plugins" => {
+                    map.next_value::<serde::de::IgnoredAny>()?;
+                }
+                _ => {
+                    return Err(suggest::unknown_field(
+                        &key,
+                        &[
+                            "calibration",
+                            "inputs",
+                            "pipeline",
+                            "plugins",
+                            "runs",
+                            "sites",
+                            "template_dir",
+                        ],
+                    ))
+                }
             }
         }
 
         let sites = sites.ok_or_else(|| serde::de::Error::missing_field("sites"))?;
         let runs = runs.ok_or_else(|| serde::de::Error::missing_field("runs"))?;
-
-        Ok(Config { sites, runs })
+        let runs = resolve_references(runs).map_err(serde::de::Error::custom)?;
+        let runs = expand_irrigation(expand_sweeps(runs).map_err(serde::de::Error::custom)?);
+        let sinks = runs
+            .iter()
+            .map(|run| {
+                let sinks = run
+                    .sinks
+                    .iter()
+                    .map(|sink| self.seed.sinks_seed.clone().deserialize(sink.clone()))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| format!("Invalid sink of run {}: {}", run.name, e))?;
+                Ok((run.name.clone(), sinks))
+            })
+            .collect::<Result<_, String>>()
+            .map_err(serde::de::Error::custom)?;
+        let engines = runs
+            .iter()
+            .map(|run| {
+                let engine = match &run.engine {
+                    Some(engine) => self.seed.engines_seed.clone().deserialize(engine.clone()),
+                    None => Ok(TemplateEngineConfig::default()),
+                };
+                let engine = engine
+                    .map_err(|e| format!("Invalid template engine of run {}: {}", run.name, e))?;
+                Ok((run.name.clone(), engine))
+            })
+            .collect::<Result<_, String>>()
+            .map_err(serde::de::Error::custom)?;
+        let providers = runs
+            .iter()
+            .map(|run| {
+                let providers = run
+                    .extra
+                    .iter()
+                    .filter_map(|(key, value)| match value {
+                        ContextValue::Provider(p) => Some((key, &p.provider)),
+                        _ => None,
+                    })
+                    .map(|(key, provider)| {
+                        let seed = self.seed.providers_seed.clone();
+                        let provider = seed.deserialize(provider.clone()).map_err(|e| {
+                            format!(
+                                "Invalid provider of variable {} of run {}: {}",
+                                key, run.name, e
+                            )
+                        })?;
+                        Ok((key.clone(), provider))
+                    })
+                    .collect::<Result<_, String>>()?;
+                Ok((run.name.clone(), providers))
+            })
+            .collect::<Result<_, String>>()
+            .map_err(serde::de::Error::custom)?;
+
+        let mut template_extensions: Vec<_> = self
+            .seed
+            .template_extensions
+            .entries()
+            .into_iter()
+            .map(|(id, resource)| (template_name(&id), resource.0.clone()))
+            .collect();
+        template_extensions.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        Ok(Config {
+            sites,
+            runs,
+            inputs: inputs.unwrap_or_default(),
+            pipeline: pipeline.unwrap_or_else(default_pipeline),
+            sinks,
+            engines,
+            providers,
+            template_extensions,
+            template_dir,
+            calibration,
+        })
     }
 }
 
-fn validate(args: &Args, config: &Config) -> Result<(), ConfigError> {
+/// Validates `args` and `config`, locating the problems of the config with `sources`. `run_names` are the names of the
+/// runs of the config file, see [`source_run`].
+fn validate(
+    args: &Args,
+    config: &Config,
+    sources: &SourceMap,
+    run_names: &[Option<String>],
+) -> Result<(), ConfigError> {
     args.validate()
         .map_err(|e| ConfigError::ConfigLoadError(Box::new(e)))?;
 
     validate_workdir_overrides(args).map_err(|e| ConfigError::ArgsValidationError(e))?;
 
-    config
-        .validate()
-        .map_err(|e| ConfigError::ConfigLoadError(Box::new(e)))?;
-
-    Ok(())
+    let Err(errors) = config.validate() else {
+        return Ok(());
+    };
+    let diagnostics = validation_errors(&errors)
+        .into_iter()
+        .map(|(mut path, mut message)| {
+            // Runs are validated once expanded, so they are located by the run they were expanded from.
+            if let [Segment::Key(field), Segment::Index(i), ..] = path.as_mut_slice() {
+                if field == "runs" {
+                    let name = &config.runs[*i].name;
+                    if let Some(source) = source_run(run_names, name) {
+                        if run_names[source].as_deref() != Some(name.as_str()) {
+                            message = format!("{} (in run {})", message, name);
+                        }
+                        *i = source;
+                    }
+                }
+            }
+            Diagnostic {
+                location: sources.locate(&path),
+                path,
+                message,
+            }
+        })
+        .collect();
+    Err(ConfigError::Invalid(diagnostics))
 }
 
 #[derive(Debug, Error)]
@@ -218,25 +615,91 @@ pub enum ConfigError {
     ConfigFileNotFound(PathBuf),
     #[error("Config load failed: {0}")]
     ConfigLoadError(Box<dyn Error>),
+    #[error("Invalid config:\n{}", .0.iter().map(|d| format!("  - {}", d)).collect::<Vec<_>>().join("\n"))]
+    Invalid(Vec<Diagnostic>),
     #[error("Arguments validation failed: {0}")]
     ArgsValidationError(ValidationError),
+    #[error("Failed to fetch inputs:\n{}", .0.iter().map(|e| format!("  - {}", e)).collect::<Vec<_>>().join("\n"))]
+    FetchError(Vec<FetchError>),
 }
 
-pub fn init(seed: ConfigSeed) -> Result<(Config, Args, PathBuf), ConfigError> {
-    let args = Args::parse();
+/// Reads the config file of `args`, composed with the files it includes and with its secrets resolved, along with the
+/// map of its sources and its path. Loaded once, for both [`init_plugins`] and [`init`].
+pub fn load(args: &Args) -> Result<(serde_json::Value, SourceMap, PathBuf), ConfigError> {
     let path = PathBuf::from(&args.config_file.clone());
     if !path.exists() || !path.is_file() {
         return Err(ConfigError::ConfigFileNotFound(path.clone()));
     }
 
-    let json_str = std::fs::read_to_string(args.config_file.clone())
+    let (mut json, sources) =
+        compose::load(&path).map_err(|e| ConfigError::ConfigLoadError(Box::new(e)))?;
+    Secrets::load(args.secrets_file.as_deref())
+        .and_then(|secrets| secrets.resolve_config(&mut json))
         .map_err(|e| ConfigError::ConfigLoadError(Box::new(e)))?;
+    Ok((json, sources, path))
+}
 
-    let config: Config = seed
-        .deserialize(&mut serde_json::Deserializer::from_str(&json_str))
-        .map_err(|e| ConfigError::ConfigLoadError(Box::new(e)))?;
+/// Loads the plugins declared in the [`load`]ed config `json` into `registries`, before the [`ConfigSeed`] is built out
+/// of them, so the config can refer to the resources of the plugins. Returns the loaded plugins.
+pub fn init_plugins(
+    json: &serde_json::Value,
+    registries: &mut Registries,
+) -> Result<Vec<PluginConfig>, ConfigError> {
+    let plugins = plugins::declared(json).map_err(|e| ConfigError::ConfigLoadError(Box::new(e)))?;
+    plugins::load(&plugins, registries).map_err(|e| ConfigError::ConfigLoadError(Box::new(e)))?;
+    Ok(plugins)
+}
 
-    validate(&args, &config)?;
+/// Deserializes and validates the [`load`]ed config, fetching its inputs along the way.
+pub fn init(
+    args: Args,
+    seed: ConfigSeed,
+    (json, sources, path): (serde_json::Value, SourceMap, PathBuf),
+) -> Result<(Config, Args, PathBuf), ConfigError> {
+    let run_names: Vec<Option<String>> = match &json["runs"] {
+        serde_json::Value::Array(runs) => runs
+            .iter()
+            .map(|run| run["name"].as_str().map(str::to_string))
+            .collect(),
+        _ => Vec::new(),
+    };
+    let config: Config = seed.deserialize(json).map_err(|e| {
+        let error = suggest::suggest(&e.to_string(), &[]);
+        ConfigError::Invalid(vec![Diagnostic::deserialization(&error, &sources)])
+    })?;
+
+    // Inputs are fetched before validation, which checks that some of them exist.
+    let cache = InputCache {
+        dir: args.input_cache_dir.clone(),
+    };
+    fetch_inputs(&config.inputs, &cache).map_err(ConfigError::FetchError)?;
+
+    validate(&args, &config, &sources, &run_names)?;
 
     Ok((config, args, path))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_run_misspelled() {
+        let misspelled = |field: &str, value: serde_json::Value| {
+            let mut raw = json!({ "name": "r1", "template": "template.SNX" });
+            raw[field] = value;
+            run(raw).unwrap_err().to_string()
+        };
+        assert!(misspelled("directory_group", json!(["country"]))
+            .starts_with("unknown field `directory_group` (did you mean `directory_groups`?)"));
+        assert!(misspelled("wth_cod", json!({ "scheme": "grid" }))
+            .starts_with("unknown field `wth_cod` (did you mean `wth_code`?)"));
+        assert!(misspelled("sink", json!([{ "type": "csv" }]))
+            .starts_with("unknown field `sink` (did you mean `sinks`?)"));
+
+        // Lists far from the list fields are variables.
+        let parsed = run(json!({ "name": "r1", "template": "template.SNX", "crops": ["MZ"] }));
+        assert!(parsed.unwrap().extra.contains_key("crops"));
+    }
+}
@@ -0,0 +1,414 @@
+use crate::processing::context::Context;
+use crate::processing::pipeline::{DataType, Rendered, Stage, StageDriver, StageTypes};
+use crate::processing::processor::drivers::DRIVER_RENDER;
+use crate::processing::processor::{Processor, ProcessorDriver, ProcessorEnvironment};
+use crate::registry::resources::{ProcessorDriverResource, StageDriverResource};
+use crate::registry::ResourceSeed;
+use serde::de::{DeserializeSeed, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use serde_json::Map;
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+
+/// How the contexts of a stage are processed, under the `executor` key of the stage.
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Executor {
+    /// On worker threads (see `--workers`), for the CPU-bound stages.
+    #[default]
+    Threads,
+    /// As tasks of a tokio runtime, for the IO-bound stages. Only some processors can run this way (e.g.
+    /// `std:rate-limit` and `std:copy`).
+    Async {
+        /// Contexts processed at once. Defaults to 64.
+        #[serde(default = "default_concurrency")]
+        concurrency: usize,
+    },
+}
+
+fn default_concurrency() -> usize {
+    64
+}
+
+/// What a stage runs: a registered [`ProcessorDriver`], run by the [`Executor`] of the stage, or a registered
+/// [`StageDriver`], conducting the contexts on its own. Either comes with the [`StageTypes`] it takes and passes on.
+#[derive(Clone)]
+pub enum StageDriverKind {
+    Processor(
+        ProcessorDriver<Box<dyn Processor<Output = Context>>, Box<dyn Any>>,
+        StageTypes,
+    ),
+    Stage(
+        StageDriver<Box<dyn Stage<Input = Context, Output = Context>>, Box<dyn Any>>,
+        StageTypes,
+    ),
+}
+
+impl StageDriverKind {
+    /// A [`Stage`], taking and passing on its [`Stage::Input`] and [`Stage::Output`].
+    pub fn stage<S: Stage + 'static, C: Any + 'static>(driver: StageDriver<S, C>) -> Self {
+        let types = StageTypes::of::<S::Input, S::Output>();
+        StageDriverKind::Stage(driver.coerce_to_dynamic(), types)
+    }
+
+    pub fn types(&self) -> StageTypes {
+        match self {
+            StageDriverKind::Processor(_, types) | StageDriverKind::Stage(_, types) => *types,
+        }
+    }
+}
+
+/// Checks that every stage takes what the stage before it passes on, the first one taking the generated contexts.
+fn check_types(stages: &[ProcessorConfig]) -> Result<(), String> {
+    let mut data = DataType::of::<Context>();
+    for (i, stage) in stages.iter().enumerate() {
+        data = stage.driver.types().pass(data).map_err(|input| match i {
+            0 => format!("Stage 1 takes {}, but is given the {}", input, data),
+            _ => format!(
+                "Stage {} takes {}, but stage {} passes on {}",
+                i + 1,
+                input,
+                i,
+                data
+            ),
+        })?;
+    }
+    Ok(())
+}
+
+/// A stage built from its [`ProcessorConfig`].
+pub enum BuiltStage {
+    Processor(Box<dyn Processor<Output = Context>>),
+    Stage(Box<dyn Stage<Input = Context, Output = Context>>),
+}
+
+/// A stage of the pipeline: a registered driver along with its options.
+#[derive(Clone)]
+pub struct ProcessorConfig {
+    pub driver: StageDriverKind,
+    pub executor: Executor,
+    args: serde_json::Value,
+}
+
+impl ProcessorConfig {
+    pub fn build(&self, env: &ProcessorEnvironment) -> Result<BuiltStage, Box<dyn Error>> {
+        match &self.driver {
+            StageDriverKind::Processor(driver, _) => {
+                let config = (driver.config_deserializer)(self.args.clone())?;
+                Ok(BuiltStage::Processor((driver.create)(config, env)?))
+            }
+            StageDriverKind::Stage(driver, _) => {
+                let config = (driver.config_deserializer)(self.args.clone())?;
+                Ok(BuiltStage::Stage((driver.create)(config, env)?))
+            }
+        }
+    }
+}
+
+/// Stages run when the config doesn't list any: rendering the templates only.
+pub fn default_pipeline() -> Vec<ProcessorConfig> {
+    vec![ProcessorConfig {
+        driver: StageDriverKind::Processor(
+            DRIVER_RENDER.clone().coerce_to_dynamic(),
+            StageTypes::of::<Context, Rendered>(),
+        ),
+        executor: Executor::default(),
+        args: serde_json::Value::Object(Map::new()),
+    }]
+}
+
+/// Deserializes a stage, either as the identifier of its processor (e.g. `"std:render"`) or as an object with the
+/// identifier under `type` along with the [`Executor`] under `executor` and the options of the processor.
+///
+/// Identifiers are looked up in the registered pipeline stages (the standard ones included) first, then in the
+/// processors registered by plugins, which pass on what they are given as it is.
+#[derive(Clone)]
+pub struct ProcessorConfigSeed<'a> {
+    pub resource_seed: ResourceSeed<'a, ProcessorDriverResource>,
+    pub stage_seed: ResourceSeed<'a, StageDriverResource>,
+}
+
+impl<'de> DeserializeSeed<'de> for ProcessorConfigSeed<'de> {
+    type Value = ProcessorConfig;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ProcessorConfigVisitor { seed: self })
+    }
+}
+
+struct ProcessorConfigVisitor<'a> {
+    seed: ProcessorConfigSeed<'a>,
+}
+
+impl<'a> ProcessorConfigVisitor<'a> {
+    /// Resolves the driver of the stage and checks the options against it, so they are reported along with the other
+    /// config errors.
+    fn processor_config<E: serde::de::Error>(
+        self,
+        id: &str,
+        executor: Executor,
+        args: Map<String, serde_json::Value>,
+    ) -> Result<ProcessorConfig, E> {
+        let stage_seed = self.seed.stage_seed;
+        let identifier = stage_seed
+            .id_seed
+            .deserialize(serde::de::value::StrDeserializer::<E>::new(id))?;
+        let driver = match stage_seed.registry.get(&identifier) {
+            Some(resource) => resource.0.clone(),
+            None => StageDriverKind::Processor(
+                self.seed
+                    .resource_seed
+                    .deserialize(serde::de::value::StrDeserializer::<E>::new(id))?
+                    .0
+                    .clone(),
+                StageTypes::Passthrough,
+            ),
+        };
+
+        let args = serde_json::Value::Object(args);
+        match &driver {
+            StageDriverKind::Processor(driver, _) => {
+                (driver.config_deserializer)(args.clone()).map_err(E::custom)?;
+            }
+            StageDriverKind::Stage(driver, _) => {
+                (driver.config_deserializer)(args.clone()).map_err(E::custom)?;
+                if executor != Executor::default() {
+                    return Err(E::custom(format!(
+                        "Stage {} conducts its contexts on its own, it takes no executor",
+                        id
+                    )));
+                }
+            }
+        }
+        Ok(ProcessorConfig {
+            driver,
+            executor,
+            args,
+        })
+    }
+}
+
+impl<'de> Visitor<'de> for ProcessorConfigVisitor<'de> {
+    type Value = ProcessorConfig;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a processor ID or a ProcessorConfig struct")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        self.processor_config(v, Executor::default(), Map::new())
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut id: Option<String> = None;
+        let mut executor = Executor::default();
+        let mut args: Map<String, serde_json::Value> = Map::new();
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "type" => id = Some(map.next_value()?),
+                "executor" => executor = map.next_value()?,
+                _ => {
+                    args.insert(key.to_string(), map.next_value()?);
+                }
+            }
+        }
+
+        let id = id.ok_or_else(|| serde::de::Error::missing_field("type"))?;
+        self.processor_config(&id, executor, args)
+    }
+}
+
+/// Deserializes the ordered list of stages (e.g. `["std:render"]`), see [`ProcessorConfigSeed`]. Every stage must take
+/// what the stage before it passes on, see [`StageTypes`].
+#[derive(Clone)]
+pub struct PipelineConfigSeed<'a> {
+    pub processor_seed: ProcessorConfigSeed<'a>,
+}
+
+impl<'de> DeserializeSeed<'de> for PipelineConfigSeed<'de> {
+    type Value = Vec<ProcessorConfig>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(PipelineConfigVisitor { seed: self })
+    }
+}
+
+struct PipelineConfigVisitor<'a> {
+    seed: PipelineConfigSeed<'a>,
+}
+
+impl<'de> Visitor<'de> for PipelineConfigVisitor<'de> {
+    type Value = Vec<ProcessorConfig>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of pipeline stages")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut stages = Vec::new();
+        while let Some(stage) = seq.next_element_seed(self.seed.processor_seed.clone())? {
+            stages.push(stage);
+        }
+        check_types(&stages).map_err(serde::de::Error::custom)?;
+        Ok(stages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::itself::init_itself;
+    use crate::registry::{Claimant, PublicIdentifierSeed, Registries};
+    use serde_json::json;
+    use std::sync::mpmc::{Receiver, Sender};
+    use std::sync::Arc;
+
+    /// Stands for the quality-control stage of a plugin.
+    struct QualityControl;
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct QualityControlConfig {
+        #[allow(dead_code)]
+        threshold: f64,
+    }
+
+    impl Stage for QualityControl {
+        type Input = Rendered;
+        type Output = Rendered;
+
+        fn conduct(
+            &self,
+            tx: &Sender<Rendered>,
+            rx: &Receiver<Rendered>,
+        ) -> Result<(), Box<dyn Error + Send>> {
+            for ctx in rx.iter() {
+                tx.send(ctx)
+                    .map_err(|err| Box::new(err) as Box<dyn Error + Send>)?;
+            }
+            Ok(())
+        }
+    }
+
+    fn registries() -> Registries {
+        let mut registries = Registries::new();
+        init_itself(&mut registries).unwrap();
+        let namespace = registries
+            .claim_namespace(
+                "qc",
+                Claimant::new("qc-plugin", "1.0.0", "Quality control stages"),
+            )
+            .unwrap();
+        let driver = StageDriver {
+            create: Arc::new(|_: QualityControlConfig, _: &ProcessorEnvironment| {
+                Ok(QualityControl)
+            }),
+            config_deserializer: Arc::new(serde_json::from_value),
+        };
+        registries
+            .regmut_pipeline_stages()
+            .register(
+                &namespace,
+                "check",
+                StageDriverResource(StageDriverKind::stage(driver)),
+            )
+            .unwrap();
+        registries
+    }
+
+    #[test]
+    fn test_pipeline_seed() {
+        let registries = registries();
+        let id_seed = PublicIdentifierSeed {
+            default_namespace: "std".to_string(),
+        };
+        let seed = PipelineConfigSeed {
+            processor_seed: ProcessorConfigSeed {
+                resource_seed: ResourceSeed {
+                    registry: registries.reg_processor_drivers(),
+                    id_seed: id_seed.clone(),
+                },
+                stage_seed: ResourceSeed {
+                    registry: registries.reg_pipeline_stages(),
+                    id_seed,
+                },
+            },
+        };
+        let parse = |json: serde_json::Value| seed.clone().deserialize(json);
+
+        assert_eq!(parse(json!(["std:render"])).unwrap().len(), 1);
+        assert!(parse(json!(["std:render", "std:unknown"])).is_err());
+        assert!(parse(json!([{ "type": "std:render", "unknown": 1 }])).is_err());
+        assert!(parse(json!([{ "type": "std:render", "retry": { "retries": 2 } }])).is_ok());
+        assert!(parse(json!([{ "type": "std:render", "writers": 4 }])).is_ok());
+        assert!(parse(json!([{ "unknown": 1 }])).is_err());
+        let rate_limited =
+            json!([{ "type": "std:rate-limit", "contexts_per_second": 5 }, "std:render"]);
+        assert_eq!(parse(rate_limited).unwrap().len(), 2);
+        assert!(parse(json!(["std:rate-limit"])).is_err());
+        assert!(
+            parse(json!(["std:render", { "type": "std:exec", "command": "dscsm048" }])).is_ok()
+        );
+        assert!(parse(json!(["std:render", "std:exec"])).is_err());
+        let retried =
+            json!({ "type": "std:exec", "command": "dscsm048", "retry": { "retries": 2 } });
+        assert!(parse(json!(["std:render", retried])).is_ok());
+        assert!(
+            parse(json!([{ "type": "std:render-batched", "retry": { "retries": 1 } }])).is_ok()
+        );
+        assert!(parse(json!("std:render")).is_err());
+
+        let stages = json!([
+            { "type": "std:rate-limit", "contexts_per_second": 5, "executor": { "type": "async" } },
+            { "type": "std:render", "executor": { "type": "threads" } },
+        ]);
+        let stages = parse(stages).unwrap();
+        assert_eq!(stages[0].executor, Executor::Async { concurrency: 64 });
+        assert_eq!(stages[1].executor, Executor::Threads);
+        assert!(
+            parse(json!([{ "type": "std:render", "executor": { "type": "fibers" } }])).is_err()
+        );
+
+        let stages = json!([
+            "std:render",
+            { "type": "qc:check", "threshold": 0.5 },
+            { "type": "std:exec", "command": "dscsm048" },
+        ]);
+        let stages = parse(stages).unwrap();
+        assert!(matches!(stages[0].driver, StageDriverKind::Processor(..)));
+        assert!(matches!(stages[1].driver, StageDriverKind::Stage(..)));
+        assert!(parse(json!(["qc:check"])).is_err());
+
+        // Every stage takes what the stage before it passes on.
+        assert!(parse(json!(["std:render", "render"])).is_err());
+        let exec = json!({ "type": "std:exec", "command": "dscsm048" });
+        assert!(parse(json!([exec])).is_err());
+        assert!(parse(json!(["std:render", exec, exec])).is_err());
+        let rate_limited =
+            json!([{ "type": "std:rate-limit", "contexts_per_second": 5 }, "std:render", exec]);
+        assert!(parse(rate_limited).is_ok());
+        assert!(parse(json!(["std:render", exec, "std:collect"])).is_ok());
+        assert!(parse(json!(["std:render", "std:collect"])).is_err());
+        let with_executor = json!([
+            { "type": "qc:check", "threshold": 0.5, "executor": { "type": "async" } }
+        ]);
+        assert!(parse(with_executor).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+//! Plugins declared in the `plugins` of the config, e.g. `"plugins": [{ "path": "libacme.so", "options": {...} }]`.
+//! They are loaded before the rest of the config is deserialized, so its drivers, providers and stages can be the ones
+//! the plugins register on their own namespaces.
+//!
+//! A plugin is a dynamic library exporting a [`PluginEntry`] function named `pythia_plugin`, which claims its namespace
+//! and registers its resources. As the resources are Rust closures and trait objects, the plugins must be built with the
+//! same compiler and the same version of Pythia as the application.
+
+use crate::registry::Registries;
+use libloading::Library;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Name of the function the plugins export, see [`PluginEntry`].
+pub const PLUGIN_ENTRY: &str = "pythia_plugin";
+
+/// Function the plugins export as [`PLUGIN_ENTRY`], called with the registries and the `options` of the plugin.
+pub type PluginEntry =
+    fn(registries: &mut Registries, options: &serde_json::Value) -> Result<(), Box<dyn Error>>;
+
+/// A plugin declared in the config.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PluginConfig {
+    /// Path to the dynamic library of the plugin, relative to the working directory of the application.
+    pub path: PathBuf,
+
+    /// Options the plugin is given when loaded, as is.
+    #[serde(default)]
+    pub options: serde_json::Value,
+}
+
+#[derive(Debug, Error)]
+pub enum PluginError {
+    #[error("Invalid plugins: {0}")]
+    Invalid(serde_json::Error),
+    #[error("Unable to load plugin {0}: {1}")]
+    Load(PathBuf, libloading::Error),
+    #[error("Plugin {0} failed to initialize: {1}")]
+    Init(PathBuf, Box<dyn Error>),
+}
+
+/// The plugins declared in the `plugins` of the config `json`, if any.
+pub fn declared(json: &serde_json::Value) -> Result<Vec<PluginConfig>, PluginError> {
+    match json.get("plugins") {
+        Some(plugins) => Vec::deserialize(plugins).map_err(PluginError::Invalid),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Loads `plugins` in order, registering their resources on `registries`.
+///
+/// The libraries are never unloaded, as the resources they registered are made of their code.
+pub fn load(plugins: &[PluginConfig], registries: &mut Registries) -> Result<(), PluginError> {
+    for plugin in plugins {
+        let load_error = |e| PluginError::Load(plugin.path.clone(), e);
+        // SAFETY: plugins are trusted as much as the application itself, and are meant to be built along with it.
+        let library = unsafe { Library::new(&plugin.path) }.map_err(load_error)?;
+        let library: &'static Library = Box::leak(Box::new(library));
+        let entry =
+            unsafe { library.get::<PluginEntry>(PLUGIN_ENTRY.as_bytes()) }.map_err(load_error)?;
+        entry(registries, &plugin.options)
+            .map_err(|e| PluginError::Init(plugin.path.clone(), e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_declared() {
+        let plugins = declared(&json!({
+            "plugins": [
+                { "path": "plugins/libacme.so", "options": { "table": "groups.csv" } },
+                { "path": "libqc.so" },
+            ],
+            "runs": [],
+        }))
+        .unwrap();
+        assert_eq!(plugins.len(), 2);
+        assert_eq!(plugins[0].path, PathBuf::from("plugins/libacme.so"));
+        assert_eq!(plugins[0].options, json!({ "table": "groups.csv" }));
+        assert_eq!(plugins[1].options, serde_json::Value::Null);
+
+        assert!(declared(&json!({ "runs": [] })).unwrap().is_empty());
+        assert!(declared(&json!({ "plugins": [{ "file": "libacme.so" }] })).is_err());
+    }
+
+    #[test]
+    fn test_load_missing() {
+        let plugins = declared(&json!({ "plugins": [{ "path": "does/not/exist.so" }] })).unwrap();
+        let mut registries = Registries::new();
+        assert!(matches!(
+            load(&plugins, &mut registries),
+            Err(PluginError::Load(path, _)) if path == PathBuf::from("does/not/exist.so")
+        ));
+    }
+}
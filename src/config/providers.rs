@@ -0,0 +1,190 @@
+use crate::processing::context::provider::{ContextProvider, ContextProviderDriver};
+use crate::registry::resources::ContextProviderResource;
+use crate::registry::ResourceSeed;
+use serde::de::{DeserializeSeed, MapAccess, Visitor};
+use serde_json::Map;
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+
+/// The provider of a variable of a run: a registered [`ContextProviderDriver`] along with its options.
+#[derive(Clone)]
+pub struct ContextProviderConfig {
+    /// Identifier of the driver as written in the config, for the logs.
+    pub name: String,
+    pub driver: ContextProviderDriver<Box<dyn ContextProvider>, Box<dyn Any>>,
+    args: serde_json::Value,
+}
+
+impl ContextProviderConfig {
+    pub fn build(&self) -> Result<Box<dyn ContextProvider>, Box<dyn Error>> {
+        let config = (self.driver.config_deserializer)(self.args.clone())?;
+        (self.driver.create)(config)
+    }
+}
+
+/// Deserializes the `provider` of a [`crate::processing::context::ProviderValue`], either as the identifier of its
+/// driver (e.g. `"acme:soil-lookup"`) or as an object with the identifier under `type` along with the options of the
+/// driver.
+#[derive(Clone)]
+pub struct ContextProviderConfigSeed<'a> {
+    pub resource_seed: ResourceSeed<'a, ContextProviderResource>,
+}
+
+impl<'de> DeserializeSeed<'de> for ContextProviderConfigSeed<'de> {
+    type Value = ContextProviderConfig;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ContextProviderConfigVisitor { seed: self })
+    }
+}
+
+struct ContextProviderConfigVisitor<'a> {
+    seed: ContextProviderConfigSeed<'a>,
+}
+
+impl<'a> ContextProviderConfigVisitor<'a> {
+    /// Checks the options against the driver, so they are reported along with the other config errors.
+    fn provider_config<E: serde::de::Error>(
+        self,
+        name: &str,
+        args: Map<String, serde_json::Value>,
+    ) -> Result<ContextProviderConfig, E> {
+        let resource = self
+            .seed
+            .resource_seed
+            .deserialize(serde::de::value::StrDeserializer::<E>::new(name))?;
+        let args = serde_json::Value::Object(args);
+        (resource.0.config_deserializer)(args.clone()).map_err(E::custom)?;
+        Ok(ContextProviderConfig {
+            name: name.to_string(),
+            driver: resource.0.clone(),
+            args,
+        })
+    }
+}
+
+impl<'de> Visitor<'de> for ContextProviderConfigVisitor<'de> {
+    type Value = ContextProviderConfig;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a context provider ID or a ContextProviderConfig struct")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        self.provider_config(v, Map::new())
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut name: Option<String> = None;
+        let mut args: Map<String, serde_json::Value> = Map::new();
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "type" => name = Some(map.next_value()?),
+                _ => {
+                    args.insert(key.to_string(), map.next_value()?);
+                }
+            }
+        }
+
+        let name = name.ok_or_else(|| serde::de::Error::missing_field("type"))?;
+        self.provider_config(&name, args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::GeoDeg;
+    use crate::processing::context::{ContextValue, PrimitiveContextValue};
+    use crate::registry::{Claimant, PublicIdentifierSeed, Registries};
+    use crate::sites::Site;
+    use serde::Deserialize;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct ScaledLatConfig {
+        factor: Option<f64>,
+    }
+
+    /// Latitude of the site times `factor`, none south of the equator.
+    struct ScaledLat(f64);
+
+    impl ContextProvider for ScaledLat {
+        fn provide(&self, site: &Site) -> Option<ContextValue> {
+            let lat = site.lat.as_f64();
+            let value = ContextValue::Prim(PrimitiveContextValue::Float(lat * self.0));
+            (lat >= 0.0).then_some(value)
+        }
+    }
+
+    #[test]
+    fn test_provider_seed() {
+        let mut registries = Registries::new();
+        let namespace = registries
+            .claim_namespace(
+                "acme",
+                Claimant::new("acme-plugin", "1.0.0", "Acme context providers"),
+            )
+            .unwrap();
+        let driver = ContextProviderDriver {
+            create: Arc::new(|c: ScaledLatConfig| Ok(ScaledLat(c.factor.unwrap_or(1.0)))),
+            config_deserializer: Arc::new(serde_json::from_value),
+        };
+        registries
+            .regmut_context_providers()
+            .register(
+                &namespace,
+                "scaled-lat",
+                ContextProviderResource(driver.coerce_to_dynamic()),
+            )
+            .unwrap();
+        let seed = ContextProviderConfigSeed {
+            resource_seed: ResourceSeed {
+                registry: registries.reg_context_providers(),
+                id_seed: PublicIdentifierSeed {
+                    default_namespace: "std".to_string(),
+                },
+            },
+        };
+        let parse = |json: serde_json::Value| seed.clone().deserialize(json);
+
+        let site = |lat: f64| Site {
+            id: 1,
+            lon: GeoDeg::from(0.0),
+            lat: GeoDeg::from(lat),
+            covariates: Default::default(),
+            attributes: Default::default(),
+            weight: None,
+        };
+        let provider = parse(json!({ "type": "acme:scaled-lat", "factor": 2 }))
+            .unwrap()
+            .build()
+            .unwrap();
+        assert!(matches!(
+            provider.provide(&site(10.0)),
+            Some(ContextValue::Prim(PrimitiveContextValue::Float(v))) if v == 20.0
+        ));
+        assert!(provider.provide(&site(-10.0)).is_none());
+
+        assert_eq!(
+            parse(json!("acme:scaled-lat")).unwrap().name,
+            "acme:scaled-lat"
+        );
+        assert!(parse(json!("scaled-lat")).is_err());
+        assert!(parse(json!({ "type": "acme:scaled-lat", "unknown": 1 })).is_err());
+        assert!(parse(json!({ "factor": 2 })).is_err());
+    }
+}
@@ -0,0 +1,289 @@
+//! References of the variables of a run to the ones of other runs, e.g. `"pdate": "${runs.baseline.pdate + 14d}"`,
+//! resolved when the config is loaded.
+//!
+//! A variable that is a single reference takes the referenced value as is (a number stays a number, a list a list),
+//! while references within text are replaced by the text of their value. The referenced value must be known before the
+//! contexts are: its own placeholders may only be variables of its run or references to other runs.
+
+use super::runs::RunConfig;
+use crate::processing::context::date::DateOffset;
+use crate::processing::context::{ContextValue, PrimitiveContextValue, TemplateString};
+use thiserror::Error;
+
+/// Prefix of the placeholders referencing other runs.
+const PREFIX: &str = "runs.";
+
+#[derive(Debug, Error)]
+pub enum ReferenceError {
+    #[error("Run {run} references {reference}, but there is no run {target}")]
+    UnknownRun {
+        run: String,
+        reference: String,
+        target: String,
+    },
+    #[error("Run {run} references {reference}, which is not a variable of run {target}")]
+    UnknownVariable {
+        run: String,
+        reference: String,
+        target: String,
+    },
+    #[error("Variable {key} of run {run} references {placeholder}, which is not known before the contexts are")]
+    NotStatic {
+        run: String,
+        key: String,
+        placeholder: String,
+    },
+    #[error("Run {run} references {reference}, which can't be {reason}")]
+    Unusable {
+        run: String,
+        reference: String,
+        reason: &'static str,
+    },
+    #[error("References of run {run} form a cycle through {reference}")]
+    Cycle { run: String, reference: String },
+}
+
+/// Run name and variable of a reference placeholder (`runs.<run>.<variable>`), if `placeholder` is one.
+fn parse_reference(placeholder: &str) -> Option<(&str, &str)> {
+    placeholder.strip_prefix(PREFIX)?.split_once('.')
+}
+
+struct Resolver<'a> {
+    runs: &'a [RunConfig],
+    /// Variables being resolved, as (run, variable), to tell cycles apart.
+    resolving: Vec<(usize, String)>,
+}
+
+impl Resolver<'_> {
+    /// Value of the variable `key` of `run` with its placeholders resolved.
+    fn value(&mut self, run: usize, key: &str) -> Result<ContextValue, ReferenceError> {
+        let runs = self.runs;
+        let value = &runs[run].extra[key];
+        let ContextValue::TemplateString(s) = value else {
+            return Ok(value.clone());
+        };
+
+        let entry = (run, key.to_string());
+        if self.resolving.contains(&entry) {
+            return Err(ReferenceError::Cycle {
+                run: self.runs[run].name.clone(),
+                reference: format!("{}{}.{}", PREFIX, self.runs[run].name, key),
+            });
+        }
+        self.resolving.push(entry);
+        let resolved = self.template(run, s, Some(key));
+        self.resolving.pop();
+        resolved
+    }
+
+    /// `s` of `run` with its references resolved. If `key` is set, the string is the one of the variable `key` of a
+    /// referenced run, so the variables of `run` it refers to are resolved too and nothing else may be left.
+    fn template(
+        &mut self,
+        run: usize,
+        s: &TemplateString,
+        key: Option<&str>,
+    ) -> Result<ContextValue, ReferenceError> {
+        if let Some((placeholder, offset)) = s.as_placeholder() {
+            if parse_reference(placeholder).is_some() {
+                return self.reference(run, placeholder, offset);
+            }
+        }
+
+        let resolved = s.substitute(|placeholder, offset| {
+            let value = match (parse_reference(placeholder), key) {
+                (Some(_), _) => self.reference(run, placeholder, offset)?,
+                (None, Some(_)) if self.runs[run].extra.contains_key(placeholder) => {
+                    let value = self.value(run, placeholder)?;
+                    self.offset(run, placeholder, value, offset)?
+                }
+                (None, Some(key)) => {
+                    return Err(ReferenceError::NotStatic {
+                        run: self.runs[run].name.clone(),
+                        key: key.to_string(),
+                        placeholder: placeholder.to_string(),
+                    })
+                }
+                (None, None) => return Ok(None),
+            };
+            match value {
+                ContextValue::Prim(p) => Ok(Some(p.as_string())),
+                ContextValue::TemplateString(s) => Ok(s.as_literal()),
+                _ => Err(ReferenceError::Unusable {
+                    run: self.runs[run].name.clone(),
+                    reference: placeholder.to_string(),
+                    reason: "part of a text",
+                }),
+            }
+        })?;
+        match resolved.as_literal() {
+            Some(text) if key.is_some() => {
+                Ok(ContextValue::Prim(PrimitiveContextValue::String(text)))
+            }
+            _ => Ok(ContextValue::TemplateString(resolved)),
+        }
+    }
+
+    /// Value of the reference `placeholder` of `run`, moved by `offset` if set.
+    fn reference(
+        &mut self,
+        run: usize,
+        placeholder: &str,
+        offset: Option<&DateOffset>,
+    ) -> Result<ContextValue, ReferenceError> {
+        let (target_name, key) = parse_reference(placeholder).unwrap();
+        let name = &self.runs[run].name;
+        let Some(target) = self.runs.iter().position(|r| r.name == target_name) else {
+            return Err(ReferenceError::UnknownRun {
+                run: name.clone(),
+                reference: placeholder.to_string(),
+                target: target_name.to_string(),
+            });
+        };
+        if !self.runs[target].extra.contains_key(key) {
+            return Err(ReferenceError::UnknownVariable {
+                run: name.clone(),
+                reference: placeholder.to_string(),
+                target: target_name.to_string(),
+            });
+        }
+        let value = self.value(target, key)?;
+        self.offset(run, placeholder, value, offset)
+    }
+
+    fn offset(
+        &self,
+        run: usize,
+        placeholder: &str,
+        value: ContextValue,
+        offset: Option<&DateOffset>,
+    ) -> Result<ContextValue, ReferenceError> {
+        let Some(offset) = offset else {
+            return Ok(value);
+        };
+        let prim = match &value {
+            ContextValue::Prim(p) => Some(p.clone()),
+            ContextValue::TemplateString(s) => s.as_literal().map(PrimitiveContextValue::String),
+            _ => None,
+        };
+        prim.and_then(|p| p.offset(offset))
+            .map(ContextValue::Prim)
+            .ok_or_else(|| ReferenceError::Unusable {
+                run: self.runs[run].name.clone(),
+                reference: placeholder.to_string(),
+                reason: "offset, as it is not a date",
+            })
+    }
+}
+
+/// Resolves the references to other runs of the variables of `runs`.
+pub fn resolve_references(mut runs: Vec<RunConfig>) -> Result<Vec<RunConfig>, ReferenceError> {
+    let mut resolved = Vec::new();
+    let mut resolver = Resolver {
+        runs: &runs,
+        resolving: Vec::new(),
+    };
+    for (i, run) in runs.iter().enumerate() {
+        for (key, value) in &run.extra {
+            let ContextValue::TemplateString(s) = value else {
+                continue;
+            };
+            let value = resolver.template(i, s, None)?;
+            resolved.push((i, key.clone(), value));
+        }
+    }
+
+    for (i, key, value) in resolved {
+        runs[i].extra.insert(key, value);
+    }
+    Ok(runs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn run(name: &str, extra: &[(&str, &str)]) -> RunConfig {
+        RunConfig {
+            name: name.to_string(),
+            extra: extra
+                .iter()
+                .map(|(k, v)| (k.to_string(), serde_json::from_str(v).unwrap()))
+                .collect::<HashMap<_, _>>(),
+            ..Default::default()
+        }
+    }
+
+    fn text(run: &RunConfig, key: &str) -> String {
+        match &run.extra[key] {
+            ContextValue::Prim(p) => p.as_string(),
+            ContextValue::TemplateString(s) => serde_json::to_string(s).unwrap(),
+            other => format!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_resolve_references() {
+        let runs = vec![
+            run(
+                "baseline",
+                &[
+                    ("pdate", r#""2024-05-01""#),
+                    ("nitrogen", "120"),
+                    ("label", r#""${name}-${pdate}""#),
+                    ("sdate", r#""${pdate - 15d}""#),
+                ],
+            ),
+            run(
+                "late",
+                &[
+                    ("pdate", r#""${runs.baseline.pdate + 14d}""#),
+                    ("sdate", r#""${runs.baseline.sdate}""#),
+                    ("nitrogen", r#""${runs.baseline.nitrogen}""#),
+                    ("note", r#""${runs.baseline.nitrogen} kg at ${lat}""#),
+                ],
+            ),
+        ];
+        let runs = resolve_references(runs).unwrap();
+        assert_eq!(text(&runs[0], "label"), r#""${name}-${pdate}""#);
+        assert_eq!(text(&runs[1], "pdate"), "2024-05-15");
+        assert_eq!(text(&runs[1], "sdate"), "2024-04-16");
+        assert!(matches!(
+            runs[1].extra["nitrogen"],
+            ContextValue::Prim(PrimitiveContextValue::Int(120))
+        ));
+        assert_eq!(text(&runs[1], "note"), r#""120 kg at ${lat}""#);
+
+        let error = |runs| resolve_references(runs).unwrap_err();
+        assert!(matches!(
+            error(vec![run("a", &[("x", r#""${runs.b.x}""#)])]),
+            ReferenceError::UnknownRun { .. }
+        ));
+        assert!(matches!(
+            error(vec![run("a", &[("x", r#""${runs.a.y}""#)])]),
+            ReferenceError::UnknownVariable { .. }
+        ));
+        assert!(matches!(
+            error(vec![
+                run("a", &[("x", r#""${runs.b.x}""#)]),
+                run("b", &[("x", r#""${runs.a.x}""#)]),
+            ]),
+            ReferenceError::Cycle { .. }
+        ));
+        assert!(matches!(
+            error(vec![
+                run("a", &[("x", r#""${runs.b.x}""#)]),
+                run("b", &[("x", r#""${lat}""#)]),
+            ]),
+            ReferenceError::NotStatic { .. }
+        ));
+        assert!(matches!(
+            error(vec![
+                run("a", &[("x", r#""${runs.b.x + 1d}""#)]),
+                run("b", &[("x", "1")]),
+            ]),
+            ReferenceError::Unusable { .. }
+        ));
+    }
+}
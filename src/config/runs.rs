@@ -1,32 +1,77 @@
+use crate::batch::BatchConfig;
+use crate::config::irrigation::IrrigationConfig;
+use crate::config::sweep::SweepValues;
+use crate::cultivar::{validate_cultivar, CultivarConfig};
+use crate::fertilizer::{validate_fertilizer, FertilizerConfig};
+use crate::hooks::HooksConfig;
+use crate::planting::calendar::CropCalendarConfig;
+use crate::planting::rules::PlantingRulesConfig;
+use crate::planting::window::PlantingWindowConfig;
+use crate::processing::context::filter::ContextFilter;
 use crate::processing::context::ContextValue;
+use crate::processing::template::UndefinedPolicy;
+use crate::provenance::MetadataScope;
+use crate::simulation::{validate_simulation, SimulationConfig};
+use crate::soil::{validate_soil, SoilConfig};
+use crate::utils::portable::portable_filename_issue;
+use crate::utils::text::{LineEnding, Whitespace};
+use crate::weather::code::{validate_station_code, StationCodeConfig};
+use crate::weather::stations::StationIndexConfig;
+use crate::weather::{validate_weather, WeatherConfig};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::PathBuf;
 use std::sync::LazyLock;
 use validator::{Validate, ValidationError};
 
 static ERRCODE_RUN_NAME_DUPE: &str = "ERRCODE_RUN_NAME_DUPE";
 static ERRCODE_TEMPLATE_FILE_NOT_FOUND: &str = "ERRCODE_TEMPLATE_FILE_NOT_FOUND";
+static ERRCODE_RUN_NAME_NOT_PORTABLE: &str = "ERRCODE_RUN_NAME_NOT_PORTABLE";
+static ERRCODE_TEMPLATE_NAME_NOT_PORTABLE: &str = "ERRCODE_TEMPLATE_NAME_NOT_PORTABLE";
+static ERRCODE_PLANTING_REQUIRES_WEATHER: &str = "ERRCODE_PLANTING_REQUIRES_WEATHER";
+static ERRCODE_RAW_BATCH: &str = "ERRCODE_RAW_BATCH";
+static ERRCODE_OUTPUT_NAME_DUPE: &str = "ERRCODE_OUTPUT_NAME_DUPE";
 
 static RE_VALID_RUN_NAME: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"^[a-zA-Z0-9_-]+$").unwrap());
 
+/// Run names become directory names, so they must also be unique on case-insensitive filesystems (e.g. `Run1` and `run1`).
 pub fn validate_unique_run_names(runs: &Vec<RunConfig>) -> Result<(), ValidationError> {
-    let mut run_names = HashSet::new();
+    let mut run_names: HashMap<String, &str> = HashMap::new();
 
     for run in runs {
-        if run_names.contains(&run.name) {
-            let msg = format!("Run name {} is not unique", run.name);
+        if let Some(other) = run_names.get(&run.name.to_lowercase()) {
+            let msg = if *other == run.name {
+                format!("Run name {} is not unique", run.name)
+            } else {
+                format!(
+                    "Run name {} is not unique: it conflicts with {} on case-insensitive filesystems",
+                    run.name, other
+                )
+            };
             return Err(ValidationError::new(ERRCODE_RUN_NAME_DUPE).with_message(Cow::from(msg)));
         }
-        run_names.insert(run.name.clone());
+        run_names.insert(run.name.to_lowercase(), &run.name);
     }
 
     Ok(())
 }
 
+fn validate_run_name_portable(name: &str) -> Result<(), ValidationError> {
+    if let Some(issue) = portable_filename_issue(name) {
+        let msg = format!(
+            "Run name {} cannot be used as a directory name on all platforms: {}",
+            name, issue
+        );
+        return Err(
+            ValidationError::new(ERRCODE_RUN_NAME_NOT_PORTABLE).with_message(Cow::from(msg))
+        );
+    }
+    Ok(())
+}
+
 fn validate_template_file_exists(path: &PathBuf) -> Result<(), ValidationError> {
     if !path.exists() || path.is_dir() {
         let msg = format!(
@@ -38,17 +83,282 @@ fn validate_template_file_exists(path: &PathBuf) -> Result<(), ValidationError>
             ValidationError::new(ERRCODE_TEMPLATE_FILE_NOT_FOUND).with_message(Cow::from(msg))
         );
     }
+
+    // The template file name is reused as the output file name in every context directory.
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    if let Some(issue) = portable_filename_issue(&name) {
+        let msg = format!(
+            "Template file name {} cannot be used as an output file name on all platforms: {}",
+            name, issue
+        );
+        return Err(
+            ValidationError::new(ERRCODE_TEMPLATE_NAME_NOT_PORTABLE).with_message(Cow::from(msg))
+        );
+    }
     Ok(())
 }
 
-#[derive(Validate, Serialize, Deserialize, Clone, Debug)]
+fn validate_planting_requires_weather(run: &RunConfig) -> Result<(), ValidationError> {
+    if run.weather.is_none() && run.planting.as_ref().is_some_and(|p| p.requires_weather()) {
+        let msg = format!(
+            "Run {} has rainfall planting rules but no weather to evaluate them on",
+            run.name
+        );
+        return Err(
+            ValidationError::new(ERRCODE_PLANTING_REQUIRES_WEATHER).with_message(Cow::from(msg))
+        );
+    }
+    Ok(())
+}
+
+fn validate_raw_without_batch(run: &RunConfig) -> Result<(), ValidationError> {
+    if run.raw && run.batch.is_some() {
+        let msg = format!(
+            "Run {} has a raw template, which has no treatments to list in a batch file",
+            run.name
+        );
+        return Err(ValidationError::new(ERRCODE_RAW_BATCH).with_message(Cow::from(msg)));
+    }
+    Ok(())
+}
+
+fn validate_unique_output_names(outputs: &Vec<RunOutputConfig>) -> Result<(), ValidationError> {
+    let mut names = HashSet::new();
+    for output in outputs {
+        if !names.insert(output.name.as_str()) {
+            let msg = format!("Output name {} is not unique", output.name);
+            return Err(ValidationError::new(ERRCODE_OUTPUT_NAME_DUPE).with_message(Cow::from(msg)));
+        }
+    }
+    Ok(())
+}
+
+/// A file rendered into every context directory of a run along with its `template`, from the same context (e.g. a
+/// soil fragment next to the X-file). It is rendered with the `engine`, `undefined`, `whitespace`, `line_endings` and
+/// `bom` of the run.
+#[derive(Validate, Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RunOutputConfig {
+    #[validate(regex(path = *RE_VALID_RUN_NAME, message = "Output name must be alphanumeric and contain only underscores and dashes"))]
+    pub name: String,
+
+    #[validate(custom(function = "validate_template_file_exists"))]
+    pub template: PathBuf,
+
+    /// Same as the `output_filename` of the run.
+    #[serde(default)]
+    pub output_filename: Option<String>,
+}
+
+/// Fields of [`RunConfig`], as the other properties of a run are its variables.
+pub const RUN_FIELDS: &[&str] = &[
+    "name",
+    "template",
+    "engine",
+    "output_filename",
+    "outputs",
+    "directory_groups",
+    "undefined",
+    "raw",
+    "whitespace",
+    "line_endings",
+    "bom",
+    "weather",
+    "weather_stations",
+    "wth_code",
+    "soil",
+    "planting_window",
+    "crop_calendar",
+    "planting",
+    "fertilizer",
+    "cultivar",
+    "irrigation",
+    "sweep",
+    "sweep_name",
+    "batch",
+    "simulation",
+    "metadata",
+    "priority",
+    "threads",
+    "memory_hint",
+    "nice",
+    "filter",
+    "sinks",
+    "hooks",
+];
+
+/// Fields of [`RUN_FIELDS`] taking a list, which tell a misspelling of them from a list variable.
+pub const RUN_LIST_FIELDS: &[&str] = &["outputs", "directory_groups", "sinks"];
+
+#[derive(Validate, Serialize, Deserialize, Clone, Debug, Default)]
+#[validate(schema(function = "validate_planting_requires_weather"))]
+#[validate(schema(function = "validate_raw_without_batch"))]
 pub struct RunConfig {
     #[validate(regex(path = *RE_VALID_RUN_NAME, message = "Run name must be alphanumeric and contain only underscores and dashes"))]
+    #[validate(custom(function = "validate_run_name_portable"))]
     pub name: String,
 
     #[validate(custom(function = "validate_template_file_exists"))]
     pub template: PathBuf,
 
+    /// Template engine the template is written for (e.g. `"std:tera"`, the default), either as the identifier of the
+    /// engine or as an object with the identifier under `type` along with its options. Resolved into
+    /// [`crate::config::Config::engines`] when the config is loaded.
+    #[serde(default)]
+    pub engine: Option<serde_json::Value>,
+
+    /// Name of the file the template is rendered to, as a Tera template of the variables of the context (e.g.
+    /// `"{{ site_id }}.SNX"`), for the naming conventions of DSSAT. Defaults to the file name of `template`.
+    #[serde(default)]
+    pub output_filename: Option<String>,
+
+    /// More templates rendered from the same contexts as `template`, each into its own file. Batch files only list the
+    /// file of `template`.
+    #[serde(default)]
+    #[validate(nested)]
+    #[validate(custom(function = "validate_unique_output_names"))]
+    pub outputs: Vec<RunOutputConfig>,
+
+    /// Context variables the context directories of the run are grouped by, each a directory between the one of the
+    /// run and the coordinates (e.g. `["country", "adm1"]` for `<run>/<country>/<adm1>/<lon>/<lat>`). Contexts without
+    /// one of them are grouped under `unknown`.
+    #[serde(default)]
+    pub directory_groups: Vec<String>,
+
+    /// How the variables the template uses but a context doesn't have are rendered: `"strict"` (the default) fails
+    /// the context, `"empty"` renders them as an empty string and `"keep"` as they are written (e.g. `{{ pdate }}`).
+    #[serde(default)]
+    pub undefined: UndefinedPolicy,
+
+    /// Copies the template into every context directory byte for byte instead of rendering it, for the files that
+    /// aren't text (e.g. binary soil grids). `engine`, `whitespace`, `line_endings` and `bom` don't apply to it.
+    #[serde(default)]
+    pub raw: bool,
+
+    /// Clean-up of the whitespace of the rendered template, e.g.
+    /// `{ "trim_trailing": true, "blank_lines": "collapse", "final_newline": true }`. Nothing is changed by default.
+    #[serde(default)]
+    pub whitespace: Whitespace,
+
+    /// Line endings of the rendered files, `"lf"` (the default) or `"crlf"` for the DSSAT builds on Windows that require
+    /// it. Also applies to the weather, soil and batch files written along with them. Templates are normalized to LF
+    /// when loaded, so this is always consistent.
+    #[serde(default)]
+    pub line_endings: LineEnding,
+
+    /// Prefixes the rendered files with a UTF-8 byte order mark. BOMs in templates are always stripped when loaded.
+    #[serde(default)]
+    pub bom: bool,
+
+    /// Weather to be acquired and written as a `.WTH` file into every context directory of the run.
+    #[serde(default)]
+    #[validate(custom(function = "validate_weather"))]
+    pub weather: Option<WeatherConfig>,
+
+    /// Index of weather stations (or grid cells). Each site is assigned the nearest one, exposed to templates as
+    /// `wsta` (station ID), `wth_file` (weather file name) and `wsta_distance_km`.
+    #[serde(default)]
+    pub weather_stations: Option<StationIndexConfig>,
+
+    /// 4-characters weather station code of each site, derived from its coordinates or its ID and exposed to templates
+    /// as `wth_code`. See [`StationCodeConfig`].
+    #[serde(default)]
+    #[validate(custom(function = "validate_station_code"))]
+    pub wth_code: Option<StationCodeConfig>,
+
+    /// Soil profiles looked up by the soil ID of each site. The profile fields are exposed to templates as
+    /// `soil_<column>` (e.g. `soil_salb`) and `soil_<column>_<layer>` (e.g. `soil_sdul_1`), and the whole profile as `soil_profile`.
+    #[serde(default)]
+    #[validate(custom(function = "validate_soil"))]
+    pub soil: Option<SoilConfig>,
+
+    /// Planting window derived from a rainfall climatology, exposed to templates as `pdate_start` and `pdate_end` (days of year).
+    #[serde(default)]
+    pub planting_window: Option<PlantingWindowConfig>,
+
+    /// Planting and harvest dates from crop calendar rasters. See [`CropCalendarConfig`].
+    #[serde(default)]
+    pub crop_calendar: Option<CropCalendarConfig>,
+
+    /// Planting rules evaluated per site, setting `pdate`, `pdate_doy` and `pdate_year`. See [`PlantingRulesConfig`].
+    #[serde(default)]
+    pub planting: Option<PlantingRulesConfig>,
+
+    /// Fertilizer schedule, exposed to templates as the list `fertilizers`. See [`FertilizerConfig`].
+    #[serde(default)]
+    #[validate(custom(function = "validate_fertilizer"))]
+    pub fertilizer: Option<FertilizerConfig>,
+
+    /// Cultivar of each site by region, exposed to templates as `ingeno` and `cname`. See [`CultivarConfig`].
+    #[serde(default)]
+    #[validate(custom(function = "validate_cultivar"))]
+    pub cultivar: Option<CultivarConfig>,
+
+    /// Irrigation scenarios, expanding this run into one run per scenario when the config is loaded. See [`IrrigationConfig`].
+    #[serde(default)]
+    pub irrigation: Option<IrrigationConfig>,
+
+    /// Parameter sweep, expanding this run into one run per combination of the values of its variables when the
+    /// config is loaded (e.g. `{"pdate": {"values": ["05-01", "06-01"]}}`), each set for templates like any other
+    /// variable of the run.
+    #[serde(default)]
+    pub sweep: BTreeMap<String, SweepValues>,
+
+    /// Tera template of the names of the runs of the sweep, given the run `name` and the swept variables (e.g.
+    /// `{{ name }}_{{ pdate }}`). Defaults to the run name followed by the values.
+    #[serde(default)]
+    pub sweep_name: Option<String>,
+
+    /// DSSAT batch file listing the treatments of the rendered template. See [`BatchConfig`].
+    #[serde(default)]
+    pub batch: Option<BatchConfig>,
+
+    /// Seasonal or sequential (crop rotation) setup of the treatments. See [`SimulationConfig`].
+    #[serde(default)]
+    #[validate(custom(function = "validate_simulation"))]
+    pub simulation: Option<SimulationConfig>,
+
+    /// Writes a provenance metadata file into every context directory, or into the run directory only.
+    /// See [`crate::provenance::DirectoryMetadata`].
+    #[serde(default)]
+    pub metadata: Option<MetadataScope>,
+
+    /// Contexts of the runs with a higher priority are passed to the pipeline first (reading up to --priority-window
+    /// contexts ahead), so they complete ahead of the others. Defaults to 0.
+    #[serde(default)]
+    pub priority: i32,
+
+    /// Most workers of a threaded pipeline stage processing contexts of the run at once, e.g. for runs whose
+    /// simulations are heavy enough to slow each other down. Unlimited by default.
+    #[serde(default)]
+    #[validate(range(min = 1))]
+    pub threads: Option<usize>,
+
+    /// Memory a context of the run takes to process, in MB. Along with --memory-budget, limits how many workers of a
+    /// threaded pipeline stage process contexts of the run at once. Ignored without it.
+    #[serde(default)]
+    pub memory_hint: Option<u64>,
+
+    /// Leaves the workers of threaded pipeline stages to the other runs: at most 1 / (1 + `nice`) of them process
+    /// contexts of the run at once. Defaults to 0.
+    #[serde(default)]
+    pub nice: u32,
+
+    /// Expression the contexts of the run must match to be processed (e.g. `lat > 5 && harvested_area >= 100`), the
+    /// others are skipped before rendering. See [`crate::processing::context::filter`].
+    #[serde(default)]
+    pub filter: Option<ContextFilter>,
+
+    /// Sinks taking the contexts of the run once they made it through the pipeline, besides the checkpoint. Either
+    /// the identifier of a sink driver (e.g. `"std:csv"`) or an object with the identifier under `type` along with its
+    /// options. Resolved into [`crate::config::Config::sinks`] when the config is loaded.
+    #[serde(default)]
+    pub sinks: Vec<serde_json::Value>,
+
+    /// Shell commands run before and after the run, and after every context of it. See [`HooksConfig`].
+    #[serde(default)]
+    pub hooks: Option<HooksConfig>,
+
     #[serde(flatten)]
     pub extra: HashMap<String, ContextValue>,
 }
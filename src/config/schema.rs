@@ -0,0 +1,225 @@
+//! JSON Schema of the config file, for editors to complete and validate configs as they are written. See the `schema`
+//! subcommand.
+//!
+//! The identifiers of the drivers are the ones registered when the schema is generated, so the schema of a build with
+//! plugins lists theirs too. The options of the drivers and of the features of the runs are only described as objects,
+//! the config load tells what is wrong with them. Only the `type` of the drivers is required, as a file may only hold a
+//! part of the config to be included by another (see [`super::compose`]).
+
+use super::migrate::VERSION;
+use crate::registry::{PublicIdentifier, Registries};
+use serde_json::{json, Value};
+
+/// Every way to write the identifiers of `ids`: with their namespace, and without for the ones of `default_namespace`.
+fn identifiers(ids: Vec<PublicIdentifier>, default_namespace: &str) -> Vec<String> {
+    let mut identifiers: Vec<String> = ids
+        .iter()
+        .filter(|id| id.namespace == default_namespace)
+        .map(|id| id.id.clone())
+        .chain(ids.iter().map(PublicIdentifier::to_string))
+        .collect();
+    identifiers.sort();
+    identifiers
+}
+
+/// A registered driver (one of `ids`) as an object with its identifier under `type`, along with its options.
+/// `properties` are the options every driver of its kind takes.
+fn driver_object(ids: &[String], mut properties: Value) -> Value {
+    properties["type"] = json!({ "enum": ids });
+    json!({ "type": "object", "required": ["type"], "properties": properties })
+}
+
+/// A registered driver either as its identifier or as an object, see [`driver_object`].
+fn driver(ids: &[String], properties: Value) -> Value {
+    json!({ "oneOf": [{ "enum": ids }, driver_object(ids, properties)] })
+}
+
+fn object(description: &str) -> Value {
+    json!({ "type": "object", "description": description })
+}
+
+/// The JSON Schema of the config file, with the drivers of `registries`.
+pub fn schema(registries: &Registries, default_namespace: &str) -> Value {
+    let ids = |ids| identifiers(ids, default_namespace);
+    let sitegen_drivers = ids(registries.reg_sitegen_drivers().ids());
+    let mut stages = ids(registries.reg_processor_drivers().ids());
+    stages.extend(ids(registries.reg_pipeline_stages().ids()));
+    stages.sort();
+    let sinks = ids(registries.reg_sinks().ids());
+    let engines = ids(registries.reg_template_engines().ids());
+
+    let sites = driver_object(
+        &sitegen_drivers,
+        json!({
+            "sample_size": { "type": "integer", "minimum": 0 },
+            "skip": { "type": "integer", "minimum": 0 },
+            "sample": object("Random sample of the sites"),
+            "id_transform": object("Rewrite of the site IDs"),
+            "bbox": { "type": "array", "items": { "type": "number" }, "minItems": 4, "maxItems": 4 },
+            "threshold": object("Raster threshold the sites must meet"),
+            "include": { "$ref": "#/$defs/sites" },
+            "exclude": { "$ref": "#/$defs/sites" },
+            "match": { "enum": ["id", "coordinates"] },
+            "elevation": object("DEM raster sampled at every site, exposed as `elev`"),
+            "regions": object("Administrative boundaries tagging every site with `country`, `adm1` and `adm2`"),
+            "cache": { "type": "string" },
+        }),
+    );
+
+    let run = json!({
+        "type": "object",
+        "description": "Properties other than these are variables of the contexts of the run",
+        "properties": {
+            "name": { "type": "string", "pattern": "^[a-zA-Z0-9_-]+$" },
+            "extends": { "type": "string", "description": "Name of the run this one is merged over" },
+            "template": { "type": "string" },
+            "engine": driver(&engines, json!({})),
+            "output_filename": { "type": "string" },
+            "directory_groups": {
+                "type": "array",
+                "items": { "type": "string" },
+                "description": "Variables the context directories are grouped by",
+            },
+            "outputs": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "required": ["name", "template"],
+                    "additionalProperties": false,
+                    "properties": {
+                        "name": { "type": "string", "pattern": "^[a-zA-Z0-9_-]+$" },
+                        "template": { "type": "string" },
+                        "output_filename": { "type": "string" },
+                    },
+                },
+            },
+            "undefined": { "enum": ["strict", "empty", "keep"] },
+            "raw": { "type": "boolean" },
+            "whitespace": {
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "trim_trailing": { "type": "boolean" },
+                    "blank_lines": { "enum": ["keep", "collapse", "remove"] },
+                    "final_newline": { "type": "boolean" },
+                },
+            },
+            "line_endings": { "enum": ["lf", "crlf"] },
+            "bom": { "type": "boolean" },
+            "weather": object("Weather written as a .WTH file into every context directory"),
+            "weather_stations": object("Index of the weather stations, exposing the nearest one as `wsta`"),
+            "wth_code": object("Weather station code of every site, exposed as `wth_code`"),
+            "soil": object("Soil profile of every context"),
+            "planting_window": object("Planting window of every context"),
+            "crop_calendar": object("Crop calendar rasters of the planting and harvest dates"),
+            "planting": object("Rules the planting date is derived from"),
+            "fertilizer": object("Fertilizer schedule"),
+            "cultivar": object("Cultivar selection"),
+            "irrigation": object("Irrigation variants of the run"),
+            "sweep": {
+                "type": "object",
+                "description": "Variables expanding the run into one run per combination of their values",
+                "additionalProperties": {
+                    "type": "object",
+                    "required": ["values"],
+                    "additionalProperties": false,
+                    "properties": {
+                        "values": { "type": "array", "items": { "type": ["boolean", "number", "string"] } },
+                    },
+                },
+            },
+            "sweep_name": { "type": "string" },
+            "batch": object("DSSAT batch file listing the treatments"),
+            "simulation": object("Simulation mode and treatments"),
+            "metadata": { "enum": ["context", "run"] },
+            "priority": { "type": "integer" },
+            "threads": { "type": "integer", "minimum": 1 },
+            "memory_hint": { "type": "integer", "minimum": 0, "description": "MB" },
+            "nice": { "type": "integer", "minimum": 0 },
+            "filter": { "type": "string", "description": "Expression the contexts must match" },
+            "sinks": { "type": "array", "items": driver(&sinks, json!({})) },
+            "hooks": object("Shell commands run around the run"),
+        },
+    });
+
+    let stage = driver(
+        &stages,
+        json!({
+            "executor": {
+                "type": "object",
+                "required": ["type"],
+                "properties": {
+                    "type": { "enum": ["threads", "async"] },
+                    "concurrency": { "type": "integer", "minimum": 1 },
+                },
+            },
+        }),
+    );
+
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "Pythia config",
+        "type": "object",
+        "additionalProperties": false,
+        "properties": {
+            "version": { "type": "integer", "minimum": 1, "maximum": VERSION },
+            "include": { "type": "array", "items": { "type": "string" } },
+            "run_defaults": run.clone(),
+            "default_setup": object("Properties set on every run that doesn't set them itself"),
+            "sites": { "$ref": "#/$defs/sites" },
+            "runs": { "type": "array", "items": run },
+            "inputs": {
+                "type": "object",
+                "additionalProperties": {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "properties": {
+                        "sha256": { "type": "string", "pattern": "^[a-fA-F0-9]{64}$" },
+                        "size": { "type": "integer", "minimum": 0 },
+                        "url": { "type": "string", "pattern": "^(https?|s3)://.+" },
+                    },
+                },
+            },
+            "pipeline": { "type": "array", "items": stage },
+            "template_dir": { "type": "string" },
+            "calibration": object("Parameters, search and observations of the calibrate command"),
+            "plugins": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "required": ["path"],
+                    "additionalProperties": false,
+                    "properties": {
+                        "path": { "type": "string" },
+                        "options": {},
+                    },
+                },
+            },
+        },
+        "$defs": { "sites": sites },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::itself::init_itself;
+
+    #[test]
+    fn test_schema() {
+        let mut registries = Registries::new();
+        let namespace = init_itself(&mut registries).unwrap();
+        let schema = schema(&registries, namespace.namespace());
+
+        let contains = |ids: &Value, id: &str| ids.as_array().unwrap().contains(&json!(id));
+
+        let sitegen_drivers = &schema["$defs"]["sites"]["properties"]["type"]["enum"];
+        assert!(contains(sitegen_drivers, "std:raster"));
+        assert!(contains(sitegen_drivers, "raster"));
+        let stages = &schema["properties"]["pipeline"]["items"]["oneOf"][0]["enum"];
+        assert!(contains(stages, "std:render"));
+        let engines =
+            &schema["properties"]["runs"]["items"]["properties"]["engine"]["oneOf"][0]["enum"];
+        assert!(contains(engines, "std:tera"));
+    }
+}
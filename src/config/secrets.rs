@@ -0,0 +1,141 @@
+//! Secret references (`${secret:NAME}`) in the options of the site sources and of the sinks, so the credentials of
+//! databases and buckets stay out of committed configs. A secret is read from the environment variable `NAME`, or else
+//! from the secrets file (see --secrets-file), a JSON object of secrets by name.
+//!
+//! References are resolved when the config is loaded, within the strings of `sites` and of the `sinks` of the runs
+//! only, so they can't end up in rendered files.
+
+use regex::{Captures, Regex};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use thiserror::Error;
+
+static SECRET: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\$\{secret:([A-Za-z_][A-Za-z0-9_]*)\}").unwrap());
+
+#[derive(Debug, Error)]
+pub enum SecretError {
+    #[error("Failed to read secrets file {path}: {source}")]
+    IOError {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Secrets file {path} must be an object of strings: {source}")]
+    Parse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("Secret {0} is neither an environment variable nor in the secrets file")]
+    Missing(String),
+}
+
+#[derive(Default)]
+pub struct Secrets {
+    file: HashMap<String, String>,
+}
+
+impl Secrets {
+    /// Secrets of the environment, and of the secrets file at `path` if any.
+    pub fn load(path: Option<&Path>) -> Result<Self, SecretError> {
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+        let json = std::fs::read_to_string(path).map_err(|source| SecretError::IOError {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let file = serde_json::from_str(&json).map_err(|source| SecretError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Ok(Self { file })
+    }
+
+    fn get(&self, name: &str) -> Result<String, SecretError> {
+        std::env::var(name)
+            .ok()
+            .or_else(|| self.file.get(name).cloned())
+            .ok_or_else(|| SecretError::Missing(name.to_string()))
+    }
+
+    /// Replaces the references of every string within `value`.
+    fn resolve(&self, value: &mut Value) -> Result<(), SecretError> {
+        match value {
+            Value::String(s) if SECRET.is_match(s) => {
+                let mut missing = None;
+                let resolved = SECRET.replace_all(s, |c: &Captures| {
+                    self.get(&c[1]).unwrap_or_else(|e| {
+                        missing.get_or_insert(e);
+                        String::new()
+                    })
+                });
+                if let Some(e) = missing {
+                    return Err(e);
+                }
+                *s = resolved.into_owned();
+            }
+            Value::Array(values) => values.iter_mut().try_for_each(|v| self.resolve(v))?,
+            Value::Object(values) => values.values_mut().try_for_each(|v| self.resolve(v))?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Resolves the references of the site sources and of the sinks of the runs of `config`.
+    pub fn resolve_config(&self, config: &mut Value) -> Result<(), SecretError> {
+        if let Some(sites) = config.get_mut("sites") {
+            self.resolve(sites)?;
+        }
+        if let Some(Value::Array(runs)) = config.get_mut("runs") {
+            for sinks in runs.iter_mut().filter_map(|run| run.get_mut("sinks")) {
+                self.resolve(sinks)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_resolve_config() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), r#"{ "PYTHIA_TEST_PG_USER": "pythia" }"#).unwrap();
+        std::env::set_var("PYTHIA_TEST_PG_PASSWORD", "hunter2");
+        let secrets = Secrets::load(Some(file.path())).unwrap();
+
+        let mut config = json!({
+            "sites": {
+                "type": "postgis",
+                "url": "postgres://${secret:PYTHIA_TEST_PG_USER}:${secret:PYTHIA_TEST_PG_PASSWORD}@db/sites",
+                "exclude": { "type": "std:csv", "file": "${secret:PYTHIA_TEST_PG_USER}.csv" }
+            },
+            "runs": [
+                { "name": "maize", "sinks": [{ "type": "s3", "key": "${secret:PYTHIA_TEST_PG_USER}" }] },
+                { "name": "rice", "note": "${secret:PYTHIA_TEST_MISSING}" }
+            ]
+        });
+        secrets.resolve_config(&mut config).unwrap();
+        assert_eq!(
+            config["sites"]["url"],
+            json!("postgres://pythia:hunter2@db/sites")
+        );
+        assert_eq!(config["sites"]["exclude"]["file"], json!("pythia.csv"));
+        assert_eq!(config["runs"][0]["sinks"][0]["key"], json!("pythia"));
+        assert_eq!(
+            config["runs"][1]["note"],
+            json!("${secret:PYTHIA_TEST_MISSING}")
+        );
+
+        let mut config = json!({ "sites": { "url": "${secret:PYTHIA_TEST_MISSING}" } });
+        assert!(matches!(
+            secrets.resolve_config(&mut config),
+            Err(SecretError::Missing(name)) if name == "PYTHIA_TEST_MISSING"
+        ));
+    }
+}
@@ -0,0 +1,132 @@
+use crate::processing::sink::{Sink, SinkDriver, SinkEnvironment};
+use crate::registry::resources::SinkDriverResource;
+use crate::registry::ResourceSeed;
+use serde::de::{DeserializeSeed, MapAccess, Visitor};
+use serde_json::Map;
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+
+/// A sink of a run: a registered [`SinkDriver`] along with its options.
+#[derive(Clone)]
+pub struct SinkConfig {
+    /// Identifier of the driver as written in the config, for the logs.
+    pub name: String,
+    pub driver: SinkDriver<Box<dyn Sink>, Box<dyn Any>>,
+    args: serde_json::Value,
+}
+
+impl SinkConfig {
+    pub fn build(&self, env: &SinkEnvironment) -> Result<Box<dyn Sink>, Box<dyn Error>> {
+        let config = (self.driver.config_deserializer)(self.args.clone())?;
+        (self.driver.create)(config, env)
+    }
+}
+
+/// Deserializes a sink of a run, either as the identifier of its driver (e.g. `"std:csv"`) or as an object with the
+/// identifier under `type` along with the options of the driver.
+#[derive(Clone)]
+pub struct SinkConfigSeed<'a> {
+    pub resource_seed: ResourceSeed<'a, SinkDriverResource>,
+}
+
+impl<'de> DeserializeSeed<'de> for SinkConfigSeed<'de> {
+    type Value = SinkConfig;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(SinkConfigVisitor { seed: self })
+    }
+}
+
+struct SinkConfigVisitor<'a> {
+    seed: SinkConfigSeed<'a>,
+}
+
+impl<'a> SinkConfigVisitor<'a> {
+    /// Checks the options against the driver, so they are reported along with the other config errors.
+    fn sink_config<E: serde::de::Error>(
+        self,
+        name: &str,
+        args: Map<String, serde_json::Value>,
+    ) -> Result<SinkConfig, E> {
+        let resource = self
+            .seed
+            .resource_seed
+            .deserialize(serde::de::value::StrDeserializer::<E>::new(name))?;
+        let args = serde_json::Value::Object(args);
+        (resource.0.config_deserializer)(args.clone()).map_err(E::custom)?;
+        Ok(SinkConfig {
+            name: name.to_string(),
+            driver: resource.0.clone(),
+            args,
+        })
+    }
+}
+
+impl<'de> Visitor<'de> for SinkConfigVisitor<'de> {
+    type Value = SinkConfig;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sink ID or a SinkConfig struct")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        self.sink_config(v, Map::new())
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut name: Option<String> = None;
+        let mut args: Map<String, serde_json::Value> = Map::new();
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "type" => name = Some(map.next_value()?),
+                _ => {
+                    args.insert(key.to_string(), map.next_value()?);
+                }
+            }
+        }
+
+        let name = name.ok_or_else(|| serde::de::Error::missing_field("type"))?;
+        self.sink_config(&name, args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::itself::init_itself;
+    use crate::registry::{PublicIdentifierSeed, Registries};
+    use serde_json::json;
+
+    #[test]
+    fn test_sink_seed() {
+        let mut registries = Registries::new();
+        init_itself(&mut registries).unwrap();
+        let seed = SinkConfigSeed {
+            resource_seed: ResourceSeed {
+                registry: registries.reg_sinks(),
+                id_seed: PublicIdentifierSeed {
+                    default_namespace: "std".to_string(),
+                },
+            },
+        };
+        let parse = |json: serde_json::Value| seed.clone().deserialize(json);
+
+        assert_eq!(parse(json!("std:csv")).unwrap().name, "std:csv");
+        assert_eq!(parse(json!("csv")).unwrap().name, "csv");
+        assert!(parse(json!({ "type": "std:csv", "fields": ["wsta"] })).is_ok());
+        assert!(parse(json!({ "type": "std:csv", "unknown": 1 })).is_err());
+        assert!(parse(json!({ "fields": ["wsta"] })).is_err());
+        assert!(parse(json!("std:unknown")).is_err());
+    }
+}
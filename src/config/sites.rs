@@ -1,24 +1,206 @@
+use crate::config::diagnostics::at;
+use crate::config::suggest::suggest;
 use crate::registry::resources::SiteGeneratorDriverResource;
 use crate::registry::ResourceSeed;
-use crate::sites::{SiteGenerator, SiteGeneratorDriver};
+use crate::sites::cache::{CachedSiteGenerator, CachingSiteGenerator};
+use crate::sites::enrich::{AdminRegions, Elevation};
+use crate::sites::filter::{BoundingBox, RasterThreshold, SiteMatch, SiteSet};
+use crate::sites::sample::SampleConfig;
+use crate::sites::transform::IdTransform;
+use crate::sites::{Site, SiteGenError, SiteGenerator, SiteGeneratorDriver};
 use serde::de::{DeserializeSeed, MapAccess, Visitor};
 use serde_json::Map;
 use std::any::Any;
 use std::error::Error;
 use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
 use validator::Validate;
 
+/// Fields of every site source, the others are the options of its driver.
+const FIELDS: &[&str] = &[
+    "type",
+    "sample_size",
+    "skip",
+    "sample",
+    "id_transform",
+    "bbox",
+    "threshold",
+    "include",
+    "exclude",
+    "match",
+    "elevation",
+    "regions",
+    "cache",
+];
+
 #[derive(Validate, Clone)]
 pub struct SiteSourceConfig {
     pub driver: SiteGeneratorDriver<Box<dyn SiteGenerator>, Box<dyn Any>>,
+    /// Truncates the sites to the first `sample_size` ones.
     pub sample_size: Option<usize>,
+    /// Skips the first `skip` entries (sites and errors) of the driver, before the filters, `sample` and `sample_size`
+    /// apply, to resume a run or split it into slices. Drivers skip entries without building sites out of them.
+    pub skip: Option<usize>,
+    /// Samples the sites left by the filters. Mutually exclusive with `sample_size`.
+    pub sample: Option<SampleConfig>,
+    /// Rewrites the ID of every site, before the filters (thus before matching against `include` and `exclude`).
+    pub id_transform: Option<IdTransform>,
+    /// Sites outside this box are dropped, whatever the driver.
+    pub bbox: Option<BoundingBox>,
+    /// Sites where this raster is below its threshold are dropped, whatever the driver.
+    pub threshold: Option<RasterThreshold>,
+    /// Only the sites also in this source are kept.
+    pub include: Option<Box<SiteSourceConfig>>,
+    /// The sites also in this source are dropped.
+    pub exclude: Option<Box<SiteSourceConfig>>,
+    /// How sites are matched against the `include` and `exclude` sources.
+    pub match_on: SiteMatch,
+    /// Attaches the elevation of every site left by the filters, exposed to templates as `${elev}`.
+    pub elevation: Option<Elevation>,
+    /// Tags every site left by the filters with the codes of its administrative regions, exposed to templates as
+    /// `${country}`, `${adm1}` and `${adm2}`.
+    pub regions: Option<AdminRegions>,
+    /// Sites are read from this file if it exists, otherwise the sites left by `skip`, the filters and the sample are
+    /// written to it once the source is exhausted. See [`crate::sites::cache`].
+    pub cache: Option<PathBuf>,
     args: serde_json::Value,
 }
 
 impl SiteSourceConfig {
     pub fn build(&self) -> Result<Box<dyn SiteGenerator>, Box<dyn Error>> {
+        Ok(match &self.cache {
+            Some(path) if path.exists() => Box::new(CachedSiteGenerator::open(path)?),
+            Some(path) => Box::new(CachingSiteGenerator::new(self.build_uncached()?, path)?),
+            None => self.build_uncached()?,
+        })
+    }
+
+    fn build_uncached(&self) -> Result<Box<dyn SiteGenerator>, Box<dyn Error>> {
         let config = (self.driver.config_deserializer)(self.args.clone())?;
-        (self.driver.create)(config)
+        let mut sitegen = (self.driver.create)(config)?;
+        // Right on top of the driver, so skipping goes through its `nth` rather than the adapters below.
+        if let Some(skip) = self.skip.filter(|&skip| skip > 0) {
+            sitegen = Box::new(sitegen.skip(skip));
+        }
+        if let Some(id_transform) = &self.id_transform {
+            let transformer = id_transform.build()?;
+            sitegen = Box::new(sitegen.map(move |site| site.and_then(|s| transformer.apply(s))));
+        }
+        // Errors are kept as they are, so they are reported whatever the filters.
+        if let Some(bbox) = self.bbox {
+            sitegen = Box::new(
+                sitegen.filter(move |site| site.as_ref().map_or(true, |s| bbox.contains(s))),
+            );
+        }
+        if let Some(threshold) = self.threshold.clone() {
+            let sampler = threshold.open()?;
+            sitegen = Box::new(sitegen.filter(move |site| {
+                site.as_ref()
+                    .map_or(true, |s| threshold.accepts(&sampler, s))
+            }));
+        }
+        // The errors of the other sources are reported along with the ones of this source.
+        let mut errors = Vec::new();
+        if let Some(include) = &self.include {
+            let set = include.site_set(self.match_on, &mut errors)?;
+            sitegen = Box::new(
+                sitegen.filter(move |site| site.as_ref().map_or(true, |s| set.contains(s))),
+            );
+        }
+        if let Some(exclude) = &self.exclude {
+            let set = exclude.site_set(self.match_on, &mut errors)?;
+            sitegen = Box::new(
+                sitegen.filter(move |site| site.as_ref().map_or(true, |s| !set.contains(s))),
+            );
+        }
+        if !errors.is_empty() {
+            sitegen = Box::new(errors.into_iter().map(Err).chain(sitegen));
+        }
+        if let Some(elevation) = self.elevation.clone() {
+            let sampler = elevation.open()?;
+            sitegen =
+                Box::new(sitegen.map(move |site| site.map(|s| elevation.enrich(&sampler, s))));
+        }
+        if let Some(regions) = self.regions.clone() {
+            let index = regions.open()?;
+            sitegen = Box::new(sitegen.map(move |site| site.map(|s| regions.enrich(&index, s))));
+        }
+        if let Some(sample) = &self.sample {
+            let mut errors = Vec::new();
+            let sites =
+                sample.sample(sitegen.filter_map(|site| site.map_err(|e| errors.push(e)).ok()));
+            sitegen = Box::new(errors.into_iter().map(Err).chain(sites.into_iter().map(Ok)));
+        }
+        Ok(sitegen)
+    }
+
+    /// Builds the source and collects its sites for matching, moving its errors to `errors`.
+    fn site_set(
+        &self,
+        on: SiteMatch,
+        errors: &mut Vec<SiteGenError>,
+    ) -> Result<SiteSet, Box<dyn Error>> {
+        let sites: Vec<Site> = self
+            .build()?
+            .filter_map(|site| site.map_err(|e| errors.push(e)).ok())
+            .collect();
+        Ok(SiteSet::new(on, &sites))
+    }
+
+    /// Counts the entries (sites and errors) the source yields without building it, after `skip`, `sample` and
+    /// `sample_size`.
+    /// `None` if the driver can't count them, or if they depend on the filters, as knowing the count would take
+    /// iterating the source. See [`crate::sites::SiteGeneratorDriver::count`].
+    pub fn count(&self) -> Option<usize> {
+        let count = match &self.cache {
+            Some(path) if path.exists() => CachedSiteGenerator::count_sites(path)?,
+            _ if self.bbox.is_some()
+                || self.threshold.is_some()
+                || self.include.is_some()
+                || self.exclude.is_some() =>
+            {
+                return None
+            }
+            _ => {
+                let config = (self.driver.config_deserializer)(self.args.clone()).ok()?;
+                let count = (self.driver.count)(&config)?.saturating_sub(self.skip.unwrap_or(0));
+                match &self.sample {
+                    Some(SampleConfig::Random { size, .. }) => count.min(*size),
+                    Some(SampleConfig::Stratified { .. }) => return None,
+                    None => count,
+                }
+            }
+        };
+        Some(self.sample_size.map_or(count, |size| count.min(size)))
+    }
+
+    /// Checks the site source without building it. See [`crate::sites::SiteGeneratorDriver::preflight`].
+    pub fn preflight(&self) -> Vec<String> {
+        let mut issues = match (self.driver.config_deserializer)(self.args.clone()) {
+            Ok(config) => (self.driver.preflight)(&config),
+            Err(e) => vec![format!("Invalid site source configuration: {}", e)],
+        };
+        if let Some(threshold) = &self.threshold {
+            issues.extend(threshold.preflight());
+        }
+        if let Some(elevation) = &self.elevation {
+            issues.extend(elevation.preflight());
+        }
+        if let Some(regions) = &self.regions {
+            issues.extend(regions.preflight());
+        }
+        for (name, other) in [("include", &self.include), ("exclude", &self.exclude)] {
+            if let Some(other) = other {
+                issues.extend(
+                    other
+                        .preflight()
+                        .into_iter()
+                        .map(|i| format!("{}: {}", name, i)),
+                );
+            }
+        }
+        issues
     }
 }
 
@@ -53,14 +235,43 @@ impl<'de> Visitor<'de> for SiteSourceConfigVisitor<'de> {
     where
         A: MapAccess<'de>,
     {
-        let mut resource: Option<SiteGeneratorDriverResource> = None;
+        let mut resource: Option<Arc<SiteGeneratorDriverResource>> = None;
         let mut sample_size = None;
+        let mut skip = None;
+        let mut sample = None;
+        let mut id_transform = None;
+        let mut bbox = None;
+        let mut threshold = None;
+        let mut include = None;
+        let mut exclude = None;
+        let mut match_on = None;
+        let mut elevation = None;
+        let mut regions = None;
+        let mut cache = None;
         let mut args: Map<String, serde_json::Value> = Map::new();
 
         while let Some(key) = map.next_key::<String>()? {
             match key.as_str() {
                 "type" => resource = Some(map.next_value_seed(self.seed.resource_seed.clone())?),
                 "sample_size" => sample_size = Some(map.next_value()?),
+                "skip" => skip = Some(map.next_value()?),
+                "sample" => sample = Some(map.next_value()?),
+                "id_transform" => id_transform = Some(map.next_value()?),
+                "bbox" => bbox = Some(map.next_value()?),
+                "threshold" => threshold = Some(map.next_value()?),
+                "include" | "exclude" => {
+                    let other = map
+                        .next_value_seed(self.seed.clone())
+                        .map_err(|e| at::<A::Error>(&[key.as_str().into()], e))?;
+                    match key.as_str() {
+                        "include" => include = Some(Box::new(other)),
+                        _ => exclude = Some(Box::new(other)),
+                    }
+                }
+                "match" => match_on = Some(map.next_value()?),
+                "elevation" => elevation = Some(map.next_value()?),
+                "regions" => regions = Some(map.next_value()?),
+                "cache" => cache = Some(map.next_value()?),
                 _ => {
                     args.insert(key.to_string(), map.next_value()?);
                 }
@@ -68,10 +279,38 @@ impl<'de> Visitor<'de> for SiteSourceConfigVisitor<'de> {
         }
 
         let resource = resource.ok_or_else(|| serde::de::Error::missing_field("type"))?;
+        // Checks the options against the driver, so they are reported along with the other config errors.
+        let args = serde_json::Value::Object(args);
+        if let Err(e) = (resource.0.config_deserializer)(args.clone()) {
+            return Err(serde::de::Error::custom(suggest(&e.to_string(), FIELDS)));
+        }
+        if let Some(Err(e)) = sample.as_ref().map(SampleConfig::validate) {
+            return Err(serde::de::Error::custom(e));
+        }
+        if let Some(Err(e)) = id_transform.as_ref().map(IdTransform::build) {
+            return Err(serde::de::Error::custom(e));
+        }
+        if sample_size.is_some() && sample.is_some() {
+            return Err(serde::de::Error::custom(
+                "\"sample_size\" and \"sample\" are mutually exclusive",
+            ));
+        }
+
         Ok(SiteSourceConfig {
-            driver: resource.0,
+            driver: resource.0.clone(),
             sample_size,
-            args: serde_json::Value::Object(args),
+            skip,
+            sample,
+            id_transform,
+            bbox,
+            threshold,
+            include,
+            exclude,
+            match_on: match_on.unwrap_or_default(),
+            elevation,
+            regions,
+            cache,
+            args,
         })
     }
 }
@@ -0,0 +1,127 @@
+//! Suggestions for the misspelled fields of the config, e.g. "unknown field `sample_sise` (did you mean
+//! `sample_size`?)".
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+static UNKNOWN_FIELD: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"unknown field `([^`]*)`, (?:expected ((?:one of )?`[^`]*`(?:(?:, | or )`[^`]*`)*)|there are no fields)")
+        .unwrap()
+});
+static FIELD: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"`([^`]*)`").unwrap());
+
+/// Levenshtein distance between `a` and `b`.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// The field of `fields` `field` is most likely a misspelling of, if any is close enough.
+pub fn closest<'a>(field: &str, fields: &[&'a str]) -> Option<&'a str> {
+    let max = (field.chars().count() / 3).max(1);
+    fields
+        .iter()
+        .map(|f| (distance(field, f), *f))
+        .filter(|(d, _)| *d <= max)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, f)| f)
+}
+
+fn list(fields: &[&str]) -> String {
+    match fields {
+        [] => "there are no fields".to_string(),
+        [field] => format!("expected `{}`", field),
+        _ => {
+            let fields: Vec<String> = fields.iter().map(|f| format!("`{}`", f)).collect();
+            format!("expected one of {}", fields.join(", "))
+        }
+    }
+}
+
+fn message(field: &str, expected: &[&str]) -> String {
+    match closest(field, expected) {
+        Some(suggestion) => format!(
+            "unknown field `{}` (did you mean `{}`?), {}",
+            field,
+            suggestion,
+            list(expected)
+        ),
+        None => format!("unknown field `{}`, {}", field, list(expected)),
+    }
+}
+
+/// Like [`serde::de::Error::unknown_field`], suggesting the closest of `expected`.
+pub fn unknown_field<E: serde::de::Error>(field: &str, expected: &[&str]) -> E {
+    E::custom(message(field, expected))
+}
+
+/// Adds a suggestion to `error` if it is an unknown field error of serde, among the fields it expected along with
+/// `also` (the fields the map was read for before the rest was handed over, e.g. the ones of every site source for the
+/// options of its driver). Other errors are left as they are.
+pub fn suggest(error: &str, also: &[&str]) -> String {
+    let Some(captures) = UNKNOWN_FIELD.captures(error) else {
+        return error.to_string();
+    };
+    let field = &captures[1];
+    let mut expected: Vec<&str> = also.to_vec();
+    if let Some(fields) = captures.get(2) {
+        expected.extend(
+            FIELD
+                .captures_iter(fields.as_str())
+                .map(|c| c.get(1).unwrap().as_str()),
+        );
+    }
+    expected.sort();
+    expected.dedup();
+
+    let whole = captures.get(0).unwrap();
+    format!(
+        "{}{}{}",
+        &error[..whole.start()],
+        message(field, &expected),
+        &error[whole.end()..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suggest() {
+        assert_eq!(distance("sample_sise", "sample_size"), 1);
+        assert_eq!(distance("", "skip"), 4);
+        assert_eq!(
+            closest("smaple_size", &["sample", "sample_size"]),
+            Some("sample_size")
+        );
+        assert_eq!(closest("soils", &["soil", "sinks"]), Some("soil"));
+        assert_eq!(closest("cache", &["bbox", "skip"]), None);
+
+        let error =
+            "unknown field `layer_idx`, expected one of `file`, `layer_index` at line 3 column 12";
+        assert_eq!(
+            suggest(error, &["skip"]),
+            "unknown field `layer_idx` (did you mean `layer_index`?), expected one of `file`, `layer_index`, \
+             `skip` at line 3 column 12"
+        );
+        assert_eq!(
+            suggest("unknown field `skp`, there are no fields", &["skip"]),
+            "unknown field `skp` (did you mean `skip`?), expected `skip`"
+        );
+        assert_eq!(
+            suggest("missing field `file`", &["file"]),
+            "missing field `file`"
+        );
+    }
+}
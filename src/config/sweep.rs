@@ -0,0 +1,158 @@
+//! Parameter sweeps, expanding a single run declaration into a run per combination of the values of its variables.
+
+use super::runs::RunConfig;
+use crate::processing::context::{ContextValue, PrimitiveContextValue};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// Values a variable of a sweep takes, one run each.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SweepValues {
+    pub values: Vec<PrimitiveContextValue>,
+}
+
+#[derive(Debug, Error)]
+pub enum SweepError {
+    #[error("Variable {variable} of the sweep of run {run} has no values")]
+    NoValues { run: String, variable: String },
+    #[error("Failed to render the sweep name of run {run}: {source}")]
+    Name { run: String, source: tera::Error },
+}
+
+/// Name of the run of `combination` of the sweep of `run`: its `sweep_name` rendered with the run name as `name` and
+/// the swept variables, or the run name followed by the values in the order of the variable names, with the characters
+/// run names can't have replaced by `-` (e.g. `maize_1-5_05-01`).
+fn name(
+    run: &RunConfig,
+    combination: &[(&String, &PrimitiveContextValue)],
+) -> Result<String, SweepError> {
+    let Some(template) = &run.sweep_name else {
+        let mut name = run.name.clone();
+        for (_, value) in combination {
+            let value = value
+                .as_string()
+                .replace(|c: char| !c.is_ascii_alphanumeric() && c != '_', "-");
+            name = format!("{}_{}", name, value);
+        }
+        return Ok(name);
+    };
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("name", &run.name);
+    for (variable, value) in combination {
+        ctx.insert(variable.as_str(), value);
+    }
+    tera::Tera::one_off(template, &ctx, false).map_err(|source| SweepError::Name {
+        run: run.name.clone(),
+        source,
+    })
+}
+
+/// Replaces every run declaring a sweep with one run per combination of the values of its variables, which are set for
+/// templates. Other runs are kept as is.
+pub fn expand_sweeps(runs: Vec<RunConfig>) -> Result<Vec<RunConfig>, SweepError> {
+    let mut expanded = Vec::with_capacity(runs.len());
+    for run in runs {
+        if run.sweep.is_empty() {
+            expanded.push(run);
+            continue;
+        }
+
+        let mut combinations: Vec<Vec<(&String, &PrimitiveContextValue)>> = vec![Vec::new()];
+        for (variable, values) in &run.sweep {
+            if values.values.is_empty() {
+                return Err(SweepError::NoValues {
+                    run: run.name.clone(),
+                    variable: variable.clone(),
+                });
+            }
+            combinations = combinations
+                .into_iter()
+                .flat_map(|combination| {
+                    values.values.iter().map(move |value| {
+                        let mut combination = combination.clone();
+                        combination.push((variable, value));
+                        combination
+                    })
+                })
+                .collect();
+        }
+
+        for combination in &combinations {
+            let mut variant = run.clone();
+            variant.name = name(&run, combination)?;
+            variant.sweep = BTreeMap::new();
+            variant.sweep_name = None;
+            for (variable, value) in combination {
+                let value = ContextValue::Prim((*value).clone());
+                variant.extra.insert(variable.to_string(), value);
+            }
+            expanded.push(variant);
+        }
+    }
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_sweeps() {
+        let sweep: BTreeMap<String, SweepValues> = serde_json::from_str(
+            r#"{ "pdate": { "values": ["05-01", "06-01"] }, "n_rate": { "values": [0, 1.5] } }"#,
+        )
+        .unwrap();
+        let runs = vec![
+            RunConfig {
+                name: "maize".to_string(),
+                sweep: sweep.clone(),
+                ..Default::default()
+            },
+            RunConfig {
+                name: "rice".to_string(),
+                sweep,
+                sweep_name: Some("{{ name }}_pd{{ pdate }}".to_string()),
+                ..Default::default()
+            },
+            RunConfig {
+                name: "wheat".to_string(),
+                ..Default::default()
+            },
+        ];
+
+        let runs = expand_sweeps(runs).unwrap();
+        let names: Vec<&str> = runs.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "maize_0_05-01",
+                "maize_0_06-01",
+                "maize_1-5_05-01",
+                "maize_1-5_06-01",
+                "rice_pd05-01",
+                "rice_pd06-01",
+                "rice_pd05-01",
+                "rice_pd06-01",
+                "wheat"
+            ]
+        );
+        assert!(matches!(
+            runs[2].extra.get("n_rate"),
+            Some(ContextValue::Prim(PrimitiveContextValue::Float(1.5)))
+        ));
+        assert!(runs[0].sweep.is_empty());
+
+        let empty = RunConfig {
+            name: "maize".to_string(),
+            sweep: serde_json::from_str(r#"{ "pdate": { "values": [] } }"#).unwrap(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            expand_sweeps(vec![empty]),
+            Err(SweepError::NoValues { .. })
+        ));
+    }
+}
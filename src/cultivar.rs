@@ -0,0 +1,166 @@
+//! Selection of the cultivar of each site by region.
+
+use crate::processing::context::{ContextValue, PrimitiveContextValue};
+use crate::sites::Site;
+use crate::utils::polygons::PolygonLayer;
+use crate::utils::raster::RasterSampler;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use thiserror::Error;
+use validator::ValidationError;
+
+static ERRCODE_CULTIVARS_EMPTY: &str = "ERRCODE_CULTIVARS_EMPTY";
+
+#[derive(Debug, Error)]
+#[error("Failed to open cultivar regions {path}: {source}")]
+pub struct CultivarError {
+    path: PathBuf,
+    source: gdal::errors::GdalError,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Cultivar {
+    pub ingeno: String,
+    #[serde(default)]
+    pub cname: String,
+}
+
+/// Where the region of each site comes from.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "source", rename_all = "snake_case", deny_unknown_fields)]
+pub enum CultivarRegions {
+    /// A raster of region classes. The region of a site is the class at its pixel, e.g. `"3"`.
+    Raster {
+        file: PathBuf,
+        /// Band to read from (**ONE-BASED**).
+        #[serde(default = "default_band")]
+        band: usize,
+    },
+    /// A polygon layer. The region of a site is the value of `field` of the polygon containing it.
+    Vector {
+        file: PathBuf,
+        /// Name of the layer. If not set, the first layer is used.
+        #[serde(default)]
+        layer: Option<String>,
+        field: String,
+    },
+}
+
+fn default_band() -> usize {
+    1
+}
+
+/// Maps each site to a cultivar by region, exposed to templates as `ingeno` and `cname`.
+/// Sites in regions without a cultivar get the `default` one. If there is no default, no cultivar is set and templates
+/// using it fail, so a missing region is never silently simulated with the wrong cultivar.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CultivarConfig {
+    pub regions: CultivarRegions,
+
+    /// Cultivar of each region.
+    pub cultivars: HashMap<String, Cultivar>,
+
+    #[serde(default)]
+    pub default: Option<Cultivar>,
+}
+
+pub fn validate_cultivar(cultivar: &CultivarConfig) -> Result<(), ValidationError> {
+    if cultivar.cultivars.is_empty() {
+        return Err(ValidationError::new(ERRCODE_CULTIVARS_EMPTY).with_message(Cow::from(
+            "No cultivars are mapped to regions. Use a run variable to set a single cultivar instead",
+        )));
+    }
+    Ok(())
+}
+
+enum Regions {
+    Raster(RasterSampler, usize),
+    Vector(PolygonLayer, String),
+}
+
+/// A [`CultivarConfig`] with its regions loaded.
+pub struct CultivarSelector {
+    config: CultivarConfig,
+    regions: Regions,
+}
+
+impl CultivarSelector {
+    pub fn load(config: &CultivarConfig) -> Result<Self, CultivarError> {
+        let regions = match &config.regions {
+            CultivarRegions::Raster { file, band } => RasterSampler::open(&file.to_string_lossy())
+                .map(|sampler| Regions::Raster(sampler, band.saturating_sub(1)))
+                .map_err(|source| CultivarError {
+                    path: file.clone(),
+                    source,
+                })?,
+            CultivarRegions::Vector { file, layer, field } => {
+                PolygonLayer::open(&file.to_string_lossy(), layer.clone())
+                    .map(|polygons| Regions::Vector(polygons, field.clone()))
+                    .map_err(|source| CultivarError {
+                        path: file.clone(),
+                        source,
+                    })?
+            }
+        };
+
+        Ok(Self {
+            config: config.clone(),
+            regions,
+        })
+    }
+
+    fn region(&self, lon: f64, lat: f64) -> Option<String> {
+        match &self.regions {
+            Regions::Raster(sampler, band) => {
+                sampler.sample(*band, lon, lat).ok()?.map(region_class)
+            }
+            Regions::Vector(polygons, field) => polygons.attribute_at(lon, lat, field).ok()?,
+        }
+    }
+
+    pub fn select(&self, site: &Site) -> Option<&Cultivar> {
+        self.region(site.lon.as_f64(), site.lat.as_f64())
+            .and_then(|region| self.config.cultivars.get(region.trim()))
+            .or(self.config.default.as_ref())
+    }
+}
+
+impl Cultivar {
+    pub fn values(&self) -> Vec<(String, ContextValue)> {
+        vec![
+            (
+                "ingeno".to_string(),
+                ContextValue::Prim(PrimitiveContextValue::String(self.ingeno.clone())),
+            ),
+            (
+                "cname".to_string(),
+                ContextValue::Prim(PrimitiveContextValue::String(self.cname.clone())),
+            ),
+        ]
+    }
+}
+
+/// Formats a raster class as a region key, without decimals if integral (`3.0` becomes `"3"`).
+fn region_class(value: f64) -> String {
+    if value.fract() == 0.0 {
+        format!("{}", value as i64)
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_region_class() {
+        assert_eq!(region_class(3.0), "3");
+        assert_eq!(region_class(-1.0), "-1");
+        assert_eq!(region_class(2.5), "2.5");
+    }
+}
@@ -1,5 +1,58 @@
 const GEO_DEG_PRECISION: f64 = 100_000.0;
 
+/// Mean radius of the Earth, in kilometers.
+pub const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+/// Great-circle distance in kilometers between two points given in degrees, using the haversine formula.
+pub fn haversine_km(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (phi1, phi2) = (lat1.to_radians(), lat2.to_radians());
+    let d_phi = (lat2 - lat1).to_radians();
+    let d_lambda = (lon2 - lon1).to_radians();
+
+    let a = (d_phi / 2.0).sin().powi(2) + phi1.cos() * phi2.cos() * (d_lambda / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
+
+/// Cell of a global lon/lat grid holding a point, counted from the north-west corner (so cell `0` is the one at
+/// -180, 90), as in the CELL5M grid for a resolution of 5 arc-minutes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GridCell {
+    pub col: u64,
+    pub row: u64,
+    /// Columns of the grid.
+    pub cols: u64,
+    /// Rows of the grid.
+    pub rows: u64,
+}
+
+impl GridCell {
+    /// Columns and rows of a global grid of `resolution` degrees. A last column (or row) narrower than the others
+    /// only counts if it isn't a rounding error.
+    pub fn global_size(resolution: f64) -> (u64, u64) {
+        let cells = |degrees: f64| (degrees / resolution - 1e-9).ceil() as u64;
+        (cells(360.0), cells(180.0))
+    }
+
+    /// Cell of a global grid of `resolution` degrees holding the point. Points on the east and south edges (or beyond)
+    /// fall in the last cells.
+    pub fn global(lon: f64, lat: f64, resolution: f64) -> Self {
+        let (cols, rows) = Self::global_size(resolution);
+        let col = ((lon + 180.0) / resolution).floor().max(0.0) as u64;
+        let row = ((90.0 - lat) / resolution).floor().max(0.0) as u64;
+        Self {
+            col: col.min(cols - 1),
+            row: row.min(rows - 1),
+            cols,
+            rows,
+        }
+    }
+
+    /// Number of the cell, row by row.
+    pub fn index(&self) -> u64 {
+        self.row * self.cols + self.col
+    }
+}
+
 /// Type that represents a latitude or longitude in degrees. It holds coordinates with a fixed precision of up to 5 decimal places.
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub struct GeoDeg(f32);
@@ -139,4 +192,24 @@ mod tests {
         assert_eq!(GeoDeg::from(1.0).ns(2), "1_00N");
         assert_eq!(GeoDeg::from(1.0).ew(2), "1_00E");
     }
+
+    #[test]
+    fn test_grid_cell() {
+        // The CELL5M ID of the pixel of testdata/DSSAT-Soils.tif holding the point.
+        let cell = GridCell::global(12.2919, 14.7917, 5.0 / 60.0);
+        assert_eq!((cell.cols, cell.rows), (4320, 2160));
+        assert_eq!((cell.col, cell.row), (2307, 902));
+        assert_eq!(cell.index(), 3898947);
+
+        assert_eq!(GridCell::global(-180.0, 90.0, 0.5).index(), 0);
+        assert_eq!(GridCell::global(180.0, -90.0, 0.5).index(), 720 * 360 - 1);
+        assert_eq!(GridCell::global_size(0.7), (515, 258));
+    }
+
+    #[test]
+    fn test_haversine() {
+        assert_eq!(haversine_km(10.0, 10.0, 10.0, 10.0), 0.0);
+        assert!((haversine_km(0.0, 0.0, 1.0, 0.0) - 111.195).abs() < 0.01);
+        assert!((haversine_km(0.0, 179.5, 0.0, -179.5) - 111.195).abs() < 0.01);
+    }
 }
@@ -0,0 +1,245 @@
+//! Expansion of fertilizer application rules into per-site schedules.
+
+use crate::processing::context::{Context, ContextValue, PrimitiveContextValue};
+use crate::utils::lookup::{LookupConfig, LookupError, LookupTable};
+use crate::utils::raster::RasterSampler;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use thiserror::Error;
+use validator::ValidationError;
+
+static ERRCODE_FERTILIZER_INVALID: &str = "ERRCODE_FERTILIZER_INVALID";
+
+#[derive(Debug, Error)]
+pub enum FertilizerError {
+    #[error("Failed to open fertilizer rate raster {path}: {source}")]
+    Gdal {
+        path: PathBuf,
+        source: gdal::errors::GdalError,
+    },
+    #[error(transparent)]
+    Lookup(#[from] LookupError),
+}
+
+/// Total amount of fertilizer of a schedule (e.g. kg N/ha), split between its applications.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum FertilizerRate {
+    Fixed(f64),
+    Source(FertilizerRateSource),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "source", rename_all = "snake_case", deny_unknown_fields)]
+pub enum FertilizerRateSource {
+    /// The value of the raster at the site. Sites outside the raster or on nodata pixels get no schedule.
+    Raster {
+        file: PathBuf,
+        /// Band to read from (**ONE-BASED**).
+        #[serde(default = "default_band")]
+        band: usize,
+    },
+    /// The value in `column` of the row of a lookup table (e.g. per region).
+    Lookup {
+        #[serde(flatten)]
+        lookup: LookupConfig,
+        column: String,
+    },
+}
+
+fn default_band() -> usize {
+    1
+}
+
+/// A single application of a schedule.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FertilizerApplication {
+    /// Fraction of the total rate applied.
+    pub fraction: f64,
+
+    /// Days after planting.
+    pub dap: i64,
+
+    /// Any other field of the application (e.g. `material`, `method`, `depth`), passed to templates as is.
+    #[serde(flatten)]
+    pub extra: HashMap<String, PrimitiveContextValue>,
+}
+
+/// A fertilizer schedule, exposed to templates as the list `fertilizers`. Each of its records has the fields `dap`,
+/// `amount` (the total rate times the fraction of the application) and the extra fields of the application.
+/// `fertilizer_total` holds the total rate.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct FertilizerConfig {
+    pub rate: FertilizerRate,
+    pub applications: Vec<FertilizerApplication>,
+}
+
+pub fn validate_fertilizer(fertilizer: &FertilizerConfig) -> Result<(), ValidationError> {
+    let invalid = |msg: String| {
+        Err(ValidationError::new(ERRCODE_FERTILIZER_INVALID).with_message(Cow::from(msg)))
+    };
+
+    if let Some(app) = fertilizer
+        .applications
+        .iter()
+        .find(|a| !(0.0..=1.0).contains(&a.fraction))
+    {
+        return invalid(format!(
+            "Fertilizer application at {} DAP has a fraction of {}, which is not between 0 and 1",
+            app.dap, app.fraction
+        ));
+    }
+
+    let total: f64 = fertilizer.applications.iter().map(|a| a.fraction).sum();
+    if (total - 1.0).abs() > 1e-6 {
+        return invalid(format!(
+            "Fertilizer application fractions add up to {} instead of 1",
+            total
+        ));
+    }
+    Ok(())
+}
+
+enum Rate {
+    Fixed(f64),
+    Raster(RasterSampler, usize),
+    Lookup(LookupTable, usize),
+}
+
+/// A [`FertilizerConfig`] with its rate source loaded.
+pub struct FertilizerSchedule {
+    config: FertilizerConfig,
+    rate: Rate,
+}
+
+impl FertilizerSchedule {
+    pub fn load(config: &FertilizerConfig) -> Result<Self, FertilizerError> {
+        let rate = match &config.rate {
+            FertilizerRate::Fixed(rate) => Rate::Fixed(*rate),
+            FertilizerRate::Source(FertilizerRateSource::Raster { file, band }) => {
+                let sampler = RasterSampler::open(&file.to_string_lossy()).map_err(|source| {
+                    FertilizerError::Gdal {
+                        path: file.clone(),
+                        source,
+                    }
+                })?;
+                Rate::Raster(sampler, band.saturating_sub(1))
+            }
+            FertilizerRate::Source(FertilizerRateSource::Lookup { lookup, column }) => {
+                let table = lookup.load()?;
+                let column = table.column(column)?;
+                Rate::Lookup(table, column)
+            }
+        };
+
+        Ok(Self {
+            config: config.clone(),
+            rate,
+        })
+    }
+
+    fn rate(&self, ctx: &Context) -> Option<f64> {
+        match &self.rate {
+            Rate::Fixed(rate) => Some(*rate),
+            Rate::Raster(sampler, band) => sampler
+                .sample(*band, ctx.site.lon.as_f64(), ctx.site.lat.as_f64())
+                .ok()?,
+            Rate::Lookup(table, column) => {
+                let FertilizerRate::Source(FertilizerRateSource::Lookup { lookup, .. }) =
+                    &self.config.rate
+                else {
+                    return None;
+                };
+                table
+                    .row(&lookup.resolve_key(ctx).ok()?)?
+                    .get(*column)?
+                    .parse()
+                    .ok()
+            }
+        }
+    }
+
+    /// The variables of the schedule of `ctx`, or `None` if the rate is not available for the site.
+    pub fn expand(&self, ctx: &Context) -> Option<Vec<(String, ContextValue)>> {
+        let rate = self.rate(ctx)?;
+        Some(vec![
+            (
+                "fertilizers".to_string(),
+                ContextValue::Records(expand(&self.config.applications, rate)),
+            ),
+            (
+                "fertilizer_total".to_string(),
+                ContextValue::Prim(PrimitiveContextValue::Float(rate)),
+            ),
+        ])
+    }
+}
+
+/// Splits `rate` between `applications`, sorted by days after planting. Amounts are rounded to one decimal, as DSSAT reads them.
+fn expand(
+    applications: &[FertilizerApplication],
+    rate: f64,
+) -> Vec<HashMap<String, PrimitiveContextValue>> {
+    let mut applications: Vec<&FertilizerApplication> = applications.iter().collect();
+    applications.sort_by_key(|a| a.dap);
+
+    applications
+        .into_iter()
+        .map(|app| {
+            let mut record = app.extra.clone();
+            record.insert("dap".to_string(), PrimitiveContextValue::Int(app.dap));
+            record.insert(
+                "amount".to_string(),
+                PrimitiveContextValue::Float((rate * app.fraction * 10.0).round() / 10.0),
+            );
+            record
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn application(fraction: f64, dap: i64) -> FertilizerApplication {
+        FertilizerApplication {
+            fraction,
+            dap,
+            extra: [(
+                "material".to_string(),
+                PrimitiveContextValue::String("FE005".to_string()),
+            )]
+            .into_iter()
+            .collect(),
+        }
+    }
+
+    #[test]
+    fn test_expand() {
+        let records = expand(&[application(0.7, 30), application(0.3, 0)], 125.0);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["dap"], PrimitiveContextValue::Int(0));
+        assert_eq!(records[0]["amount"], PrimitiveContextValue::Float(37.5));
+        assert_eq!(records[1]["dap"], PrimitiveContextValue::Int(30));
+        assert_eq!(records[1]["amount"], PrimitiveContextValue::Float(87.5));
+        assert_eq!(
+            records[1]["material"],
+            PrimitiveContextValue::String("FE005".to_string())
+        );
+    }
+
+    #[test]
+    fn test_validate_fertilizer() {
+        let config = |fractions: &[f64]| FertilizerConfig {
+            rate: FertilizerRate::Fixed(100.0),
+            applications: fractions.iter().map(|f| application(*f, 0)).collect(),
+        };
+
+        assert!(validate_fertilizer(&config(&[0.5, 0.5])).is_ok());
+        assert!(validate_fertilizer(&config(&[0.5, 0.4])).is_err());
+        assert!(validate_fertilizer(&config(&[1.5, -0.5])).is_err());
+    }
+}
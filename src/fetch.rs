@@ -0,0 +1,325 @@
+//! Module _fetch_ downloads the input datasets declared with a URL into a content-addressed local cache, and places
+//! them at their declared path so the rest of the program (e.g. GDAL) opens them as any other local file.
+//!
+//! The cache is laid out as:
+//! - `sha256/<digest>`: the downloaded files, named after their SHA-256 digest;
+//! - `urls/<digest of the URL>`: the digest of the file downloaded from a URL, for inputs without a declared checksum;
+//! - `locks/<digest of the URL>.lock`: held while a URL is being downloaded, so concurrent campaigns sharing the cache
+//!   download each file only once.
+
+use crate::config::inputs::InputConfig;
+use crate::provenance::hex;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use thiserror::Error;
+
+/// Locks not refreshed for longer than this are assumed to be left behind by a crashed process.
+const STALE_LOCK: Duration = Duration::from_secs(60 * 60);
+
+/// How often the lock of a download in progress is refreshed, so it doesn't pass for stale however long it takes.
+const LOCK_REFRESH: Duration = Duration::from_secs(60);
+
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Error)]
+pub enum FetchError {
+    #[error("Failed to download {0}: {1}")]
+    Http(String, Box<ureq::Error>),
+    #[error("IO error on {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("File downloaded from {url} has SHA-256 {actual}, expected {expected}")]
+    ChecksumMismatch {
+        url: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+/// Translates `s3://bucket/key` URLs into their public HTTPS endpoint. Other URLs are returned as is.
+pub fn http_url(url: &str) -> String {
+    match url
+        .strip_prefix("s3://")
+        .and_then(|rest| rest.split_once('/'))
+    {
+        Some((bucket, key)) => format!("https://{}.s3.amazonaws.com/{}", bucket, key),
+        None => url.to_string(),
+    }
+}
+
+/// Removes the lock file when dropped.
+struct LockGuard(PathBuf, File);
+
+impl LockGuard {
+    /// Marks the lock as still held, see [`STALE_LOCK`].
+    fn refresh(&self) -> Result<(), FetchError> {
+        self.1
+            .set_modified(SystemTime::now())
+            .map_err(InputCache::io_err(&self.0))
+    }
+}
+
+enum Lock {
+    Acquired(LockGuard),
+    /// Whoever held the lock finished downloading the file, stored under this digest.
+    Downloaded(String),
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+pub struct InputCache {
+    pub dir: PathBuf,
+}
+
+impl InputCache {
+    fn io_err(path: &Path) -> impl Fn(std::io::Error) -> FetchError + '_ {
+        move |e| FetchError::Io(path.to_path_buf(), e)
+    }
+
+    fn blob(&self, sha256: &str) -> PathBuf {
+        self.dir.join("sha256").join(sha256)
+    }
+
+    /// The digest of the file already downloaded from `url`, either declared or recorded by a previous download.
+    fn cached_digest(&self, url_key: &str, declared: Option<&str>) -> Option<String> {
+        let digest = match declared {
+            Some(sha256) => sha256.to_lowercase(),
+            None => std::fs::read_to_string(self.dir.join("urls").join(url_key))
+                .ok()?
+                .trim()
+                .to_string(),
+        };
+        self.blob(&digest).is_file().then_some(digest)
+    }
+
+    /// SHA-256 digest of the file at `path`.
+    fn file_digest(path: &Path) -> Result<String, FetchError> {
+        let mut file = File::open(path).map_err(Self::io_err(path))?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut file, &mut hasher).map_err(Self::io_err(path))?;
+        Ok(hex(&hasher.finalize()))
+    }
+
+    /// Makes sure the input at `path` exists and matches the declared `sha256`, placing the file downloaded from `url`
+    /// there otherwise. The file is only downloaded if it isn't in the cache yet, and is rejected if it doesn't match
+    /// the declared `sha256` either.
+    pub fn fetch(&self, path: &Path, url: &str, sha256: Option<&str>) -> Result<(), FetchError> {
+        if path.exists() {
+            let Some(expected) = sha256 else {
+                return Ok(());
+            };
+            if Self::file_digest(path)?.eq_ignore_ascii_case(expected) {
+                return Ok(());
+            }
+            // The file was modified since it was placed. It may be a hard link to the cached one, which would then be
+            // just as modified.
+            let blob = self.blob(&expected.to_lowercase());
+            if blob.is_file() && !Self::file_digest(&blob)?.eq_ignore_ascii_case(expected) {
+                std::fs::remove_file(&blob).map_err(Self::io_err(&blob))?;
+            }
+            std::fs::remove_file(path).map_err(Self::io_err(path))?;
+        }
+
+        let url_key = hex(&Sha256::digest(url.as_bytes()));
+        let digest = match self.cached_digest(&url_key, sha256) {
+            Some(digest) => digest,
+            None => self.download(url, &url_key, sha256)?,
+        };
+
+        let blob = self.blob(&digest);
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).map_err(Self::io_err(parent))?;
+        }
+        // Hard links save the space of a copy, but don't work across filesystems.
+        std::fs::hard_link(&blob, path)
+            .or_else(|_| std::fs::copy(&blob, path).map(|_| ()))
+            .map_err(Self::io_err(path))
+    }
+
+    /// Takes the lock of `url_key`, waiting for whoever holds it to finish.
+    fn lock(&self, url_key: &str, declared: Option<&str>) -> Result<Lock, FetchError> {
+        let locks = self.dir.join("locks");
+        std::fs::create_dir_all(&locks).map_err(Self::io_err(&locks))?;
+        let path = locks.join(format!("{}.lock", url_key));
+
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => return Ok(Lock::Acquired(LockGuard(path, file))),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    if let Some(digest) = self.cached_digest(url_key, declared) {
+                        return Ok(Lock::Downloaded(digest));
+                    }
+
+                    let stale = std::fs::metadata(&path)
+                        .and_then(|m| m.modified())
+                        .ok()
+                        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                        .is_some_and(|age| age > STALE_LOCK);
+                    if stale {
+                        let _ = std::fs::remove_file(&path);
+                    } else {
+                        std::thread::sleep(LOCK_POLL_INTERVAL);
+                    }
+                }
+                Err(e) => return Err(FetchError::Io(path, e)),
+            }
+        }
+    }
+
+    /// Downloads `url` into the cache, returning the digest it is stored under.
+    fn download(
+        &self,
+        url: &str,
+        url_key: &str,
+        declared: Option<&str>,
+    ) -> Result<String, FetchError> {
+        let lock = match self.lock(url_key, declared)? {
+            Lock::Acquired(guard) => guard,
+            Lock::Downloaded(digest) => return Ok(digest),
+        };
+        // Someone else may have finished the download right before the lock was taken.
+        if let Some(digest) = self.cached_digest(url_key, declared) {
+            return Ok(digest);
+        }
+
+        let blobs = self.dir.join("sha256");
+        std::fs::create_dir_all(&blobs).map_err(Self::io_err(&blobs))?;
+        let mut tmp = tempfile::NamedTempFile::new_in(&blobs).map_err(Self::io_err(&blobs))?;
+
+        let response = ureq::get(&http_url(url))
+            .call()
+            .map_err(|e| FetchError::Http(url.to_string(), Box::new(e)))?;
+        let mut reader = response.into_reader();
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0u8; 1 << 16];
+        let mut refreshed = Instant::now();
+        loop {
+            if refreshed.elapsed() > LOCK_REFRESH {
+                lock.refresh()?;
+                refreshed = Instant::now();
+            }
+            let read = reader.read(&mut buf).map_err(Self::io_err(tmp.path()))?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buf[..read]);
+            tmp.write_all(&buf[..read])
+                .map_err(Self::io_err(tmp.path()))?;
+        }
+
+        let digest = hex(&hasher.finalize());
+        if let Some(expected) = declared {
+            if !expected.eq_ignore_ascii_case(&digest) {
+                return Err(FetchError::ChecksumMismatch {
+                    url: url.to_string(),
+                    expected: expected.to_lowercase(),
+                    actual: digest,
+                });
+            }
+        }
+
+        // Files are only ever renamed into place, so nobody reads a partially downloaded file.
+        let blob = self.blob(&digest);
+        tmp.persist(&blob)
+            .map_err(|e| FetchError::Io(blob.clone(), e.error))?;
+
+        let urls = self.dir.join("urls");
+        std::fs::create_dir_all(&urls).map_err(Self::io_err(&urls))?;
+        let record = tempfile::NamedTempFile::new_in(&urls).map_err(Self::io_err(&urls))?;
+        std::fs::write(record.path(), &digest).map_err(Self::io_err(record.path()))?;
+        let record_path = urls.join(url_key);
+        record
+            .persist(&record_path)
+            .map_err(|e| FetchError::Io(record_path, e.error))?;
+
+        Ok(digest)
+    }
+}
+
+/// Fetches every declared input with a URL. All the failures are reported at once, instead of failing on the first one.
+pub fn fetch_inputs(
+    inputs: &HashMap<PathBuf, InputConfig>,
+    cache: &InputCache,
+) -> Result<(), Vec<FetchError>> {
+    let errors: Vec<FetchError> = inputs
+        .iter()
+        .filter_map(|(path, input)| {
+            let url = input.url.as_deref()?;
+            cache.fetch(path, url, input.sha256.as_deref()).err()
+        })
+        .collect();
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_http_url() {
+        assert_eq!(
+            http_url("s3://bucket/path/to/file.tif"),
+            "https://bucket.s3.amazonaws.com/path/to/file.tif"
+        );
+        assert_eq!(
+            http_url("https://example.com/a.tif"),
+            "https://example.com/a.tif"
+        );
+    }
+
+    #[test]
+    fn test_fetch_from_cache() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cache = InputCache {
+            dir: cache_dir.path().to_path_buf(),
+        };
+
+        let digest = hex(&Sha256::digest(b"pythia"));
+        std::fs::create_dir_all(cache_dir.path().join("sha256")).unwrap();
+        std::fs::write(cache.blob(&digest), b"pythia").unwrap();
+
+        // Cached files are placed without downloading anything (the URL doesn't resolve).
+        let out = tempfile::tempdir().unwrap();
+        let path = out.path().join("data").join("input.tif");
+        cache
+            .fetch(&path, "http://pythia.invalid/input.tif", Some(&digest))
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"pythia");
+
+        // A file that no longer matches its checksum is placed again.
+        std::fs::remove_file(&path).unwrap();
+        std::fs::write(&path, b"truncated").unwrap();
+        cache
+            .fetch(&path, "http://pythia.invalid/input.tif", Some(&digest))
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"pythia");
+
+        // A lock held by someone else is given up on once the file shows up in the cache.
+        let url_key = hex(&Sha256::digest(b"http://pythia.invalid/input.tif"));
+        std::fs::create_dir_all(cache_dir.path().join("locks")).unwrap();
+        std::fs::write(
+            cache_dir
+                .path()
+                .join("locks")
+                .join(format!("{}.lock", url_key)),
+            b"",
+        )
+        .unwrap();
+        assert!(matches!(
+            cache.lock(&url_key, Some(&digest)).unwrap(),
+            Lock::Downloaded(d) if d == digest
+        ));
+    }
+}
@@ -0,0 +1,189 @@
+//! Comparison of every run against a baseline run, site by site, on the end of season values harvested by the
+//! `std:collect` stage.
+
+use super::daily::DailyRecord;
+use super::summary::SummaryRecord;
+use super::HarvestError;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// The run the other runs are compared against, and the variables compared.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct BaselineConfig {
+    pub run: String,
+
+    /// Variables compared, either harvested columns or derived metrics (e.g. `HWAM`, `CWAD`, `water_stress_index`).
+    pub variables: Vec<String>,
+
+    /// CSV file the anomalies are written to, relative to the working directory. Defaults to `anomalies.csv`.
+    #[serde(default = "default_output")]
+    pub output: PathBuf,
+}
+
+fn default_output() -> PathBuf {
+    PathBuf::from("anomalies.csv")
+}
+
+/// The difference between the value of a variable of a run and the baseline, at the end of a season of a site.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Anomaly {
+    pub site: i32,
+    pub run: String,
+    pub season: u32,
+    pub variable: String,
+    pub baseline: f64,
+    pub value: f64,
+    /// `value - baseline`.
+    pub delta: f64,
+    /// `delta` as a percentage of `baseline`. Not set when the baseline is 0.
+    pub percent: Option<f64>,
+}
+
+/// End of season value of the compared variables, keyed by site, run, season and variable, as the records come.
+pub struct EndOfSeason {
+    variables: Vec<String>,
+    values: HashMap<(i32, String, u32, String), (NaiveDate, f64)>,
+}
+
+impl EndOfSeason {
+    pub fn new(config: &BaselineConfig) -> Self {
+        Self {
+            variables: config.variables.clone(),
+            values: HashMap::new(),
+        }
+    }
+
+    /// Keeps `value` if `variable` is compared, and it is the latest one of its season so far.
+    fn add(&mut self, key: (i32, &str, u32, &str), date: NaiveDate, value: f64) {
+        let (site, run, season, variable) = key;
+        if !self.variables.iter().any(|v| v == variable) {
+            return;
+        }
+        self.values
+            .entry((site, run.to_string(), season, variable.to_string()))
+            .and_modify(|v| {
+                if date >= v.0 {
+                    *v = (date, value)
+                }
+            })
+            .or_insert((date, value));
+    }
+
+    pub fn add_daily(&mut self, r: &DailyRecord) {
+        let key = (r.site, r.run.as_str(), r.season, r.variable.as_str());
+        self.add(key, r.date, r.value);
+    }
+
+    /// Adds `r` if its value is a number. End of season outputs hold the final values of their season, so they take
+    /// over the daily ones.
+    pub fn add_summary(&mut self, r: &SummaryRecord) {
+        if let Ok(value) = r.value.parse() {
+            let key = (r.site, r.run.as_str(), r.season, r.variable.as_str());
+            self.add(key, NaiveDate::MAX, value);
+        }
+    }
+}
+
+/// Compares the end of season value of the compared variables of every run against the baseline run. Sites, seasons
+/// or variables the baseline has no value for are left out.
+pub fn compare(config: &BaselineConfig, values: &EndOfSeason) -> Vec<Anomaly> {
+    let mut anomalies: Vec<Anomaly> = values
+        .values
+        .iter()
+        .filter(|((_, run, _, _), _)| *run != config.run)
+        .filter_map(|((site, run, season, variable), &(_, value))| {
+            let key = (*site, config.run.clone(), *season, variable.clone());
+            let &(_, baseline) = values.values.get(&key)?;
+            let delta = value - baseline;
+            Some(Anomaly {
+                site: *site,
+                run: run.clone(),
+                season: *season,
+                variable: variable.clone(),
+                baseline,
+                value,
+                delta,
+                percent: (baseline != 0.0).then(|| delta / baseline * 100.0),
+            })
+        })
+        .collect();
+
+    anomalies.sort_by(|a, b| {
+        (a.site, &a.run, a.season, &a.variable).cmp(&(b.site, &b.run, b.season, &b.variable))
+    });
+    anomalies
+}
+
+/// Writes `anomalies` as CSV, with the columns `site`, `run`, `season`, `variable`, `baseline`, `value`, `delta` and `percent`.
+pub fn write(anomalies: &[Anomaly], writer: impl Write) -> Result<(), HarvestError> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for anomaly in anomalies {
+        writer.serialize(anomaly)?;
+    }
+    writer.flush().map_err(csv::Error::from)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(run: &str, day: u32, variable: &str, value: f64) -> DailyRecord {
+        DailyRecord {
+            site: 1,
+            run: run.to_string(),
+            season: 1,
+            date: NaiveDate::from_yo_opt(2020, day).unwrap(),
+            variable: variable.to_string(),
+            value,
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let config = BaselineConfig {
+            run: "rainfed".to_string(),
+            variables: vec!["CWAD".to_string(), "LAID".to_string()],
+            output: default_output(),
+        };
+        let records = [
+            record("rainfed", 1, "CWAD", 10.0),
+            record("rainfed", 2, "CWAD", 100.0),
+            record("rainfed", 2, "LAID", 0.0),
+            record("rainfed", 2, "HWAM", 5.0),
+            record("irrigated", 2, "CWAD", 150.0),
+            record("irrigated", 1, "CWAD", 20.0),
+            record("irrigated", 2, "LAID", 1.0),
+            record("irrigated", 2, "HWAM", 6.0),
+        ];
+
+        let mut values = EndOfSeason::new(&config);
+        records.iter().for_each(|r| values.add_daily(r));
+        let anomalies = compare(&config, &values);
+        assert_eq!(anomalies.len(), 2);
+        assert_eq!(anomalies[0].variable, "CWAD");
+        assert_eq!(anomalies[0].baseline, 100.0);
+        assert_eq!(anomalies[0].delta, 50.0);
+        assert_eq!(anomalies[0].percent, Some(50.0));
+        assert_eq!(anomalies[1].variable, "LAID");
+        assert_eq!(anomalies[1].percent, None);
+
+        // End of season outputs take over the daily ones.
+        let summary = |run: &str, value: &str| SummaryRecord {
+            site: 1,
+            run: run.to_string(),
+            season: 1,
+            variable: "CWAD".to_string(),
+            value: value.to_string(),
+        };
+        values.add_summary(&summary("rainfed", "200"));
+        values.add_summary(&summary("irrigated", "-"));
+        let anomalies = compare(&config, &values);
+        assert_eq!(anomalies[0].baseline, 200.0);
+        assert_eq!(anomalies[0].value, 150.0);
+    }
+}
@@ -0,0 +1,431 @@
+//! The `std:collect` stage, harvesting the outputs DSSAT wrote into the directory of every executed context into
+//! tables in the root of the working directory.
+
+use super::baseline::{self, BaselineConfig, EndOfSeason};
+use super::daily::{self, DailyOutputConfig, DailyRecord};
+use super::summary::{self, SummaryOutputConfig, SummaryRecord};
+use super::HarvestError;
+use crate::processing::checkpoint::{load_checkpoint, CompletedContexts};
+use crate::processing::context::Context;
+use crate::processing::failure::FailedContext;
+use crate::processing::pipeline::{Executed, Stage, StageDriver};
+use crate::processing::processor::{track_context, ProcessorEnvironment};
+use crate::processing::report::StageStats;
+use serde::Deserialize;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::sync::mpmc::{Receiver, Sender};
+use std::sync::{Arc, LazyLock};
+use std::time::Instant;
+
+/// Options of [`DRIVER_COLLECT`].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CollectStageConfig {
+    #[serde(default)]
+    pub summary: SummaryOutputConfig,
+    /// Daily outputs to harvest as well, if any.
+    #[serde(default)]
+    pub daily: Option<DailyOutputConfig>,
+    /// Run to compare the other runs against once every context is harvested, if any.
+    #[serde(default)]
+    pub baseline: Option<BaselineConfig>,
+}
+
+/// Opens the CSV table at `path`. Resuming appends to the rows of the interrupted run, which already has the header,
+/// once the rows of the contexts it didn't complete are dropped (see [`prune`]).
+fn table(
+    path: &Path,
+    resume: Option<&CompletedContexts>,
+) -> Result<csv::Writer<File>, HarvestError> {
+    let append = resume.is_some() && path.exists();
+    if let (true, Some(completed)) = (append, resume) {
+        prune(path, completed)?;
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(!append)
+        .append(append)
+        .open(path)
+        .map_err(|e| HarvestError::Open(path.to_path_buf(), e))?;
+    Ok(csv::WriterBuilder::new()
+        .has_headers(!append)
+        .from_writer(file))
+}
+
+/// Rewrites the CSV table at `path` with only the rows of the `completed` contexts. The interrupted run may have
+/// harvested contexts it didn't get to checkpoint, which are processed again, and may have been killed halfway through
+/// a row.
+fn prune(path: &Path, completed: &CompletedContexts) -> Result<(), HarvestError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_path(path)
+        .map_err(|e| HarvestError::Io(path.to_path_buf(), e.into()))?;
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|h| h == name)
+            .ok_or_else(|| HarvestError::MissingColumn {
+                path: path.to_path_buf(),
+                column: name.to_string(),
+            })
+    };
+    let (site, run) = (column("site")?, column("run")?);
+
+    let pruned = path.with_extension("pruned");
+    let mut writer = csv::Writer::from_path(&pruned)
+        .map_err(|e| HarvestError::Open(pruned.clone(), e.into()))?;
+    writer.write_record(&headers)?;
+    let is_completed = |record: &csv::StringRecord| {
+        let site = record[site].parse::<i32>().ok()?;
+        completed.get(&record[run])?.contains(&site).then_some(())
+    };
+    for record in reader.records() {
+        let record = record?;
+        // A row cut short has fewer fields, or belongs to the context being harvested when the run was killed.
+        if record.len() == headers.len() && is_completed(&record).is_some() {
+            writer.write_record(&record)?;
+        }
+    }
+    writer.flush().map_err(csv::Error::from)?;
+    std::fs::rename(&pruned, path).map_err(|e| HarvestError::Open(path.to_path_buf(), e))
+}
+
+/// Harvests the outputs of every executed context (see [`SummaryOutputConfig`] and [`DailyOutputConfig`]) as it comes,
+/// and passes it on. The contexts whose outputs can't be harvested (e.g. DSSAT didn't write them) are sent to the
+/// dead-letter channel, and none of their records are written.
+///
+/// Once every context is harvested, the runs are compared against the baseline run, if any (see [`BaselineConfig`]).
+pub struct CollectStage {
+    pub workdir: PathBuf,
+    pub summary: SummaryOutputConfig,
+    pub daily: Option<DailyOutputConfig>,
+    pub baseline: Option<BaselineConfig>,
+    pub failures: Sender<FailedContext>,
+    pub stats: Arc<StageStats>,
+    /// The contexts completed by the interrupted run the run resumes, if any, whose rows the tables are added to
+    /// instead of replaced.
+    pub resume: Option<CompletedContexts>,
+}
+
+impl CollectStage {
+    /// Harvests the outputs of `ctx`.
+    fn harvest(
+        &self,
+        ctx: &Context,
+    ) -> Result<(Vec<SummaryRecord>, Vec<DailyRecord>), HarvestError> {
+        let (location, dir) = (ctx.location(), ctx.dir(&self.workdir));
+        let summaries = self.summary.harvest(&location, &dir)?;
+        let days = match &self.daily {
+            Some(daily) => daily.harvest(&location, &dir)?,
+            None => Vec::new(),
+        };
+        Ok((summaries, days))
+    }
+
+    /// Compares the runs of the harvested tables against `config`. The tables are read back rather than kept along
+    /// the way, so a resumed run compares the contexts harvested before it was interrupted too.
+    fn compare(&self, config: &BaselineConfig) -> Result<(), HarvestError> {
+        let read = |output: &Path| {
+            let path = self.workdir.join(output);
+            let file = File::open(&path).map_err(|e| HarvestError::Io(path, e))?;
+            Ok::<_, HarvestError>(csv::Reader::from_reader(file))
+        };
+
+        let mut values = EndOfSeason::new(config);
+        if let Some(daily) = &self.daily {
+            for record in read(&daily.output)?.deserialize() {
+                values.add_daily(&record?);
+            }
+        }
+        for record in read(&self.summary.output)?.deserialize() {
+            values.add_summary(&record?);
+        }
+
+        let path = self.workdir.join(&config.output);
+        let file = File::create(&path).map_err(|e| HarvestError::Open(path, e))?;
+        baseline::write(&baseline::compare(config, &values), file)
+    }
+}
+
+impl Stage for CollectStage {
+    type Input = Executed;
+    type Output = Executed;
+
+    fn conduct(
+        &self,
+        tx: &Sender<Executed>,
+        rx: &Receiver<Executed>,
+    ) -> Result<(), Box<dyn Error + Send>> {
+        let fatal = |err: HarvestError| Box::new(err) as Box<dyn Error + Send>;
+        let mut summaries = table(
+            &self.workdir.join(&self.summary.output),
+            self.resume.as_ref(),
+        )
+        .map_err(fatal)?;
+        let mut days = self
+            .daily
+            .as_ref()
+            .map(|daily| table(&self.workdir.join(&daily.output), self.resume.as_ref()))
+            .transpose()
+            .map_err(fatal)?;
+
+        for Executed(ctx) in rx.iter() {
+            track_context(&ctx);
+            let started = Instant::now();
+            match self.harvest(&ctx) {
+                Ok((summary_records, daily_records)) => {
+                    // The context may be checkpointed as soon as it is passed on, so its rows are flushed right
+                    // away for a resumed run to keep them.
+                    let flushed = |err: std::io::Error| fatal(HarvestError::Csv(err.into()));
+                    summary::write(&summary_records, &mut summaries).map_err(fatal)?;
+                    summaries.flush().map_err(flushed)?;
+                    if let Some(days) = days.as_mut() {
+                        daily::write(&daily_records, days).map_err(fatal)?;
+                        days.flush().map_err(flushed)?;
+                    }
+                    self.stats.record(Ok(()), started.elapsed());
+                    tx.send(Executed(ctx))
+                        .map_err(|err| Box::new(err) as Box<dyn Error + Send>)?;
+                }
+                Err(err) => {
+                    self.stats.record(Err(err.class()), started.elapsed());
+                    self.failures
+                        .send(FailedContext::new(&ctx.location(), 1, &err))
+                        .map_err(|err| Box::new(err) as Box<dyn Error + Send>)?;
+                }
+            }
+        }
+
+        if let Some(config) = &self.baseline {
+            self.compare(config).map_err(fatal)?;
+        }
+        Ok(())
+    }
+}
+
+/// Harvests the outputs of the executed contexts, see [`CollectStage`].
+pub const DRIVER_COLLECT: LazyLock<StageDriver<CollectStage, CollectStageConfig>> =
+    LazyLock::new(|| StageDriver {
+        create: Arc::new(|c: CollectStageConfig, env: &ProcessorEnvironment| {
+            if let Some(baseline) = &c.baseline {
+                if !env.config.runs.iter().any(|run| run.name == baseline.run) {
+                    return Err(format!(
+                        "Baseline run {} is not a run of the config",
+                        baseline.run
+                    )
+                    .into());
+                }
+            }
+            Ok(CollectStage {
+                workdir: env.workdir.to_path_buf(),
+                summary: c.summary,
+                daily: c.daily,
+                baseline: c.baseline,
+                failures: env.failures.clone(),
+                stats: env.stats.clone(),
+                resume: match env.resume {
+                    true => Some(load_checkpoint(env.workdir)?),
+                    false => None,
+                },
+            })
+        }),
+        config_deserializer: Arc::new(serde_json::from_value),
+    });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::GeoDeg;
+    use std::sync::mpmc::channel;
+
+    const SUMMARY: &str = "*SUMMARY : PYTH0001MZ\n\
+        @   RUNNO   TRNO    HWAM\n       \
+        1      1    5123\n";
+
+    const PLANTGRO: &str = "*RUN   1        : Rainfed\n\
+        @YEAR DOY   DAS   LAID\n \
+        2020 001     0   0.00\n \
+        2020 002     1   0.01\n";
+
+    /// A context of run `maize` at site `id`, in its own directory.
+    fn context(id: i32) -> Context {
+        let mut ctx = Context::for_test("maize", id);
+        ctx.site.lon = GeoDeg::from(id as f64);
+        ctx
+    }
+
+    #[test]
+    fn test_collect() {
+        let dir = tempfile::tempdir().unwrap();
+        let workdir = dir.path().to_path_buf();
+        let (tx_failures, failures) = channel();
+        let stage = CollectStage {
+            workdir: workdir.clone(),
+            summary: Default::default(),
+            daily: Some(
+                serde_json::from_str(r#"{ "files": [{ "file": "PlantGro.OUT" }] }"#).unwrap(),
+            ),
+            baseline: None,
+            failures: tx_failures,
+            stats: Default::default(),
+            resume: None,
+        };
+
+        // DSSAT wrote every output of the first context, and no daily output of the second.
+        let contexts = [context(1), context(2)];
+        for ctx in &contexts {
+            std::fs::create_dir_all(ctx.dir(&workdir)).unwrap();
+            std::fs::write(ctx.dir(&workdir).join("Summary.OUT"), SUMMARY).unwrap();
+        }
+        std::fs::write(contexts[0].dir(&workdir).join("PlantGro.OUT"), PLANTGRO).unwrap();
+
+        let (tx, rx) = channel();
+        for ctx in contexts {
+            tx.send(Executed(ctx)).unwrap();
+        }
+        drop(tx);
+        let (tx_out, rx_out) = channel();
+        stage.conduct(&tx_out, &rx).unwrap();
+        drop(tx_out);
+
+        let collected: Vec<i32> = rx_out.iter().map(|Executed(ctx)| ctx.site.id).collect();
+        assert_eq!(collected, [1]);
+        assert_eq!(failures.try_recv().unwrap().site, 2);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("summary.csv")).unwrap(),
+            "site,run,season,variable,value\n\
+            1,maize,1,RUNNO,1\n\
+            1,maize,1,TRNO,1\n\
+            1,maize,1,HWAM,5123\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("daily.csv")).unwrap(),
+            "site,run,season,date,variable,value\n\
+            1,maize,1,2020-01-01,DAS,0.0\n\
+            1,maize,1,2020-01-01,LAID,0.0\n\
+            1,maize,1,2020-01-02,DAS,1.0\n\
+            1,maize,1,2020-01-02,LAID,0.01\n"
+        );
+    }
+
+    #[test]
+    fn test_collect_resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let workdir = dir.path().to_path_buf();
+        // The interrupted run harvested sites 1 and 2, only checkpointed site 1, and was killed halfway through a row.
+        std::fs::write(
+            dir.path().join("summary.csv"),
+            "site,run,season,variable,value\n\
+            1,maize,1,HWAM,4000\n\
+            2,maize,1,HWAM,4100\n\
+            2,maize,1,HW",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path()
+                .join(crate::processing::checkpoint::CHECKPOINT_FILE_NAME),
+            "maize,1\n",
+        )
+        .unwrap();
+        let stage = CollectStage {
+            workdir: workdir.clone(),
+            summary: serde_json::from_str(r#"{ "variables": ["HWAM"] }"#).unwrap(),
+            daily: None,
+            baseline: None,
+            failures: channel().0,
+            stats: Default::default(),
+            resume: Some(load_checkpoint(&workdir).unwrap()),
+        };
+
+        let ctx = context(2);
+        std::fs::create_dir_all(ctx.dir(&workdir)).unwrap();
+        std::fs::write(ctx.dir(&workdir).join("Summary.OUT"), SUMMARY).unwrap();
+        let (tx, rx) = channel();
+        tx.send(Executed(ctx)).unwrap();
+        drop(tx);
+        stage.conduct(&channel().0, &rx).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("summary.csv")).unwrap(),
+            "site,run,season,variable,value\n\
+            1,maize,1,HWAM,4000\n\
+            2,maize,1,HWAM,5123\n"
+        );
+    }
+
+    #[test]
+    fn test_collect_metrics() {
+        let dir = tempfile::tempdir().unwrap();
+        let workdir = dir.path().to_path_buf();
+        let daily = r#"{
+            "files": [{ "file": "PlantGro.OUT", "variables": ["LAID"], "metrics": [{ "metric": "season_length" }] }],
+            "output": "plantgro.csv"
+        }"#;
+        let stage = CollectStage {
+            workdir: workdir.clone(),
+            summary: Default::default(),
+            daily: Some(serde_json::from_str(daily).unwrap()),
+            baseline: None,
+            failures: channel().0,
+            stats: Default::default(),
+            resume: None,
+        };
+
+        let ctx = context(1);
+        std::fs::create_dir_all(ctx.dir(&workdir)).unwrap();
+        std::fs::write(ctx.dir(&workdir).join("Summary.OUT"), SUMMARY).unwrap();
+        std::fs::write(ctx.dir(&workdir).join("PlantGro.OUT"), PLANTGRO).unwrap();
+
+        let (tx, rx) = channel();
+        tx.send(Executed(ctx)).unwrap();
+        drop(tx);
+        stage.conduct(&channel().0, &rx).unwrap();
+
+        // The metrics of every season are emitted alongside the harvested columns, dated at its last day.
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("plantgro.csv")).unwrap(),
+            "site,run,season,date,variable,value\n\
+            1,maize,1,2020-01-01,LAID,0.0\n\
+            1,maize,1,2020-01-02,LAID,0.01\n\
+            1,maize,1,2020-01-02,season_length,2.0\n"
+        );
+    }
+
+    #[test]
+    fn test_collect_baseline() {
+        let dir = tempfile::tempdir().unwrap();
+        let workdir = dir.path().to_path_buf();
+        let baseline = r#"{ "run": "rainfed", "variables": ["HWAM"] }"#;
+        let stage = CollectStage {
+            workdir: workdir.clone(),
+            summary: Default::default(),
+            daily: None,
+            baseline: Some(serde_json::from_str(baseline).unwrap()),
+            failures: channel().0,
+            stats: Default::default(),
+            resume: None,
+        };
+
+        let (tx, rx) = channel();
+        for (run, yield_) in [("rainfed", "4000"), ("irrigated", "5000")] {
+            let mut ctx = context(1);
+            ctx.run.name = run.to_string();
+            std::fs::create_dir_all(ctx.dir(&workdir)).unwrap();
+            let summary = SUMMARY.replace("5123", yield_);
+            std::fs::write(ctx.dir(&workdir).join("Summary.OUT"), summary).unwrap();
+            tx.send(Executed(ctx)).unwrap();
+        }
+        drop(tx);
+        stage.conduct(&channel().0, &rx).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("anomalies.csv")).unwrap(),
+            "site,run,season,variable,baseline,value,delta,percent\n\
+            1,irrigated,1,HWAM,4000.0,5000.0,1000.0,25.0\n"
+        );
+    }
+}
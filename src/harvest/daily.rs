@@ -0,0 +1,379 @@
+//! Parser for daily DSSAT outputs (e.g. `PlantGro.OUT`, `SoilWat.OUT`), harvested into long-format tables with one
+//! row per site, run, date and variable.
+
+use super::metrics::DerivedMetric;
+use super::HarvestError;
+use crate::processing::context::ContextLocation;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Value DSSAT writes for missing data.
+const MISSING: f64 = -99.0;
+
+/// A daily output file of every context directory.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DailyOutputFile {
+    /// File name, e.g. `PlantGro.OUT`.
+    pub file: String,
+
+    /// Columns to harvest, as in the header of the file (e.g. `LAID`, `CWAD`). If empty, every column is harvested.
+    #[serde(default)]
+    pub variables: Vec<String>,
+
+    /// Metrics computed for every season, emitted alongside the harvested columns.
+    #[serde(default)]
+    pub metrics: Vec<DerivedMetric>,
+}
+
+/// Daily outputs to harvest from every context directory.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DailyOutputConfig {
+    pub files: Vec<DailyOutputFile>,
+
+    /// Keeps one day out of every `every` days of each season, always including the last one. 1 keeps every day.
+    #[serde(default = "default_every")]
+    pub every: usize,
+
+    /// CSV file the records are written to, relative to the working directory. Defaults to `daily.csv`.
+    #[serde(default = "default_output")]
+    pub output: PathBuf,
+}
+
+fn default_every() -> usize {
+    1
+}
+
+fn default_output() -> PathBuf {
+    PathBuf::from("daily.csv")
+}
+
+/// A row of the long-format table.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DailyRecord {
+    pub site: i32,
+    pub run: String,
+    /// DSSAT run number (`*RUN`) within the output file, i.e. the season or treatment.
+    pub season: u32,
+    pub date: NaiveDate,
+    pub variable: String,
+    pub value: f64,
+}
+
+/// The table of a single season of a daily output file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DailyTable {
+    pub season: u32,
+    /// Column names, without `YEAR` and `DOY`.
+    pub columns: Vec<String>,
+    /// One row per day. Values that aren't numbers, or are missing (-99), are `None`.
+    pub rows: Vec<(NaiveDate, Vec<Option<f64>>)>,
+}
+
+/// Parses every season of the contents of a daily output file. Line endings are expected to be normalized to LF.
+pub fn parse(contents: &str, path: &Path) -> Result<Vec<DailyTable>, HarvestError> {
+    let malformed = |line: usize, message: String| HarvestError::Malformed {
+        path: path.to_path_buf(),
+        line: line + 1,
+        message,
+    };
+
+    let mut tables = Vec::new();
+    let mut season = 0;
+    // The current table, along with the positions of YEAR and DOY in its header.
+    let mut current: Option<(DailyTable, usize, usize)> = None;
+
+    for (i, line) in contents.lines().enumerate() {
+        if let Some(run) = line.strip_prefix("*RUN") {
+            tables.extend(current.take().map(|(table, _, _)| table));
+            season = run
+                .split_whitespace()
+                .next()
+                .and_then(|n| n.parse().ok())
+                .ok_or_else(|| malformed(i, "Invalid run number".to_string()))?;
+        } else if let Some(header) = line.strip_prefix('@') {
+            tables.extend(current.take().map(|(table, _, _)| table));
+            let header: Vec<&str> = header.split_whitespace().collect();
+            let position = |name: &str| {
+                header
+                    .iter()
+                    .position(|c| *c == name)
+                    .ok_or_else(|| malformed(i, format!("Header has no {} column", name)))
+            };
+            let (year, doy) = (position("YEAR")?, position("DOY")?);
+            let columns = header
+                .iter()
+                .enumerate()
+                .filter(|(idx, _)| *idx != year && *idx != doy)
+                .map(|(_, c)| c.to_string())
+                .collect();
+            current = Some((
+                DailyTable {
+                    season,
+                    columns,
+                    rows: Vec::new(),
+                },
+                year,
+                doy,
+            ));
+        } else if let Some((table, year, doy)) = current.as_mut() {
+            let (year, doy) = (*year, *doy);
+            if line.trim().is_empty() || line.starts_with(['*', '!']) {
+                continue;
+            }
+
+            let values: Vec<&str> = line.split_whitespace().collect();
+            if values.len() != table.columns.len() + 2 {
+                return Err(malformed(
+                    i,
+                    format!(
+                        "Expected {} values, found {}",
+                        table.columns.len() + 2,
+                        values.len()
+                    ),
+                ));
+            }
+
+            let date = values[year]
+                .parse()
+                .ok()
+                .zip(values[doy].parse().ok())
+                .and_then(|(year, doy)| NaiveDate::from_yo_opt(year, doy))
+                .ok_or_else(|| {
+                    malformed(i, format!("Invalid date {} {}", values[year], values[doy]))
+                })?;
+            let row = values
+                .iter()
+                .enumerate()
+                .filter(|(idx, _)| *idx != year && *idx != doy)
+                .map(|(_, v)| v.parse::<f64>().ok().filter(|v| *v != MISSING))
+                .collect();
+            table.rows.push((date, row));
+        }
+    }
+    tables.extend(current.map(|(table, _, _)| table));
+
+    Ok(tables)
+}
+
+impl DailyOutputConfig {
+    /// Harvests the daily outputs of the context at `location`, whose directory is `dir`.
+    pub fn harvest(
+        &self,
+        location: &ContextLocation,
+        dir: &Path,
+    ) -> Result<Vec<DailyRecord>, HarvestError> {
+        let mut records = Vec::new();
+        for output in &self.files {
+            let path = dir.join(&output.file);
+            let bytes = std::fs::read(&path).map_err(|e| HarvestError::Io(path.clone(), e))?;
+            let contents = crate::utils::text::normalize(&String::from_utf8_lossy(&bytes));
+
+            for table in parse(&contents, &path)? {
+                records.extend(self.select(location, &table, &output.variables));
+                records.extend(derive(location, &table, &output.metrics, &path)?);
+            }
+        }
+        Ok(records)
+    }
+
+    /// Turns the rows of `table` into records of the selected `variables`, keeping one day out of every [`DailyOutputConfig::every`].
+    fn select(
+        &self,
+        location: &ContextLocation,
+        table: &DailyTable,
+        variables: &[String],
+    ) -> Vec<DailyRecord> {
+        let columns: Vec<(usize, &String)> = table
+            .columns
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| variables.is_empty() || variables.contains(*c))
+            .collect();
+        let every = self.every.max(1);
+        let last = table.rows.len().saturating_sub(1);
+
+        table
+            .rows
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| i % every == 0 || *i == last)
+            .flat_map(|(_, (date, values))| {
+                columns.iter().filter_map(move |(idx, column)| {
+                    Some(DailyRecord {
+                        site: location.site_id,
+                        run: location.run.clone(),
+                        season: table.season,
+                        date: *date,
+                        variable: column.to_string(),
+                        value: values[*idx]?,
+                    })
+                })
+            })
+            .collect()
+    }
+}
+
+/// Computes `metrics` over `table`, as records dated at its last day.
+fn derive(
+    location: &ContextLocation,
+    table: &DailyTable,
+    metrics: &[DerivedMetric],
+    path: &Path,
+) -> Result<Vec<DailyRecord>, HarvestError> {
+    let Some((last, _)) = table.rows.last() else {
+        return Ok(Vec::new());
+    };
+
+    let mut records = Vec::new();
+    for metric in metrics {
+        if let Some(variable) = metric.variable() {
+            if !table.columns.iter().any(|c| c == variable) {
+                return Err(HarvestError::MissingColumn {
+                    path: path.to_path_buf(),
+                    column: variable.to_string(),
+                });
+            }
+        }
+
+        if let Some(value) = metric.compute(table) {
+            records.push(DailyRecord {
+                site: location.site_id,
+                run: location.run.clone(),
+                season: table.season,
+                date: *last,
+                variable: metric.name().to_string(),
+                value,
+            });
+        }
+    }
+    Ok(records)
+}
+
+/// Writes `records` as CSV rows, with the columns `site`, `run`, `season`, `date`, `variable` and `value`.
+pub fn write<W: Write>(
+    records: &[DailyRecord],
+    writer: &mut csv::Writer<W>,
+) -> Result<(), HarvestError> {
+    for record in records {
+        writer.serialize(record)?;
+    }
+    writer.flush().map_err(csv::Error::from)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::GeoDeg;
+    use chrono::Datelike;
+
+    const PLANTGRO: &str = "*DSSAT Cropping System Model Ver. 4.8.2.000\n\
+        \n\
+        *RUN   1        : Rainfed                  MZCER048 PYTH0001 1\n \
+        MODEL          : MZCER048 - Maize\n\
+        \n\
+        @YEAR DOY   DAS   DAP   LAID   CWAD\n \
+        2020 001     0     0   0.00      0\n \
+        2020 002     1     1   0.01    -99\n \
+        2020 003     2     2   0.02     12\n\
+        \n\
+        *RUN   2        : Irrigated                MZCER048 PYTH0001 2\n\
+        \n\
+        @YEAR DOY   DAS   DAP   LAID   CWAD\n \
+        2020 001     0     0   0.00      0\n";
+
+    fn location() -> ContextLocation {
+        ContextLocation {
+            run: "maize".to_string(),
+            site_id: 7,
+            lon: GeoDeg::from(1.0),
+            lat: GeoDeg::from(2.0),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let tables = parse(PLANTGRO, Path::new("PlantGro.OUT")).unwrap();
+        assert_eq!(tables.len(), 2);
+        assert_eq!(tables[0].season, 1);
+        assert_eq!(tables[0].columns, ["DAS", "DAP", "LAID", "CWAD"]);
+        assert_eq!(tables[0].rows.len(), 3);
+        assert_eq!(
+            tables[0].rows[1].0,
+            NaiveDate::from_ymd_opt(2020, 1, 2).unwrap()
+        );
+        assert_eq!(tables[0].rows[1].1[3], None);
+        assert_eq!(tables[1].season, 2);
+
+        let truncated = "@YEAR DOY LAID\n 2020 001\n";
+        assert!(parse(truncated, Path::new("PlantGro.OUT")).is_err());
+    }
+
+    #[test]
+    fn test_derive() {
+        let tables = parse(PLANTGRO, Path::new("PlantGro.OUT")).unwrap();
+        let path = Path::new("PlantGro.OUT");
+
+        let records = derive(
+            &location(),
+            &tables[0],
+            &[DerivedMetric::SeasonLength],
+            path,
+        )
+        .unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].variable, "season_length");
+        assert_eq!(records[0].value, 3.0);
+        assert_eq!(
+            records[0].date,
+            NaiveDate::from_ymd_opt(2020, 1, 3).unwrap()
+        );
+
+        let stress = DerivedMetric::WaterStressIndex {
+            variable: "WSPD".to_string(),
+        };
+        assert!(derive(&location(), &tables[0], &[stress], path).is_err());
+    }
+
+    #[test]
+    fn test_select() {
+        let tables = parse(PLANTGRO, Path::new("PlantGro.OUT")).unwrap();
+        let config = DailyOutputConfig {
+            files: vec![],
+            every: 2,
+            output: default_output(),
+        };
+
+        let records = config.select(&location(), &tables[0], &["LAID".to_string()]);
+        let days: Vec<u32> = records.iter().map(|r| r.date.ordinal()).collect();
+        assert_eq!(days, [1, 3]);
+        assert_eq!(records[1].value, 0.02);
+        assert_eq!(records[1].site, 7);
+
+        // Missing values are left out.
+        let records = config.select(&location(), &tables[0], &["CWAD".to_string()]);
+        assert_eq!(records.len(), 2);
+    }
+
+    #[test]
+    fn test_write() {
+        let tables = parse(PLANTGRO, Path::new("PlantGro.OUT")).unwrap();
+        let config = DailyOutputConfig {
+            files: vec![],
+            every: 1,
+            output: default_output(),
+        };
+        let records = config.select(&location(), &tables[1], &["LAID".to_string()]);
+
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        write(&records, &mut writer).unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+            "site,run,season,date,variable,value\n7,maize,2,2020-01-01,LAID,0.0\n"
+        );
+    }
+}
@@ -0,0 +1,115 @@
+//! Metrics derived from the daily outputs of a season, computed by the `std:collect` stage while harvesting them so
+//! they don't need another pass over the (much larger) long-format tables. See [`super::daily::DailyOutputFile`].
+
+use super::daily::DailyTable;
+use serde::{Deserialize, Serialize};
+
+/// A metric computed for every season of a daily output file, emitted as a variable named after it (e.g.
+/// `water_stress_index`) dated at the last day of the season.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "metric", rename_all = "snake_case", deny_unknown_fields)]
+pub enum DerivedMetric {
+    /// Mean of a daily stress factor over the season, e.g. `WSPD` of `PlantGro.OUT` (0 is no stress, 1 is full stress).
+    WaterStressIndex {
+        #[serde(default = "default_stress_variable")]
+        variable: String,
+    },
+    /// Sum of a daily evapotranspiration column over the season (mm), e.g. `ETAA` of `ET.OUT`.
+    CumulativeEt {
+        #[serde(default = "default_et_variable")]
+        variable: String,
+    },
+    /// Number of days of the season, from the first to the last day of the table.
+    SeasonLength,
+}
+
+fn default_stress_variable() -> String {
+    "WSPD".to_string()
+}
+
+fn default_et_variable() -> String {
+    "ETAA".to_string()
+}
+
+impl DerivedMetric {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DerivedMetric::WaterStressIndex { .. } => "water_stress_index",
+            DerivedMetric::CumulativeEt { .. } => "cumulative_et",
+            DerivedMetric::SeasonLength => "season_length",
+        }
+    }
+
+    /// The column the metric is computed from, if any.
+    pub fn variable(&self) -> Option<&str> {
+        match self {
+            DerivedMetric::WaterStressIndex { variable }
+            | DerivedMetric::CumulativeEt { variable } => Some(variable.as_str()),
+            DerivedMetric::SeasonLength => None,
+        }
+    }
+
+    /// Computes the metric over `table`, or `None` if the table has no (non-missing) values for it, including when
+    /// it doesn't have the column of the metric at all (see [`DerivedMetric::variable`]).
+    pub fn compute(&self, table: &DailyTable) -> Option<f64> {
+        let values = || {
+            let column = table
+                .columns
+                .iter()
+                .position(|c| Some(c.as_str()) == self.variable());
+            table.rows.iter().filter_map(move |(_, row)| row[column?])
+        };
+
+        match self {
+            DerivedMetric::WaterStressIndex { .. } => {
+                let (sum, count) = values().fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+                (count > 0).then(|| sum / count as f64)
+            }
+            DerivedMetric::CumulativeEt { .. } => {
+                let mut values = values().peekable();
+                values.peek()?;
+                Some(values.sum())
+            }
+            DerivedMetric::SeasonLength => {
+                let (first, last) = (table.rows.first()?.0, table.rows.last()?.0);
+                Some(((last - first).num_days() + 1) as f64)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn table() -> DailyTable {
+        let day = |d| NaiveDate::from_yo_opt(2020, d).unwrap();
+        DailyTable {
+            season: 1,
+            columns: vec!["WSPD".to_string(), "ETAA".to_string()],
+            rows: vec![
+                (day(100), vec![Some(0.0), Some(1.5)]),
+                (day(101), vec![Some(0.5), None]),
+                (day(110), vec![Some(1.0), Some(2.5)]),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_compute() {
+        let stress: DerivedMetric =
+            serde_json::from_str(r#"{ "metric": "water_stress_index" }"#).unwrap();
+        assert_eq!(stress.compute(&table()), Some(0.5));
+
+        let et: DerivedMetric = serde_json::from_str(r#"{ "metric": "cumulative_et" }"#).unwrap();
+        assert_eq!(et.compute(&table()), Some(4.0));
+
+        assert_eq!(DerivedMetric::SeasonLength.compute(&table()), Some(11.0));
+
+        let missing = DerivedMetric::CumulativeEt {
+            variable: "EOAA".to_string(),
+        };
+        assert_eq!(missing.compute(&table()), None);
+    }
+}
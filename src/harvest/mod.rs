@@ -0,0 +1,42 @@
+//! Module _harvest_ collects the outputs DSSAT wrote into context directories into tables, on the `std:collect` stage
+//! of the pipeline (see [`collect::CollectStage`]) put after `std:exec`.
+
+pub mod baseline;
+pub mod collect;
+pub mod daily;
+pub mod metrics;
+pub mod summary;
+
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum HarvestError {
+    #[error("Failed to read {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("Failed to open {0} for writing: {1}")]
+    Open(PathBuf, std::io::Error),
+    #[error("{path}, line {line}: {message}")]
+    Malformed {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    #[error("{path} has no {column} column")]
+    MissingColumn { path: PathBuf, column: String },
+    #[error("Failed to write the harvested table: {0}")]
+    Csv(#[from] csv::Error),
+}
+
+impl HarvestError {
+    /// Short name of the kind of error, to tally the failures by in the run report.
+    pub fn class(&self) -> &'static str {
+        match self {
+            HarvestError::Io(..) => "harvest_io",
+            HarvestError::Open(..) => "harvest_open",
+            HarvestError::Malformed { .. } => "harvest_malformed",
+            HarvestError::MissingColumn { .. } => "harvest_missing_column",
+            HarvestError::Csv(_) => "harvest_csv",
+        }
+    }
+}
@@ -0,0 +1,270 @@
+//! Parser for end of season DSSAT outputs (`Summary.OUT`, `Evaluate.OUT`), harvested into long-format tables with one
+//! row per site, run, season and variable.
+
+use super::HarvestError;
+use crate::processing::context::ContextLocation;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Value DSSAT writes for missing data.
+const MISSING: f64 = -99.0;
+
+/// Columns holding the DSSAT run number, in `Summary.OUT` and `Evaluate.OUT` respectively.
+const RUN_NUMBER_COLUMNS: [&str; 2] = ["RUNNO", "RUN"];
+
+/// End of season outputs to harvest from every context directory.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SummaryOutputConfig {
+    /// File names, e.g. `Summary.OUT` and `Evaluate.OUT`.
+    #[serde(default = "default_files")]
+    pub files: Vec<String>,
+
+    /// Columns to harvest, as in the header of the files without their trailing dots (e.g. `HWAM`, `TNAM`). If empty,
+    /// every column is harvested.
+    #[serde(default)]
+    pub variables: Vec<String>,
+
+    /// CSV file the records are written to, relative to the working directory. Defaults to `summary.csv`.
+    #[serde(default = "default_output")]
+    pub output: PathBuf,
+}
+
+fn default_files() -> Vec<String> {
+    vec!["Summary.OUT".to_string()]
+}
+
+fn default_output() -> PathBuf {
+    PathBuf::from("summary.csv")
+}
+
+impl Default for SummaryOutputConfig {
+    fn default() -> Self {
+        Self {
+            files: default_files(),
+            variables: Vec::new(),
+            output: default_output(),
+        }
+    }
+}
+
+/// A row of the long-format table. Values are kept as written by DSSAT, as some are text (e.g. `TNAM`) or dates.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SummaryRecord {
+    pub site: i32,
+    pub run: String,
+    /// DSSAT run number (`RUNNO` or `RUN`) within the output file, i.e. the season or treatment.
+    pub season: u32,
+    pub variable: String,
+    pub value: String,
+}
+
+/// A row of an end of season output file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SummaryRow {
+    pub season: u32,
+    /// Column names and values, in header order. Missing values (-99) and blank fields are left out.
+    pub values: Vec<(String, String)>,
+}
+
+/// Spans of the columns of a fixed-width `header` (without its `@`), along with their names. Numbers are right
+/// aligned to the end of their header, and text left aligned to its start and padded with dots in the header, so
+/// every column spans from the end of the previous one to the end of its own header.
+fn columns(header: &str) -> Vec<(String, usize, usize)> {
+    let mut columns = Vec::new();
+    let mut start = 0;
+    let mut token_start = None;
+    for (i, c) in header.char_indices().chain([(header.len(), ' ')]) {
+        match (c.is_whitespace(), token_start) {
+            (false, None) => token_start = Some(i),
+            (true, Some(from)) => {
+                let name = header[from..i].trim_end_matches('.').to_string();
+                columns.push((name, start, i));
+                start = i;
+                token_start = None;
+            }
+            _ => {}
+        }
+    }
+    columns
+}
+
+/// Parses every row of the contents of an end of season output file. Line endings are expected to be normalized to
+/// LF.
+pub fn parse(contents: &str, path: &Path) -> Result<Vec<SummaryRow>, HarvestError> {
+    let malformed = |line: usize, message: String| HarvestError::Malformed {
+        path: path.to_path_buf(),
+        line: line + 1,
+        message,
+    };
+
+    let mut rows = Vec::new();
+    // The columns of the current header, along with the position of the run number among them.
+    let mut current: Option<(Vec<(String, usize, usize)>, usize)> = None;
+
+    for (i, line) in contents.lines().enumerate() {
+        if let Some(header) = line.strip_prefix('@') {
+            // Keeps the positions of the header in line with the ones of the rows.
+            let columns = columns(&format!(" {}", header));
+            let run_number = columns
+                .iter()
+                .position(|(name, _, _)| RUN_NUMBER_COLUMNS.contains(&name.as_str()))
+                .ok_or_else(|| {
+                    malformed(
+                        i,
+                        format!("Header has no {} column", RUN_NUMBER_COLUMNS.join(" or ")),
+                    )
+                })?;
+            current = Some((columns, run_number));
+        } else if let Some((columns, run_number)) = current.as_ref() {
+            if line.trim().is_empty() || line.starts_with(['*', '!']) {
+                continue;
+            }
+
+            let field = |from: usize, to: usize| {
+                line.get(from.min(line.len())..to.min(line.len()))
+                    .unwrap_or_default()
+                    .trim()
+            };
+            let (_, from, to) = &columns[*run_number];
+            let season = field(*from, *to).parse().map_err(|_| {
+                malformed(i, format!("Invalid run number \"{}\"", field(*from, *to)))
+            })?;
+            let values = columns
+                .iter()
+                .map(|(name, from, to)| (name, field(*from, *to)))
+                .filter(|(_, value)| {
+                    !value.is_empty() && value.parse::<f64>().ok() != Some(MISSING)
+                })
+                .map(|(name, value)| (name.clone(), value.to_string()))
+                .collect();
+            rows.push(SummaryRow { season, values });
+        }
+    }
+
+    Ok(rows)
+}
+
+impl SummaryOutputConfig {
+    /// Harvests the end of season outputs of the context at `location`, whose directory is `dir`.
+    pub fn harvest(
+        &self,
+        location: &ContextLocation,
+        dir: &Path,
+    ) -> Result<Vec<SummaryRecord>, HarvestError> {
+        let mut records = Vec::new();
+        for file in &self.files {
+            let path = dir.join(file);
+            let bytes = std::fs::read(&path).map_err(|e| HarvestError::Io(path.clone(), e))?;
+            let contents = crate::utils::text::normalize(&String::from_utf8_lossy(&bytes));
+
+            for row in parse(&contents, &path)? {
+                records.extend(self.select(location, &row));
+            }
+        }
+        Ok(records)
+    }
+
+    /// Turns `row` into records of the selected [`SummaryOutputConfig::variables`].
+    fn select(&self, location: &ContextLocation, row: &SummaryRow) -> Vec<SummaryRecord> {
+        row.values
+            .iter()
+            .filter(|(name, _)| self.variables.is_empty() || self.variables.contains(name))
+            .map(|(name, value)| SummaryRecord {
+                site: location.site_id,
+                run: location.run.clone(),
+                season: row.season,
+                variable: name.clone(),
+                value: value.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Writes `records` as CSV rows, with the columns `site`, `run`, `season`, `variable` and `value`.
+pub fn write<W: Write>(
+    records: &[SummaryRecord],
+    writer: &mut csv::Writer<W>,
+) -> Result<(), HarvestError> {
+    for record in records {
+        writer.serialize(record)?;
+    }
+    writer.flush().map_err(csv::Error::from)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::GeoDeg;
+
+    const SUMMARY: &str = "*SUMMARY : PYTH0001MZ DSSAT Cropping System Model Ver. 4.8.2.000\n\
+        \n\
+        !IDENTIFIERS......................\n\
+        @   RUNNO   TRNO R# O# P# CR TNAM..................... FNAM....    PDAT    HWAM  HWUM\n       \
+        1      1  0  0  1 MZ Rainfed maize              PYTH0001 2020120    5123 0.312\n       \
+        2      2  0  0  1 MZ Irrigated maize            PYTH0001 2020120     -99   -99\n";
+
+    fn location() -> ContextLocation {
+        ContextLocation {
+            run: "maize".to_string(),
+            site_id: 7,
+            lon: GeoDeg::from(1.0),
+            lat: GeoDeg::from(2.0),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let rows = parse(SUMMARY, Path::new("Summary.OUT")).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].season, 1);
+        let value = |row: &SummaryRow, name: &str| {
+            row.values
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.clone())
+        };
+        assert_eq!(value(&rows[0], "TNAM").as_deref(), Some("Rainfed maize"));
+        assert_eq!(value(&rows[0], "HWAM").as_deref(), Some("5123"));
+        assert_eq!(value(&rows[0], "PDAT").as_deref(), Some("2020120"));
+        assert_eq!(value(&rows[1], "TNAM").as_deref(), Some("Irrigated maize"));
+        assert_eq!(value(&rows[1], "HWAM"), None);
+
+        let no_run_number = "@TRNO HWAM\n 1 5123\n";
+        assert!(parse(no_run_number, Path::new("Summary.OUT")).is_err());
+    }
+
+    #[test]
+    fn test_harvest() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("Summary.OUT"),
+            SUMMARY.replace('\n', "\r\n"),
+        )
+        .unwrap();
+        let config: SummaryOutputConfig =
+            serde_json::from_str(r#"{ "variables": ["HWAM", "TNAM"] }"#).unwrap();
+        assert_eq!(config.output, Path::new("summary.csv"));
+
+        let records = config.harvest(&location(), dir.path()).unwrap();
+        assert_eq!(records.len(), 3);
+
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        write(&records, &mut writer).unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+            "site,run,season,variable,value\n\
+            7,maize,1,TNAM,Rainfed maize\n\
+            7,maize,1,HWAM,5123\n\
+            7,maize,2,TNAM,Irrigated maize\n"
+        );
+
+        let missing = SummaryOutputConfig {
+            files: vec!["Evaluate.OUT".to_string()],
+            ..Default::default()
+        };
+        assert!(missing.harvest(&location(), dir.path()).is_err());
+    }
+}
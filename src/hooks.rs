@@ -0,0 +1,192 @@
+//! Shell commands run around the processing of a run (see [`HooksConfig`]), e.g. to stage its weather data before it
+//! starts or to trigger the downstream processing once it is done.
+
+use crate::config::runs::RunConfig;
+use crate::processing::context::{Context, ContextValue};
+use crate::processing::sink::Sink;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+use thiserror::Error;
+
+/// Prefix of the environment variables set for the hooks, so they don't clobber the ones of the system (e.g. `PATH`).
+const VAR_PREFIX: &str = "PYTHIA_";
+
+#[derive(Debug, Error)]
+pub enum HookError {
+    #[error("Unable to run hook `{0}`: {1}")]
+    Spawn(String, std::io::Error),
+    #[error("Hook `{0}` failed ({1})")]
+    Failed(String, ExitStatus),
+}
+
+/// Commands run by the shell (`sh -c`, or `cmd /C` on Windows) in the working directory, with the variables of the run
+/// (or of the context) in the environment as `PYTHIA_<NAME>` (e.g. `PYTHIA_NAME`, `PYTHIA_SITE_ID`), along with
+/// `PYTHIA_WORKDIR` and `PYTHIA_RUN_DIR`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct HooksConfig {
+    /// Run before any context is processed. The processing is not started if it fails.
+    #[serde(default)]
+    pub pre_run: Option<String>,
+    /// Run once every context of every run was processed.
+    #[serde(default)]
+    pub post_run: Option<String>,
+    /// Run for every context that made it through the pipeline, with `PYTHIA_CONTEXT_DIR` set to its directory.
+    #[serde(default)]
+    pub post_context: Option<String>,
+}
+
+/// Name of the environment variable holding the context (or run) variable `key`.
+fn var_name(key: &str) -> String {
+    format!("{}{}", VAR_PREFIX, key.to_uppercase())
+}
+
+/// Variables of a run hook. Only the plain values of the run are set, the templated ones need a context.
+fn run_vars(run: &RunConfig, workdir: &Path) -> Vec<(String, String)> {
+    let values = run.extra.iter().filter_map(|(k, v)| match v {
+        ContextValue::Prim(p) => Some((var_name(k), p.as_string())),
+        _ => None,
+    });
+    values
+        .chain([
+            (var_name("name"), run.name.clone()),
+            (var_name("workdir"), workdir.display().to_string()),
+            (
+                var_name("run_dir"),
+                workdir.join(&run.name).display().to_string(),
+            ),
+        ])
+        .collect()
+}
+
+/// Variables of a context hook: every plain variable its template gets.
+fn context_vars(ctx: &Context, workdir: &PathBuf) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let serde_json::Value::Object(values) = ctx.tera()?.into_json() else {
+        return Ok(Vec::new());
+    };
+    let values = values.into_iter().filter_map(|(k, v)| match v {
+        serde_json::Value::String(s) => Some((var_name(&k), s)),
+        serde_json::Value::Number(n) => Some((var_name(&k), n.to_string())),
+        serde_json::Value::Bool(b) => Some((var_name(&k), b.to_string())),
+        _ => None,
+    });
+    Ok(values
+        .chain([
+            (var_name("workdir"), workdir.display().to_string()),
+            (
+                var_name("run_dir"),
+                workdir.join(&ctx.run.name).display().to_string(),
+            ),
+            (
+                var_name("context_dir"),
+                ctx.dir(workdir).display().to_string(),
+            ),
+        ])
+        .collect())
+}
+
+/// Runs `command` by the shell in `workdir` with `vars` in the environment, waiting for it to exit.
+fn execute(command: &str, vars: Vec<(String, String)>, workdir: &Path) -> Result<(), HookError> {
+    #[cfg(windows)]
+    let mut shell = Command::new("cmd");
+    #[cfg(windows)]
+    shell.arg("/C");
+    #[cfg(not(windows))]
+    let mut shell = Command::new("sh");
+    #[cfg(not(windows))]
+    shell.arg("-c");
+
+    let status = shell
+        .arg(command)
+        .envs(vars)
+        .current_dir(workdir)
+        .status()
+        .map_err(|e| HookError::Spawn(command.to_string(), e))?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(HookError::Failed(command.to_string(), status)),
+    }
+}
+
+/// Runs the `pre_run` hook of `run`, if any.
+pub fn pre_run(run: &RunConfig, workdir: &Path) -> Result<(), HookError> {
+    match run.hooks.as_ref().and_then(|h| h.pre_run.as_ref()) {
+        Some(command) => execute(command, run_vars(run, workdir), workdir),
+        None => Ok(()),
+    }
+}
+
+/// Runs the `post_run` hook of `run`, if any.
+pub fn post_run(run: &RunConfig, workdir: &Path) -> Result<(), HookError> {
+    match run.hooks.as_ref().and_then(|h| h.post_run.as_ref()) {
+        Some(command) => execute(command, run_vars(run, workdir), workdir),
+        None => Ok(()),
+    }
+}
+
+/// Runs the `post_context` hook of a run for every context of it that made it through the pipeline. A context the
+/// hook fails on is reported without giving up on the next ones.
+pub struct PostContextHook {
+    command: String,
+    workdir: PathBuf,
+}
+
+impl PostContextHook {
+    pub fn new(command: String, workdir: PathBuf) -> Self {
+        Self { command, workdir }
+    }
+}
+
+impl Sink for PostContextHook {
+    fn accept(&mut self, ctx: &Context) -> Result<(), Box<dyn Error>> {
+        let vars = context_vars(ctx, &self.workdir)?;
+        match execute(&self.command, vars, &self.workdir) {
+            Err(HookError::Failed(command, status)) => {
+                let dir = ctx.dir(&self.workdir);
+                eprintln!(
+                    "Hook `{}` failed on {} ({})",
+                    command,
+                    dir.display(),
+                    status
+                );
+                Ok(())
+            }
+            result => Ok(result?),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::processing::context::PrimitiveContextValue;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_run_hooks() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = RunConfig {
+            name: "r1".to_string(),
+            extra: HashMap::from([(
+                "crop".to_string(),
+                ContextValue::Prim(PrimitiveContextValue::String("MZ".to_string())),
+            )]),
+            hooks: Some(HooksConfig {
+                pre_run: Some("echo \"$PYTHIA_NAME-$PYTHIA_CROP\" > hook.txt".to_string()),
+                post_run: Some("exit 3".to_string()),
+                post_context: None,
+            }),
+            ..Default::default()
+        };
+
+        pre_run(&run, dir.path()).unwrap();
+        let written = std::fs::read_to_string(dir.path().join("hook.txt")).unwrap();
+        assert_eq!(written, "r1-MZ\n");
+        assert!(matches!(
+            post_run(&run, dir.path()),
+            Err(HookError::Failed(_, status)) if status.code() == Some(3)
+        ));
+    }
+}
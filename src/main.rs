@@ -1,21 +1,98 @@
 #![feature(mpmc_channel)]
 
+mod batch;
+mod calibration;
 mod config;
+mod cultivar;
 mod data;
+mod fertilizer;
+mod fetch;
+mod harvest;
+mod hooks;
+mod planting;
 mod processing;
+mod provenance;
 mod registry;
+mod simulation;
 mod sites;
+mod soil;
 mod utils;
+mod weather;
 mod workdir;
 
+use crate::config::diagnostics::SourceMap;
+use crate::processing::preflight::preflight;
 use crate::processing::ProcessingBuilder;
-use crate::workdir::make_workdir;
+use crate::provenance::{verify_inputs, InputDigest, Manifest};
+use crate::workdir::{make_workdir, temp_workdir_prefix};
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches};
 use registry::{itself::init_itself, Registries};
+use std::error::Error;
+use std::path::PathBuf;
+
+/// Loads the config the same as a run would and runs the preflight checks on it, see [`config::Command::Validate`].
+fn validate(
+    args: config::Args,
+    seed: config::ConfigSeed,
+    loaded: (serde_json::Value, SourceMap, PathBuf),
+) -> Result<PathBuf, Box<dyn Error>> {
+    let (config, args, config_file) = config::init(args, seed, loaded)?;
+    verify_inputs(&config.inputs).map_err(|errors| {
+        let errors: Vec<String> = errors.iter().map(|e| format!("  - {}", e)).collect();
+        format!("Input verification failed:\n{}", errors.join("\n"))
+    })?;
+    preflight(&config, None, args.strict_templates)?;
+    Ok(config_file)
+}
 
 fn main() {
+    let matches = config::Args::command().get_matches();
+    let args = config::Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
     let mut registries = Registries::new();
     let namespace = init_itself(&mut registries).unwrap();
+
+    // Printed alone, to be redirected into a file.
+    if args.command == Some(config::Command::Schema) {
+        let schema = config::schema::schema(&registries, namespace.namespace());
+        println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+        return;
+    }
+    if let Some(config::Command::List { kind }) = args.command {
+        // Only lists the resources of plugins when given the config declaring them, as there may be no config at all.
+        if matches.value_source("config_file") == Some(ValueSource::CommandLine) {
+            let loaded = config::load(&args)
+                .and_then(|(json, _, _)| config::init_plugins(&json, &mut registries));
+            if let Err(e) = loaded {
+                println!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        for line in config::list::list(&registries, kind) {
+            println!("{}", line);
+        }
+        return;
+    }
+
+    let loaded = match config::load(&args) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    // Loaded before anything reads the registries, so the config can refer to the plugins' resources.
+    let plugins = match config::init_plugins(&loaded.0, &mut registries) {
+        Ok(plugins) => plugins,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
     println!("Initialized own resources on namespace \"{}\"", namespace);
+    for plugin in &plugins {
+        println!("Loaded plugin {}", plugin.path.display());
+    }
 
     let cfg_seed = config::ConfigSeedBuilder::default()
         .with_default_namespace(namespace.namespace().to_string())
@@ -23,7 +100,18 @@ fn main() {
         .build()
         .unwrap();
 
-    let cfg_result = config::init(cfg_seed);
+    if args.command == Some(config::Command::Validate) {
+        match validate(args, cfg_seed, loaded) {
+            Ok(path) => println!("Configuration file {} is valid", path.display()),
+            Err(e) => {
+                println!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let cfg_result = config::init(args, cfg_seed, loaded);
     if let Err(e) = cfg_result {
         println!("{}", e);
         return;
@@ -35,14 +123,37 @@ fn main() {
         config_file.canonicalize().ok().unwrap().display()
     );
 
-    let (workdir, temp_wd) =
-        match make_workdir(&args.workdir, &args.keep_workdir, args.clear_workdir) {
-            Ok(workdir) => workdir,
-            Err(e) => {
-                println!("Unable to validate working directory: {}", e);
-                return;
+    let config_digest = match InputDigest::of(&config_file) {
+        Ok(digest) => digest,
+        Err(e) => {
+            println!("Unable to read configuration file: {}", e);
+            return;
+        }
+    };
+
+    let digests = match verify_inputs(&config.inputs) {
+        Ok(digests) => digests,
+        Err(errors) => {
+            println!("Input verification failed:");
+            for e in errors {
+                println!("  - {}", e);
             }
-        };
+            return;
+        }
+    };
+
+    let (workdir, temp_wd) = match make_workdir(
+        &args.workdir,
+        &args.keep_workdir,
+        args.clear_workdir,
+        &temp_workdir_prefix(&config_digest.sha256),
+    ) {
+        Ok(workdir) => workdir,
+        Err(e) => {
+            println!("Unable to validate working directory: {}", e);
+            return;
+        }
+    };
 
     println!(
         "Initialized working directory at {}{}",
@@ -50,13 +161,40 @@ fn main() {
         if temp_wd { " (temporary)" } else { "" }
     );
 
-    let processing = ProcessingBuilder {
+    let manifest = Manifest::new(config_digest, workdir.clone(), temp_wd, digests);
+    match manifest.write(&workdir) {
+        Ok(path) => println!("Wrote provenance manifest to {}", path.display()),
+        Err(e) => {
+            println!("Unable to write provenance manifest: {}", e);
+            return;
+        }
+    }
+
+    if args.command == Some(config::Command::Calibrate) {
+        if let Err(e) = calibration::calibrate(&config, &args, &workdir, &manifest) {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let processing = match (ProcessingBuilder {
         config: &config,
         args: &args,
         workdir,
-    }
+        manifest: &manifest,
+    })
     .build()
-    .unwrap();
+    {
+        Ok(processing) => processing,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
 
-    processing.start();
+    if let Err(e) = processing.start() {
+        println!("{}", e);
+        std::process::exit(1);
+    }
 }
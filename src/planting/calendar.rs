@@ -0,0 +1,219 @@
+//! Planting and harvest dates from crop calendar rasters (e.g. SAGE or MIRCA2000).
+
+use crate::processing::context::{ContextValue, PrimitiveContextValue};
+use crate::sites::Site;
+use crate::utils::raster::RasterSampler;
+use crate::weather::wth::yyddd;
+use chrono::{Datelike, Days, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+#[error("Failed to open crop calendar raster {path}: {source}")]
+pub struct CropCalendarError {
+    path: PathBuf,
+    source: gdal::errors::GdalError,
+}
+
+/// Unit of the values of a crop calendar raster.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CalendarUnit {
+    /// Day of year, 1 to 366 (SAGE).
+    #[default]
+    Doy,
+    /// Month, 1 to 12 (MIRCA2000).
+    Month,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CalendarRaster {
+    pub file: PathBuf,
+
+    /// Band to read from (**ONE-BASED**).
+    #[serde(default = "default_band")]
+    pub band: usize,
+
+    #[serde(default)]
+    pub unit: CalendarUnit,
+}
+
+fn default_band() -> usize {
+    1
+}
+
+/// Reads the planting (and optionally harvest) dates of each site from crop calendar rasters, exposed to templates as
+/// `pdate`, `hdate` and `sdate` (start of simulation) in the DSSAT `YYDDD` format, along with `pdate_doy` and `pdate_year`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CropCalendarConfig {
+    pub planting: CalendarRaster,
+
+    #[serde(default)]
+    pub harvest: Option<CalendarRaster>,
+
+    /// Year of planting. Harvests earlier in the year than the planting fall in the next year.
+    pub year: i32,
+
+    /// Day of the month used for calendars in months.
+    #[serde(default = "default_day_of_month")]
+    pub day_of_month: u32,
+
+    /// Days between the start of the simulation and planting.
+    #[serde(default)]
+    pub sim_start_offset_days: u64,
+}
+
+fn default_day_of_month() -> u32 {
+    15
+}
+
+/// A [`CropCalendarConfig`] with its rasters opened.
+pub struct CropCalendar {
+    config: CropCalendarConfig,
+    planting: RasterSampler,
+    harvest: Option<RasterSampler>,
+}
+
+impl CropCalendar {
+    pub fn load(config: &CropCalendarConfig) -> Result<Self, CropCalendarError> {
+        let open = |raster: &CalendarRaster| {
+            RasterSampler::open(&raster.file.to_string_lossy()).map_err(|source| {
+                CropCalendarError {
+                    path: raster.file.clone(),
+                    source,
+                }
+            })
+        };
+
+        Ok(Self {
+            config: config.clone(),
+            planting: open(&config.planting)?,
+            harvest: config.harvest.as_ref().map(open).transpose()?,
+        })
+    }
+
+    fn sample(&self, sampler: &RasterSampler, raster: &CalendarRaster, site: &Site) -> Option<f64> {
+        sampler
+            .sample(
+                raster.band.saturating_sub(1),
+                site.lon.as_f64(),
+                site.lat.as_f64(),
+            )
+            .ok()?
+    }
+
+    /// The calendar variables of `site`, or `None` if the site has no planting date.
+    /// Harvest dates are left out if missing for the site.
+    pub fn values(&self, site: &Site) -> Option<Vec<(String, ContextValue)>> {
+        let config = &self.config;
+        let value = |k: &str, v| (k.to_string(), ContextValue::Prim(v));
+
+        let planting = self.sample(&self.planting, &config.planting, site)?;
+        let pdate = calendar_date(
+            planting,
+            config.planting.unit,
+            config.year,
+            config.day_of_month,
+        )?;
+        let sdate = pdate.checked_sub_days(Days::new(config.sim_start_offset_days))?;
+
+        let mut values = vec![
+            value("pdate", PrimitiveContextValue::String(yyddd(pdate))),
+            value(
+                "pdate_doy",
+                PrimitiveContextValue::Int(pdate.ordinal() as i64),
+            ),
+            value(
+                "pdate_year",
+                PrimitiveContextValue::Int(pdate.year() as i64),
+            ),
+            value("sdate", PrimitiveContextValue::String(yyddd(sdate))),
+        ];
+
+        let harvest =
+            self.harvest
+                .as_ref()
+                .zip(config.harvest.as_ref())
+                .and_then(|(sampler, raster)| {
+                    let harvest = self.sample(sampler, raster, site)?;
+                    harvest_date(pdate, harvest, raster.unit, config.day_of_month)
+                });
+        if let Some(hdate) = harvest {
+            values.push(value("hdate", PrimitiveContextValue::String(yyddd(hdate))));
+        }
+
+        Some(values)
+    }
+}
+
+/// Converts a calendar value to a date in `year`. Days of year beyond the end of a non-leap year are clamped to its last day.
+fn calendar_date(
+    value: f64,
+    unit: CalendarUnit,
+    year: i32,
+    day_of_month: u32,
+) -> Option<NaiveDate> {
+    let value = value.round() as u32;
+    match unit {
+        CalendarUnit::Doy => NaiveDate::from_yo_opt(year, value)
+            .or_else(|| NaiveDate::from_ymd_opt(year, 12, 31).filter(|_| value == 366)),
+        CalendarUnit::Month => NaiveDate::from_ymd_opt(year, value, day_of_month.clamp(1, 28)),
+    }
+}
+
+/// The harvest date following `pdate`, in the year of planting or the next one.
+fn harvest_date(
+    pdate: NaiveDate,
+    value: f64,
+    unit: CalendarUnit,
+    day_of_month: u32,
+) -> Option<NaiveDate> {
+    let hdate = calendar_date(value, unit, pdate.year(), day_of_month)?;
+    if hdate > pdate {
+        Some(hdate)
+    } else {
+        calendar_date(value, unit, pdate.year() + 1, day_of_month)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calendar_date() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d);
+
+        assert_eq!(
+            calendar_date(32.0, CalendarUnit::Doy, 2021, 15),
+            date(2021, 2, 1)
+        );
+        assert_eq!(
+            calendar_date(366.0, CalendarUnit::Doy, 2021, 15),
+            date(2021, 12, 31)
+        );
+        assert_eq!(
+            calendar_date(366.0, CalendarUnit::Doy, 2020, 15),
+            date(2020, 12, 31)
+        );
+        assert_eq!(calendar_date(0.0, CalendarUnit::Doy, 2021, 15), None);
+        assert_eq!(
+            calendar_date(11.0, CalendarUnit::Month, 2021, 15),
+            date(2021, 11, 15)
+        );
+        assert_eq!(calendar_date(13.0, CalendarUnit::Month, 2021, 15), None);
+
+        let pdate = date(2021, 11, 15).unwrap();
+        assert_eq!(
+            harvest_date(pdate, 3.0, CalendarUnit::Month, 15),
+            date(2022, 3, 15)
+        );
+        assert_eq!(
+            harvest_date(pdate, 350.0, CalendarUnit::Doy, 15),
+            date(2021, 12, 16)
+        );
+    }
+}
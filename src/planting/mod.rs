@@ -0,0 +1,5 @@
+//! Module _planting_ derives planting dates for each site.
+
+pub mod calendar;
+pub mod rules;
+pub mod window;
@@ -0,0 +1,224 @@
+//! Declarative planting rules, evaluated per site.
+
+use crate::processing::context::{Context, ContextValue, PrimitiveContextValue};
+use crate::utils::lookup::{LookupConfig, LookupError, LookupTable};
+use crate::weather::wth::{yyddd, WeatherSeries};
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+/// A rule yielding a planting date (or only a day of year, when the year is not known).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "rule", rename_all = "snake_case", deny_unknown_fields)]
+pub enum PlantingRule {
+    /// The first day on or after `after_doy` (and before `before_doy`, if set) closing a span of `days` consecutive days
+    /// with at least `rain_mm` of rain. Evaluated on the weather of the run, so it requires [`crate::config::runs::RunConfig::weather`].
+    Rainfall {
+        after_doy: u32,
+        #[serde(default)]
+        before_doy: Option<u32>,
+        rain_mm: f64,
+        days: usize,
+    },
+    /// A fixed day of year.
+    Fixed { doy: u32 },
+    /// The day of year in `column` of the row of a lookup table (e.g. per region).
+    Lookup {
+        #[serde(flatten)]
+        lookup: LookupConfig,
+        #[serde(default = "default_lookup_column")]
+        column: String,
+    },
+}
+
+fn default_lookup_column() -> String {
+    "pdate".to_string()
+}
+
+/// Planting rules, tried in order until one yields a date. Sets `pdate_doy`, plus `pdate` (`YYDDD`) and `pdate_year`
+/// if the year is known.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PlantingRulesConfig {
+    /// Year of the planting date. If not set, it's the first year of the weather of the run, if any.
+    #[serde(default)]
+    pub year: Option<i32>,
+
+    pub rules: Vec<PlantingRule>,
+}
+
+impl PlantingRulesConfig {
+    pub fn requires_weather(&self) -> bool {
+        self.rules
+            .iter()
+            .any(|r| matches!(r, PlantingRule::Rainfall { .. }))
+    }
+}
+
+/// The outcome of [`PlantingRules::evaluate`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlantingDate {
+    Date(NaiveDate),
+    DayOfYear(u32),
+}
+
+impl PlantingDate {
+    /// The variables set by the date, see [`PlantingRulesConfig`].
+    pub fn values(&self) -> Vec<(String, ContextValue)> {
+        let value = |k: &str, v| (k.to_string(), ContextValue::Prim(v));
+        match self {
+            PlantingDate::Date(date) => vec![
+                value("pdate", PrimitiveContextValue::String(yyddd(*date))),
+                value(
+                    "pdate_doy",
+                    PrimitiveContextValue::Int(date.ordinal() as i64),
+                ),
+                value("pdate_year", PrimitiveContextValue::Int(date.year() as i64)),
+            ],
+            PlantingDate::DayOfYear(doy) => {
+                vec![value("pdate_doy", PrimitiveContextValue::Int(*doy as i64))]
+            }
+        }
+    }
+}
+
+/// A [`PlantingRulesConfig`] with its lookup tables loaded.
+pub struct PlantingRules {
+    config: PlantingRulesConfig,
+    tables: Vec<Option<LookupTable>>,
+}
+
+impl PlantingRules {
+    pub fn load(config: &PlantingRulesConfig) -> Result<Self, LookupError> {
+        let tables = config
+            .rules
+            .iter()
+            .map(|rule| match rule {
+                PlantingRule::Lookup { lookup, .. } => lookup.load().map(Some),
+                _ => Ok(None),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            config: config.clone(),
+            tables,
+        })
+    }
+
+    /// Evaluates the rules for `ctx`, with the weather of the site if available.
+    pub fn evaluate(&self, ctx: &Context, weather: Option<&WeatherSeries>) -> Option<PlantingDate> {
+        let year = self
+            .config
+            .year
+            .or_else(|| Some(weather?.days.first()?.date.year()));
+
+        self.config
+            .rules
+            .iter()
+            .zip(&self.tables)
+            .find_map(|(rule, table)| {
+                let doy = match rule {
+                    PlantingRule::Rainfall {
+                        after_doy,
+                        before_doy,
+                        rain_mm,
+                        days,
+                    } => {
+                        let date = rainfall_onset(
+                            weather?,
+                            year?,
+                            *after_doy,
+                            *before_doy,
+                            *rain_mm,
+                            *days,
+                        )?;
+                        return Some(PlantingDate::Date(date));
+                    }
+                    PlantingRule::Fixed { doy } => *doy,
+                    PlantingRule::Lookup { lookup, column } => {
+                        let table = table.as_ref()?;
+                        let row = table.row(&lookup.resolve_key(ctx).ok()?)?;
+                        row.get(table.column(column).ok()?)?.parse().ok()?
+                    }
+                };
+
+                Some(match year.and_then(|y| NaiveDate::from_yo_opt(y, doy)) {
+                    Some(date) => PlantingDate::Date(date),
+                    None => PlantingDate::DayOfYear(doy),
+                })
+            })
+    }
+}
+
+/// First day in `year`, on or after `after_doy` and before `before_doy`, closing a span of `days` days with at least `rain_mm` of rain.
+/// Missing rain counts as no rain.
+fn rainfall_onset(
+    weather: &WeatherSeries,
+    year: i32,
+    after_doy: u32,
+    before_doy: Option<u32>,
+    rain_mm: f64,
+    days: usize,
+) -> Option<NaiveDate> {
+    let from = NaiveDate::from_yo_opt(year, after_doy)?;
+    let until = before_doy.and_then(|doy| NaiveDate::from_yo_opt(year, doy));
+    let days = days.max(1);
+
+    weather
+        .days
+        .windows(days)
+        .map(|span| {
+            (
+                span[days - 1].date,
+                span.iter().filter_map(|d| d.rain).sum::<f64>(),
+            )
+        })
+        .filter(|(date, _)| *date >= from && until.is_none_or(|until| *date < until))
+        .find(|(_, rain)| *rain >= rain_mm)
+        .map(|(date, _)| date)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weather::wth::WeatherDay;
+
+    fn series(rain: &[f64]) -> WeatherSeries {
+        let start = NaiveDate::from_ymd_opt(2020, 4, 1).unwrap();
+        WeatherSeries {
+            station: "TEST".to_string(),
+            source: "test".to_string(),
+            lat: 0.0,
+            lon: 0.0,
+            elev: None,
+            days: rain
+                .iter()
+                .enumerate()
+                .map(|(i, r)| WeatherDay {
+                    date: start + chrono::Days::new(i as u64),
+                    srad: None,
+                    tmax: None,
+                    tmin: None,
+                    rain: Some(*r),
+                    dewp: None,
+                    wind: None,
+                    rhum: None,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_rainfall_onset() {
+        // 2020-04-01 is DOY 92.
+        let weather = series(&[30.0, 0.0, 0.0, 5.0, 10.0, 0.0, 8.0, 12.0, 2.0]);
+
+        let onset = rainfall_onset(&weather, 2020, 92, None, 20.0, 3);
+        assert_eq!(onset, NaiveDate::from_ymd_opt(2020, 4, 3));
+
+        let onset = rainfall_onset(&weather, 2020, 95, None, 20.0, 3);
+        assert_eq!(onset, NaiveDate::from_ymd_opt(2020, 4, 8));
+
+        let onset = rainfall_onset(&weather, 2020, 95, Some(99), 20.0, 3);
+        assert_eq!(onset, None);
+    }
+}
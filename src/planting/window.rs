@@ -0,0 +1,254 @@
+//! Planting windows derived from rainfall climatologies.
+
+use crate::processing::context::Context;
+use crate::utils::lookup::{LookupConfig, LookupError, LookupTable};
+use crate::utils::raster::RasterSampler;
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum PlantingWindowError {
+    #[error("Failed to open rainfall climatology {path}: {source}")]
+    Gdal {
+        path: PathBuf,
+        source: gdal::errors::GdalError,
+    },
+    #[error(transparent)]
+    Lookup(#[from] LookupError),
+}
+
+/// Length of each period of a rainfall climatology.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    /// 365 periods.
+    Daily,
+    /// 36 periods, the 1st, 11th and 21st of each month.
+    Dekadal,
+    /// 12 periods.
+    #[default]
+    Monthly,
+}
+
+impl Period {
+    pub fn count(&self) -> usize {
+        match self {
+            Period::Daily => 365,
+            Period::Dekadal => 36,
+            Period::Monthly => 12,
+        }
+    }
+
+    /// Day of year (1-based, non-leap year) the period `idx` starts at.
+    pub fn start_doy(&self, idx: usize) -> u32 {
+        let (month, day) = match self {
+            Period::Daily => return idx as u32 + 1,
+            Period::Dekadal => (idx / 3 + 1, (idx % 3) * 10 + 1),
+            Period::Monthly => (idx + 1, 1),
+        };
+        NaiveDate::from_ymd_opt(2001, month as u32, day as u32)
+            .expect("period index out of range")
+            .ordinal()
+    }
+
+    /// Index of the period containing the day of year `doy`.
+    pub fn of_doy(&self, doy: u32) -> usize {
+        (0..self.count())
+            .rev()
+            .find(|&idx| self.start_doy(idx) <= doy)
+            .unwrap_or(0)
+    }
+}
+
+/// Where the rainfall climatology (mm per period) of a site comes from.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "source", rename_all = "snake_case", deny_unknown_fields)]
+pub enum RainfallClimatology {
+    /// A raster stack with one band per period.
+    Raster { file: PathBuf },
+    /// A CSV lookup table with one row per key (e.g. per region or station) and one column per period.
+    Lookup {
+        #[serde(flatten)]
+        lookup: LookupConfig,
+        /// Columns holding the rainfall of each period, in order.
+        columns: Vec<String>,
+    },
+}
+
+/// When the rainy season starts.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "rule", rename_all = "snake_case", deny_unknown_fields)]
+pub enum OnsetRule {
+    /// The first period at which the rainfall accumulated over `periods` consecutive periods reaches `threshold_mm`.
+    Accumulation { periods: usize, threshold_mm: f64 },
+    /// The period at which the rainfall accumulated since the start of the search reaches `fraction` of the annual total.
+    FractionOfAnnual { fraction: f64 },
+}
+
+/// Derives a planting window for each site, exposed to templates as `pdate_start` and `pdate_end` (days of year).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PlantingWindowConfig {
+    pub rainfall: RainfallClimatology,
+
+    #[serde(default)]
+    pub period: Period,
+
+    pub onset: OnsetRule,
+
+    /// Length of the window, in days after the onset.
+    pub window_days: u32,
+
+    /// Day of year the search for the onset starts at, wrapping around the end of the year. Useful for rainy seasons spanning two years.
+    #[serde(default = "default_search_from")]
+    pub search_from: u32,
+}
+
+fn default_search_from() -> u32 {
+    1
+}
+
+enum Climatology {
+    Raster(RasterSampler),
+    Lookup(LookupTable, Vec<usize>),
+}
+
+/// A [`PlantingWindowConfig`] with its climatology loaded.
+pub struct PlantingWindow {
+    config: PlantingWindowConfig,
+    climatology: Climatology,
+}
+
+impl PlantingWindow {
+    pub fn load(config: &PlantingWindowConfig) -> Result<Self, PlantingWindowError> {
+        let climatology = match &config.rainfall {
+            RainfallClimatology::Raster { file } => {
+                Climatology::Raster(RasterSampler::open(&file.to_string_lossy()).map_err(
+                    |source| PlantingWindowError::Gdal {
+                        path: file.clone(),
+                        source,
+                    },
+                )?)
+            }
+            RainfallClimatology::Lookup { lookup, columns } => {
+                let table = lookup.load()?;
+                let columns = columns
+                    .iter()
+                    .map(|c| table.column(c))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Climatology::Lookup(table, columns)
+            }
+        };
+
+        Ok(Self {
+            config: config.clone(),
+            climatology,
+        })
+    }
+
+    /// Rainfall of each period at the site of `ctx`, if available for every period.
+    fn rainfall(&self, ctx: &Context) -> Option<Vec<f64>> {
+        let rainfall: Vec<f64> = match &self.climatology {
+            Climatology::Raster(sampler) => sampler
+                .sample_all(ctx.site.lon.as_f64(), ctx.site.lat.as_f64())
+                .ok()?
+                .into_iter()
+                .collect::<Option<_>>()?,
+            Climatology::Lookup(table, columns) => {
+                let RainfallClimatology::Lookup { lookup, .. } = &self.config.rainfall else {
+                    return None;
+                };
+                let row = table.row(&lookup.resolve_key(ctx).ok()?)?;
+                columns
+                    .iter()
+                    .map(|&idx| row.get(idx)?.parse().ok())
+                    .collect::<Option<_>>()?
+            }
+        };
+
+        (rainfall.len() == self.config.period.count()).then_some(rainfall)
+    }
+
+    /// Planting window `(start, end)` of the site of `ctx`, as days of year.
+    /// `None` if the climatology is not available for the site or the onset is never reached.
+    pub fn compute(&self, ctx: &Context) -> Option<(u32, u32)> {
+        let rainfall = self.rainfall(ctx)?;
+        let period = self.config.period;
+        let onset = onset_period(
+            &rainfall,
+            &self.config.onset,
+            period.of_doy(self.config.search_from),
+        )?;
+
+        let start = period.start_doy(onset);
+        let end = (start - 1 + self.config.window_days) % 365 + 1;
+        Some((start, end))
+    }
+}
+
+/// Index of the period the rainy season starts at, scanning `rainfall` circularly from the period `from`.
+pub fn onset_period(rainfall: &[f64], rule: &OnsetRule, from: usize) -> Option<usize> {
+    let n = rainfall.len();
+    let at = |i: usize| rainfall[(from + i) % n];
+
+    match rule {
+        OnsetRule::Accumulation {
+            periods,
+            threshold_mm,
+        } => (0..n)
+            .find(|&i| (i..i + periods).map(at).sum::<f64>() >= *threshold_mm)
+            .map(|i| (from + i) % n),
+        OnsetRule::FractionOfAnnual { fraction } => {
+            let target = rainfall.iter().sum::<f64>() * fraction;
+            let mut accumulated = 0.0;
+            (0..n)
+                .find(|&i| {
+                    accumulated += at(i);
+                    accumulated > 0.0 && accumulated >= target
+                })
+                .map(|i| (from + i) % n)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_period_doy() {
+        assert_eq!(Period::Monthly.start_doy(0), 1);
+        assert_eq!(Period::Monthly.start_doy(2), 60);
+        assert_eq!(Period::Dekadal.start_doy(4), 42);
+        assert_eq!(Period::Daily.start_doy(99), 100);
+        assert_eq!(Period::Monthly.of_doy(59), 1);
+        assert_eq!(Period::Monthly.of_doy(60), 2);
+        assert_eq!(Period::Dekadal.of_doy(365), 35);
+    }
+
+    #[test]
+    fn test_onset_period() {
+        let rainfall = [
+            10.0, 10.0, 20.0, 60.0, 120.0, 150.0, 100.0, 40.0, 10.0, 5.0, 200.0, 10.0,
+        ];
+
+        let rule = OnsetRule::Accumulation {
+            periods: 2,
+            threshold_mm: 150.0,
+        };
+        assert_eq!(onset_period(&rainfall, &rule, 0), Some(3));
+        // Wraps around the end of the year.
+        assert_eq!(onset_period(&rainfall, &rule, 8), Some(9));
+
+        let rule = OnsetRule::FractionOfAnnual { fraction: 0.25 };
+        assert_eq!(onset_period(&rainfall, &rule, 0), Some(4));
+
+        let rule = OnsetRule::Accumulation {
+            periods: 1,
+            threshold_mm: 1000.0,
+        };
+        assert_eq!(onset_period(&rainfall, &rule, 0), None);
+    }
+}
@@ -0,0 +1,170 @@
+//! Checkpoints of the contexts that made it through every stage of the pipeline, so an interrupted run can be resumed
+//! (see `--resume`) without processing them again.
+
+use super::context::Context;
+use super::sink::Sink;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::Display;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+/// Name of the checkpoint file, in the root of the working directory. Holds a `run,site` row per completed context.
+pub const CHECKPOINT_FILE_NAME: &str = "pythia-checkpoint.csv";
+
+/// How many contexts are completed between writes of the checkpoint file.
+const CHECKPOINT_INTERVAL: usize = 1000;
+
+/// Site IDs of the completed contexts, by run name.
+pub type CompletedContexts = HashMap<String, HashSet<i32>>;
+
+/// Reads the contexts recorded as completed in the checkpoint file of `workdir`, if any.
+pub fn load_checkpoint(workdir: &Path) -> Result<CompletedContexts, csv::Error> {
+    let path = workdir.join(CHECKPOINT_FILE_NAME);
+    let mut completed = CompletedContexts::new();
+    if !path.exists() {
+        return Ok(completed);
+    }
+
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_path(path)?;
+    for record in reader.deserialize() {
+        let (run, site): (String, i32) = record?;
+        completed.entry(run).or_default().insert(site);
+    }
+    Ok(completed)
+}
+
+/// Rewrites the checkpoint file of `workdir` in canonical (run, site ID) order, whatever order the contexts completed
+/// in. See `--deterministic`.
+pub fn sort_checkpoint(workdir: &Path) -> Result<(), csv::Error> {
+    let path = workdir.join(CHECKPOINT_FILE_NAME);
+    let mut rows = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_path(&path)?
+        .deserialize()
+        .collect::<Result<Vec<(String, i32)>, _>>()?;
+    rows.sort();
+
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_path(&path)?;
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Records the contexts that made it out of the last stage to the checkpoint file of a working directory. Always one
+/// of the sinks of the processing, see [`super::sink`].
+pub struct CheckpointSink {
+    path: PathBuf,
+    writer: csv::Writer<File>,
+    completed: usize,
+}
+
+impl CheckpointSink {
+    /// Opens the checkpoint file of `workdir`, appending to it when resuming.
+    pub fn new(workdir: &Path, resume: bool) -> Result<Self, Box<dyn Error>> {
+        let path = workdir.join(CHECKPOINT_FILE_NAME);
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(!resume)
+            .append(resume)
+            .open(&path)
+            .map_err(|e| format!("Unable to open the checkpoint {}: {}", path.display(), e))?;
+
+        Ok(Self {
+            path,
+            writer: csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(file),
+            completed: 0,
+        })
+    }
+
+    fn write(&mut self, ctx: &Context) -> Result<(), csv::Error> {
+        self.writer.serialize((&ctx.run.name, ctx.site.id))?;
+        self.completed += 1;
+        if self.completed % CHECKPOINT_INTERVAL == 0 {
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+
+    fn write_error(&self, e: impl Display) -> Box<dyn Error> {
+        format!(
+            "Unable to write the checkpoint {}: {}",
+            self.path.display(),
+            e
+        )
+        .into()
+    }
+}
+
+impl Sink for CheckpointSink {
+    fn accept(&mut self, ctx: &Context) -> Result<(), Box<dyn Error>> {
+        self.write(ctx).map_err(|e| self.write_error(e))
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.writer.flush().map_err(|e| self.write_error(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::sink::{drain, NamedSink, Sinks};
+    use std::sync::mpmc::channel;
+
+    fn sink(workdir: &Path, resume: bool, contexts: Vec<Context>) -> usize {
+        let (tx, rx) = channel();
+        for ctx in contexts {
+            tx.send(ctx).unwrap();
+        }
+        drop(tx);
+        let checkpoint = CheckpointSink::new(workdir, resume).unwrap();
+        drain(
+            rx,
+            Sinks {
+                all: vec![NamedSink {
+                    name: "checkpoint".to_string(),
+                    sink: Box::new(checkpoint),
+                }],
+                by_run: Default::default(),
+            },
+        )
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(load_checkpoint(dir.path()).unwrap().is_empty());
+
+        let contexts = vec![
+            Context::for_test("r1", 1),
+            Context::for_test("r2", 1),
+            Context::for_test("r1", 2),
+        ];
+        assert_eq!(sink(dir.path(), false, contexts), 3);
+        assert_eq!(sink(dir.path(), true, vec![Context::for_test("r2", 2)]), 1);
+
+        let completed = load_checkpoint(dir.path()).unwrap();
+        assert_eq!(completed["r1"], HashSet::from([1, 2]));
+        assert_eq!(completed["r2"], HashSet::from([1, 2]));
+
+        sort_checkpoint(dir.path()).unwrap();
+        let rows = std::fs::read_to_string(dir.path().join(CHECKPOINT_FILE_NAME)).unwrap();
+        assert_eq!(rows, "r1,1\nr1,2\nr2,1\nr2,2\n");
+
+        // A new run starts over.
+        assert_eq!(sink(dir.path(), false, vec![Context::for_test("r1", 3)]), 1);
+        let completed = load_checkpoint(dir.path()).unwrap();
+        assert_eq!(completed.len(), 1);
+        assert_eq!(completed["r1"], HashSet::from([3]));
+    }
+}
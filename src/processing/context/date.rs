@@ -0,0 +1,161 @@
+//! Date arithmetic of the context values, e.g. `${pdate + 30d}` in a template string or
+//! `{{ pdate | date_offset(by="-15d") }}` in a template, to place events and windows relative to the planting date.
+
+use chrono::{Days, Months, NaiveDate};
+use regex::Regex;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::LazyLock;
+use thiserror::Error;
+
+static RE_OFFSET: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^([+-])\s*(\d+)\s*([dwmy])$").unwrap());
+
+/// Placeholder of a template string offsetting a variable, e.g. `pdate + 30d`.
+static RE_OFFSET_PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\s*(.*?)\s*([+-]\s*\d+\s*[dwmy])\s*$").unwrap());
+
+#[derive(Debug, Error)]
+#[error(
+    "Invalid date offset '{0}', expected a sign, an amount and a unit (d, w, m or y), e.g. '+30d'"
+)]
+pub struct DateOffsetError(String);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Unit {
+    Days,
+    Weeks,
+    Months,
+    Years,
+}
+
+/// Amount of days, weeks, months or years to move a date by. Months and years keep the day of the month, clamped to
+/// the last day of the target month (e.g. `2024-01-31 + 1m` is `2024-02-29`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DateOffset {
+    negative: bool,
+    amount: u32,
+    unit: Unit,
+}
+
+impl DateOffset {
+    /// `date` moved by the offset, or `None` if out of the range of dates.
+    pub fn apply(&self, date: NaiveDate) -> Option<NaiveDate> {
+        let (days, months) = match self.unit {
+            Unit::Days => (Some(self.amount as u64), None),
+            Unit::Weeks => (Some(self.amount as u64 * 7), None),
+            Unit::Months => (None, Some(self.amount)),
+            Unit::Years => (None, self.amount.checked_mul(12)),
+        };
+        match (days, months, self.negative) {
+            (Some(days), _, false) => date.checked_add_days(Days::new(days)),
+            (Some(days), _, true) => date.checked_sub_days(Days::new(days)),
+            (None, Some(months), false) => date.checked_add_months(Months::new(months)),
+            (None, Some(months), true) => date.checked_sub_months(Months::new(months)),
+            (None, None, _) => None,
+        }
+    }
+}
+
+impl FromStr for DateOffset {
+    type Err = DateOffsetError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let captures = RE_OFFSET
+            .captures(s.trim())
+            .ok_or_else(|| DateOffsetError(s.to_string()))?;
+        let unit = match &captures[3] {
+            "d" => Unit::Days,
+            "w" => Unit::Weeks,
+            "m" => Unit::Months,
+            _ => Unit::Years,
+        };
+        Ok(Self {
+            negative: &captures[1] == "-",
+            amount: captures[2]
+                .parse()
+                .map_err(|_| DateOffsetError(s.to_string()))?,
+            unit,
+        })
+    }
+}
+
+impl Display for DateOffset {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let unit = match self.unit {
+            Unit::Days => 'd',
+            Unit::Weeks => 'w',
+            Unit::Months => 'm',
+            Unit::Years => 'y',
+        };
+        let sign = if self.negative { '-' } else { '+' };
+        write!(f, "{}{}{}", sign, self.amount, unit)
+    }
+}
+
+/// Splits a placeholder of a template string into its variable and its offset, if it has one (e.g. `pdate + 30d`).
+pub fn split_placeholder(placeholder: &str) -> Result<(&str, Option<DateOffset>), DateOffsetError> {
+    match RE_OFFSET_PLACEHOLDER.captures(placeholder) {
+        Some(captures) if !captures[1].is_empty() => {
+            let offset = captures[2].parse()?;
+            Ok((captures.get(1).unwrap().as_str(), Some(offset)))
+        }
+        _ => Ok((placeholder, None)),
+    }
+}
+
+/// Parses a date in the `YYYY-MM-DD` or `YYYYDDD` format.
+pub fn parse_date(s: &str) -> Option<NaiveDate> {
+    let s = s.trim();
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").ok().or_else(|| {
+        (s.len() == 7)
+            .then(|| NaiveDate::parse_from_str(s, "%Y%j").ok())
+            .flatten()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_date_offset() {
+        let date = |s| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        let offset = |s: &str| s.parse::<DateOffset>().unwrap();
+
+        assert_eq!(
+            offset("+30d").apply(date("2024-02-01")),
+            Some(date("2024-03-02"))
+        );
+        assert_eq!(
+            offset("- 2w").apply(date("2024-02-01")),
+            Some(date("2024-01-18"))
+        );
+        assert_eq!(
+            offset("+1m").apply(date("2024-01-31")),
+            Some(date("2024-02-29"))
+        );
+        assert_eq!(
+            offset("-1y").apply(date("2024-02-29")),
+            Some(date("2023-02-28"))
+        );
+        assert_eq!(offset("-15d").to_string(), "-15d");
+        assert!("30d".parse::<DateOffset>().is_err());
+        assert!("+30h".parse::<DateOffset>().is_err());
+
+        assert_eq!(
+            split_placeholder("pdate + 30d").unwrap(),
+            ("pdate", Some(offset("+30d")))
+        );
+        assert_eq!(
+            split_placeholder("pdate-1m").unwrap(),
+            ("pdate", Some(offset("-1m")))
+        );
+        assert_eq!(split_placeholder("pdate").unwrap(), ("pdate", None));
+        assert_eq!(split_placeholder("+30d").unwrap(), ("+30d", None));
+
+        assert_eq!(parse_date("2024-02-01"), Some(date("2024-02-01")));
+        assert_eq!(parse_date("2024032"), Some(date("2024-02-01")));
+        assert_eq!(parse_date("24032"), None);
+    }
+}
@@ -0,0 +1,343 @@
+//! Filters of the contexts of a run (see [`crate::config::runs::RunConfig::filter`]), such as
+//! `lat > 5 && harvested_area >= 100`.
+//!
+//! An expression compares the variables of the context (the same ones its template gets) to numbers
+//! (`5`, `-1.5`), strings (`"MZ"`, `'MZ'`) or booleans (`true`, `false`) with `==`, `!=`, `<`, `<=`, `>` and `>=`,
+//! and combines the comparisons with `&&`, `||`, `!` and parentheses. A string compared to a number is read as a number,
+//! and a string compared to a date as a date.
+
+use super::date::parse_date;
+use super::{Context, PrimitiveContextValue};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::cmp::Ordering;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum FilterError {
+    #[error("Invalid filter \"{0}\": {1}")]
+    Syntax(String, String),
+    #[error("Variable '{0}' of the filter could not be resolved")]
+    Unresolved(String),
+    #[error("Unable to compare {0} to {1}")]
+    Incomparable(String, String),
+    #[error("Expected a boolean, got {0}")]
+    NotBoolean(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Str(String),
+    Ident(String),
+    Op(&'static str),
+    Open,
+    Close,
+}
+
+/// Operators, longest first so `<=` isn't read as `<` then `=`.
+const OPERATORS: [&str; 9] = ["&&", "||", "==", "!=", "<=", ">=", "<", ">", "!"];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = source.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let (token, len) = if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            (Token::Op(op), op.len())
+        } else if c == '(' || c == ')' {
+            (if c == '(' { Token::Open } else { Token::Close }, 1)
+        } else if c == '"' || c == '\'' {
+            let end = rest[1..]
+                .find(c)
+                .ok_or_else(|| format!("unterminated string {}", rest))?;
+            (Token::Str(rest[1..end + 1].to_string()), end + 2)
+        } else if c.is_ascii_digit() || c == '-' || c == '.' {
+            let len = rest[1..]
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .map_or(rest.len(), |i| i + 1);
+            let number = rest[..len]
+                .parse()
+                .map_err(|_| format!("invalid number {}", &rest[..len]))?;
+            (Token::Number(number), len)
+        } else if c.is_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            (Token::Ident(rest[..len].to_string()), len)
+        } else {
+            return Err(format!("unexpected character '{}'", c));
+        };
+        tokens.push(token);
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Literal(PrimitiveContextValue),
+    Variable(String),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Compare(&'static str, Box<Expr>, Box<Expr>),
+}
+
+/// Recursive descent over the tokens, from the loosest operator (`||`) to the tightest (`!`).
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        let matches = matches!(self.peek(), Some(Token::Op(o)) if *o == op);
+        if matches {
+            self.pos += 1;
+        }
+        matches
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.eat("||") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.not()?;
+        while self.eat("&&") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        match self.eat("!") {
+            true => Ok(Expr::Not(Box::new(self.not()?))),
+            false => self.compare(),
+        }
+    }
+
+    fn compare(&mut self) -> Result<Expr, String> {
+        let lhs = self.operand()?;
+        match self.peek() {
+            Some(Token::Op(op)) if ["==", "!=", "<", "<=", ">", ">="].contains(op) => {
+                let op = *op;
+                self.pos += 1;
+                Ok(Expr::Compare(op, Box::new(lhs), Box::new(self.operand()?)))
+            }
+            _ => Ok(lhs),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Literal(PrimitiveContextValue::Float(n))),
+            Some(Token::Str(s)) => Ok(Expr::Literal(PrimitiveContextValue::String(s))),
+            Some(Token::Ident(i)) if i == "true" || i == "false" => {
+                Ok(Expr::Literal(PrimitiveContextValue::Bool(i == "true")))
+            }
+            Some(Token::Ident(i)) => Ok(Expr::Variable(i)),
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err("missing ')'".to_string()),
+                }
+            }
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Err("unexpected end of the expression".to_string()),
+        }
+    }
+}
+
+/// A parsed filter expression, see [`self`].
+#[derive(Debug, Clone)]
+pub struct ContextFilter {
+    source: String,
+    expr: Expr,
+}
+
+impl ContextFilter {
+    pub fn parse(source: &str) -> Result<Self, FilterError> {
+        let syntax = |msg: String| FilterError::Syntax(source.to_string(), msg);
+        let mut parser = Parser {
+            tokens: tokenize(source).map_err(syntax)?,
+            pos: 0,
+        };
+        let expr = parser.or().map_err(syntax)?;
+        if let Some(token) = parser.peek() {
+            return Err(syntax(format!("unexpected {:?}", token)));
+        }
+        Ok(Self {
+            source: source.to_string(),
+            expr,
+        })
+    }
+
+    /// Whether `ctx` passes the filter.
+    pub fn matches(&self, ctx: &Context) -> Result<bool, FilterError> {
+        boolean(&self.expr, ctx)
+    }
+}
+
+fn boolean(expr: &Expr, ctx: &Context) -> Result<bool, FilterError> {
+    match evaluate(expr, ctx)? {
+        PrimitiveContextValue::Bool(b) => Ok(b),
+        other => Err(FilterError::NotBoolean(other.as_string())),
+    }
+}
+
+fn evaluate(expr: &Expr, ctx: &Context) -> Result<PrimitiveContextValue, FilterError> {
+    let value = match expr {
+        Expr::Literal(value) => value.clone(),
+        Expr::Variable(key) => ctx
+            .get(key)
+            .and_then(|v| v.to_prim(ctx).ok())
+            .ok_or_else(|| FilterError::Unresolved(key.to_string()))?,
+        Expr::Not(expr) => PrimitiveContextValue::Bool(!boolean(expr, ctx)?),
+        // Short-circuits, so `has_x && x > 1` doesn't fail on the contexts without `x`.
+        Expr::And(lhs, rhs) => {
+            PrimitiveContextValue::Bool(boolean(lhs, ctx)? && boolean(rhs, ctx)?)
+        }
+        Expr::Or(lhs, rhs) => PrimitiveContextValue::Bool(boolean(lhs, ctx)? || boolean(rhs, ctx)?),
+        Expr::Compare(op, lhs, rhs) => {
+            let ordering = compare(&evaluate(lhs, ctx)?, &evaluate(rhs, ctx)?)?;
+            PrimitiveContextValue::Bool(match *op {
+                "==" => ordering == Ordering::Equal,
+                "!=" => ordering != Ordering::Equal,
+                "<" => ordering == Ordering::Less,
+                "<=" => ordering != Ordering::Greater,
+                ">" => ordering == Ordering::Greater,
+                _ => ordering != Ordering::Less,
+            })
+        }
+    };
+    Ok(value)
+}
+
+fn number(value: &PrimitiveContextValue) -> Option<f64> {
+    match value {
+        PrimitiveContextValue::Int(i) => Some(*i as f64),
+        PrimitiveContextValue::Float(f) => Some(*f),
+        PrimitiveContextValue::String(s) => s.trim().parse().ok(),
+        PrimitiveContextValue::Bool(_) | PrimitiveContextValue::Date(_) => None,
+    }
+}
+
+fn compare(
+    lhs: &PrimitiveContextValue,
+    rhs: &PrimitiveContextValue,
+) -> Result<Ordering, FilterError> {
+    use PrimitiveContextValue::*;
+    let ordering = match (lhs, rhs) {
+        (String(l), String(r)) => Some(l.cmp(r)),
+        (Date(l), Date(r)) => Some(l.cmp(r)),
+        (Date(l), String(r)) => parse_date(r).map(|r| l.cmp(&r)),
+        (String(l), Date(r)) => parse_date(l).map(|l| l.cmp(r)),
+        (Date(_), _) | (_, Date(_)) => None,
+        (Bool(l), Bool(r)) => Some(l.cmp(r)),
+        (Bool(_), _) | (_, Bool(_)) => None,
+        _ => number(lhs)
+            .zip(number(rhs))
+            .and_then(|(l, r)| l.partial_cmp(&r)),
+    };
+    ordering.ok_or_else(|| FilterError::Incomparable(lhs.as_string(), rhs.as_string()))
+}
+
+impl<'de> Deserialize<'de> for ContextFilter {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        ContextFilter::parse(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for ContextFilter {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::runs::RunConfig;
+    use crate::data::GeoDeg;
+    use crate::processing::context::ContextValue;
+    use crate::sites::Site;
+    use std::collections::HashMap;
+
+    fn context() -> Context {
+        Context {
+            site: Site {
+                id: 42,
+                lon: GeoDeg::from(10.0),
+                lat: GeoDeg::from(7.5),
+                covariates: HashMap::from([("harvested_area".to_string(), 150.0)]),
+                attributes: Default::default(),
+                weight: None,
+            },
+            run: RunConfig {
+                name: "r1".to_string(),
+                extra: HashMap::from([(
+                    "crop".to_string(),
+                    ContextValue::Prim(PrimitiveContextValue::String("MZ".to_string())),
+                )]),
+                ..Default::default()
+            },
+            provided: Default::default(),
+        }
+    }
+
+    fn matches(source: &str) -> Result<bool, FilterError> {
+        ContextFilter::parse(source).unwrap().matches(&context())
+    }
+
+    #[test]
+    fn test_filter() {
+        assert_eq!(matches("lat > 5 && harvested_area >= 100"), Ok(true));
+        assert_eq!(matches("lat > 5 && harvested_area >= 200"), Ok(false));
+        assert_eq!(matches("lat < -5 || crop == 'MZ'"), Ok(true));
+        assert_eq!(matches("!(crop != \"MZ\") && site_id == 42"), Ok(true));
+        assert_eq!(matches("lon<=10&&lon>=10"), Ok(true));
+        assert_eq!(matches("crop == 'MZ' || unknown > 1"), Ok(true));
+        assert_eq!(
+            matches("unknown > 1"),
+            Err(FilterError::Unresolved("unknown".to_string()))
+        );
+        assert!(matches!(
+            matches("crop > 1"),
+            Err(FilterError::Incomparable(..))
+        ));
+        assert!(matches!(matches("lat"), Err(FilterError::NotBoolean(_))));
+
+        for invalid in [
+            "",
+            "lat >",
+            "(lat > 5",
+            "lat > 5)",
+            "lat = 5",
+            "crop == 'MZ",
+        ] {
+            assert!(ContextFilter::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -1,6 +1,100 @@
 use crate::config;
-use crate::processing::context::Context;
-use crate::sites::{Site, SiteGenerator};
+use crate::config::providers::ContextProviderConfig;
+use crate::cultivar::CultivarSelector;
+use crate::fertilizer::FertilizerSchedule;
+use crate::planting::calendar::CropCalendar;
+use crate::planting::window::PlantingWindow;
+use crate::processing::checkpoint::CompletedContexts;
+use crate::processing::context::provider::ContextProvider;
+use crate::processing::context::{Context, ContextValue, PrimitiveContextValue};
+use crate::sites::{Site, SiteGenError, SiteGenerator};
+use crate::soil::sol::SoilProfile;
+use crate::soil::SoilLibrary;
+use crate::utils::polygons::PolygonIndex;
+use crate::utils::raster::RasterSampler;
+use crate::weather::stations::StationIndex;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// How many of the errors of the site source are kept to be reported. The others are only counted.
+const MAX_REPORTED_SITE_ERRORS: usize = 10;
+
+#[derive(Debug, Error)]
+#[error("Failed to open {path} of variable {key} of run {run}: {source}")]
+pub struct SiteVariableError {
+    run: String,
+    key: String,
+    path: PathBuf,
+    source: gdal::errors::GdalError,
+}
+
+/// Dataset a variable is read from at the site of every context.
+enum SiteLookup {
+    /// A raster and its band (**ZERO-BASED**).
+    Raster(RasterSampler, usize),
+    /// Polygons of a layer, shared by the variables of every run reading the same field of it.
+    Vector(Arc<PolygonIndex>),
+}
+
+/// Key of the polygons of a [`SiteLookup::Vector`]: the file, layer and field.
+type VectorKey = (PathBuf, Option<String>, String);
+
+/// A [`ContextValue::Raster`] or [`ContextValue::Vector`] variable of a run, with its dataset opened.
+struct SiteVariable {
+    key: String,
+    lookup: SiteLookup,
+}
+
+/// Sites and errors yielded by the site source so far.
+#[derive(Debug, Default)]
+pub struct SiteSourceSummary {
+    pub sites: usize,
+    pub errors: usize,
+    /// The first errors, up to `MAX_REPORTED_SITE_ERRORS`.
+    pub first_errors: Vec<SiteGenError>,
+}
+
+/// Contexts skipped by the `filter` of their run, see [`super::filter`].
+#[derive(Debug, Default)]
+pub struct FilterSummary {
+    pub skipped: usize,
+    /// Contexts the filter couldn't be evaluated on, also skipped.
+    pub errors: usize,
+    /// The first errors along with their context, up to `MAX_REPORTED_SITE_ERRORS`.
+    pub first_errors: Vec<String>,
+}
+
+impl FilterSummary {
+    pub fn print(&self) {
+        println!(
+            "Filters: {} context(s) skipped, {} error(s)",
+            self.skipped, self.errors
+        );
+        for e in &self.first_errors {
+            println!("  - {}", e);
+        }
+        if self.errors > self.first_errors.len() {
+            println!("  ... and {} more", self.errors - self.first_errors.len());
+        }
+    }
+}
+
+impl SiteSourceSummary {
+    pub fn print(&self) {
+        println!(
+            "Site source: {} site(s), {} error(s)",
+            self.sites, self.errors
+        );
+        for e in &self.first_errors {
+            println!("  - {}", e);
+        }
+        if self.errors > self.first_errors.len() {
+            println!("  ... and {} more", self.errors - self.first_errors.len());
+        }
+    }
+}
 
 /// Given a site source configuration, ContextGenerator will generate a sequence of Contexts to be processed.
 ///
@@ -10,11 +104,25 @@ use crate::sites::{Site, SiteGenerator};
 /// TODO: decouple from config. Maybe create a registry for SiteGenerators (abstract factory?) and couple it with config instead. Will allow for plugin extensibility later.
 pub struct ContextGenerator {
     site_generator: Box<dyn SiteGenerator>,
+    site_summary: SiteSourceSummary,
     curr_site: Option<Site>,
     site_sample_size: Option<usize>,
     current_site_count: usize,
     runs: Vec<config::runs::RunConfig>,
+    station_indexes: Vec<Option<Arc<StationIndex>>>,
+    soil_libraries: Vec<Option<Arc<SoilLibrary>>>,
+    planting_windows: Vec<Option<PlantingWindow>>,
+    fertilizer_schedules: Vec<Option<FertilizerSchedule>>,
+    cultivar_selectors: Vec<Option<CultivarSelector>>,
+    crop_calendars: Vec<Option<CropCalendar>>,
+    site_variables: Vec<Vec<SiteVariable>>,
+    /// Providers of the [`ContextValue::Provider`] variables of every run, by variable.
+    providers: Vec<Vec<(String, Box<dyn ContextProvider>)>>,
     current_run: usize,
+    /// Contexts completed by a previous run, skipped when resuming it.
+    completed: CompletedContexts,
+    skipped_completed: usize,
+    filter_summary: FilterSummary,
 }
 
 impl ContextGenerator {
@@ -24,44 +132,435 @@ impl ContextGenerator {
         runs: Vec<config::runs::RunConfig>,
         site_sample_size: Option<usize>,
     ) -> Result<Self, Box<dyn std::error::Error>> {
+        let station_indexes = runs
+            .iter()
+            .map(|run| {
+                run.weather_stations
+                    .as_ref()
+                    .map(|cfg| StationIndex::load(cfg).map(Arc::new))
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let soil_libraries = runs
+            .iter()
+            .map(|run| {
+                run.soil
+                    .as_ref()
+                    .map(|cfg| SoilLibrary::load(cfg).map(Arc::new))
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let planting_windows = runs
+            .iter()
+            .map(|run| {
+                run.planting_window
+                    .as_ref()
+                    .map(PlantingWindow::load)
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let fertilizer_schedules = runs
+            .iter()
+            .map(|run| {
+                run.fertilizer
+                    .as_ref()
+                    .map(FertilizerSchedule::load)
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let cultivar_selectors = runs
+            .iter()
+            .map(|run| {
+                run.cultivar
+                    .as_ref()
+                    .map(CultivarSelector::load)
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let crop_calendars = runs
+            .iter()
+            .map(|run| {
+                run.crop_calendar
+                    .as_ref()
+                    .map(CropCalendar::load)
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut vectors = HashMap::new();
+        let site_variables = runs
+            .iter()
+            .map(|run| open_site_variables(run, &mut vectors))
+            .collect::<Result<Vec<_>, _>>()?;
+
         Ok(ContextGenerator {
             site_generator,
+            site_summary: SiteSourceSummary::default(),
             curr_site: None,
             site_sample_size,
             current_site_count: 0,
             runs,
+            station_indexes,
+            soil_libraries,
+            planting_windows,
+            fertilizer_schedules,
+            cultivar_selectors,
+            crop_calendars,
+            site_variables,
+            providers: Vec::new(),
             current_run: 0,
+            completed: CompletedContexts::new(),
+            skipped_completed: 0,
+            filter_summary: FilterSummary::default(),
         })
     }
-}
 
-impl Iterator for ContextGenerator {
-    type Item = Context;
+    /// Skips the contexts in `completed`, as if they had already been generated (so they still count towards the
+    /// sample size).
+    pub fn with_completed(mut self, completed: CompletedContexts) -> Self {
+        self.completed = completed;
+        self
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if let Some(sample_size) = self.site_sample_size {
-            if self.current_site_count >= sample_size {
-                return None;
+    /// Creates the providers of the [`ContextValue::Provider`] variables of the runs, from the ones of the config by
+    /// run name and variable.
+    pub fn with_providers(
+        mut self,
+        providers: &HashMap<String, HashMap<String, ContextProviderConfig>>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        self.providers = self
+            .runs
+            .iter()
+            .map(|run| {
+                let Some(providers) = providers.get(&run.name) else {
+                    return Ok(Vec::new());
+                };
+                providers
+                    .iter()
+                    .map(|(key, provider)| match provider.build() {
+                        Ok(built) => Ok((key.clone(), built)),
+                        Err(e) => Err(format!(
+                            "Failed to create provider {} of variable {} of run {}: {}",
+                            provider.name, key, run.name, e
+                        )
+                        .into()),
+                    })
+                    .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(self)
+    }
+
+    /// How many contexts were skipped because they were completed by a previous run.
+    pub fn skipped_completed(&self) -> usize {
+        self.skipped_completed
+    }
+
+    /// Sites and errors yielded by the site source so far.
+    pub fn site_summary(&self) -> &SiteSourceSummary {
+        &self.site_summary
+    }
+
+    /// Contexts skipped by the filters of their runs so far.
+    pub fn filter_summary(&self) -> &FilterSummary {
+        &self.filter_summary
+    }
+
+    /// Whether `ctx` passes the filter of its run, counting the ones that don't.
+    fn accepts(&mut self, ctx: &Context) -> bool {
+        let Some(filter) = &ctx.run.filter else {
+            return true;
+        };
+        match filter.matches(ctx) {
+            Ok(matches) => {
+                self.filter_summary.skipped += !matches as usize;
+                matches
+            }
+            Err(e) => {
+                self.filter_summary.errors += 1;
+                if self.filter_summary.first_errors.len() < MAX_REPORTED_SITE_ERRORS {
+                    let error = format!("{}: {}", ctx.location(), e);
+                    self.filter_summary.first_errors.push(error);
+                }
+                false
+            }
+        }
+    }
+
+    /// The next site of the site source, counting (and skipping) the errors on the way.
+    fn next_site(&mut self) -> Option<Site> {
+        loop {
+            match self.site_generator.next()? {
+                Ok(site) => {
+                    self.site_summary.sites += 1;
+                    return Some(site);
+                }
+                Err(e) => {
+                    self.site_summary.errors += 1;
+                    if self.site_summary.first_errors.len() < MAX_REPORTED_SITE_ERRORS {
+                        self.site_summary.first_errors.push(e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Resolves the values derived from the site for the run at `run_idx`, such as the nearest weather station.
+    fn provide(&self, run_idx: usize, site: &Site) -> HashMap<String, ContextValue> {
+        let mut provided = HashMap::new();
+
+        if let Some(simulation) = &self.runs[run_idx].simulation {
+            provided.extend(simulation.values());
+        }
+
+        if let Some(selector) = &self.cultivar_selectors[run_idx] {
+            if let Some(cultivar) = selector.select(site) {
+                provided.extend(cultivar.values());
             }
         }
 
-        if self.current_run >= self.runs.len() {
-            self.current_run = 0;
-            self.curr_site = None;
+        if let Some(calendar) = &self.crop_calendars[run_idx] {
+            if let Some(values) = calendar.values(site) {
+                provided.extend(values);
+            }
+        }
+
+        for variable in &self.site_variables[run_idx] {
+            let (lon, lat) = (site.lon.as_f64(), site.lat.as_f64());
+            let value = match &variable.lookup {
+                SiteLookup::Raster(sampler, band) => sampler
+                    .sample(*band, lon, lat)
+                    .ok()
+                    .flatten()
+                    .map(PrimitiveContextValue::Float),
+                SiteLookup::Vector(polygons) => polygons
+                    .attribute_at(lon, lat)
+                    .map(|value| PrimitiveContextValue::String(value.to_string())),
+            };
+            if let Some(value) = value {
+                provided.insert(variable.key.clone(), ContextValue::Prim(value));
+            }
+        }
+
+        for (key, provider) in self.providers.get(run_idx).into_iter().flatten() {
+            if let Some(value) = provider.provide(site) {
+                provided.insert(key.clone(), value);
+            }
+        }
+
+        if let Some(config) = &self.runs[run_idx].wth_code {
+            if let Some(code) = config.code(site) {
+                provided.insert(
+                    "wth_code".to_string(),
+                    ContextValue::Prim(PrimitiveContextValue::String(code)),
+                );
+            }
         }
 
-        if self.curr_site.is_none() {
-            self.curr_site = self.site_generator.next();
-            self.curr_site.as_ref()?;
+        if let Some(index) = &self.station_indexes[run_idx] {
+            if let Some(m) = index.nearest(site.lat.as_f64(), site.lon.as_f64()) {
+                let mut insert = |k: &str, v| {
+                    provided.insert(k.to_string(), ContextValue::Prim(v));
+                };
+                insert("wsta", PrimitiveContextValue::String(m.station.id.clone()));
+                insert(
+                    "wth_file",
+                    PrimitiveContextValue::String(m.station.file.clone()),
+                );
+                insert(
+                    "wsta_distance_km",
+                    PrimitiveContextValue::Float(m.distance_km),
+                );
+            }
         }
 
-        let run = self.runs[self.current_run].clone();
-        self.current_run += 1;
-        self.current_site_count += 1;
-        Some(Context {
-            site: self.curr_site.clone()?,
-            run,
+        provided
+    }
+
+    /// Looks up the soil profile of `ctx` and exposes it. The soil ID may depend on other variables, so this runs
+    /// on the otherwise complete context. If the ID can't be resolved or the profile doesn't exist, nothing is
+    /// provided and the processor reports it.
+    fn provide_soil(&self, run_idx: usize, ctx: &mut Context) {
+        let (Some(library), Some(config)) = (&self.soil_libraries[run_idx], &ctx.run.soil) else {
+            return;
+        };
+        let Ok(id) = config.resolve_id(ctx) else {
+            return;
+        };
+        if let Some(profile) = library.get(&id) {
+            ctx.provided.extend(soil_values(profile));
+        }
+    }
+
+    /// Derives the planting window of `ctx`. Like [`ContextGenerator::provide_soil`], the climatology lookup key
+    /// may depend on other variables, and nothing is provided if the window can't be derived.
+    fn provide_planting_window(&self, run_idx: usize, ctx: &mut Context) {
+        let Some(window) = &self.planting_windows[run_idx] else {
+            return;
+        };
+        if let Some((start, end)) = window.compute(ctx) {
+            for (k, v) in [("pdate_start", start), ("pdate_end", end)] {
+                ctx.provided.insert(
+                    k.to_string(),
+                    ContextValue::Prim(PrimitiveContextValue::Int(v as i64)),
+                );
+            }
+        }
+    }
+
+    /// Expands the fertilizer schedule of `ctx`, whose rate may come from a lookup keyed by other variables.
+    /// Nothing is provided if the rate is not available for the site.
+    fn provide_fertilizer(&self, run_idx: usize, ctx: &mut Context) {
+        let Some(schedule) = &self.fertilizer_schedules[run_idx] else {
+            return;
+        };
+        if let Some(values) = schedule.expand(ctx) {
+            ctx.provided.extend(values);
+        }
+    }
+}
+
+/// Opens the datasets of the [`ContextValue::Raster`] and [`ContextValue::Vector`] variables of `run`. The polygons of
+/// `vectors` are reused, and the ones read are added to it.
+fn open_site_variables(
+    run: &config::runs::RunConfig,
+    vectors: &mut HashMap<VectorKey, Arc<PolygonIndex>>,
+) -> Result<Vec<SiteVariable>, SiteVariableError> {
+    let mut variables = Vec::new();
+    for (key, value) in &run.extra {
+        let error = |path: &PathBuf, source| SiteVariableError {
+            run: run.name.clone(),
+            key: key.clone(),
+            path: path.clone(),
+            source,
+        };
+        let lookup = match value {
+            ContextValue::Raster(value) => {
+                let raster = &value.raster;
+                let sampler = RasterSampler::open(&raster.file.to_string_lossy())
+                    .map_err(|e| error(&raster.file, e))?;
+                SiteLookup::Raster(sampler, raster.band.saturating_sub(1))
+            }
+            ContextValue::Vector(value) => {
+                let vector = &value.vector;
+                let vector_key = (
+                    vector.file.clone(),
+                    vector.layer.clone(),
+                    vector.field.clone(),
+                );
+                let polygons = match vectors.get(&vector_key) {
+                    Some(polygons) => polygons.clone(),
+                    None => {
+                        let polygons = PolygonIndex::load(
+                            &vector.file.to_string_lossy(),
+                            vector.layer.clone(),
+                            &vector.field,
+                        )
+                        .map_err(|e| error(&vector.file, e))?;
+                        let polygons = Arc::new(polygons);
+                        vectors.insert(vector_key, polygons.clone());
+                        polygons
+                    }
+                };
+                SiteLookup::Vector(polygons)
+            }
+            _ => continue,
+        };
+        variables.push(SiteVariable {
+            key: key.clone(),
+            lookup,
+        });
+    }
+    Ok(variables)
+}
+
+fn soil_values(profile: &SoilProfile) -> HashMap<String, ContextValue> {
+    let value = |v: &String| {
+        ContextValue::Prim(match v.parse::<f64>() {
+            Ok(f) => PrimitiveContextValue::Float(f),
+            Err(_) => PrimitiveContextValue::String(v.clone()),
         })
+    };
+
+    let mut values = HashMap::new();
+    for (k, v) in &profile.fields {
+        values.insert(format!("soil_{}", k), value(v));
+    }
+    for (n, layer) in profile.layers.iter().enumerate() {
+        for (k, v) in layer {
+            values.insert(format!("soil_{}_{}", k, n + 1), value(v));
+        }
+    }
+    values.insert(
+        "soil_id".to_string(),
+        ContextValue::Prim(PrimitiveContextValue::String(profile.id.clone())),
+    );
+    values.insert(
+        "soil_layers".to_string(),
+        ContextValue::Prim(PrimitiveContextValue::Int(profile.layers.len() as i64)),
+    );
+    values.insert(
+        "soil_profile".to_string(),
+        ContextValue::Prim(PrimitiveContextValue::String(profile.text.clone())),
+    );
+    values
+}
+
+impl Iterator for ContextGenerator {
+    type Item = Context;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(sample_size) = self.site_sample_size {
+                if self.current_site_count >= sample_size {
+                    return None;
+                }
+            }
+
+            if self.current_run >= self.runs.len() {
+                self.current_run = 0;
+                self.curr_site = None;
+            }
+
+            if self.curr_site.is_none() {
+                self.curr_site = self.next_site();
+                self.curr_site.as_ref()?;
+            }
+
+            let run_idx = self.current_run;
+            self.current_run += 1;
+            self.current_site_count += 1;
+
+            let site = self.curr_site.clone()?;
+            let completed = self
+                .completed
+                .get(&self.runs[run_idx].name)
+                .is_some_and(|sites| sites.contains(&site.id));
+            if completed {
+                self.skipped_completed += 1;
+                continue;
+            }
+
+            let run = self.runs[run_idx].clone();
+            let provided = self.provide(run_idx, &site);
+            let mut ctx = Context {
+                site,
+                run,
+                provided,
+            };
+            self.provide_soil(run_idx, &mut ctx);
+            self.provide_planting_window(run_idx, &mut ctx);
+            self.provide_fertilizer(run_idx, &mut ctx);
+            if !self.accepts(&ctx) {
+                continue;
+            }
+            return Some(ctx);
+        }
     }
 }
 
@@ -76,10 +575,15 @@ mod tests {
 
     #[test]
     fn context_gen() {
-        let site_src: Box<dyn SiteGenerator> = Box::new((0..200).map(|id| Site {
-            id,
-            lon: GeoDeg::from(0.0),
-            lat: GeoDeg::from(0.0),
+        let site_src: Box<dyn SiteGenerator> = Box::new((0..200).map(|id| {
+            Ok(Site {
+                id,
+                lon: GeoDeg::from(0.0),
+                lat: GeoDeg::from(0.0),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
+            })
         }));
 
         let runs = vec![
@@ -87,11 +591,13 @@ mod tests {
                 name: String::from("r1"),
                 extra: HashMap::new(),
                 template: PathBuf::from("dummy"),
+                ..Default::default()
             },
             config::runs::RunConfig {
                 name: String::from("r2"),
                 extra: HashMap::new(),
                 template: PathBuf::from("dummy"),
+                ..Default::default()
             },
         ];
 
@@ -113,21 +619,181 @@ mod tests {
         assert_eq!(max, 199);
     }
 
+    #[test]
+    fn test_completed() {
+        let site_src: Box<dyn SiteGenerator> = Box::new((0..10).map(|id| {
+            Ok(Site {
+                id,
+                lon: GeoDeg::from(0.0),
+                lat: GeoDeg::from(0.0),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
+            })
+        }));
+
+        let runs = ["r1", "r2"]
+            .map(|name| config::runs::RunConfig {
+                name: String::from(name),
+                extra: HashMap::new(),
+                template: PathBuf::from("dummy"),
+                ..Default::default()
+            })
+            .to_vec();
+        let completed = CompletedContexts::from([
+            ("r1".to_string(), (0..5).collect()),
+            ("r2".to_string(), [0].into()),
+        ]);
+
+        let mut generator = ContextGenerator::new(site_src, runs, Some(12))
+            .unwrap()
+            .with_completed(completed);
+        let remaining: Vec<(String, i32)> = generator
+            .by_ref()
+            .map(|ctx| (ctx.run.name, ctx.site.id))
+            .collect();
+        assert_eq!(generator.skipped_completed(), 6);
+        assert_eq!(remaining.len(), 6);
+        assert_eq!(remaining[0], ("r2".to_string(), 1));
+        assert_eq!(remaining[5], ("r2".to_string(), 5));
+    }
+
+    #[test]
+    fn test_filter() {
+        let site_src: Box<dyn SiteGenerator> = Box::new((0..10).map(|id| {
+            Ok(Site {
+                id,
+                lon: GeoDeg::from(0.0),
+                lat: GeoDeg::from(id as f64),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
+            })
+        }));
+
+        let runs = [("r1", "lat >= 5"), ("r2", "unknown > 1")]
+            .map(|(name, filter)| config::runs::RunConfig {
+                name: String::from(name),
+                template: PathBuf::from("dummy"),
+                filter: Some(serde_json::from_value(serde_json::json!(filter)).unwrap()),
+                ..Default::default()
+            })
+            .to_vec();
+
+        let mut generator = ContextGenerator::new(site_src, runs, None).unwrap();
+        let sites: Vec<i32> = generator.by_ref().map(|ctx| ctx.site.id).collect();
+        assert_eq!(sites, vec![5, 6, 7, 8, 9]);
+        assert_eq!(generator.filter_summary().skipped, 5);
+        assert_eq!(generator.filter_summary().errors, 10);
+    }
+
+    #[test]
+    fn test_raster_variables() {
+        // The test raster holds site IDs, so the sampled value is the ID of the site.
+        let sites = [(3898947, 12.2919, 14.7917), (0, -50.0, -50.0)];
+        let site_src: Box<dyn SiteGenerator> = Box::new(sites.into_iter().map(|(id, lon, lat)| {
+            Ok(Site {
+                id,
+                lon: GeoDeg::from(lon),
+                lat: GeoDeg::from(lat),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
+            })
+        }));
+
+        let raster = r#"{ "raster": { "file": "testdata/DSSAT-Soils.tif" } }"#;
+        let runs = vec![config::runs::RunConfig {
+            name: String::from("r1"),
+            extra: HashMap::from([(
+                "soil_cell".to_string(),
+                serde_json::from_str(raster).unwrap(),
+            )]),
+            template: PathBuf::from("dummy"),
+            ..Default::default()
+        }];
+
+        let contexts: Vec<Context> = ContextGenerator::new(site_src, runs, None)
+            .unwrap()
+            .collect();
+        assert!(matches!(
+            contexts[0].get("soil_cell"),
+            Some(ContextValue::Prim(PrimitiveContextValue::Float(id))) if id == 3898947.0
+        ));
+        assert!(contexts[1].get("soil_cell").is_none());
+
+        let missing = config::runs::RunConfig {
+            name: String::from("r1"),
+            extra: HashMap::from([(
+                "soil_cell".to_string(),
+                serde_json::from_str(r#"{ "raster": { "file": "missing.tif" } }"#).unwrap(),
+            )]),
+            template: PathBuf::from("dummy"),
+            ..Default::default()
+        };
+        let site_src: Box<dyn SiteGenerator> =
+            Box::new(std::iter::empty::<Result<Site, SiteGenError>>());
+        assert!(ContextGenerator::new(site_src, vec![missing], None).is_err());
+    }
+
     #[test]
     fn test_sample_size() {
-        let site_src: Box<dyn SiteGenerator> = Box::new((0..200).map(|id| Site {
-            id,
-            lon: GeoDeg::from(0.0),
-            lat: GeoDeg::from(0.0),
+        let site_src: Box<dyn SiteGenerator> = Box::new((0..200).map(|id| {
+            Ok(Site {
+                id,
+                lon: GeoDeg::from(0.0),
+                lat: GeoDeg::from(0.0),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
+            })
         }));
 
         let runs = vec![config::runs::RunConfig {
             name: String::from("r1"),
             extra: HashMap::new(),
             template: PathBuf::from("dummy"),
+            ..Default::default()
         }];
 
         let generator = ContextGenerator::new(site_src, runs, Some(50)).unwrap();
         assert_eq!(generator.count(), 50);
     }
+
+    #[test]
+    fn test_site_errors() {
+        let site_src: Box<dyn SiteGenerator> = Box::new((0..30).map(|id| match id % 2 {
+            0 => Ok(Site {
+                id,
+                lon: GeoDeg::from(0.0),
+                lat: GeoDeg::from(0.0),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
+            }),
+            _ => Err(SiteGenError::Row {
+                row: id as usize,
+                message: "Invalid site ID".to_string(),
+            }),
+        }));
+
+        let runs = vec![config::runs::RunConfig {
+            name: String::from("r1"),
+            extra: HashMap::new(),
+            template: PathBuf::from("dummy"),
+            ..Default::default()
+        }];
+
+        let mut generator = ContextGenerator::new(site_src, runs, None).unwrap();
+        assert!(generator.by_ref().all(|ctx| ctx.site.id % 2 == 0));
+
+        let summary = generator.site_summary();
+        assert_eq!(summary.sites, 15);
+        assert_eq!(summary.errors, 15);
+        assert_eq!(summary.first_errors.len(), MAX_REPORTED_SITE_ERRORS);
+        assert_eq!(
+            summary.first_errors[0].to_string(),
+            "Row 1: Invalid site ID"
+        );
+    }
 }
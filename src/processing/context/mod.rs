@@ -1,10 +1,18 @@
+pub mod date;
+pub mod filter;
 mod gen;
+pub mod provider;
 
 use super::PipelineData;
 use crate::config;
+use crate::data::GeoDeg;
 use crate::sites::Site;
+use crate::utils::rng::Rng;
+use chrono::NaiveDate;
+use date::DateOffset;
 pub use gen::ContextGenerator;
 use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::LazyLock;
 use thiserror::Error;
@@ -28,15 +36,39 @@ mod tests {
                 id: 0,
                 lon: GeoDeg::from(15.222),
                 lat: GeoDeg::from(-15.23133),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             run: config::runs::RunConfig {
                 name: String::from("r1"),
                 extra: HashMap::new(),
                 template: PathBuf::from("dummy"),
+                ..Default::default()
             },
+            provided: HashMap::new(),
         };
 
         assert_eq!(ctx.dir(&wd), PathBuf::from("/tmp/r1/15_2220N/15_2313W"));
+
+        let grouped = Context {
+            site: Site {
+                attributes: HashMap::from([
+                    ("country".to_string(), "NER".to_string()),
+                    ("adm1".to_string(), "Tahoua/North".to_string()),
+                ]),
+                ..ctx.site.clone()
+            },
+            run: config::runs::RunConfig {
+                directory_groups: vec!["country".into(), "adm1".into(), "adm2".into()],
+                ..ctx.run.clone()
+            },
+            provided: HashMap::new(),
+        };
+        assert_eq!(
+            grouped.dir(&wd),
+            PathBuf::from("/tmp/r1/NER/Tahoua_North/unknown/15_2220N/15_2313W")
+        );
     }
 
     #[test]
@@ -46,6 +78,9 @@ mod tests {
                 id: 0,
                 lon: GeoDeg::from(15.222),
                 lat: GeoDeg::from(-15.23133),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             run: config::runs::RunConfig {
                 name: String::from("r1"),
@@ -76,7 +111,9 @@ mod tests {
                 .iter()
                 .cloned()
                 .collect(),
+                ..Default::default()
             },
+            provided: HashMap::new(),
         };
 
         assert_eq!(
@@ -90,6 +127,180 @@ mod tests {
             ))
         );
     }
+
+    #[test]
+    fn test_date_offsets() {
+        let value = |s: &str| serde_json::from_str::<ContextValue>(s).unwrap();
+        let ctx = Context {
+            site: Site {
+                id: 0,
+                lon: GeoDeg::from(15.222),
+                lat: GeoDeg::from(-15.23133),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
+            },
+            run: config::runs::RunConfig {
+                name: String::from("r1"),
+                template: PathBuf::from("dummy"),
+                extra: [
+                    ("pdate", value(r#""2024-02-01""#)),
+                    ("sdate", value(r#""${pdate - 15d}""#)),
+                    ("window", value(r#""${sdate}/${pdate+1m}""#)),
+                    ("hdate", value(r#""${pdate + 30d}""#)),
+                    ("bad", value(r#""${name + 30d}""#)),
+                ]
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+                ..Default::default()
+            },
+            provided: HashMap::new(),
+        };
+        let prim = |k: &str| ctx.run.extra[k].to_prim(&ctx);
+
+        assert_eq!(
+            prim("window").unwrap(),
+            PrimitiveContextValue::String("2024-01-17/2024-03-01".to_string())
+        );
+        assert_eq!(
+            prim("hdate").unwrap(),
+            PrimitiveContextValue::String("2024-03-02".to_string())
+        );
+        assert!(matches!(
+            prim("bad"),
+            Err(ContextEvaluationError::NotDate { .. })
+        ));
+        assert_eq!(
+            serde_json::to_string(&ctx.run.extra["sdate"]).unwrap(),
+            r#""${pdate - 15d}""#
+        );
+        assert!(serde_json::from_str::<TemplateString>(r#""${pdate + 99999999999d}""#).is_err());
+        assert_eq!(
+            serde_json::from_str::<PrimitiveContextValue>(r#""2024-02-01""#).unwrap(),
+            PrimitiveContextValue::Date(NaiveDate::from_ymd_opt(2024, 2, 1).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_random_values() {
+        let random: ContextValue =
+            serde_json::from_str(r#"{ "random": { "min": 0.9, "max": 1.1, "seed": 42 } }"#)
+                .unwrap();
+        let ctx = |id| Context {
+            site: Site {
+                id,
+                lon: GeoDeg::from(15.222),
+                lat: GeoDeg::from(-15.23133),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
+            },
+            run: config::runs::RunConfig {
+                name: String::from("r1"),
+                template: PathBuf::from("dummy"),
+                extra: HashMap::from([("factor".to_string(), random.clone())]),
+                ..Default::default()
+            },
+            provided: HashMap::new(),
+        };
+        let factor = |id| match ctx(id).get("factor").unwrap().to_prim(&ctx(id)) {
+            Ok(PrimitiveContextValue::Float(f)) => f,
+            other => panic!("{:?}", other),
+        };
+
+        assert_eq!(factor(1), factor(1));
+        assert_ne!(factor(1), factor(2));
+        assert!((0..100).map(factor).all(|f| (0.9..1.1).contains(&f)));
+    }
+
+    #[test]
+    fn test_site_attributes() {
+        let ctx = Context {
+            site: Site {
+                id: 0,
+                lon: GeoDeg::from(15.222),
+                lat: GeoDeg::from(-15.23133),
+                covariates: HashMap::from([("zone".to_string(), 2.0)]),
+                attributes: HashMap::from([
+                    ("region".to_string(), "Sahel".to_string()),
+                    ("zone".to_string(), "north".to_string()),
+                    ("cultivar".to_string(), "IB0001".to_string()),
+                ]),
+                weight: None,
+            },
+            run: config::runs::RunConfig {
+                name: String::from("r1"),
+                template: PathBuf::from("dummy"),
+                extra: HashMap::from([(
+                    "cultivar".to_string(),
+                    ContextValue::Prim(PrimitiveContextValue::String("IB0002".to_string())),
+                )]),
+                ..Default::default()
+            },
+            provided: HashMap::new(),
+        };
+        let text = |key| ctx.get(key).unwrap().to_prim(&ctx).unwrap().as_string();
+
+        assert_eq!(text("region"), "Sahel");
+        // Covariates and run values take precedence over the attributes.
+        assert_eq!(text("zone"), "2");
+        assert_eq!(text("cultivar"), "IB0002");
+        let tera = ctx.tera().unwrap();
+        assert_eq!(tera.get("region").unwrap(), "Sahel");
+        assert_eq!(tera.get("zone").unwrap(), 2.0);
+        assert_eq!(tera.get("cultivar").unwrap(), "IB0002");
+    }
+
+    #[test]
+    fn test_lists() {
+        let value = |s: &str| serde_json::from_str::<ContextValue>(s).unwrap();
+        let ctx = Context {
+            site: Site {
+                id: 0,
+                lon: GeoDeg::from(15.222),
+                lat: GeoDeg::from(-15.23133),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
+            },
+            run: config::runs::RunConfig {
+                name: String::from("r1"),
+                template: PathBuf::from("dummy"),
+                extra: [
+                    ("pdate", value(r#""2024-02-01""#)),
+                    ("depths", value("[5, 15, 30]")),
+                    (
+                        "fertilizers",
+                        value(
+                            r#"[
+                                { "date": "${pdate}", "amount": 30 },
+                                { "date": "${pdate + 30d}", "amount": 60.5 }
+                            ]"#,
+                        ),
+                    ),
+                ]
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+                ..Default::default()
+            },
+            provided: HashMap::new(),
+        };
+
+        let rendered = tera::Tera::one_off(
+            "{% for d in depths %}{{ d }} {% endfor %}\
+             {% for f in fertilizers %}{{ f.date }}={{ f.amount }};{% endfor %}",
+            &ctx.tera().unwrap(),
+            false,
+        )
+        .unwrap();
+        assert_eq!(rendered, "5 15 30 2024-02-01=30;2024-03-02=60.5;");
+        assert!(matches!(
+            ctx.run.extra["depths"].to_prim(&ctx),
+            Err(ContextEvaluationError::NotPrimitive)
+        ));
+    }
 }
 
 /// Holds the information about the execution of a single run on a specific site with its bound run configurations.
@@ -102,6 +313,30 @@ pub struct Context {
     #[allow(dead_code)]
     // The part of the code that uses this is not yet implemented, so it's not dead code.
     pub run: config::runs::RunConfig,
+
+    /// Values derived from the site when the context was generated (e.g. the nearest weather station).
+    /// Values in [`config::runs::RunConfig::extra`] take precedence over these.
+    pub provided: HashMap<String, ContextValue>,
+}
+
+/// Identifies which [`Context`] something happened in (run name, site ID and coordinates).
+/// Attached to errors so failures can be attributed to a context without re-running.
+#[derive(Debug, Clone)]
+pub struct ContextLocation {
+    pub run: String,
+    pub site_id: i32,
+    pub lon: GeoDeg,
+    pub lat: GeoDeg,
+}
+
+impl std::fmt::Display for ContextLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "run \"{}\", site {} ({}, {})",
+            self.run, self.site_id, self.lon, self.lat
+        )
+    }
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
@@ -110,6 +345,8 @@ pub enum PrimitiveContextValue {
     Bool(bool),
     Int(i64),
     Float(f64),
+    /// A `YYYY-MM-DD` date, which can be offset (see [`date`]).
+    Date(NaiveDate),
     String(String),
 }
 
@@ -118,6 +355,101 @@ pub enum PrimitiveContextValue {
 pub enum ContextValue {
     TemplateString(TemplateString),
     Prim(PrimitiveContextValue),
+    /// A list of values or records (e.g. the fertilizer applications of a treatment), for templates to iterate over
+    /// with `{% for %}`.
+    List(Vec<ListItem>),
+    /// A raster sampled at the site of every context of the run, by the context generator (see [`RasterValue`]).
+    Raster(RasterValue),
+    /// The attribute of the polygon the site of every context of the run is in, by the context generator (see
+    /// [`VectorValue`]).
+    Vector(VectorValue),
+    /// A random number, the same for a site on every run of the config (see [`RandomValue`]).
+    Random(RandomValue),
+    /// A value resolved at the site of every context of the run by a registered provider (see [`ProviderValue`]).
+    Provider(ProviderValue),
+    /// A list of records (e.g. the applications of a fertilizer schedule), for templates to iterate over with `{% for %}`.
+    /// Only produced by providers, never read from the config.
+    #[serde(skip_deserializing)]
+    Records(Vec<HashMap<String, PrimitiveContextValue>>),
+}
+
+/// A band of a raster, e.g. `{ "raster": { "file": "aez.tif", "band": 2 } }`. The value of the variable is the one of
+/// the pixel of the site, and the variable is left out for the sites out of the raster or on its nodata pixels.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RasterValue {
+    pub raster: RasterValueSource,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RasterValueSource {
+    pub file: PathBuf,
+
+    /// Band to read from (**ONE-BASED**).
+    #[serde(default = "default_band")]
+    pub band: usize,
+}
+
+fn default_band() -> usize {
+    1
+}
+
+/// A field of a polygon layer, e.g. `{ "vector": { "file": "admin.gpkg", "field": "GID_1" } }`. The value of the
+/// variable is the one of the first polygon containing the site, and the variable is left out for the sites out of
+/// every polygon or whose polygon has no value.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct VectorValue {
+    pub vector: VectorValueSource,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct VectorValueSource {
+    pub file: PathBuf,
+
+    /// Layer to read from, the first one of the dataset if not set.
+    #[serde(default)]
+    pub layer: Option<String>,
+
+    pub field: String,
+}
+
+/// A number drawn uniformly in `[min, max)`, e.g. `{ "random": { "min": 0.9, "max": 1.1, "seed": 42 } }` to perturb a
+/// parameter. The number only depends on the seed and on the ID of the site, so re-runs get the same ones.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RandomValue {
+    pub random: RandomValueSource,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RandomValueSource {
+    pub min: f64,
+    pub max: f64,
+    #[serde(default)]
+    pub seed: u64,
+}
+
+/// A value of a provider registered by a plugin (see [`provider`]), e.g.
+/// `{ "provider": { "type": "acme:soil-lookup", "table": "groups.csv" } }` or `{ "provider": "acme:soil-lookup" }` if
+/// it takes no options. The variable is left out for the sites the provider has no value for.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ProviderValue {
+    /// Identifier of the provider, either as is or under `type` along with its options. Resolved when the config is
+    /// loaded, see [`crate::config::providers`].
+    pub provider: serde_json::Value,
+}
+
+/// An item of a [`ContextValue::List`]. The values of records may be lists themselves.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum ListItem {
+    Value(ContextValue),
+    Record(HashMap<String, ContextValue>),
 }
 
 #[derive(Clone, Debug)]
@@ -127,12 +459,30 @@ pub struct TemplateString(Vec<TemplateStringFragment>);
 pub enum ContextEvaluationError {
     #[error("Placeholder '{0}' could not be resolved.")]
     Interpolation(String),
+    #[error("A list can't be used as a single value.")]
+    NotPrimitive,
+    #[error("{0} is only read for the variables of the runs.")]
+    NotSampled(PathBuf),
+    #[error("Provider {0} is only resolved for the variables of the runs.")]
+    NotProvided(String),
+    #[error("Placeholder '{key}' offsets '{value}', which is not a date.")]
+    NotDate { key: String, value: String },
+    #[error("Failed to evaluate variable '{key}': {source}")]
+    Variable {
+        key: String,
+        source: Box<ContextEvaluationError>,
+    },
 }
 
 #[derive(Clone, Debug)]
 enum TemplateStringFragment {
     Literal(String),
-    Template(String),
+    /// A variable, optionally offset if it is a date (e.g. `${pdate + 30d}`).
+    Template {
+        placeholder: String,
+        key: String,
+        offset: Option<DateOffset>,
+    },
 }
 
 impl PrimitiveContextValue {
@@ -141,33 +491,139 @@ impl PrimitiveContextValue {
             PrimitiveContextValue::Bool(b) => b.to_string(),
             PrimitiveContextValue::Int(i) => i.to_string(),
             PrimitiveContextValue::Float(f) => f.to_string(),
+            PrimitiveContextValue::Date(d) => d.format("%Y-%m-%d").to_string(),
             PrimitiveContextValue::String(s) => s.clone(),
         }
     }
+
+    /// The date moved by `offset`, if this is a date or a string of a date (see [`date::parse_date`]).
+    pub fn offset(&self, offset: &DateOffset) -> Option<PrimitiveContextValue> {
+        let date = match self {
+            PrimitiveContextValue::Date(d) => *d,
+            PrimitiveContextValue::String(s) => date::parse_date(s)?,
+            _ => return None,
+        };
+        offset.apply(date).map(PrimitiveContextValue::Date)
+    }
 }
 
 impl ContextValue {
+    /// Whether the value is read at the site of every context (a raster, vector or provider value), the result of
+    /// which is provided instead by the context generator.
+    pub fn is_site_lookup(&self) -> bool {
+        matches!(
+            self,
+            ContextValue::Raster(_) | ContextValue::Vector(_) | ContextValue::Provider(_)
+        )
+    }
+
     pub fn to_prim(&self, ctx: &Context) -> Result<PrimitiveContextValue, ContextEvaluationError> {
         match self {
             ContextValue::Prim(p) => Ok(p.clone()),
             ContextValue::TemplateString(s) => {
                 Ok(PrimitiveContextValue::String(s.interpolate(ctx)?))
             }
+            ContextValue::List(_) | ContextValue::Records(_) => {
+                Err(ContextEvaluationError::NotPrimitive)
+            }
+            ContextValue::Raster(r) => {
+                Err(ContextEvaluationError::NotSampled(r.raster.file.clone()))
+            }
+            ContextValue::Vector(v) => {
+                Err(ContextEvaluationError::NotSampled(v.vector.file.clone()))
+            }
+            ContextValue::Provider(p) => {
+                Err(ContextEvaluationError::NotProvided(p.provider.to_string()))
+            }
+            ContextValue::Random(r) => {
+                let RandomValueSource { min, max, seed } = r.random;
+                let mut rng = Rng::with_stream(seed, ctx.site.id as u64);
+                Ok(PrimitiveContextValue::Float(
+                    min + (max - min) * rng.next_f64(),
+                ))
+            }
         }
     }
+
+    /// The value as inserted into the context of the templates, with the template strings within lists interpolated.
+    pub fn to_tera(&self, ctx: &Context) -> Result<tera::Value, ContextEvaluationError> {
+        let value = match self {
+            ContextValue::List(items) => {
+                let items = items.iter().map(|item| match item {
+                    ListItem::Value(value) => value.to_tera(ctx),
+                    ListItem::Record(record) => record
+                        .iter()
+                        .map(|(k, v)| Ok((k.clone(), v.to_tera(ctx)?)))
+                        .collect::<Result<_, _>>()
+                        .map(tera::Value::Object),
+                });
+                tera::Value::Array(items.collect::<Result<_, _>>()?)
+            }
+            ContextValue::Records(records) => tera::to_value(records).unwrap_or_default(),
+            other => tera::to_value(other.to_prim(ctx)?).unwrap_or_default(),
+        };
+        Ok(value)
+    }
 }
 
 impl TemplateString {
+    /// Variable and offset of the string if it is a single placeholder, e.g. `${pdate + 30d}`.
+    pub fn as_placeholder(&self) -> Option<(&str, Option<&DateOffset>)> {
+        match self.0.as_slice() {
+            [TemplateStringFragment::Template { key, offset, .. }] => Some((key, offset.as_ref())),
+            _ => None,
+        }
+    }
+
+    /// The text of the string if it has no placeholders.
+    pub fn as_literal(&self) -> Option<String> {
+        self.0
+            .iter()
+            .map(|fragment| match fragment {
+                TemplateStringFragment::Literal(l) => Some(l.as_str()),
+                TemplateStringFragment::Template { .. } => None,
+            })
+            .collect()
+    }
+
+    /// The string with the placeholders `resolve` returns a text for replaced by that text, e.g. the ones known before
+    /// the contexts are. The others are kept.
+    pub fn substitute<E>(
+        &self,
+        mut resolve: impl FnMut(&str, Option<&DateOffset>) -> Result<Option<String>, E>,
+    ) -> Result<Self, E> {
+        let mut fragments = Vec::with_capacity(self.0.len());
+        for fragment in &self.0 {
+            if let TemplateStringFragment::Template { key, offset, .. } = fragment {
+                if let Some(text) = resolve(key, offset.as_ref())? {
+                    fragments.push(TemplateStringFragment::Literal(text));
+                    continue;
+                }
+            }
+            fragments.push(fragment.clone());
+        }
+        Ok(TemplateString(fragments))
+    }
+
     pub fn interpolate(&self, ctx: &Context) -> Result<String, ContextEvaluationError> {
         let mut s = String::new();
         for fragment in &self.0 {
             match fragment {
                 TemplateStringFragment::Literal(l) => s.push_str(l),
-                TemplateStringFragment::Template(k) => {
-                    let value = ctx
-                        .get(k)
-                        .ok_or(ContextEvaluationError::Interpolation(k.to_string()))?;
-                    s.push_str(value.to_prim(ctx)?.as_string().as_str());
+                TemplateStringFragment::Template { key, offset, .. } => {
+                    let mut value = ctx
+                        .get(key)
+                        .ok_or(ContextEvaluationError::Interpolation(key.to_string()))?
+                        .to_prim(ctx)?;
+                    if let Some(offset) = offset {
+                        value = value.offset(offset).ok_or_else(|| {
+                            ContextEvaluationError::NotDate {
+                                key: key.to_string(),
+                                value: value.as_string(),
+                            }
+                        })?;
+                    }
+                    s.push_str(value.as_string().as_str());
                 }
             }
         }
@@ -187,12 +643,18 @@ impl<'de> Deserialize<'de> for TemplateString {
                 let matched = &cap[0];
                 if matched.starts_with("${") && matched.ends_with('}') {
                     let placeholder = matched.trim_start_matches("${").trim_end_matches('}');
-                    TemplateStringFragment::Template(placeholder.to_string())
+                    let (key, offset) =
+                        date::split_placeholder(placeholder).map_err(serde::de::Error::custom)?;
+                    Ok(TemplateStringFragment::Template {
+                        placeholder: placeholder.to_string(),
+                        key: key.to_string(),
+                        offset,
+                    })
                 } else {
-                    TemplateStringFragment::Literal(matched.to_string())
+                    Ok(TemplateStringFragment::Literal(matched.to_string()))
                 }
             })
-            .collect();
+            .collect::<Result<_, D::Error>>()?;
 
         if fragments.is_empty() {
             return Err(serde::de::Error::custom(format!(
@@ -214,14 +676,40 @@ impl Serialize for TemplateString {
         for fragment in &self.0 {
             match fragment {
                 TemplateStringFragment::Literal(l) => s.push_str(l),
-                TemplateStringFragment::Template(t) => s.push_str(&format!("${{{}}}", t)),
+                TemplateStringFragment::Template { placeholder, .. } => {
+                    s.push_str(&format!("${{{}}}", placeholder))
+                }
             }
         }
         serializer.serialize_str(&s)
     }
 }
 
-impl PipelineData for Context {}
+impl PipelineData for Context {
+    const NAME: &'static str = "generated contexts";
+}
+
+#[cfg(test)]
+impl Context {
+    /// A context of the run named `run` at site `site` (at 0, 0), with nothing else set.
+    pub fn for_test(run: &str, site: i32) -> Self {
+        Context {
+            site: Site {
+                id: site,
+                lon: GeoDeg::from(0.0),
+                lat: GeoDeg::from(0.0),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
+            },
+            run: config::runs::RunConfig {
+                name: run.to_string(),
+                ..Default::default()
+            },
+            provided: Default::default(),
+        }
+    }
+}
 
 impl Context {
     pub fn get(&self, key: &str) -> Option<ContextValue> {
@@ -241,18 +729,74 @@ impl Context {
             "name" => Some(ContextValue::Prim(PrimitiveContextValue::String(
                 self.run.name.clone(),
             ))),
-            _ => self.run.extra.get(key).cloned(),
+            // Falls back to the other variables when the site has no weight.
+            "weight" if self.site.weight.is_some() => self
+                .site
+                .weight
+                .map(|weight| ContextValue::Prim(PrimitiveContextValue::Float(weight))),
+            _ => self
+                .run
+                .extra
+                .get(key)
+                .filter(|v| !v.is_site_lookup())
+                .or_else(|| self.provided.get(key))
+                .cloned()
+                .or_else(|| {
+                    let value = *self.site.covariates.get(key)?;
+                    Some(ContextValue::Prim(PrimitiveContextValue::Float(value)))
+                })
+                .or_else(|| {
+                    let value = self.site.attributes.get(key)?.clone();
+                    Some(ContextValue::Prim(PrimitiveContextValue::String(value)))
+                }),
+        }
+    }
+
+    pub fn location(&self) -> ContextLocation {
+        ContextLocation {
+            run: self.run.name.clone(),
+            site_id: self.site.id,
+            lon: self.site.lon,
+            lat: self.site.lat,
         }
     }
 
     pub fn dir(&self, base: &PathBuf) -> PathBuf {
         let mut path = base.clone();
         path.push(&self.run.name);
+        for key in &self.run.directory_groups {
+            path.push(self.directory_group(key));
+        }
         path.push(&self.site.lon.ns(4));
         path.push(&self.site.lat.ew(4));
         path
     }
 
+    /// Name of the directory grouping the context by the variable `key`, with the characters that aren't safe in file
+    /// names replaced by `_`. See [`RunConfig::directory_groups`](config::runs::RunConfig::directory_groups).
+    fn directory_group(&self, key: &str) -> String {
+        let value = self
+            .get(key)
+            .and_then(|value| value.to_prim(self).ok())
+            .map(|value| value.as_string())
+            .unwrap_or_default();
+        let name: String = value
+            .trim()
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || "-_.".contains(c) {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        match name.trim_matches('.') {
+            "" => "unknown".to_string(),
+            _ => name,
+        }
+    }
+
     pub fn tera(&self) -> Result<tera::Context, ContextEvaluationError> {
         let mut ctx = tera::Context::new();
         ctx.insert("site_id", &self.site.id);
@@ -261,9 +805,27 @@ impl Context {
         ctx.insert("lon", &self.site.lon.as_f32());
         ctx.insert("lat", &self.site.lat.as_f32());
         ctx.insert("name", &self.run.name);
+        if let Some(weight) = self.site.weight {
+            ctx.insert("weight", &weight);
+        }
+
+        // Inserted first, so provided values and run values take precedence over them.
+        for (k, v) in &self.site.attributes {
+            ctx.insert(k, v);
+        }
+        for (k, v) in &self.site.covariates {
+            ctx.insert(k, v);
+        }
 
-        for (k, v) in &self.run.extra {
-            ctx.insert(k, &v.to_prim(self)?);
+        let extra = self.run.extra.iter().filter(|(_, v)| !v.is_site_lookup());
+        for (k, v) in self.provided.iter().chain(extra) {
+            let value = v
+                .to_tera(self)
+                .map_err(|e| ContextEvaluationError::Variable {
+                    key: k.to_string(),
+                    source: Box::new(e),
+                })?;
+            ctx.insert(k, &value);
         }
 
         Ok(ctx)
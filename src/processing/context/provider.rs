@@ -0,0 +1,67 @@
+//! Providers registered by plugins resolving the variables of the runs at the site of every context, e.g. a
+//! `"soil_group": { "provider": { "type": "acme:soil-lookup", "table": "groups.csv" } }` variable (see
+//! [`super::ProviderValue`]). They are created once per variable, when the context generator is.
+
+use super::ContextValue;
+use crate::sites::Site;
+use std::any::Any;
+use std::error::Error;
+use std::sync::Arc;
+
+/// Resolves a variable at the site of every context, from the context generator thread.
+pub trait ContextProvider: Send + Sync {
+    /// Value of the variable at `site`, if there is one. The variable is left out of the context otherwise.
+    fn provide(&self, site: &Site) -> Option<ContextValue>;
+}
+
+impl<P: ContextProvider + ?Sized> ContextProvider for Box<P> {
+    fn provide(&self, site: &Site) -> Option<ContextValue> {
+        (**self).provide(site)
+    }
+}
+
+/// Constructs a new [`ContextProvider`] of type [`P`] from the config [`C`].
+#[allow(type_alias_bounds)] // Same as the site generator drivers, see [`crate::sites::SiteGeneratorDriver`].
+type ContextProviderFactory<P: ContextProvider, C> = Arc<dyn Fn(C) -> Result<P, Box<dyn Error>>>;
+
+/// Deserializes a config of type [`C`] from a [`serde_json::Value`].
+type ContextProviderConfigDeserializer<C> =
+    Arc<dyn Fn(serde_json::Value) -> Result<C, serde_json::error::Error>>;
+
+/// A [`ContextProvider`] addressable by identifier from the `provider` values of the variables of a run.
+pub struct ContextProviderDriver<P: ContextProvider, C> {
+    pub create: ContextProviderFactory<P, C>,
+    pub config_deserializer: ContextProviderConfigDeserializer<C>,
+}
+
+impl<P: ContextProvider, C> Clone for ContextProviderDriver<P, C> {
+    fn clone(&self) -> Self {
+        ContextProviderDriver {
+            create: self.create.clone(),
+            config_deserializer: self.config_deserializer.clone(),
+        }
+    }
+}
+
+impl<P: ContextProvider, C> ContextProviderDriver<P, C> {
+    #[allow(dead_code)] // Only plugins provide these.
+    pub fn coerce_to_dynamic(self) -> ContextProviderDriver<Box<dyn ContextProvider>, Box<dyn Any>>
+    where
+        P: 'static,
+        C: Any + 'static,
+    {
+        ContextProviderDriver {
+            create: Arc::new(move |c: Box<dyn Any>| {
+                let config = c
+                    .downcast::<C>()
+                    .map_err(|_| Box::<dyn Error>::from("Failed to downcast config"))?;
+                let concrete_provider = (self.create)(*config)?;
+                Ok(Box::new(concrete_provider) as Box<dyn ContextProvider>)
+            }),
+            config_deserializer: Arc::new(move |v| {
+                let concrete_config = (self.config_deserializer)(v)?;
+                Ok(Box::new(concrete_config) as Box<dyn Any>)
+            }),
+        }
+    }
+}
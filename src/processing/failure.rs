@@ -0,0 +1,151 @@
+//! Dead-letter channel of the pipeline: the contexts a stage gave up on are sent there instead of crashing the
+//! workers, and recorded for the user to look into (or re-run) once the processing is done.
+
+use super::context::ContextLocation;
+use serde::Serialize;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::mpmc::Receiver;
+
+/// Name of the file the failed contexts are recorded to, as JSON lines, in the root of the working directory.
+pub const FAILED_CONTEXTS_FILE_NAME: &str = "failed_contexts.jsonl";
+
+/// A context a stage gave up on, along with why.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FailedContext {
+    pub run: String,
+    pub site: i32,
+    pub lon: f64,
+    pub lat: f64,
+    /// Attempts made before giving up, see [`super::processor::retry::RetryPolicy`].
+    pub attempts: u32,
+    pub error: String,
+}
+
+impl FailedContext {
+    pub fn new(location: &ContextLocation, attempts: u32, err: &dyn Display) -> Self {
+        Self {
+            run: location.run.clone(),
+            site: location.site_id,
+            lon: location.lon.as_f64(),
+            lat: location.lat.as_f64(),
+            attempts,
+            error: err.to_string(),
+        }
+    }
+}
+
+/// Records the failed contexts received on `rx` to [`FAILED_CONTEXTS_FILE_NAME`] in `workdir` until every sender hangs
+/// up. The file is only created on the first failure. Returns how many contexts failed, whether they could be recorded
+/// or not.
+///
+/// If `deterministic`, the failures are held until every sender hangs up, then recorded in canonical (run, site ID)
+/// order rather than as they come. See `--deterministic`.
+pub fn failure_sink(rx: Receiver<FailedContext>, workdir: &Path, deterministic: bool) -> usize {
+    let path = workdir.join(FAILED_CONTEXTS_FILE_NAME);
+    let mut writer: Option<BufWriter<File>> = None;
+    let mut held = Vec::new();
+    let mut failed = 0;
+
+    for failure in rx {
+        failed += 1;
+        eprintln!(
+            "Giving up on run \"{}\", site {} after {} attempt(s): {}",
+            failure.run, failure.site, failure.attempts, failure.error
+        );
+        match deterministic {
+            true => held.push(failure),
+            false => {
+                if let Err(e) = record(&mut writer, &path, &failure) {
+                    eprintln!("Unable to record the failure to {}: {}", path.display(), e);
+                }
+            }
+        }
+    }
+
+    held.sort_by(|a, b| (&a.run, a.site).cmp(&(&b.run, b.site)));
+    for failure in held {
+        if let Err(e) = record(&mut writer, &path, &failure) {
+            eprintln!("Unable to record the failure to {}: {}", path.display(), e);
+        }
+    }
+    failed
+}
+
+fn record(
+    writer: &mut Option<BufWriter<File>>,
+    path: &Path,
+    failure: &FailedContext,
+) -> std::io::Result<()> {
+    let writer = match writer {
+        Some(writer) => writer,
+        None => writer.insert(BufWriter::new(File::create(path)?)),
+    };
+    serde_json::to_writer(&mut *writer, failure)?;
+    writer.write_all(b"\n")?;
+    // Flushed on every record, so the failures can be followed while the processing goes on.
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::GeoDeg;
+    use std::sync::mpmc::channel;
+
+    #[test]
+    fn test_failure_sink() {
+        let location = ContextLocation {
+            run: "maize".to_string(),
+            site_id: 7,
+            lon: GeoDeg::from(1.5),
+            lat: GeoDeg::from(-2.5),
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(FAILED_CONTEXTS_FILE_NAME);
+
+        let (tx, rx) = channel();
+        drop(tx);
+        assert_eq!(failure_sink(rx, dir.path(), false), 0);
+        assert!(!path.exists());
+
+        let (tx, rx) = channel();
+        tx.send(FailedContext::new(&location, 3, &"No space left on device"))
+            .unwrap();
+        tx.send(FailedContext::new(&location, 1, &"Soil profile not found"))
+            .unwrap();
+        drop(tx);
+        assert_eq!(failure_sink(rx, dir.path(), false), 2);
+
+        let lines: Vec<serde_json::Value> = std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["site"], 7);
+        assert_eq!(lines[0]["attempts"], 3);
+        assert_eq!(lines[1]["error"], "Soil profile not found");
+
+        // Deterministic failures are recorded by run and site, whatever order they come in.
+        let (tx, rx) = channel();
+        for site_id in [9, 7] {
+            let location = ContextLocation {
+                site_id,
+                ..location.clone()
+            };
+            tx.send(FailedContext::new(&location, 1, &"Soil profile not found"))
+                .unwrap();
+        }
+        drop(tx);
+        assert_eq!(failure_sink(rx, dir.path(), true), 2);
+        let contents = std::fs::read_to_string(dir.path().join(FAILED_CONTEXTS_FILE_NAME)).unwrap();
+        let sites: Vec<serde_json::Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["site"].clone())
+            .collect();
+        assert_eq!(sites, [7, 9]);
+    }
+}
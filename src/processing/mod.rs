@@ -1,94 +1,396 @@
+use crate::batch::{self, BatchScope};
+use crate::config::runs::RunConfig;
 use crate::config::{Args, Config};
+use crate::hooks::{self, PostContextHook};
 use crate::processing::template::TemplateEngine;
+use crate::provenance::{Manifest, MetadataScope};
+use checkpoint::{load_checkpoint, sort_checkpoint, CheckpointSink};
 use context::{Context, ContextGenerator};
+use failure::{failure_sink, FailedContext, FAILED_CONTEXTS_FILE_NAME};
 use pipeline::{create_pipeline_from_config, Pipeline, Pipelines};
-use processor::unbatched::UnbatchedProcessor;
+use preflight::preflight;
+use priority::Prioritized;
+use processor::ProcessorEnvironment;
+use report::{RunReport, StageStats};
+use sink::{drain, NamedSink, SinkEnvironment, Sinks};
+use spill::{spool, SpillQueue};
+use std::collections::HashSet;
 use std::path::PathBuf;
-use std::sync::mpmc::sync_channel;
+use std::sync::mpmc::{channel, sync_channel, Receiver};
 use std::sync::Arc;
 use std::thread;
 
+pub mod checkpoint;
 pub mod context;
-mod pipeline;
-mod processor;
-mod template;
+pub mod failure;
+pub mod pipeline;
+pub mod preflight;
+pub mod priority;
+pub mod processor;
+pub mod report;
+pub mod sink;
+pub mod spill;
+pub mod template;
 
-pub trait PipelineData: Sized + Send + Sync {}
+/// What a stage of the pipeline passes on to the next. Every stage passes contexts on, the type tells what was done
+/// with them so far (e.g. [`pipeline::Executed`] contexts had DSSAT run in their directory). See [`pipeline::StageTypes`].
+pub trait PipelineData: Sized + Send + Sync + From<Context> + Into<Context> + 'static {
+    /// What the data is, in the errors of the stages that don't fit together, e.g. `rendered contexts`.
+    const NAME: &'static str;
+}
+
+/// How many contexts are generated between progress reports.
+const PROGRESS_INTERVAL: usize = 1000;
+
+/// "x of N contexts", or "x contexts" when the total is unknown.
+fn progress(done: usize, total: Option<usize>) -> String {
+    match total {
+        Some(total) => format!("{} of {} contexts", done, total),
+        None => format!("{} contexts", done),
+    }
+}
 
 pub struct ProcessingBuilder<'a> {
     pub config: &'a Config,
     pub args: &'a Args,
     pub workdir: PathBuf,
+    pub manifest: &'a Manifest,
 }
 
 impl<'a> ProcessingBuilder<'a> {
     pub fn build(self) -> Result<Processing<Context>, Box<dyn std::error::Error>> {
+        preflight(self.config, Some(&self.workdir), self.args.strict_templates)?;
+
         let sitegen = self.config.sites.build()?;
+        let completed = match self.args.resume {
+            true => load_checkpoint(&self.workdir)?,
+            false => Default::default(),
+        };
+        let completed_count: usize = completed.values().map(|sites| sites.len()).sum();
+        if self.args.resume {
+            println!("Resuming, {} context(s) already completed", completed_count);
+        }
+        let total_contexts = self
+            .config
+            .sites
+            .count()
+            .map(|sites| (sites * self.config.runs.len()).saturating_sub(completed_count));
 
         let ctx_gen = ContextGenerator::new(
             Box::new(sitegen),
             self.config.runs.clone(),
             self.config.sites.sample_size,
-        )?;
+        )?
+        .with_completed(completed)
+        .with_providers(&self.config.providers)?;
 
-        let processor = UnbatchedProcessor {
-            workdir: self.workdir,
-        };
+        // Run directories only get their metadata once, before any context is processed.
+        for run in &self.config.runs {
+            if run.metadata == Some(MetadataScope::Run) {
+                let dir = self.workdir.join(&run.name);
+                std::fs::create_dir_all(&dir)?;
+                self.manifest
+                    .directory_metadata(&run.name, None)
+                    .write(&dir)?;
+            }
+        }
 
-        let pipeline = create_pipeline_from_config(self.config, self.args.workers, processor)?;
+        let (tx_failures, failures) = channel::<FailedContext>();
+        let env = ProcessorEnvironment {
+            config: self.config,
+            workdir: &self.workdir,
+            manifest: self.manifest,
+            failures: &tx_failures,
+            resume: self.args.resume,
+            // Every stage gets its own, see create_pipeline_from_config.
+            stats: Default::default(),
+        };
+        let (pipelines, stats) = create_pipeline_from_config(
+            &env,
+            self.args.workers,
+            self.args.worker_restarts,
+            self.args.memory_budget,
+        )?
+        .into_iter()
+        .unzip();
 
         let mut templates = TemplateEngine::default();
+        templates.register_extensions(&self.config.template_extensions);
+        if let Some(dir) = &self.config.template_dir {
+            templates.register_dir(dir)?;
+        }
+        for run in &self.config.runs {
+            templates.register(run, &self.config.engines[&run.name])?;
+        }
+
+        let priorities: HashSet<i32> = self.config.runs.iter().map(|run| run.priority).collect();
+        let priority_window = match priorities.len() {
+            1 => 0,
+            _ => self.args.priority_window,
+        };
+
+        let spill = match self.args.spill_to_disk {
+            true => Some(SpillQueue::new(&self.workdir, self.config.runs.clone())?),
+            false => None,
+        };
+
+        let mut sinks = Sinks::default();
+        // The checkpoint can't hold up the processing, it is only needed to resume it.
+        match CheckpointSink::new(&self.workdir, self.args.resume) {
+            Ok(checkpoint) => sinks.all.push(NamedSink {
+                name: "checkpoint".to_string(),
+                sink: Box::new(checkpoint),
+            }),
+            Err(e) => eprintln!("{}", e),
+        }
+        for run in &self.config.runs {
+            let env = SinkEnvironment {
+                workdir: &self.workdir,
+                run,
+                resume: self.args.resume,
+            };
+            let run_sinks = self.config.sinks.get(&run.name).into_iter().flatten();
+            let mut run_sinks = run_sinks
+                .map(|sink| {
+                    let built = sink
+                        .build(&env)
+                        .map_err(|e| format!("Sink {} of run {}: {}", sink.name, run.name, e))?;
+                    Ok(NamedSink {
+                        name: format!("{} of run {}", sink.name, run.name),
+                        sink: built,
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            if let Some(command) = run.hooks.as_ref().and_then(|h| h.post_context.clone()) {
+                run_sinks.push(NamedSink {
+                    name: format!("post_context hook of run {}", run.name),
+                    sink: Box::new(PostContextHook::new(command, self.workdir.clone())),
+                });
+            }
+            sinks.by_run.insert(run.name.clone(), run_sinks);
+        }
+
+        let sorted_batches = match self.args.deterministic {
+            true => self
+                .config
+                .runs
+                .iter()
+                .filter_map(|run| {
+                    let batch = run.batch.as_ref().filter(|b| b.scope == BatchScope::Run)?;
+                    Some(self.workdir.join(&run.name).join(batch.file_name()))
+                })
+                .collect(),
+            false => Vec::new(),
+        };
+
+        // Run last, so the staging they do isn't left behind by a processing that fails to build.
         for run in &self.config.runs {
-            templates.register(run.name.as_str(), &run.template)?;
+            hooks::pre_run(run, &self.workdir)?;
         }
 
         Ok(Processing {
-            pipeline,
+            pipelines,
+            stats,
             ctx_gen,
             templates,
             buffer_size: self.args.pipeline_buffer_size,
+            total_contexts,
+            failures,
+            workdir: self.workdir,
+            runs: self.config.runs.clone(),
+            sinks,
+            spill,
+            priority_window,
+            deterministic: self.args.deterministic,
+            sorted_batches,
         })
     }
 }
 
 pub struct Processing<T: PipelineData> {
-    pipeline: Pipelines<T>,
+    /// One pipeline per stage, in order.
+    pipelines: Vec<Pipelines<T>>,
+    /// Statistics of every stage, in order, for the run report.
+    stats: Vec<Arc<StageStats>>,
     ctx_gen: ContextGenerator,
     templates: TemplateEngine,
     buffer_size: usize,
+    /// Number of contexts to be generated, if the site source could be counted up front.
+    total_contexts: Option<usize>,
+    /// Dead-letter channel of the processors, see [`failure`].
+    failures: Receiver<FailedContext>,
+    workdir: PathBuf,
+    /// Runs of the config, for their `post_run` hooks.
+    runs: Vec<RunConfig>,
+    /// Where the contexts that made it through every stage end up, see [`sink`].
+    sinks: Sinks,
+    /// Where the contexts the first stage can't keep up with are parked, if anywhere. See `--spill-to-disk`.
+    spill: Option<SpillQueue>,
+    /// How many contexts are read ahead to pass them on by priority, see [`priority`]. 0 keeps the generated order.
+    priority_window: usize,
+    /// Whether the outputs are put in a canonical order once every context is processed. See `--deterministic`.
+    deterministic: bool,
+    /// Run-wide batch files to put in a canonical order, if `deterministic`.
+    sorted_batches: Vec<PathBuf>,
 }
 
-impl<T: PipelineData + 'static> Processing<T> {
-    pub fn start(self) {
-        let ctx_gen = self.ctx_gen;
-        let pipeline: Arc<dyn Pipeline<Output = T>> = match self.pipeline {
-            Pipelines::SYNC(pipeline) => Arc::new(pipeline),
-            Pipelines::THREADED(pipeline) => Arc::new(pipeline),
-        };
+impl Processing<Context> {
+    /// Processes every context, and reports on the run. Returns how many contexts failed, or an error if a stage
+    /// failed and cut the run short.
+    pub fn start(self) -> Result<usize, Box<dyn std::error::Error>> {
+        let started_at = chrono::Utc::now().to_rfc3339();
+        let mut ctx_gen = self.ctx_gen;
+        let total_contexts = self.total_contexts;
+        let templates = &self.templates;
+        let mut generated = 0;
+        let pipelines: Vec<Arc<dyn Pipeline<Output = Context>>> = self
+            .pipelines
+            .into_iter()
+            .map(|pipeline| -> Arc<dyn Pipeline<Output = Context>> {
+                match pipeline {
+                    Pipelines::SYNC(pipeline) => Arc::new(pipeline),
+                    Pipelines::THREADED(pipeline) => Arc::new(pipeline),
+                    Pipelines::ASYNC(pipeline) => Arc::new(pipeline),
+                    Pipelines::STAGE(pipeline) => Arc::new(pipeline),
+                }
+            })
+            .collect();
 
-        thread::scope(|s| {
-            let (tx, rx_conduct) = sync_channel::<Context>(self.buffer_size);
-            let (tx_conduct, rx) = sync_channel::<T>(self.buffer_size);
-
-            let tx_conduct2 = tx_conduct.clone();
-            let t_conductor = s.spawn(move || {
-                pipeline
-                    .conduct(&tx_conduct2, &rx_conduct, &self.templates)
-                    .unwrap()
-            });
-            let t_sink = s.spawn(move || {
-                for _ in rx { /* noop */ }
-            });
-
-            for ctx in ctx_gen {
-                tx.send(ctx).unwrap();
+        let (completed, failed, failed_stages) = thread::scope(|s| {
+            let (tx, mut rx) = sync_channel::<Context>(self.buffer_size);
+            let (tx, t_spool) = match self.spill {
+                Some(queue) => {
+                    let (tx_gen, rx_gen) = sync_channel::<Context>(self.buffer_size);
+                    (tx_gen, Some(s.spawn(move || spool(rx_gen, tx, queue))))
+                }
+                None => (tx, None),
+            };
+
+            // Every stage takes the contexts the previous one passed on. A stage hangs up on the next one when it is
+            // done (or fails), and on the previous one when it fails, so the whole chain winds down either way.
+            let t_conductors: Vec<_> = pipelines
+                .into_iter()
+                .enumerate()
+                .map(|(i, pipeline)| {
+                    let (tx_conduct, rx_next) = sync_channel::<Context>(self.buffer_size);
+                    let rx_conduct = std::mem::replace(&mut rx, rx_next);
+                    s.spawn(move || {
+                        let result = pipeline.conduct(&tx_conduct, &rx_conduct, templates);
+                        if let Err(err) = &result {
+                            eprintln!("Processing failed on stage {}: {}", i + 1, err);
+                        }
+                        result.is_err()
+                    })
+                })
+                .collect();
+            let sinks = self.sinks;
+            let t_sink = s.spawn(move || drain(rx, sinks));
+            let workdir = &self.workdir;
+            // The processors hang up on the dead-letter channel when their stage is done.
+            let failures = self.failures;
+            let deterministic = self.deterministic;
+            let t_failures = s.spawn(move || failure_sink(failures, workdir, deterministic));
+
+            for ctx in Prioritized::new(ctx_gen.by_ref(), self.priority_window) {
+                // The conductor hangs up if it fails, there is no point in generating more contexts.
+                if tx.send(ctx).is_err() {
+                    break;
+                }
+                generated += 1;
+                if generated % PROGRESS_INTERVAL == 0 {
+                    println!("Generated {}", progress(generated, total_contexts));
+                }
             }
 
             drop(tx);
-            t_conductor.join().unwrap();
+            match t_spool.map(|t| t.join().unwrap()) {
+                Some(Ok(spilled)) if spilled > 0 => {
+                    println!("Spilled {} context(s) to disk", spilled)
+                }
+                Some(Err(e)) => eprintln!("Spilling contexts to disk failed: {}", e),
+                _ => {}
+            }
+            let failed_stages = t_conductors
+                .into_iter()
+                .map(|t| t.join().unwrap())
+                .filter(|&stage_failed| stage_failed)
+                .count();
+            (
+                t_sink.join().unwrap(),
+                t_failures.join().unwrap(),
+                failed_stages,
+            )
+        });
 
-            drop(tx_conduct);
-            t_sink.join().unwrap();
-        })
+        if self.deterministic {
+            if let Err(e) = sort_checkpoint(&self.workdir) {
+                eprintln!("Unable to sort the checkpoint: {}", e);
+            }
+            for path in self.sorted_batches.iter().filter(|path| path.exists()) {
+                if let Err(e) = batch::sort_entries(path) {
+                    eprintln!("Unable to sort the batch file {}: {}", path.display(), e);
+                }
+            }
+        }
+
+        println!("Generated {}", progress(generated, total_contexts));
+        ctx_gen.site_summary().print();
+        if self.runs.iter().any(|run| run.filter.is_some()) {
+            ctx_gen.filter_summary().print();
+        }
+        if ctx_gen.skipped_completed() > 0 {
+            println!(
+                "Skipped {} context(s) completed by the interrupted run",
+                ctx_gen.skipped_completed()
+            );
+        }
+        if failed > 0 {
+            println!(
+                "{} context(s) failed, see {}",
+                failed,
+                self.workdir.join(FAILED_CONTEXTS_FILE_NAME).display()
+            );
+        }
+
+        let report = RunReport {
+            started_at,
+            finished_at: chrono::Utc::now().to_rfc3339(),
+            generated,
+            completed,
+            skipped: ctx_gen.skipped_completed(),
+            failed,
+            stages: self
+                .stats
+                .iter()
+                .enumerate()
+                .map(|(i, stats)| stats.report(i + 1))
+                .collect(),
+        };
+        match report.write(&self.workdir) {
+            Ok(path) => println!("Run report written to {}", path.display()),
+            Err(e) => eprintln!("Unable to write the run report: {}", e),
+        }
+
+        for run in &self.runs {
+            if let Err(e) = hooks::post_run(run, &self.workdir) {
+                eprintln!("{}", e);
+            }
+        }
+
+        match failed_stages {
+            0 => Ok(failed),
+            _ => Err(format!("{} stage(s) of the pipeline failed", failed_stages).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress() {
+        assert_eq!(progress(1000, Some(2314)), "1000 of 2314 contexts");
+        assert_eq!(progress(1000, None), "1000 contexts");
     }
 }
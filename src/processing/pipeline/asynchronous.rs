@@ -0,0 +1,147 @@
+use super::super::context::Context;
+use super::super::processor::{AsyncProcessor, ProcessFuture, Processor};
+use super::super::template::TemplateEngine;
+use super::{Pipeline, PipelineData};
+use std::error::Error;
+use std::future::poll_fn;
+use std::sync::mpmc::{Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::Duration;
+
+/// How long to wait on the contexts in flight before checking the previous stage for new ones.
+const IDLE_WAIT: Duration = Duration::from_millis(1);
+
+/// Processes up to `concurrency` contexts at once as tasks of a tokio runtime, for the IO-bound stages, where
+/// [`ThreadedPipeline`](super::ThreadedPipeline) would need a thread per context in flight. The processor must be an
+/// [`AsyncProcessor`].
+pub struct AsyncPipeline<O: PipelineData> {
+    concurrency: usize,
+    processor: Arc<dyn Processor<Output = O>>,
+}
+
+impl<O: PipelineData> AsyncPipeline<O> {
+    pub fn new(
+        processor: impl Processor<Output = O> + 'static,
+        concurrency: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        if concurrency == 0 {
+            return Err("The async pipeline needs a concurrency of at least 1".into());
+        }
+        if processor.as_async().is_none() {
+            return Err("The processor can't run on the async pipeline".into());
+        }
+
+        Ok(Self {
+            concurrency,
+            processor: Arc::new(processor),
+        })
+    }
+}
+
+/// Waits for the first of the futures in `in_flight` to resolve, and removes it.
+async fn next_completed<O>(
+    in_flight: &mut Vec<ProcessFuture<'_, O>>,
+) -> Result<Option<O>, Box<dyn Error + Send>> {
+    let (i, result) = poll_fn(|cx| {
+        in_flight
+            .iter_mut()
+            .enumerate()
+            .find_map(|(i, f)| match f.as_mut().poll(cx) {
+                Poll::Ready(result) => Some((i, result)),
+                Poll::Pending => None,
+            })
+            .map_or(Poll::Pending, Poll::Ready)
+    })
+    .await;
+    drop(in_flight.swap_remove(i));
+    result
+}
+
+impl<O: PipelineData> Pipeline for AsyncPipeline<O> {
+    type Output = O;
+
+    fn conduct(
+        &self,
+        tx: &Sender<Self::Output>,
+        rx: &Receiver<Context>,
+        templates: &TemplateEngine,
+    ) -> Result<(), Box<dyn Error + Send>> {
+        let processor: &dyn AsyncProcessor<Output = O> = self
+            .processor
+            .as_async()
+            .expect("checked by AsyncPipeline::new");
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|err| Box::new(err) as Box<dyn Error + Send>)?;
+
+        runtime.block_on(async {
+            let mut in_flight: Vec<ProcessFuture<O>> = Vec::new();
+            let mut open = true;
+            while open || !in_flight.is_empty() {
+                while open && in_flight.len() < self.concurrency {
+                    let received = match in_flight.is_empty() {
+                        // Nothing to drive in the meantime, so wait for the previous stage.
+                        true => rx.recv().map_err(|_| TryRecvError::Disconnected),
+                        false => rx.try_recv(),
+                    };
+                    match received {
+                        Ok(ctx) => in_flight.push(processor.process_one(ctx, templates)),
+                        Err(TryRecvError::Empty) => break,
+                        Err(TryRecvError::Disconnected) => open = false,
+                    }
+                }
+                if in_flight.is_empty() {
+                    continue;
+                }
+
+                // Unless the stage is at capacity, new contexts are picked up every now and then while waiting.
+                let picking_up = open && in_flight.len() < self.concurrency;
+                let next = next_completed(&mut in_flight);
+                let result = match picking_up {
+                    true => match tokio::time::timeout(IDLE_WAIT, next).await {
+                        Ok(result) => result,
+                        Err(_) => continue,
+                    },
+                    false => next.await,
+                };
+                // Blocks the runtime while the next stage is backed up, which holds this one back as intended.
+                if let Some(output) = result? {
+                    tx.send(output)
+                        .map_err(|err| Box::new(err) as Box<dyn Error + Send>)?;
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::processor::rate_limit::RateLimitProcessor;
+    use std::sync::mpmc::channel;
+
+    #[test]
+    fn test_async_pipeline() {
+        let processor = RateLimitProcessor::new(1000.0).unwrap();
+        assert!(AsyncPipeline::new(RateLimitProcessor::new(1000.0).unwrap(), 0).is_err());
+        let pipeline = AsyncPipeline::new(processor, 4).unwrap();
+
+        let (tx, rx) = channel();
+        for site in 0..10 {
+            tx.send(Context::for_test("", site)).unwrap();
+        }
+        drop(tx);
+
+        let (tx_out, rx_out) = channel();
+        pipeline
+            .conduct(&tx_out, &rx, &TemplateEngine::default())
+            .unwrap();
+        drop(tx_out);
+        let mut sites: Vec<i32> = rx_out.iter().map(|ctx| ctx.site.id).collect();
+        sites.sort();
+        assert_eq!(sites, (0..10).collect::<Vec<_>>());
+    }
+}
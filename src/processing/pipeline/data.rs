@@ -0,0 +1,102 @@
+//! Types of what the stages of the pipeline pass on to each other, so the pipeline of the config is checked to only
+//! hand a stage what it works on (e.g. `std:exec` after `std:render`) when it is built. See [`PipelineData`].
+
+use super::super::context::Context;
+use super::PipelineData;
+use std::any::TypeId;
+use std::fmt;
+
+/// A [`PipelineData`] type, named after its [`PipelineData::NAME`].
+#[derive(Clone, Copy, Debug)]
+pub struct DataType {
+    id: TypeId,
+    name: &'static str,
+}
+
+impl DataType {
+    pub fn of<T: PipelineData>() -> Self {
+        Self {
+            id: TypeId::of::<T>(),
+            name: T::NAME,
+        }
+    }
+}
+
+impl PartialEq for DataType {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+/// What a stage takes in and passes on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StageTypes {
+    /// Passes on what it is given as it is, whatever it is (e.g. `std:rate-limit`).
+    Passthrough,
+    Typed {
+        input: DataType,
+        output: DataType,
+    },
+}
+
+impl StageTypes {
+    pub fn of<I: PipelineData, O: PipelineData>() -> Self {
+        StageTypes::Typed {
+            input: DataType::of::<I>(),
+            output: DataType::of::<O>(),
+        }
+    }
+
+    /// What the stage passes on when given `data`, or what it takes instead if that isn't `data`.
+    pub fn pass(&self, data: DataType) -> Result<DataType, DataType> {
+        match *self {
+            StageTypes::Passthrough => Ok(data),
+            StageTypes::Typed { input, output } if input == data => Ok(output),
+            StageTypes::Typed { input, .. } => Err(input),
+        }
+    }
+}
+
+/// Contexts whose directory holds their rendered inputs, passed on by `std:render`.
+pub struct Rendered(pub Context);
+
+/// Contexts DSSAT was run in, passed on by `std:exec`.
+pub struct Executed(pub Context);
+
+impl PipelineData for Rendered {
+    const NAME: &'static str = "rendered contexts";
+}
+
+impl PipelineData for Executed {
+    const NAME: &'static str = "executed contexts";
+}
+
+impl From<Context> for Rendered {
+    fn from(ctx: Context) -> Self {
+        Rendered(ctx)
+    }
+}
+
+impl From<Rendered> for Context {
+    fn from(rendered: Rendered) -> Self {
+        rendered.0
+    }
+}
+
+impl From<Context> for Executed {
+    fn from(ctx: Context) -> Self {
+        Executed(ctx)
+    }
+}
+
+impl From<Executed> for Context {
+    fn from(executed: Executed) -> Self {
+        executed.0
+    }
+}
@@ -1,13 +1,22 @@
+mod asynchronous;
+mod data;
+mod scheduler;
+mod stage;
 mod sync;
 mod threaded;
 
 use super::super::processing::context::Context;
-use super::processor::Processor;
+use super::processor::ProcessorEnvironment;
+use super::report::StageStats;
 use super::template::TemplateEngine;
 use super::PipelineData;
-use crate::config::Config;
+use crate::config::pipeline::{BuiltStage, Executor};
+pub use asynchronous::*;
+pub use data::*;
+pub use stage::*;
 use std::error::Error;
 use std::sync::mpmc::{Receiver, Sender};
+use std::sync::Arc;
 pub use sync::*;
 pub use threaded::*;
 
@@ -21,25 +30,56 @@ pub trait Pipeline: Send + Sync {
     ) -> Result<(), Box<dyn Error + Send>>;
 }
 
-pub fn create_pipeline_from_config<'a, O: PipelineData + 'static>(
-    _config: &Config,
+/// Builds the pipeline of every stage of the config, in order, along with the statistics its processor records. Each
+/// stage gets its own workers, or its own runtime if it runs on the async pipeline. A [`Stage`] is conducted as it is.
+pub fn create_pipeline_from_config(
+    env: &ProcessorEnvironment,
     workers: usize,
-    processor: impl Processor<Output = O> + 'static,
-) -> Result<Pipelines<O>, Box<dyn Error>> {
+    max_restarts: usize,
+    memory_budget: Option<u64>,
+) -> Result<Vec<(Pipelines<Context>, Arc<StageStats>)>, Box<dyn Error>> {
     let worker_count = match workers {
         0 => num_cpus::get(),
         workers => workers,
     };
 
-    let pipeline: Pipelines<O> = match workers {
-        1 => Pipelines::SYNC(SyncPipeline::new(processor)),
-        _ => Pipelines::THREADED(ThreadedPipeline::new(processor, worker_count)?),
-    };
-
-    Ok(pipeline)
+    env.config
+        .pipeline
+        .iter()
+        .enumerate()
+        .map(|(i, stage)| {
+            let stats = Arc::new(StageStats::default());
+            let env = ProcessorEnvironment {
+                stats: stats.clone(),
+                ..*env
+            };
+            let processor = match stage.build(&env)? {
+                BuiltStage::Processor(processor) => processor,
+                BuiltStage::Stage(stage) => {
+                    return Ok((Pipelines::STAGE(StagePipeline::new(stage)), stats));
+                }
+            };
+            let pipeline: Pipelines<Context> = match (&stage.executor, workers) {
+                (Executor::Async { concurrency }, _) => Pipelines::ASYNC(
+                    AsyncPipeline::new(processor, *concurrency)
+                        .map_err(|e| format!("Stage {}: {}", i + 1, e))?,
+                ),
+                (Executor::Threads, 1) => Pipelines::SYNC(SyncPipeline::new(processor)),
+                (Executor::Threads, _) => Pipelines::THREADED(ThreadedPipeline::new(
+                    processor,
+                    worker_count,
+                    max_restarts,
+                    memory_budget,
+                )?),
+            };
+            Ok((pipeline, stats))
+        })
+        .collect()
 }
 
 pub enum Pipelines<T: PipelineData> {
     SYNC(SyncPipeline<T>),
     THREADED(ThreadedPipeline<T>),
+    ASYNC(AsyncPipeline<T>),
+    STAGE(StagePipeline<T>),
 }
@@ -0,0 +1,277 @@
+//! Hands the contexts of a [`ThreadedPipeline`](super::ThreadedPipeline) out to its workers.
+//!
+//! Every worker gets a queue of contexts claimed for it, kept together by site so the runs of a site still reach the
+//! same worker (see [`BatchedProcessor`](crate::processing::processor::batched::BatchedProcessor)). A context is only
+//! handed to a worker once it is idle, and an idle worker with nothing queued steals the last site of the longest
+//! queue, so a few slow contexts don't leave the other workers waiting out the tail of the run.
+//!
+//! Runs may hint at what their contexts take (see [`RunConfig::threads`], [`RunConfig::memory_hint`] and
+//! [`RunConfig::nice`]), which caps how many workers process contexts of the run at once. Workers never tell when they
+//! are done with a context, so a worker counts as busy with the last context handed to it until it takes the next
+//! one. A capped run thus gets at most its share of the workers, if sometimes a bit less.
+
+use super::super::context::Context;
+use crate::config::runs::RunConfig;
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+use std::sync::mpmc::{Receiver, Sender, TryRecvError, TrySendError};
+use std::time::Duration;
+
+/// How many contexts can be claimed ahead of the workers, per worker.
+const CLAIMED_PER_WORKER: usize = 4;
+
+/// How long to wait before trying again when every worker is busy.
+const IDLE_WAIT: Duration = Duration::from_millis(1);
+
+pub(super) struct Scheduler {
+    queues: Vec<VecDeque<Context>>,
+    /// Site and queue of the last context claimed.
+    last: Option<(i32, usize)>,
+    /// Run of the last context handed to every worker.
+    handed: Vec<Option<String>>,
+    /// Memory the contexts of a run may take together, in MB. See --memory-budget.
+    memory_budget: Option<u64>,
+}
+
+impl Scheduler {
+    pub(super) fn new(workers: usize, memory_budget: Option<u64>) -> Self {
+        Self {
+            queues: vec![VecDeque::new(); workers],
+            last: None,
+            handed: vec![None; workers],
+            memory_budget,
+        }
+    }
+
+    /// How many workers may process contexts of `run` at once.
+    fn cap(&self, run: &RunConfig) -> usize {
+        let workers = self.queues.len();
+        let mut cap = workers.div_ceil(1 + run.nice as usize);
+        if let Some(threads) = run.threads {
+            cap = cap.min(threads);
+        }
+        if let (Some(budget), Some(hint)) = (self.memory_budget, run.memory_hint) {
+            cap = cap.min((budget / hint.max(1)) as usize);
+        }
+        cap.max(1)
+    }
+
+    /// Whether `worker` may take `ctx`, given the contexts the other workers may still be busy with.
+    fn fits(&self, ctx: &Context, worker: usize) -> bool {
+        let busy = self
+            .handed
+            .iter()
+            .enumerate()
+            .filter(|&(i, run)| i != worker && run.as_deref() == Some(ctx.run.name.as_str()))
+            .count();
+        busy < self.cap(&ctx.run)
+    }
+
+    fn claimed(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum()
+    }
+
+    /// Queues `ctx` after the other contexts of its site, or on the shortest queue if it starts a new site.
+    fn claim(&mut self, ctx: Context) {
+        let queue = match self.last {
+            Some((site, queue)) if site == ctx.site.id => queue,
+            _ => (0..self.queues.len())
+                .min_by_key(|&i| self.queues[i].len())
+                .unwrap_or_default(),
+        };
+        self.last = Some((ctx.site.id, queue));
+        self.queues[queue].push_back(ctx);
+    }
+
+    /// Next context for `worker`: the next one of its own queue, or else the first of the last site of the longest
+    /// queue it may take. The other contexts of that site move over to its queue along with it, so they still reach the
+    /// same worker.
+    fn take(&mut self, worker: usize) -> Option<(Context, usize)> {
+        if self.queues[worker]
+            .front()
+            .is_some_and(|ctx| self.fits(ctx, worker))
+        {
+            return self.queues[worker].pop_front().map(|ctx| (ctx, worker));
+        }
+        let mut victims: Vec<usize> = (0..self.queues.len()).filter(|&i| i != worker).collect();
+        victims.sort_by_key(|&i| Reverse(self.queues[i].len()));
+        let victim = victims.into_iter().find(|&i| {
+            self.queues[i]
+                .back()
+                .is_some_and(|ctx| self.fits(ctx, worker))
+        })?;
+        let site = self.queues[victim].back()?.site.id;
+        let group = self.queues[victim]
+            .iter()
+            .rev()
+            .take_while(|ctx| ctx.site.id == site)
+            .count();
+        let at = self.queues[victim].len() - group;
+        for ctx in self.queues[victim].split_off(at).into_iter().rev() {
+            self.queues[worker].push_front(ctx);
+        }
+        if self.last == Some((site, victim)) {
+            self.last = Some((site, worker));
+        }
+        self.queues[worker].pop_front().map(|ctx| (ctx, worker))
+    }
+
+    fn put_back(&mut self, ctx: Context, worker: usize, from: usize) {
+        match from == worker {
+            true => self.queues[from].push_front(ctx),
+            false => self.queues[from].push_back(ctx),
+        }
+    }
+
+    /// Hands the contexts received on `rx` out to the `workers` (one rendezvous channel each, so a send only goes
+    /// through to an idle worker) until every sender hangs up and every claimed context is handed out, or every worker
+    /// is gone.
+    pub(super) fn dispatch(mut self, rx: &Receiver<Context>, workers: &[Sender<Context>]) {
+        let mut open = true;
+        let mut alive = vec![true; workers.len()];
+        loop {
+            let mut progressed = false;
+
+            if open && self.claimed() < CLAIMED_PER_WORKER * workers.len() {
+                match rx.try_recv() {
+                    Ok(ctx) => {
+                        self.claim(ctx);
+                        progressed = true;
+                    }
+                    Err(TryRecvError::Empty) => {}
+                    Err(TryRecvError::Disconnected) => open = false,
+                }
+            }
+
+            for (worker, tx) in workers.iter().enumerate() {
+                if !alive[worker] {
+                    continue;
+                }
+                let Some((ctx, from)) = self.take(worker) else {
+                    continue;
+                };
+                let run = ctx.run.name.clone();
+                match tx.try_send(ctx) {
+                    Ok(()) => {
+                        self.handed[worker] = Some(run);
+                        progressed = true;
+                    }
+                    Err(TrySendError::Full(ctx)) => self.put_back(ctx, worker, from),
+                    // The worker gave up, its queue is left to the others to steal from.
+                    Err(TrySendError::Disconnected(ctx)) => {
+                        alive[worker] = false;
+                        self.put_back(ctx, worker, from);
+                    }
+                }
+            }
+
+            let claimed = self.claimed();
+            if (!open && claimed == 0) || !alive.contains(&true) {
+                return;
+            }
+            if progressed {
+                continue;
+            }
+            if open && claimed == 0 {
+                // Nothing to hand out, so wait for the previous stage rather than polling it.
+                match rx.recv() {
+                    Ok(ctx) => self.claim(ctx),
+                    Err(_) => open = false,
+                }
+            } else {
+                std::thread::sleep(IDLE_WAIT);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::runs::RunConfig;
+
+    #[test]
+    fn test_scheduler() {
+        let mut scheduler = Scheduler::new(2, None);
+        for (run, site) in [("r1", 1), ("r2", 1), ("r1", 2), ("r2", 2), ("r1", 3)] {
+            scheduler.claim(Context::for_test(run, site));
+        }
+        let sites = |queue: &VecDeque<Context>| queue.iter().map(|c| c.site.id).collect::<Vec<_>>();
+        assert_eq!(sites(&scheduler.queues[0]), vec![1, 1, 3]);
+        assert_eq!(sites(&scheduler.queues[1]), vec![2, 2]);
+
+        let mut take = |worker| scheduler.take(worker).map(|(c, from)| (c.site.id, from));
+        // Worker 1 runs out of its own contexts, then steals from the end of worker 0's queue.
+        assert_eq!(take(1), Some((2, 1)));
+        assert_eq!(take(1), Some((2, 1)));
+        assert_eq!(take(1), Some((3, 1)));
+        assert_eq!(take(0), Some((1, 0)));
+        assert_eq!(scheduler.claimed(), 1);
+    }
+
+    #[test]
+    fn test_scheduler_steals_sites() {
+        let mut scheduler = Scheduler::new(2, None);
+        for (run, site) in [("r1", 1), ("r1", 2), ("r1", 3), ("r2", 3), ("r3", 3)] {
+            scheduler.claim(Context::for_test(run, site));
+        }
+        let sites = |queue: &VecDeque<Context>| queue.iter().map(|c| c.site.id).collect::<Vec<_>>();
+        assert_eq!(sites(&scheduler.queues[0]), vec![1, 3, 3, 3]);
+        assert_eq!(sites(&scheduler.queues[1]), vec![2]);
+
+        fn take(scheduler: &mut Scheduler, worker: usize) -> Option<(i32, String, usize)> {
+            scheduler
+                .take(worker)
+                .map(|(c, from)| (c.site.id, c.run.name, from))
+        }
+        assert_eq!(take(&mut scheduler, 1), Some((2, "r1".to_string(), 1)));
+        // Worker 1 steals every run of site 3, in order, rather than just the last one.
+        assert_eq!(take(&mut scheduler, 1), Some((3, "r1".to_string(), 1)));
+        assert_eq!(sites(&scheduler.queues[0]), vec![1]);
+        assert_eq!(sites(&scheduler.queues[1]), vec![3, 3]);
+        assert_eq!(take(&mut scheduler, 1), Some((3, "r2".to_string(), 1)));
+        assert_eq!(take(&mut scheduler, 1), Some((3, "r3".to_string(), 1)));
+
+        // Runs of site 3 claimed after the steal follow it to worker 1.
+        scheduler.claim(Context::for_test("r4", 3));
+        assert_eq!(sites(&scheduler.queues[1]), vec![3]);
+    }
+
+    #[test]
+    fn test_scheduler_caps() {
+        let run = |name: &str, threads, memory_hint, nice| RunConfig {
+            name: name.to_string(),
+            threads,
+            memory_hint,
+            nice,
+            ..Default::default()
+        };
+        let scheduler = Scheduler::new(8, Some(4096));
+        assert_eq!(scheduler.cap(&run("r", None, None, 0)), 8);
+        assert_eq!(scheduler.cap(&run("r", Some(3), None, 0)), 3);
+        assert_eq!(scheduler.cap(&run("r", None, Some(2048), 0)), 2);
+        assert_eq!(scheduler.cap(&run("r", None, Some(8192), 0)), 1);
+        assert_eq!(scheduler.cap(&run("r", None, None, 1)), 4);
+        assert_eq!(scheduler.cap(&run("r", Some(3), None, 3)), 2);
+
+        let mut scheduler = Scheduler::new(2, None);
+        let heavy = run("heavy", Some(1), None, 0);
+        for site in [1, 2] {
+            scheduler.claim(Context {
+                run: heavy.clone(),
+                ..Context::for_test("", site)
+            });
+        }
+        scheduler.handed[0] = Some("heavy".to_string());
+        // Worker 1 can't take the heavy contexts while worker 0 may be busy with one.
+        assert!(scheduler.take(1).is_none());
+        assert_eq!(
+            scheduler.take(0).map(|(c, from)| (c.site.id, from)),
+            Some((1, 0))
+        );
+        assert_eq!(
+            scheduler.take(0).map(|(c, from)| (c.site.id, from)),
+            Some((2, 0))
+        );
+    }
+}
@@ -0,0 +1,191 @@
+use super::super::context::Context;
+use super::super::processor::ProcessorEnvironment;
+use super::super::template::TemplateEngine;
+use super::{Pipeline, PipelineData};
+use std::any::Any;
+use std::error::Error;
+use std::sync::mpmc::{sync_channel, Receiver, Sender};
+use std::sync::Arc;
+
+/// A stage of the pipeline conducting its contexts on its own, for the plugins whose work doesn't fit a
+/// [`super::super::processor::Processor`] run by an executor (e.g. a quality-control stage holding contexts back until
+/// it saw enough of them). Registered in [`crate::registry::Registries::reg_pipeline_stages`] and listed in the
+/// pipeline of the config like any processor. A stage takes and passes on any [`PipelineData`], and is only put in a
+/// pipeline after a stage passing on its [`Stage::Input`] (see [`super::StageTypes`]).
+///
+/// A stage takes the contexts of the previous stage from `rx` until it hangs up, and passes the ones it keeps on to
+/// the next stage through `tx`. Returning an error (or dropping `tx`) winds down the stages after it.
+pub trait Stage: Send + Sync {
+    type Input: PipelineData;
+    type Output: PipelineData;
+    fn conduct(
+        &self,
+        tx: &Sender<Self::Output>,
+        rx: &Receiver<Self::Input>,
+    ) -> Result<(), Box<dyn Error + Send>>;
+}
+
+impl<S: Stage + ?Sized> Stage for Box<S> {
+    type Input = S::Input;
+    type Output = S::Output;
+
+    fn conduct(
+        &self,
+        tx: &Sender<Self::Output>,
+        rx: &Receiver<Self::Input>,
+    ) -> Result<(), Box<dyn Error + Send>> {
+        (**self).conduct(tx, rx)
+    }
+}
+
+/// Constructs a new [`Stage`] of type [`S`] from the config [`C`].
+#[allow(type_alias_bounds)] // Same as the site generator drivers, see [`crate::sites::SiteGeneratorDriver`].
+type StageFactory<S: Stage, C> = Arc<dyn Fn(C, &ProcessorEnvironment) -> Result<S, Box<dyn Error>>>;
+
+/// Deserializes a config of type [`C`] from a [`serde_json::Value`].
+type StageConfigDeserializer<C> =
+    Arc<dyn Fn(serde_json::Value) -> Result<C, serde_json::error::Error>>;
+
+/// A [`Stage`] addressable by identifier from the pipeline of the config.
+pub struct StageDriver<S: Stage, C> {
+    pub create: StageFactory<S, C>,
+    pub config_deserializer: StageConfigDeserializer<C>,
+}
+
+impl<S: Stage, C> Clone for StageDriver<S, C> {
+    fn clone(&self) -> Self {
+        StageDriver {
+            create: self.create.clone(),
+            config_deserializer: self.config_deserializer.clone(),
+        }
+    }
+}
+
+/// Conducts a [`Stage`] on the contexts the stages pass on to each other, whatever its data: the contexts are turned
+/// into its [`Stage::Input`] on the way in, and its [`Stage::Output`] back into contexts on the way out.
+struct Bridged<S: Stage>(S);
+
+impl<S: Stage> Stage for Bridged<S> {
+    type Input = Context;
+    type Output = Context;
+
+    fn conduct(
+        &self,
+        tx: &Sender<Self::Output>,
+        rx: &Receiver<Self::Input>,
+    ) -> Result<(), Box<dyn Error + Send>> {
+        let (tx_input, rx_input) = sync_channel::<S::Input>(0);
+        let (tx_output, rx_output) = sync_channel::<S::Output>(0);
+        std::thread::scope(|s| {
+            s.spawn(move || {
+                for ctx in rx.iter() {
+                    if tx_input.send(ctx.into()).is_err() {
+                        break;
+                    }
+                }
+            });
+            s.spawn(move || {
+                for data in rx_output.iter() {
+                    if tx.send(data.into()).is_err() {
+                        break;
+                    }
+                }
+            });
+            let result = self.0.conduct(&tx_output, &rx_input);
+            // Hangs up on both ends, so the bridges wind down along with the stage.
+            drop((tx_output, rx_input));
+            result
+        })
+    }
+}
+
+impl<S: Stage, C> StageDriver<S, C> {
+    pub fn coerce_to_dynamic(
+        self,
+    ) -> StageDriver<Box<dyn Stage<Input = Context, Output = Context>>, Box<dyn Any>>
+    where
+        S: 'static,
+        C: Any + 'static,
+    {
+        StageDriver {
+            create: Arc::new(move |c: Box<dyn Any>, env: &ProcessorEnvironment| {
+                let config = c
+                    .downcast::<C>()
+                    .map_err(|_| Box::<dyn Error>::from("Failed to downcast config"))?;
+                let concrete_stage = (self.create)(*config, env)?;
+                Ok(Box::new(Bridged(concrete_stage))
+                    as Box<dyn Stage<Input = Context, Output = Context>>)
+            }),
+            config_deserializer: Arc::new(move |v| {
+                let concrete_config = (self.config_deserializer)(v)?;
+                Ok(Box::new(concrete_config) as Box<dyn Any>)
+            }),
+        }
+    }
+}
+
+/// Conducts a [`Stage`] as a pipeline. The stage runs on the thread of its conductor, it is up to it to spawn workers.
+pub struct StagePipeline<O: PipelineData> {
+    stage: Box<dyn Stage<Input = Context, Output = O>>,
+}
+
+impl<O: PipelineData> StagePipeline<O> {
+    pub fn new(stage: Box<dyn Stage<Input = Context, Output = O>>) -> Self {
+        Self { stage }
+    }
+}
+
+impl<O: PipelineData> Pipeline for StagePipeline<O> {
+    type Output = O;
+
+    fn conduct(
+        &self,
+        tx: &Sender<Self::Output>,
+        rx: &Receiver<Context>,
+        _templates: &TemplateEngine,
+    ) -> Result<(), Box<dyn Error + Send>> {
+        self.stage.conduct(tx, rx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Executed, Rendered};
+    use super::*;
+    use std::sync::mpmc::channel;
+
+    /// Passes on the rendered contexts of even sites only, as executed.
+    struct EvenSites;
+
+    impl Stage for EvenSites {
+        type Input = Rendered;
+        type Output = Executed;
+
+        fn conduct(
+            &self,
+            tx: &Sender<Executed>,
+            rx: &Receiver<Rendered>,
+        ) -> Result<(), Box<dyn Error + Send>> {
+            for Rendered(ctx) in rx.iter().filter(|r| r.0.site.id % 2 == 0) {
+                tx.send(Executed(ctx))
+                    .map_err(|err| Box::new(err) as Box<dyn Error + Send>)?;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_bridged() {
+        let (tx, rx) = channel();
+        for id in 0..5 {
+            tx.send(Context::for_test("", id)).unwrap();
+        }
+        drop(tx);
+
+        let (tx_out, rx_out) = channel();
+        Bridged(EvenSites).conduct(&tx_out, &rx).unwrap();
+        drop(tx_out);
+        let sites: Vec<i32> = rx_out.iter().map(|ctx| ctx.site.id).collect();
+        assert_eq!(sites, [0, 2, 4]);
+    }
+}
@@ -1,9 +1,12 @@
 use super::super::context::Context;
-use super::super::processor::Processor;
+use super::super::processor::{take_tracked_context, Processor};
 use super::super::template::TemplateEngine;
+use super::scheduler::Scheduler;
 use super::{Pipeline, PipelineData};
+use std::any::Any;
 use std::error::Error;
-use std::sync::mpmc::{Receiver, Sender};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpmc::{sync_channel, Receiver, Sender};
 use std::sync::Arc;
 use std::thread;
 use std::thread::ScopedJoinHandle;
@@ -25,6 +28,8 @@ impl<O: PipelineData> ThreadedPipeline<O> {
     pub fn new(
         processor: impl Processor<Output = O> + 'static,
         workers: usize,
+        max_restarts: usize,
+        memory_budget: Option<u64>,
     ) -> Result<ThreadedPipeline<O>, NotEnoughWorkersError> {
         if workers <= 1 {
             return Err(NotEnoughWorkersError.into());
@@ -32,6 +37,8 @@ impl<O: PipelineData> ThreadedPipeline<O> {
 
         Ok(ThreadedPipeline {
             workers,
+            max_restarts,
+            memory_budget,
             processor: Arc::new(processor),
         })
     }
@@ -39,9 +46,73 @@ impl<O: PipelineData> ThreadedPipeline<O> {
 
 pub struct ThreadedPipeline<O: Sized + Send + Sync> {
     workers: usize,
+    max_restarts: usize,
+    /// See [`crate::config::Args::memory_budget`].
+    memory_budget: Option<u64>,
     processor: Arc<dyn Processor<Output = O>>,
 }
 
+/// Extracts the message out of a panic payload, if it is a string.
+fn panic_message(payload: &Box<dyn Any + Send>) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
+
+impl<O: PipelineData + 'static> ThreadedPipeline<O> {
+    /// Runs the processor on the current thread, restarting it up to `self.max_restarts` times if it
+    /// fails or panics. Failures are logged along with the context that was being processed.
+    fn supervise(
+        &self,
+        worker: usize,
+        tx: &Sender<O>,
+        rx: &Receiver<Context>,
+        templates: &TemplateEngine,
+    ) {
+        let mut restarts = 0;
+        loop {
+            let result = catch_unwind(AssertUnwindSafe(|| {
+                self.processor.process(tx, rx, templates)
+            }));
+
+            let reason = match result {
+                Ok(Ok(())) => return,
+                Ok(Err(err)) => err.to_string(),
+                Err(payload) => format!("panicked: {}", panic_message(&payload)),
+            };
+
+            match take_tracked_context() {
+                Some(location) => eprintln!(
+                    "ThreadedPipeline: Worker Thread {} crashed while processing {}: {}",
+                    worker, location, reason
+                ),
+                None => eprintln!(
+                    "ThreadedPipeline: Worker Thread {} crashed: {}",
+                    worker, reason
+                ),
+            }
+
+            if restarts >= self.max_restarts {
+                eprintln!(
+                    "ThreadedPipeline: Worker Thread {} will not be restarted. The remaining workers will carry on.",
+                    worker
+                );
+                return;
+            }
+
+            restarts += 1;
+            eprintln!(
+                "ThreadedPipeline: Restarting Worker Thread {} ({}/{}).",
+                worker, restarts, self.max_restarts
+            );
+        }
+    }
+}
+
 impl<O: PipelineData + 'static> Pipeline for ThreadedPipeline<O> {
     type Output = O;
 
@@ -52,23 +123,27 @@ impl<O: PipelineData + 'static> Pipeline for ThreadedPipeline<O> {
         templates: &TemplateEngine,
     ) -> Result<(), Box<dyn Error + Send>> {
         thread::scope(|s| {
-            let thread_pool: Vec<ScopedJoinHandle<()>> = (0..self.workers)
+            // Each worker pulls from its own rendezvous channel, fed by the scheduler on this thread.
+            let (senders, receivers): (Vec<_>, Vec<_>) = (0..self.workers)
+                .map(|_| sync_channel::<Context>(0))
+                .unzip();
+            let thread_pool: Vec<ScopedJoinHandle<()>> = receivers
+                .into_iter()
                 .enumerate()
-                .map(move |(i, _)| {
-                    s.spawn(move || {
-                        // TODO better error recovery
-                        if let Err(err) = self.processor.process(&tx, &rx, &templates) {
-                            panic!("ThreadedPipeline: Worker Thread {} crashed: {}", i, err);
-                        }
-                    })
-                })
+                .map(|(i, rx)| s.spawn(move || self.supervise(i, tx, &rx, templates)))
                 .collect();
 
+            Scheduler::new(self.workers, self.memory_budget).dispatch(rx, &senders);
+            drop(senders);
+
+            // Workers never unwind past the supervisor, so joining only fails on a bug in the supervisor itself.
             thread_pool.into_iter().enumerate().for_each(|(i, t)| {
-                t.join().expect(&format!(
-                    "ThreadedPipeline: Worker Thread {} crashed when joining.",
-                    i
-                ));
+                if t.join().is_err() {
+                    eprintln!(
+                        "ThreadedPipeline: Supervisor of Worker Thread {} crashed.",
+                        i
+                    );
+                }
             });
 
             Ok(())
@@ -0,0 +1,129 @@
+use super::template::TemplateEngine;
+use crate::config::runs::RunConfig;
+use crate::config::Config;
+use crate::sites::enrich::{ELEVATION_COVARIATE, REGION_ATTRIBUTES};
+use std::collections::BTreeSet;
+use std::path::Path;
+use thiserror::Error;
+
+/// Every problem found by [`preflight`], reported at once.
+#[derive(Debug, Error)]
+#[error("Preflight checks failed:\n{}", .0.iter().map(|issue| format!("  - {}", issue)).collect::<Vec<_>>().join("\n"))]
+pub struct PreflightError(pub Vec<String>);
+
+/// Checks everything that can be checked before starting the pipeline, so problems show up before hours of processing rather than after:
+/// - The site source datasets can be opened and have the requested bands, layers and fields (see [`crate::config::sites::SiteSourceConfig::preflight`]);
+/// - The templates of the template directory and of every run parse;
+/// - The templates only use variables their contexts have (see [`unresolved_variables`]). As the covariates of the
+///   sites aren't known until they are read, those that don't are only warned about unless `strict_templates`;
+/// - The working directory is writable, if there is one yet.
+pub fn preflight(
+    config: &Config,
+    workdir: Option<&Path>,
+    strict_templates: bool,
+) -> Result<(), PreflightError> {
+    let mut issues = config.sites.preflight();
+
+    let mut templates = TemplateEngine::default();
+    templates.register_extensions(&config.template_extensions);
+    if let Some(dir) = &config.template_dir {
+        if let Err(e) = templates.register_dir(dir) {
+            issues.push(e.to_string());
+        }
+    }
+    for run in &config.runs {
+        if let Err(e) = templates.register(run, &config.engines[&run.name]) {
+            issues.push(e.to_string());
+            continue;
+        }
+
+        let unresolved = unresolved_variables(config, run, &templates);
+        if unresolved.is_empty() {
+            continue;
+        }
+        let issue = format!(
+            "Template of run \"{}\" uses variables its contexts don't have: {}",
+            run.name,
+            unresolved.into_iter().collect::<Vec<_>>().join(", ")
+        );
+        match strict_templates {
+            true => issues.push(issue),
+            false => eprintln!(
+                "Warning: {} (unless they are covariates or attributes of the sites)",
+                issue
+            ),
+        }
+    }
+
+    if let Some(workdir) = workdir {
+        if let Err(e) = tempfile::Builder::new()
+            .prefix(".pythia-preflight")
+            .tempfile_in(workdir)
+        {
+            issues.push(format!(
+                "Working directory {} is not writable: {}",
+                workdir.display(),
+                e
+            ));
+        }
+    }
+
+    if issues.is_empty() {
+        Ok(())
+    } else {
+        Err(PreflightError(issues))
+    }
+}
+
+/// Variables the template of `run` fails to render without (see [`TemplateEngine::variables`]) that are neither
+/// standard context variables, nor `extra` of the run, nor provided by its features (e.g. `wsta` for
+/// `weather_stations`). Empty if its engine can't tell.
+fn unresolved_variables(
+    config: &Config,
+    run: &RunConfig,
+    templates: &TemplateEngine,
+) -> BTreeSet<String> {
+    let Some(mut variables) = templates.variables(run) else {
+        return BTreeSet::new();
+    };
+
+    let mut known = vec!["site_id", "soil_id", "lng", "lon", "lat", "name", "weight"];
+    let features: [(bool, &[&str]); 10] = [
+        (config.sites.elevation.is_some(), &[ELEVATION_COVARIATE]),
+        (config.sites.regions.is_some(), &REGION_ATTRIBUTES),
+        (
+            run.simulation.is_some(),
+            &["simulation_mode", "nyers", "treatments_block", "treatments"],
+        ),
+        (run.cultivar.is_some(), &["ingeno", "cname"]),
+        (
+            run.crop_calendar.is_some(),
+            &["pdate", "pdate_doy", "pdate_year", "sdate", "hdate"],
+        ),
+        (
+            run.planting.is_some(),
+            &["pdate", "pdate_doy", "pdate_year"],
+        ),
+        (
+            run.weather_stations.is_some(),
+            &["wsta", "wth_file", "wsta_distance_km"],
+        ),
+        (run.wth_code.is_some(), &["wth_code"]),
+        (run.planting_window.is_some(), &["pdate_start", "pdate_end"]),
+        (
+            run.fertilizer.is_some(),
+            &["fertilizers", "fertilizer_total"],
+        ),
+    ];
+    for (enabled, names) in features {
+        if enabled {
+            known.extend(names);
+        }
+    }
+
+    variables.retain(|name| {
+        let soil = run.soil.is_some() && name.starts_with("soil_");
+        !soil && !known.contains(&name.as_str()) && !run.extra.contains_key(name)
+    });
+    variables
+}
@@ -0,0 +1,106 @@
+//! Delivery of the contexts to the pipeline by priority of their run (see [`RunConfig::priority`]), so the runs with a
+//! deadline complete ahead of the background ones sharing their site stream.
+//!
+//! [`RunConfig::priority`]: crate::config::runs::RunConfig::priority
+
+use super::context::Context;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// A context waiting to be delivered. The greatest is the one of the highest priority generated first.
+struct Pending {
+    priority: i32,
+    seq: usize,
+    ctx: Context,
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Pending {}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Pending {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+/// Reads up to `window` contexts ahead of `inner` and yields the one of the highest priority first, in the order they
+/// were generated otherwise. A window of 1 or less keeps the order of `inner`.
+pub struct Prioritized<I: Iterator<Item = Context>> {
+    inner: I,
+    window: usize,
+    pending: BinaryHeap<Pending>,
+    seq: usize,
+}
+
+impl<I: Iterator<Item = Context>> Prioritized<I> {
+    pub fn new(inner: I, window: usize) -> Self {
+        Self {
+            inner,
+            window,
+            pending: BinaryHeap::new(),
+            seq: 0,
+        }
+    }
+}
+
+impl<I: Iterator<Item = Context>> Iterator for Prioritized<I> {
+    type Item = Context;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.window <= 1 {
+            return self.inner.next();
+        }
+        while self.pending.len() < self.window {
+            let Some(ctx) = self.inner.next() else {
+                break;
+            };
+            self.pending.push(Pending {
+                priority: ctx.run.priority,
+                seq: self.seq,
+                ctx,
+            });
+            self.seq += 1;
+        }
+        self.pending.pop().map(|pending| pending.ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Order of delivery of a deadline run ("d") and a sweep run ("s") on 3 sites, e.g. "d0" for the deadline run on
+    /// site 0.
+    fn order(window: usize) -> Vec<String> {
+        let context = |run: &str, priority, site| {
+            let mut ctx = Context::for_test(run, site);
+            ctx.run.priority = priority;
+            ctx
+        };
+        let contexts = (0..3).flat_map(|site| [context("s", 0, site), context("d", 5, site)]);
+        Prioritized::new(contexts, window)
+            .map(|ctx| format!("{}{}", ctx.run.name, ctx.site.id))
+            .collect()
+    }
+
+    #[test]
+    fn test_prioritized() {
+        assert_eq!(order(1), ["s0", "d0", "s1", "d1", "s2", "d2"]);
+        assert_eq!(order(100), ["d0", "d1", "d2", "s0", "s1", "s2"]);
+        // The deadline run only gets ahead by as many contexts as the window reads ahead.
+        assert_eq!(order(2), ["d0", "s0", "d1", "s1", "d2", "s2"]);
+    }
+}
@@ -0,0 +1,124 @@
+use super::super::context::Context;
+use super::super::template::TemplateEngine;
+use super::unbatched::{SiteWeather, UnbatchedProcessor};
+use super::Processor;
+use std::error::Error;
+use std::sync::mpmc::{Receiver, Sender};
+
+/// Same as [`UnbatchedProcessor`], but handles the contexts of every run of a site as one unit, so the per-site setup
+/// (e.g. fetching the weather) is done once rather than once per run. Relies on the contexts of a site arriving
+/// one after the other, as [`crate::processing::context::ContextGenerator`] emits them and the scheduler of
+/// [`crate::processing::pipeline::ThreadedPipeline`] hands them out.
+pub struct BatchedProcessor {
+    pub inner: UnbatchedProcessor,
+    /// Number of runs, the most contexts a site can have.
+    pub runs: usize,
+}
+
+impl BatchedProcessor {
+    pub fn new(inner: UnbatchedProcessor, runs: usize) -> Self {
+        Self { inner, runs }
+    }
+
+    /// Receives the contexts of the next site, starting with `pending` (the first context of the next site, received
+    /// while collecting the previous one). Empty once every sender hung up.
+    fn next_site(&self, rx: &Receiver<Context>, pending: &mut Option<Context>) -> Vec<Context> {
+        let Some(first) = pending.take().or_else(|| rx.recv().ok()) else {
+            return Vec::new();
+        };
+
+        let mut site = vec![first];
+        while site.len() < self.runs {
+            match rx.recv() {
+                Ok(ctx) if ctx.site.id == site[0].site.id => site.push(ctx),
+                Ok(ctx) => {
+                    *pending = Some(ctx);
+                    break;
+                }
+                Err(_) => break,
+            }
+        }
+        site
+    }
+}
+
+impl Processor for BatchedProcessor {
+    type Output = Context;
+
+    fn process(
+        &self,
+        tx: &Sender<Self::Output>,
+        rx: &Receiver<Self::Output>,
+        templates: &TemplateEngine,
+    ) -> Result<(), Box<dyn Error + Send>> {
+        self.inner.with_writers(tx, |queue| {
+            let mut pending = None;
+            loop {
+                let site = self.next_site(rx, &mut pending);
+                if site.is_empty() {
+                    return Ok(());
+                }
+
+                let mut weather = SiteWeather::default();
+                for ctx in site {
+                    self.inner.handle(ctx, queue, templates, &mut weather)?;
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provenance::{InputDigest, Manifest};
+    use std::sync::mpmc::channel;
+
+    #[test]
+    fn test_next_site() {
+        let (failures, _) = channel();
+        let processor = BatchedProcessor::new(
+            UnbatchedProcessor {
+                workdir: Default::default(),
+                planting_rules: Default::default(),
+                run_batches: Default::default(),
+                manifest: Manifest::new(
+                    InputDigest {
+                        path: Default::default(),
+                        size: 0,
+                        sha256: String::new(),
+                    },
+                    Default::default(),
+                    false,
+                    vec![],
+                ),
+                retry: Default::default(),
+                failures,
+                stats: Default::default(),
+                writers: 0,
+            },
+            2,
+        );
+
+        let (tx, rx) = channel();
+        for (run, site) in [("r1", 1), ("r2", 1), ("r1", 2), ("r1", 3), ("r2", 3)] {
+            tx.send(Context::for_test(run, site)).unwrap();
+        }
+        drop(tx);
+
+        let mut pending = None;
+        let sites: Vec<Vec<(String, i32)>> = std::iter::from_fn(|| {
+            let site = processor.next_site(&rx, &mut pending);
+            (!site.is_empty()).then(|| site.into_iter().map(|c| (c.run.name, c.site.id)).collect())
+        })
+        .collect();
+        assert_eq!(
+            sites,
+            vec![
+                vec![("r1".to_string(), 1), ("r2".to_string(), 1)],
+                vec![("r1".to_string(), 2)],
+                vec![("r1".to_string(), 3), ("r2".to_string(), 3)],
+            ]
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use super::super::context::Context;
+use super::super::failure::FailedContext;
+use super::super::report::StageStats;
+use super::super::template::TemplateEngine;
+use super::{AsyncProcessor, ProcessFuture, Processor, ProcessorError};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::mpmc::{Receiver, Sender};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Copies the files of the directory of every context to the same place under `destination`, where the results of the
+/// campaign are kept (e.g. a network filesystem or a mounted bucket). The contexts whose files can't be copied are sent
+/// to the dead-letter channel, and not passed on.
+///
+/// Copying mostly waits on the storage, so the stage is best run on the async pipeline, which copies many contexts at
+/// once without a worker thread each.
+pub struct CopyProcessor {
+    pub workdir: PathBuf,
+    pub destination: PathBuf,
+    /// Names of the files copied, e.g. `Summary.OUT`. Every file of the directory if empty.
+    pub files: Vec<String>,
+    pub failures: Sender<FailedContext>,
+    pub stats: Arc<StageStats>,
+}
+
+impl CopyProcessor {
+    /// Copies the files of `ctx`.
+    async fn copy(&self, ctx: &Context) -> Result<(), ProcessorError> {
+        let (from, to) = (ctx.dir(&self.workdir), ctx.dir(&self.destination));
+        let err = |path: &Path| {
+            let (location, path) = (ctx.location(), path.to_path_buf());
+            move |source| ProcessorError::Copy {
+                location,
+                path,
+                source,
+            }
+        };
+
+        let mut names: Vec<PathBuf> = self.files.iter().map(PathBuf::from).collect();
+        if names.is_empty() {
+            let mut entries = tokio::fs::read_dir(&from).await.map_err(err(&from))?;
+            while let Some(entry) = entries.next_entry().await.map_err(err(&from))? {
+                if entry.file_type().await.map_err(err(&from))?.is_file() {
+                    names.push(entry.file_name().into());
+                }
+            }
+        }
+
+        tokio::fs::create_dir_all(&to).await.map_err(err(&to))?;
+        for name in names {
+            let path = from.join(&name);
+            tokio::fs::copy(&path, to.join(&name))
+                .await
+                .map_err(err(&path))?;
+        }
+        Ok(())
+    }
+}
+
+impl Processor for CopyProcessor {
+    type Output = Context;
+
+    /// Copies the contexts one at a time, on a runtime of the worker.
+    fn process(
+        &self,
+        tx: &Sender<Self::Output>,
+        rx: &Receiver<Context>,
+        templates: &TemplateEngine,
+    ) -> Result<(), Box<dyn Error + Send>> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .map_err(|err| Box::new(err) as Box<dyn Error + Send>)?;
+        for ctx in rx.iter() {
+            if let Some(ctx) = runtime.block_on(self.process_one(ctx, templates))? {
+                tx.send(ctx)
+                    .map_err(|err| Box::new(err) as Box<dyn Error + Send>)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn as_async(&self) -> Option<&dyn AsyncProcessor<Output = Self::Output>> {
+        Some(self)
+    }
+}
+
+impl AsyncProcessor for CopyProcessor {
+    type Output = Context;
+
+    fn process_one<'a>(
+        &'a self,
+        ctx: Context,
+        _templates: &'a TemplateEngine,
+    ) -> ProcessFuture<'a, Self::Output> {
+        Box::pin(async move {
+            let started = Instant::now();
+            match self.copy(&ctx).await {
+                Ok(()) => {
+                    self.stats.record(Ok(()), started.elapsed());
+                    Ok(Some(ctx))
+                }
+                Err(err) => {
+                    self.stats.record(Err(err.class()), started.elapsed());
+                    self.failures
+                        .send(FailedContext::new(&ctx.location(), 1, &err))
+                        .map_err(|err| Box::new(err) as Box<dyn Error + Send>)?;
+                    Ok(None)
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::GeoDeg;
+    use crate::processing::pipeline::{AsyncPipeline, Pipeline};
+    use std::sync::mpmc::channel;
+
+    #[test]
+    fn test_copy() {
+        let workdir = tempfile::tempdir().unwrap();
+        let destination = tempfile::tempdir().unwrap();
+        let (tx_failures, failures) = channel();
+        let processor = CopyProcessor {
+            workdir: workdir.path().to_path_buf(),
+            destination: destination.path().to_path_buf(),
+            files: vec!["Summary.OUT".to_string()],
+            failures: tx_failures,
+            stats: Default::default(),
+        };
+
+        // Every context but the last one has a directory of its own, with its outputs in it.
+        let (tx, rx) = channel();
+        for site in 0..5 {
+            let mut ctx = Context::for_test("maize", site);
+            ctx.site.lon = GeoDeg::from(site as f64);
+            if site < 4 {
+                let dir = ctx.dir(&workdir.path().to_path_buf());
+                std::fs::create_dir_all(&dir).unwrap();
+                std::fs::write(dir.join("Summary.OUT"), site.to_string()).unwrap();
+                std::fs::write(dir.join("PlantGro.OUT"), "").unwrap();
+            }
+            tx.send(ctx).unwrap();
+        }
+        drop(tx);
+
+        let (tx_out, rx_out) = channel();
+        AsyncPipeline::new(processor, 2)
+            .unwrap()
+            .conduct(&tx_out, &rx, &TemplateEngine::default())
+            .unwrap();
+        drop(tx_out);
+
+        let mut copied: Vec<Context> = rx_out.iter().collect();
+        copied.sort_by_key(|ctx| ctx.site.id);
+        assert_eq!(copied.len(), 4);
+        for ctx in copied {
+            let dir = ctx.dir(&destination.path().to_path_buf());
+            let summary = std::fs::read_to_string(dir.join("Summary.OUT")).unwrap();
+            assert_eq!(summary, ctx.site.id.to_string());
+            assert!(!dir.join("PlantGro.OUT").exists());
+        }
+        assert_eq!(failures.try_recv().unwrap().site, 4);
+    }
+}
@@ -0,0 +1,175 @@
+use super::batched::BatchedProcessor;
+use super::copy::CopyProcessor;
+use super::exec::ExecProcessor;
+use super::rate_limit::RateLimitProcessor;
+use super::retry::RetryPolicy;
+use super::unbatched::UnbatchedProcessor;
+use super::{ProcessorDriver, ProcessorEnvironment};
+use crate::batch::BatchScope;
+use crate::planting::rules::PlantingRules;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::{Arc, LazyLock, Mutex};
+
+/// Options of [`DRIVER_RENDER`] and [`DRIVER_RENDER_BATCHED`]. Everything else it needs is in the runs.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RenderProcessorConfig {
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    /// Threads of every worker writing the rendered files, so a slow filesystem (e.g. NFS or an object storage
+    /// mount) doesn't keep the workers from rendering. Defaults to 0, writing them on the workers.
+    #[serde(default)]
+    pub writers: usize,
+}
+
+/// Builds the [`UnbatchedProcessor`] behind [`DRIVER_RENDER`] and [`DRIVER_RENDER_BATCHED`].
+fn render_processor(
+    c: RenderProcessorConfig,
+    env: &ProcessorEnvironment,
+) -> Result<UnbatchedProcessor, Box<dyn Error>> {
+    let planting_rules = env
+        .config
+        .runs
+        .iter()
+        .filter_map(|run| {
+            let rules = run.planting.as_ref()?;
+            Some(PlantingRules::load(rules).map(|rules| (run.name.clone(), rules)))
+        })
+        .collect::<Result<HashMap<_, _>, _>>()?;
+
+    // Run-wide batch files left by an interrupted run already hold the entries of its completed contexts.
+    let run_batches = env
+        .config
+        .runs
+        .iter()
+        .filter(|run| {
+            env.resume
+                && run.batch.as_ref().is_some_and(|batch| {
+                    batch.scope == BatchScope::Run
+                        && env.workdir.join(&run.name).join(batch.file_name()).exists()
+                })
+        })
+        .map(|run| run.name.clone())
+        .collect();
+
+    Ok(UnbatchedProcessor {
+        workdir: env.workdir.to_path_buf(),
+        planting_rules,
+        run_batches: Mutex::new(run_batches),
+        manifest: env.manifest.clone(),
+        retry: c.retry,
+        failures: env.failures.clone(),
+        stats: env.stats.clone(),
+        writers: c.writers,
+    })
+}
+
+/// Renders the templates (and their weather, soil and batch files) into the context directories.
+pub const DRIVER_RENDER: LazyLock<ProcessorDriver<UnbatchedProcessor, RenderProcessorConfig>> =
+    LazyLock::new(|| ProcessorDriver {
+        create: Arc::new(render_processor),
+        config_deserializer: Arc::new(serde_json::from_value),
+    });
+
+/// Same as [`DRIVER_RENDER`], handling the runs of a site together, see [`BatchedProcessor`].
+pub const DRIVER_RENDER_BATCHED: LazyLock<
+    ProcessorDriver<BatchedProcessor, RenderProcessorConfig>,
+> = LazyLock::new(|| ProcessorDriver {
+    create: Arc::new(|c: RenderProcessorConfig, env: &ProcessorEnvironment| {
+        Ok(BatchedProcessor::new(
+            render_processor(c, env)?,
+            env.config.runs.len(),
+        ))
+    }),
+    config_deserializer: Arc::new(serde_json::from_value),
+});
+
+/// Options of [`DRIVER_RATE_LIMIT`].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitProcessorConfig {
+    pub contexts_per_second: f64,
+}
+
+/// Limits the rate contexts are passed on to the next stage at, see [`RateLimitProcessor`].
+pub const DRIVER_RATE_LIMIT: LazyLock<
+    ProcessorDriver<RateLimitProcessor, RateLimitProcessorConfig>,
+> = LazyLock::new(|| ProcessorDriver {
+    create: Arc::new(|c: RateLimitProcessorConfig, env: &ProcessorEnvironment| {
+        Ok(RateLimitProcessor::new(c.contexts_per_second)?.with_stats(env.stats.clone()))
+    }),
+    config_deserializer: Arc::new(serde_json::from_value),
+});
+
+/// Options of [`DRIVER_EXEC`].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ExecProcessorConfig {
+    /// The DSSAT executable, e.g. `/opt/dssat/dscsm048`. Looked up in the `PATH` if it is a bare file name, relative to
+    /// the working directory of the application otherwise.
+    pub command: PathBuf,
+    #[serde(default)]
+    pub retry: RetryPolicy,
+}
+
+/// Runs DSSAT in the context directories, see [`ExecProcessor`].
+pub const DRIVER_EXEC: LazyLock<ProcessorDriver<ExecProcessor, ExecProcessorConfig>> =
+    LazyLock::new(|| ProcessorDriver {
+        create: Arc::new(|c: ExecProcessorConfig, env: &ProcessorEnvironment| {
+            // Run-wide batch files are only complete once every context of the run is rendered.
+            if let Some(run) = env.config.runs.iter().find(|run| {
+                run.batch
+                    .as_ref()
+                    .is_some_and(|batch| batch.scope == BatchScope::Run)
+            }) {
+                return Err(format!(
+                    "std:exec runs DSSAT context by context, it can't run the run-wide batch file of run {}",
+                    run.name
+                )
+                .into());
+            }
+
+            // DSSAT runs in the context directories, so relative paths are resolved beforehand.
+            let command = match c.command.components().count() {
+                1 => c.command,
+                _ => std::path::absolute(&c.command)?,
+            };
+            Ok(ExecProcessor {
+                command,
+                workdir: env.workdir.to_path_buf(),
+                retry: c.retry,
+                failures: env.failures.clone(),
+                stats: env.stats.clone(),
+            })
+        }),
+        config_deserializer: Arc::new(serde_json::from_value),
+    });
+
+/// Options of [`DRIVER_COPY`].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CopyProcessorConfig {
+    /// Directory the context directories are copied to, under the same relative paths as in the working directory.
+    pub destination: PathBuf,
+    /// Names of the files copied, e.g. `Summary.OUT`. Every file of the context directories if empty.
+    #[serde(default)]
+    pub files: Vec<String>,
+}
+
+/// Copies the files of the context directories elsewhere, see [`CopyProcessor`].
+pub const DRIVER_COPY: LazyLock<ProcessorDriver<CopyProcessor, CopyProcessorConfig>> =
+    LazyLock::new(|| ProcessorDriver {
+        create: Arc::new(|c: CopyProcessorConfig, env: &ProcessorEnvironment| {
+            Ok(CopyProcessor {
+                workdir: env.workdir.to_path_buf(),
+                destination: c.destination,
+                files: c.files,
+                failures: env.failures.clone(),
+                stats: env.stats.clone(),
+            })
+        }),
+        config_deserializer: Arc::new(serde_json::from_value),
+    });
@@ -0,0 +1,198 @@
+use super::super::context::Context;
+use super::super::failure::FailedContext;
+use super::super::report::StageStats;
+use super::super::template::TemplateEngine;
+use super::retry::RetryPolicy;
+use super::{track_context, Processor, ProcessorError};
+use std::error::Error;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::mpmc::{Receiver, Sender};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Runs DSSAT in the directory of every context rendered by the previous stages: on its batch file if its run has one
+/// (in sequence mode for crop rotations, see [`crate::simulation`]), on the experiment file rendered from its template
+/// otherwise. DSSAT writes its outputs (e.g. `Summary.OUT`) next to the inputs.
+pub struct ExecProcessor {
+    /// The DSSAT executable, e.g. `/opt/dssat/dscsm048`.
+    pub command: PathBuf,
+    pub workdir: PathBuf,
+    pub retry: RetryPolicy,
+    pub failures: Sender<FailedContext>,
+    pub stats: Arc<StageStats>,
+}
+
+impl ExecProcessor {
+    /// Run mode and file DSSAT is run with in the directory of `ctx`.
+    fn args(
+        &self,
+        ctx: &Context,
+        templates: &TemplateEngine,
+    ) -> Result<[String; 2], ProcessorError> {
+        if let Some(batch) = &ctx.run.batch {
+            let sequential = ctx
+                .run
+                .simulation
+                .as_ref()
+                .is_some_and(|s| s.is_sequential());
+            let mode = if sequential { "Q" } else { "B" };
+            return Ok([mode.to_string(), batch.file_name()]);
+        }
+
+        let filename = templates.file_name(ctx, None)?;
+        let filename = filename.ok_or_else(|| ProcessorError::TemplateNotRegistered {
+            location: ctx.location(),
+        })?;
+        Ok(["A".to_string(), filename])
+    }
+
+    fn execute(&self, ctx: &Context, templates: &TemplateEngine) -> Result<(), ProcessorError> {
+        let output = Command::new(&self.command)
+            .args(self.args(ctx, templates)?)
+            .current_dir(ctx.dir(&self.workdir))
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|source| ProcessorError::Exec {
+                location: ctx.location(),
+                source,
+            })?;
+        if !output.status.success() {
+            // DSSAT reports what went wrong on the last line it prints.
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let message = stdout.lines().rev().find(|line| !line.trim().is_empty());
+            return Err(ProcessorError::ExecFailed {
+                location: ctx.location(),
+                status: output.status,
+                message: message.unwrap_or_default().trim().to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Processor for ExecProcessor {
+    type Output = Context;
+
+    fn process(
+        &self,
+        tx: &Sender<Self::Output>,
+        rx: &Receiver<Context>,
+        templates: &TemplateEngine,
+    ) -> Result<(), Box<dyn Error + Send>> {
+        for ctx in rx.iter() {
+            track_context(&ctx);
+            let location = ctx.location();
+            let started = Instant::now();
+            match self.retry.run(&location, || self.execute(&ctx, templates)) {
+                Ok(()) => {
+                    self.stats.record(Ok(()), started.elapsed());
+                    tx.send(ctx)
+                        .map_err(|err| Box::new(err) as Box<dyn Error + Send>)?;
+                }
+                Err((err, attempts)) => {
+                    self.stats.record(Err(err.class()), started.elapsed());
+                    self.failures
+                        .send(FailedContext::new(&location, attempts, &err))
+                        .map_err(|err| Box::new(err) as Box<dyn Error + Send>)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::config::engines::TemplateEngineConfig;
+    use crate::config::runs::RunConfig;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+    use std::sync::mpmc::channel;
+
+    /// Stands for DSSAT: fails on the experiment files named `FAIL.SNX` (and on the first run of `FLAKY.SNX`), and
+    /// writes its arguments to `Summary.OUT` otherwise.
+    fn fake_dssat(dir: &Path) -> PathBuf {
+        let path = dir.join("dscsm048");
+        let script = "#!/bin/sh\n\
+            if [ \"$2\" = FAIL.SNX ]; then echo 'Error in input file'; echo; exit 99; fi\n\
+            if [ \"$2\" = FLAKY.SNX ] && [ ! -e tried ]; then touch tried; exit 1; fi\n\
+            echo \"$1 $2\" > Summary.OUT\n";
+        std::fs::write(&path, script).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_exec() {
+        let dir = tempfile::tempdir().unwrap();
+        let (tx_failures, failures) = channel();
+        let processor = ExecProcessor {
+            command: fake_dssat(dir.path()),
+            workdir: dir.path().to_path_buf(),
+            retry: RetryPolicy {
+                retries: 1,
+                backoff_ms: 0,
+            },
+            failures: tx_failures,
+            stats: Default::default(),
+        };
+
+        let mut templates = TemplateEngine::default();
+        let contexts: Vec<Context> = [
+            ("maize", "MAIZE.SNX", None),
+            ("batched", "MAIZE.SNX", Some(serde_json::json!({}))),
+            ("fail", "FAIL.SNX", None),
+            ("flaky", "FLAKY.SNX", None),
+        ]
+        .into_iter()
+        .map(|(name, template, batch)| {
+            let template = dir.path().join(template);
+            std::fs::write(&template, "*EXP.DETAILS").unwrap();
+            let run = RunConfig {
+                name: name.to_string(),
+                template,
+                raw: true,
+                batch: batch.map(|batch| serde_json::from_value(batch).unwrap()),
+                ..Default::default()
+            };
+            templates
+                .register(&run, &TemplateEngineConfig::default())
+                .unwrap();
+            let ctx = Context {
+                run,
+                ..Context::for_test("", 1)
+            };
+            std::fs::create_dir_all(ctx.dir(&processor.workdir)).unwrap();
+            ctx
+        })
+        .collect();
+
+        let (tx, rx) = channel();
+        for ctx in &contexts {
+            tx.send(ctx.clone()).unwrap();
+        }
+        drop(tx);
+        let (tx_out, rx_out) = channel();
+        processor.process(&tx_out, &rx, &templates).unwrap();
+        drop(tx_out);
+
+        let executed: Vec<String> = rx_out.iter().map(|ctx| ctx.run.name).collect();
+        assert_eq!(executed, ["maize", "batched", "flaky"]);
+        let summary = |ctx: &Context| {
+            std::fs::read_to_string(ctx.dir(&processor.workdir).join("Summary.OUT")).unwrap()
+        };
+        assert_eq!(summary(&contexts[0]), "A MAIZE.SNX\n");
+        assert_eq!(summary(&contexts[1]), "B DSSBatch.v48\n");
+
+        let failed = failures.try_recv().unwrap();
+        assert_eq!(failed.run, "fail");
+        assert_eq!(failed.attempts, 2);
+        assert!(
+            failed.error.ends_with("Error in input file"),
+            "{}",
+            failed.error
+        );
+    }
+}
@@ -1,10 +1,30 @@
+pub mod batched;
+pub mod copy;
+pub mod drivers;
+pub mod exec;
+pub mod rate_limit;
+pub mod retry;
 pub mod unbatched;
 
-use super::context::Context;
-use super::template::TemplateEngine;
+use super::context::{Context, ContextEvaluationError, ContextLocation};
+use super::failure::FailedContext;
+use super::report::StageStats;
+use super::template::{TemplateEngine, TemplateError};
 use super::PipelineData;
+use crate::batch::BatchError;
+use crate::config::Config;
+use crate::provenance::Manifest;
+use crate::weather::WeatherError;
+use std::any::Any;
+use std::cell::RefCell;
 use std::error::Error;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::process::ExitStatus;
 use std::sync::mpmc::{Receiver, Sender};
+use std::sync::Arc;
+use thiserror::Error;
 
 pub trait Processor: Send + Sync {
     type Output: PipelineData;
@@ -15,4 +35,197 @@ pub trait Processor: Send + Sync {
         rx: &Receiver<Context>,
         templates: &TemplateEngine,
     ) -> Result<(), Box<dyn Error + Send>>;
+
+    /// The processor as an [`AsyncProcessor`], if it can run on the async pipeline.
+    fn as_async(&self) -> Option<&dyn AsyncProcessor<Output = Self::Output>> {
+        None
+    }
+}
+
+/// Future of [`AsyncProcessor::process_one`].
+pub type ProcessFuture<'a, O> =
+    Pin<Box<dyn Future<Output = Result<Option<O>, Box<dyn Error + Send>>> + Send + 'a>>;
+
+/// A [`Processor`] that can also process contexts concurrently without blocking a thread each, for the IO-bound
+/// stages (e.g. object storage or HTTP). Run by the async pipeline when the stage asks for it.
+pub trait AsyncProcessor: Send + Sync {
+    type Output: PipelineData;
+
+    /// Processes `ctx`, resolving to what is passed on to the next stage, if anything.
+    fn process_one<'a>(
+        &'a self,
+        ctx: Context,
+        templates: &'a TemplateEngine,
+    ) -> ProcessFuture<'a, Self::Output>;
+}
+
+impl<P: Processor + ?Sized> Processor for Box<P> {
+    type Output = P::Output;
+
+    fn process(
+        &self,
+        tx: &Sender<Self::Output>,
+        rx: &Receiver<Context>,
+        templates: &TemplateEngine,
+    ) -> Result<(), Box<dyn Error + Send>> {
+        (**self).process(tx, rx, templates)
+    }
+
+    fn as_async(&self) -> Option<&dyn AsyncProcessor<Output = Self::Output>> {
+        (**self).as_async()
+    }
+}
+
+/// What a [`Processor`] is built from, besides its own config.
+pub struct ProcessorEnvironment<'a> {
+    pub config: &'a Config,
+    pub workdir: &'a Path,
+    pub manifest: &'a Manifest,
+    /// Dead-letter channel the contexts a processor gives up on are sent to.
+    pub failures: &'a Sender<FailedContext>,
+    /// Whether the run resumes an interrupted one, so the files it left behind are added to instead of replaced.
+    pub resume: bool,
+    /// Statistics of the stage being built, for the run report.
+    pub stats: Arc<StageStats>,
+}
+
+/// Constructs a new [`Processor`] of type [`P`] from the config [`C`].
+#[allow(type_alias_bounds)] // Same as the site generator drivers, see [`crate::sites::SiteGeneratorDriver`].
+type ProcessorFactory<P: Processor, C> =
+    Arc<dyn Fn(C, &ProcessorEnvironment) -> Result<P, Box<dyn Error>>>;
+
+/// Deserializes a config of type [`C`] from a [`serde_json::Value`].
+type ProcessorConfigDeserializer<C> =
+    Arc<dyn Fn(serde_json::Value) -> Result<C, serde_json::error::Error>>;
+
+/// A [`Processor`] addressable by identifier from the config, as a stage of the pipeline. Every stage takes
+/// [`Context`]s in and passes them on to the next one.
+pub struct ProcessorDriver<P: Processor, C> {
+    pub create: ProcessorFactory<P, C>,
+    pub config_deserializer: ProcessorConfigDeserializer<C>,
+}
+
+impl<P: Processor, C> Clone for ProcessorDriver<P, C> {
+    fn clone(&self) -> Self {
+        ProcessorDriver {
+            create: self.create.clone(),
+            config_deserializer: self.config_deserializer.clone(),
+        }
+    }
+}
+
+impl<P: Processor<Output = Context>, C> ProcessorDriver<P, C> {
+    pub fn coerce_to_dynamic(
+        self,
+    ) -> ProcessorDriver<Box<dyn Processor<Output = Context>>, Box<dyn Any>>
+    where
+        P: 'static,
+        C: Any + 'static,
+    {
+        ProcessorDriver {
+            create: Arc::new(move |c: Box<dyn Any>, env: &ProcessorEnvironment| {
+                let config = c
+                    .downcast::<C>()
+                    .map_err(|_| Box::<dyn Error>::from("Failed to downcast config"))?;
+                let concrete_processor = (self.create)(*config, env)?;
+                Ok(Box::new(concrete_processor) as Box<dyn Processor<Output = Context>>)
+            }),
+            config_deserializer: Arc::new(move |v| {
+                let concrete_config = (self.config_deserializer)(v)?;
+                Ok(Box::new(concrete_config) as Box<dyn Any>)
+            }),
+        }
+    }
+}
+
+thread_local! {
+    /// Location of the [`Context`] being processed on the current thread. Used to report the offending context when a worker crashes.
+    static CURRENT_CONTEXT: RefCell<Option<ContextLocation>> = const { RefCell::new(None) };
+}
+
+/// Records `ctx` as the [`Context`] currently being processed on this thread. See [`take_tracked_context`].
+pub fn track_context(ctx: &Context) {
+    CURRENT_CONTEXT.with_borrow_mut(|current| *current = Some(ctx.location()));
+}
+
+/// Takes the location of the last [`Context`] recorded with [`track_context`] on this thread, if any.
+pub fn take_tracked_context() -> Option<ContextLocation> {
+    CURRENT_CONTEXT.with_borrow_mut(|current| current.take())
+}
+
+/// Error raised while processing a single [`Context`]. Every variant carries the [`ContextLocation`] it happened at.
+#[derive(Debug, Error)]
+pub enum ProcessorError {
+    #[error("Failed to create directory {path} for {location}: {source}")]
+    CreateDir {
+        location: ContextLocation,
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("No template file name is registered for {location}")]
+    TemplateNotRegistered { location: ContextLocation },
+    #[error(transparent)]
+    Template(#[from] TemplateError),
+    #[error("Failed to acquire weather for {location}: {source}")]
+    Weather {
+        location: ContextLocation,
+        source: WeatherError,
+    },
+    #[error("Failed to resolve the soil ID for {location}: {source}")]
+    SoilId {
+        location: ContextLocation,
+        source: ContextEvaluationError,
+    },
+    #[error("Soil profile {id} not found for {location}")]
+    SoilNotFound {
+        location: ContextLocation,
+        id: String,
+    },
+    #[error("Failed to generate the batch file for {location}: {source}")]
+    Batch {
+        location: ContextLocation,
+        source: BatchError,
+    },
+    #[error("Failed to write {path} for {location}: {source}")]
+    Write {
+        location: ContextLocation,
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to run DSSAT for {location}: {source}")]
+    Exec {
+        location: ContextLocation,
+        source: std::io::Error,
+    },
+    #[error("DSSAT exited with {status} for {location}: {message}")]
+    ExecFailed {
+        location: ContextLocation,
+        status: ExitStatus,
+        message: String,
+    },
+    #[error("Failed to copy {path} for {location}: {source}")]
+    Copy {
+        location: ContextLocation,
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+impl ProcessorError {
+    /// Short name of the kind of error, to tally the failures by in the run report.
+    pub fn class(&self) -> &'static str {
+        match self {
+            ProcessorError::CreateDir { .. } => "create_dir",
+            ProcessorError::TemplateNotRegistered { .. } => "template_not_registered",
+            ProcessorError::Template(_) => "template",
+            ProcessorError::Weather { .. } => "weather",
+            ProcessorError::SoilId { .. } => "soil_id",
+            ProcessorError::SoilNotFound { .. } => "soil_not_found",
+            ProcessorError::Batch { .. } => "batch",
+            ProcessorError::Write { .. } => "write",
+            ProcessorError::Exec { .. } => "exec",
+            ProcessorError::ExecFailed { .. } => "exec_failed",
+            ProcessorError::Copy { .. } => "copy",
+        }
+    }
 }
@@ -0,0 +1,107 @@
+use super::super::context::Context;
+use super::super::report::StageStats;
+use super::super::template::TemplateEngine;
+use super::{AsyncProcessor, ProcessFuture, Processor};
+use std::error::Error;
+use std::sync::mpmc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Passes contexts on to the next stage no faster than a given rate, whatever the number of workers, so the stages
+/// after it don't overwhelm shared resources (e.g. a license server or a network filesystem).
+pub struct RateLimitProcessor {
+    interval: Duration,
+    /// Earliest time the next context may be passed on. Shared by the workers of the stage.
+    next: Mutex<Instant>,
+    /// Records the wait of every context.
+    stats: Arc<StageStats>,
+}
+
+impl RateLimitProcessor {
+    pub fn new(contexts_per_second: f64) -> Result<Self, Box<dyn Error>> {
+        if !contexts_per_second.is_finite() || contexts_per_second <= 0.0 {
+            return Err(format!(
+                "Rate limit must be a positive number of contexts per second, got {}",
+                contexts_per_second
+            )
+            .into());
+        }
+
+        Ok(Self {
+            interval: Duration::from_secs_f64(1.0 / contexts_per_second),
+            next: Mutex::new(Instant::now()),
+            stats: Default::default(),
+        })
+    }
+
+    pub fn with_stats(mut self, stats: Arc<StageStats>) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    /// Reserves the next slot, returning how long to wait for it.
+    fn reserve(&self) -> Duration {
+        let mut next = self.next.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let slot = (*next).max(now);
+        *next = slot + self.interval;
+        let wait = slot - now;
+        self.stats.record(Ok(()), wait);
+        wait
+    }
+}
+
+impl Processor for RateLimitProcessor {
+    type Output = Context;
+
+    fn process(
+        &self,
+        tx: &Sender<Self::Output>,
+        rx: &Receiver<Context>,
+        _templates: &TemplateEngine,
+    ) -> Result<(), Box<dyn Error + Send>> {
+        for ctx in rx.iter() {
+            std::thread::sleep(self.reserve());
+            tx.send(ctx)
+                .map_err(|err| Box::new(err) as Box<dyn Error + Send>)?;
+        }
+        Ok(())
+    }
+
+    fn as_async(&self) -> Option<&dyn AsyncProcessor<Output = Self::Output>> {
+        Some(self)
+    }
+}
+
+impl AsyncProcessor for RateLimitProcessor {
+    type Output = Context;
+
+    fn process_one<'a>(
+        &'a self,
+        ctx: Context,
+        _templates: &'a TemplateEngine,
+    ) -> ProcessFuture<'a, Self::Output> {
+        Box::pin(async move {
+            tokio::time::sleep(self.reserve()).await;
+            Ok(Some(ctx))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit() {
+        assert!(RateLimitProcessor::new(0.0).is_err());
+        assert!(RateLimitProcessor::new(f64::NAN).is_err());
+
+        let limiter = RateLimitProcessor::new(10.0).unwrap();
+        assert_eq!(limiter.reserve(), Duration::ZERO);
+        let second = limiter.reserve();
+        assert!(second > Duration::from_millis(50) && second <= Duration::from_millis(100));
+        let third = limiter.reserve();
+        assert!(third > Duration::from_millis(150) && third <= Duration::from_millis(200));
+    }
+}
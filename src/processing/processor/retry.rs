@@ -0,0 +1,108 @@
+//! Retries of the processing of a [`Context`](super::Context). The contexts that fail anyway are sent to the
+//! [dead-letter channel](crate::processing::failure).
+
+use super::ContextLocation;
+use serde::Deserialize;
+use std::fmt::Display;
+use std::time::Duration;
+
+/// Retries the processing of a context that failed, to get past transient problems (e.g. NFS hiccups, failed forks).
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RetryPolicy {
+    /// Attempts made after the first one. Defaults to 0 (no retry).
+    #[serde(default)]
+    pub retries: u32,
+
+    /// Wait before the first retry, in milliseconds, doubled on every retry after it.
+    #[serde(default = "default_backoff_ms")]
+    pub backoff_ms: u64,
+}
+
+fn default_backoff_ms() -> u64 {
+    1000
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            backoff_ms: default_backoff_ms(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait before the `retry`-th retry (zero-based).
+    fn backoff(&self, retry: u32) -> Duration {
+        Duration::from_millis(self.backoff_ms.saturating_mul(1 << retry.min(16)))
+    }
+
+    /// Runs `attempt` until it succeeds or the retries run out. On failure, returns the last error along with the
+    /// number of attempts made.
+    pub fn run<T, E: Display>(
+        &self,
+        location: &ContextLocation,
+        mut attempt: impl FnMut() -> Result<T, E>,
+    ) -> Result<T, (E, u32)> {
+        let mut retry = 0;
+        loop {
+            match attempt() {
+                Ok(value) => return Ok(value),
+                Err(err) if retry < self.retries => {
+                    let backoff = self.backoff(retry);
+                    eprintln!(
+                        "Processing {} failed, retrying in {:?} ({}/{}): {}",
+                        location,
+                        backoff,
+                        retry + 1,
+                        self.retries,
+                        err
+                    );
+                    std::thread::sleep(backoff);
+                    retry += 1;
+                }
+                Err(err) => return Err((err, retry + 1)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::GeoDeg;
+
+    fn location() -> ContextLocation {
+        ContextLocation {
+            run: "maize".to_string(),
+            site_id: 7,
+            lon: GeoDeg::from(1.5),
+            lat: GeoDeg::from(-2.5),
+        }
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let policy = RetryPolicy {
+            retries: 2,
+            backoff_ms: 0,
+        };
+
+        let mut attempts = 0;
+        let result = policy.run(&location(), || {
+            attempts += 1;
+            if attempts < 3 {
+                Err("transient")
+            } else {
+                Ok(attempts)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        let result: Result<(), _> = policy.run(&location(), || Err("permanent"));
+        assert_eq!(result.unwrap_err(), ("permanent", 3));
+
+        assert_eq!(RetryPolicy::default().backoff(2), Duration::from_secs(4));
+    }
+}
@@ -1,58 +1,445 @@
 use super::super::context::Context;
+use super::super::failure::FailedContext;
+use super::super::report::StageStats;
 use super::super::template::TemplateEngine;
-use super::Processor;
+use super::retry::RetryPolicy;
+use super::{track_context, Processor, ProcessorError};
+use crate::batch::{self, BatchConfig, BatchScope};
+use crate::planting::rules::PlantingRules;
+use crate::processing::context::{ContextValue, PrimitiveContextValue};
+use crate::provenance::{Manifest, MetadataScope, METADATA_FILE_NAME};
+use crate::sites::Site;
+use crate::soil::standalone_sol;
+use crate::utils::text::{finalize, tidy};
+use crate::weather::wth::WeatherSeries;
+use crate::weather::{WeatherConfig, WeatherError};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
-use std::fs::create_dir_all;
-use std::path::PathBuf;
-use std::sync::mpmc::{Receiver, Sender};
+use std::fs::{create_dir_all, OpenOptions};
+use std::io::Write;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::mpmc::{sync_channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
 
 pub struct UnbatchedProcessor {
     pub workdir: PathBuf,
+    /// Planting rules of each run, by run name.
+    pub planting_rules: HashMap<String, PlantingRules>,
+    /// Runs whose run-wide batch file was already started, so it is truncated only once. Holding the lock also
+    /// serializes the workers appending to it.
+    pub run_batches: Mutex<HashSet<String>>,
+    /// Provenance of the campaign, summarized into the metadata files of context directories.
+    pub manifest: Manifest,
+    /// Retries of the contexts that failed. Those failing anyway are sent to `failures` and not passed on.
+    pub retry: RetryPolicy,
+    pub failures: Sender<FailedContext>,
+    pub stats: Arc<StageStats>,
+    /// Threads of every worker writing the files it rendered, so slow filesystems don't keep it from rendering the
+    /// next contexts. 0 writes them on the worker itself.
+    pub writers: usize,
 }
 
-impl Processor for UnbatchedProcessor {
-    type Output = Context;
+/// A file of a context, rendered and waiting to be written. See [`UnbatchedProcessor::write`].
+enum Output {
+    File {
+        path: PathBuf,
+        contents: String,
+    },
+    /// The template of a `raw` run, copied byte for byte.
+    Raw {
+        path: PathBuf,
+        contents: Arc<[u8]>,
+    },
+    Weather(WeatherSeries),
+    /// Entries of a run-wide batch file, appended to it. The header is only written by the first context of the run.
+    RunBatch {
+        path: PathBuf,
+        header: String,
+        lines: String,
+    },
+    Metadata,
+}
 
-    fn process(
+/// A context along with its rendered files, passed from the worker rendering it to the writer writing them.
+pub struct RenderedContext {
+    ctx: Context,
+    outputs: Vec<Output>,
+    /// When the worker started on it, to time the whole processing of the context.
+    started: Instant,
+}
+
+/// Where a worker hands the contexts it rendered over to, see [`UnbatchedProcessor::with_writers`].
+pub enum WriteQueue<'a> {
+    /// Written on the worker itself, then passed on to the next stage.
+    Inline(&'a Sender<Context>),
+    /// Sent to the writers of the worker.
+    Writers(Sender<RenderedContext>),
+}
+
+/// Weather already fetched for the site being processed, along with the config it was fetched with, so the runs
+/// sharing a weather config fetch it only once. See [`super::batched::BatchedProcessor`].
+#[derive(Default)]
+pub struct SiteWeather(Vec<(WeatherConfig, WeatherSeries)>);
+
+impl SiteWeather {
+    fn fetch(
+        &mut self,
+        config: &WeatherConfig,
+        site: &Site,
+    ) -> Result<WeatherSeries, WeatherError> {
+        if let Some((_, series)) = self.0.iter().find(|(fetched, _)| fetched == config) {
+            return Ok(series.clone());
+        }
+        let series = config.fetch(site)?;
+        self.0.push((config.clone(), series.clone()));
+        Ok(series)
+    }
+}
+
+impl UnbatchedProcessor {
+    /// Renders `ctx` (retrying it as configured), then hands it over to `queue`, or passes it on to the dead-letter
+    /// channel if it failed anyway.
+    pub fn handle(
         &self,
-        tx: &Sender<Self::Output>,
-        rx: &Receiver<Self::Output>,
+        ctx: Context,
+        queue: &WriteQueue,
         templates: &TemplateEngine,
+        weather: &mut SiteWeather,
     ) -> Result<(), Box<dyn Error + Send>> {
-        // TODO better error handling
-        rx.iter()
-            .map(|ctx| {
-                let path = ctx.dir(&self.workdir);
-                if let Err(err) = create_dir_all(&path) {
-                    eprintln!("UnbatchedProcessor: Failed to create directory: {}", err);
-                }
-                ctx
-            })
-            .map(|ctx| {
-                let filename = match templates.file_name(ctx.run.name.as_str()) {
-                    Some(filename) => filename,
-                    None => {
-                        panic!(
-                            "Failed to render template for context ID {} ({}, {}): Template file name not registered",
-                            ctx.site.id, ctx.site.lon, ctx.site.lat
-                        );
+        track_context(&ctx);
+        let location = ctx.location();
+        let started = Instant::now();
+        let result = self
+            .retry
+            .run(&location, || self.render(&ctx, templates, weather));
+        let outputs = match result {
+            Ok(outputs) => outputs,
+            Err((err, attempts)) => {
+                self.stats.record(Err(err.class()), started.elapsed());
+                return self
+                    .failures
+                    .send(FailedContext::new(&location, attempts, &err))
+                    .map_err(|err| Box::new(err) as Box<dyn Error + Send>);
+            }
+        };
+
+        let rendered = RenderedContext {
+            ctx,
+            outputs,
+            started,
+        };
+        match queue {
+            WriteQueue::Inline(tx) => self.handle_rendered(rendered, tx),
+            WriteQueue::Writers(writers) => writers
+                .send(rendered)
+                .map_err(|err| Box::new(err) as Box<dyn Error + Send>),
+        }
+    }
+
+    /// Writes the files of `rendered` (retrying it as configured), then passes its context on to `tx`, or to the
+    /// dead-letter channel if it failed anyway.
+    fn handle_rendered(
+        &self,
+        rendered: RenderedContext,
+        tx: &Sender<Context>,
+    ) -> Result<(), Box<dyn Error + Send>> {
+        let RenderedContext {
+            ctx,
+            outputs,
+            started,
+        } = rendered;
+        let location = ctx.location();
+        let result = self.retry.run(&location, || self.write(&ctx, &outputs));
+        let outcome = result.as_ref().map(|_| ()).map_err(|(err, _)| err.class());
+        self.stats.record(outcome, started.elapsed());
+        match result {
+            Ok(()) => tx
+                .send(ctx)
+                .map_err(|err| Box::new(err) as Box<dyn Error + Send>),
+            Err((err, attempts)) => self
+                .failures
+                .send(FailedContext::new(&location, attempts, &err))
+                .map_err(|err| Box::new(err) as Box<dyn Error + Send>),
+        }
+    }
+
+    /// Runs `work` (rendering contexts on this thread and handing them over to the [`WriteQueue`] it is given)
+    /// alongside the `writers` of the worker, which pass the contexts on to `tx` once written. Returns once `work`
+    /// and the writers are done.
+    pub fn with_writers(
+        &self,
+        tx: &Sender<Context>,
+        work: impl FnOnce(&WriteQueue) -> Result<(), Box<dyn Error + Send>>,
+    ) -> Result<(), Box<dyn Error + Send>> {
+        if self.writers == 0 {
+            return work(&WriteQueue::Inline(tx));
+        }
+
+        thread::scope(|s| {
+            let (queue, rx) = sync_channel::<RenderedContext>(self.writers);
+            let writers: Vec<_> = (0..self.writers)
+                .map(|_| {
+                    let rx = rx.clone();
+                    s.spawn(move || -> Result<(), Box<dyn Error + Send>> {
+                        for rendered in rx {
+                            self.handle_rendered(rendered, tx)?;
+                        }
+                        Ok(())
+                    })
+                })
+                .collect();
+            drop(rx);
+
+            // The writers hang up on the worker if they fail, which fails it in turn.
+            let mut result = work(&WriteQueue::Writers(queue));
+            for writer in writers {
+                let written = writer.join().unwrap_or_else(|e| panic::resume_unwind(e));
+                result = result.and(written);
+            }
+            result
+        })
+    }
+
+    /// Batch file entries of the experiment file at `template_path`, rendered as `rendered`.
+    fn render_batch(
+        &self,
+        ctx: &Context,
+        batch: &BatchConfig,
+        template_path: &Path,
+        rendered: &str,
+    ) -> Result<Output, ProcessorError> {
+        let batch_err = |source| ProcessorError::Batch {
+            location: ctx.location(),
+            source,
+        };
+        let treatments = batch::treatments(rendered);
+        if treatments.is_empty() {
+            return Err(batch_err(batch::BatchError::NoTreatments(
+                template_path.to_path_buf(),
+            )));
+        }
+
+        let (batch_dir, filex) = match batch.scope {
+            BatchScope::Context => (
+                ctx.dir(&self.workdir),
+                template_path.file_name().map(PathBuf::from),
+            ),
+            BatchScope::Run => {
+                let run_dir = self.workdir.join(&ctx.run.name);
+                let filex = template_path
+                    .strip_prefix(&run_dir)
+                    .ok()
+                    .map(Path::to_path_buf);
+                (run_dir, filex)
+            }
+        };
+        let sequential = ctx
+            .run
+            .simulation
+            .as_ref()
+            .is_some_and(|s| s.is_sequential());
+        let lines = batch::lines(
+            &filex.unwrap_or_else(|| template_path.to_path_buf()),
+            &treatments,
+            sequential,
+        )
+        .map_err(batch_err)?;
+
+        let path = batch_dir.join(batch.file_name());
+        let line_endings = ctx.run.line_endings;
+        Ok(match batch.scope {
+            BatchScope::Context => Output::File {
+                path,
+                contents: finalize(batch.header() + &lines, line_endings, false),
+            },
+            BatchScope::Run => Output::RunBatch {
+                path,
+                header: finalize(batch.header(), line_endings, false),
+                lines: finalize(lines, line_endings, false),
+            },
+        })
+    }
+
+    /// Renders the files of `ctx`, fetching its weather on the way. Nothing is written yet, see
+    /// [`UnbatchedProcessor::write`].
+    fn render(
+        &self,
+        ctx: &Context,
+        templates: &TemplateEngine,
+        site_weather: &mut SiteWeather,
+    ) -> Result<Vec<Output>, ProcessorError> {
+        let dir = ctx.dir(&self.workdir);
+        let mut outputs = Vec::new();
+
+        let weather = match &ctx.run.weather {
+            Some(weather) => {
+                let series = site_weather.fetch(weather, &ctx.site).map_err(|source| {
+                    ProcessorError::Weather {
+                        location: ctx.location(),
+                        source,
                     }
-                };
+                })?;
+                outputs.push(Output::Weather(series.clone()));
+                Some(series)
+            }
+            None => None,
+        };
+
+        if let Some(soil) = ctx.run.soil.as_ref().filter(|s| s.copy) {
+            let Some(ContextValue::Prim(PrimitiveContextValue::String(profile))) =
+                ctx.provided.get("soil_profile")
+            else {
+                let id = soil
+                    .resolve_id(ctx)
+                    .map_err(|source| ProcessorError::SoilId {
+                        location: ctx.location(),
+                        source,
+                    })?;
+                return Err(ProcessorError::SoilNotFound {
+                    location: ctx.location(),
+                    id,
+                });
+            };
+
+            outputs.push(Output::File {
+                path: dir.join(&soil.output_file),
+                contents: finalize(standalone_sol(profile), ctx.run.line_endings, false),
+            });
+        }
 
-                let rendered = templates.render(&ctx).unwrap();
-                let mut template_path = ctx.dir(&self.workdir);
-                template_path.push(filename);
+        // Planting rules may depend on the weather, so they are only evaluated here.
+        let planted = self
+            .planting_rules
+            .get(&ctx.run.name)
+            .and_then(|rules| rules.evaluate(ctx, weather.as_ref()))
+            .map(|date| {
+                let mut planted = ctx.clone();
+                planted.provided.extend(date.values());
+                planted
+            });
+        let ctx = planted.as_ref().unwrap_or(ctx);
 
-                if let Err(err) = std::fs::write(template_path, rendered) {
-                    panic!(
-                        "Failed to render template for context ID {} ({}, {}): {}",
-                        ctx.site.id, ctx.site.lon, ctx.site.lat, err
-                    );
+        let filename = templates.file_name(ctx, None)?;
+        let filename = filename.ok_or_else(|| ProcessorError::TemplateNotRegistered {
+            location: ctx.location(),
+        })?;
+
+        let template_path = dir.join(filename);
+        if let Some(contents) = templates.raw(&ctx.run.name) {
+            outputs.push(Output::Raw {
+                path: template_path,
+                contents,
+            });
+        } else {
+            let rendered = tidy(templates.render(ctx, None)?, ctx.run.whitespace);
+            let batch = match &ctx.run.batch {
+                Some(batch) => Some(self.render_batch(ctx, batch, &template_path, &rendered)?),
+                None => None,
+            };
+            outputs.push(Output::File {
+                path: template_path,
+                contents: finalize(rendered, ctx.run.line_endings, ctx.run.bom),
+            });
+            outputs.extend(batch);
+        }
+
+        for output in &ctx.run.outputs {
+            let name = Some(output.name.as_str());
+            let filename = templates.file_name(ctx, name)?;
+            let filename = filename.ok_or_else(|| ProcessorError::TemplateNotRegistered {
+                location: ctx.location(),
+            })?;
+            let rendered = tidy(templates.render(ctx, name)?, ctx.run.whitespace);
+            outputs.push(Output::File {
+                path: dir.join(filename),
+                contents: finalize(rendered, ctx.run.line_endings, ctx.run.bom),
+            });
+        }
+
+        if ctx.run.metadata == Some(MetadataScope::Context) {
+            outputs.push(Output::Metadata);
+        }
+        Ok(outputs)
+    }
+
+    /// Writes the files rendered for `ctx` into its directory.
+    fn write(&self, ctx: &Context, outputs: &[Output]) -> Result<(), ProcessorError> {
+        let dir = ctx.dir(&self.workdir);
+        create_dir_all(&dir).map_err(|source| ProcessorError::CreateDir {
+            location: ctx.location(),
+            path: dir.clone(),
+            source,
+        })?;
+
+        let write_err = |path: &Path, source| ProcessorError::Write {
+            location: ctx.location(),
+            path: path.to_path_buf(),
+            source,
+        };
+        for output in outputs {
+            match output {
+                Output::File { path, contents } => {
+                    std::fs::write(path, contents).map_err(|e| write_err(path, e))?;
+                }
+                Output::Raw { path, contents } => {
+                    std::fs::write(path, contents).map_err(|e| write_err(path, e))?;
                 }
+                Output::Weather(series) => {
+                    series.write(&dir, ctx.run.line_endings).map_err(|e| {
+                        ProcessorError::Weather {
+                            location: ctx.location(),
+                            source: WeatherError::Io(e),
+                        }
+                    })?;
+                }
+                Output::RunBatch {
+                    path,
+                    header,
+                    lines,
+                } => {
+                    let mut started = self.run_batches.lock().unwrap_or_else(|e| e.into_inner());
+                    let first = started.insert(ctx.run.name.clone());
+                    OpenOptions::new()
+                        .create(true)
+                        .write(true)
+                        .truncate(first)
+                        .append(!first)
+                        .open(path)
+                        .and_then(|mut file| match first {
+                            true => file.write_all((header.clone() + lines).as_bytes()),
+                            false => file.write_all(lines.as_bytes()),
+                        })
+                        .map_err(|e| write_err(path, e))?;
+                }
+                Output::Metadata => {
+                    self.manifest
+                        .directory_metadata(&ctx.run.name, Some(&ctx.site))
+                        .write(&dir)
+                        .map_err(|e| write_err(&dir.join(METADATA_FILE_NAME), e))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Processor for UnbatchedProcessor {
+    type Output = Context;
 
-                ctx
-            })
-            .try_for_each(|ctx| tx.send(ctx))
-            .map_err(|err| Box::new(err) as Box<dyn Error + Send>)
+    fn process(
+        &self,
+        tx: &Sender<Self::Output>,
+        rx: &Receiver<Self::Output>,
+        templates: &TemplateEngine,
+    ) -> Result<(), Box<dyn Error + Send>> {
+        self.with_writers(tx, |queue| {
+            for ctx in rx.iter() {
+                self.handle(ctx, queue, templates, &mut SiteWeather::default())?;
+            }
+            Ok(())
+        })
     }
 }
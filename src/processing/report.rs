@@ -0,0 +1,118 @@
+//! End-of-run report: what happened to the contexts, overall and on every stage of the pipeline, written as
+//! [`RUN_REPORT_FILE_NAME`] into the working directory.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Name of the report file, in the root of the working directory.
+pub const RUN_REPORT_FILE_NAME: &str = "run_report.json";
+
+/// Statistics of a stage, recorded by its processor as it goes. Shared by the workers of the stage.
+#[derive(Default, Debug)]
+pub struct StageStats(Mutex<StageTally>);
+
+#[derive(Default, Debug)]
+struct StageTally {
+    successes: usize,
+    failures: BTreeMap<String, usize>,
+    total: Duration,
+    min: Option<Duration>,
+    max: Duration,
+}
+
+impl StageStats {
+    /// Records a context that took `elapsed` to process, and either succeeded or failed with an error of class
+    /// `Err(class)`.
+    pub fn record(&self, outcome: Result<(), &str>, elapsed: Duration) {
+        let mut tally = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        match outcome {
+            Ok(()) => tally.successes += 1,
+            Err(class) => *tally.failures.entry(class.to_string()).or_default() += 1,
+        }
+        tally.total += elapsed;
+        tally.min = Some(tally.min.map_or(elapsed, |min| min.min(elapsed)));
+        tally.max = tally.max.max(elapsed);
+    }
+
+    /// Report of the `stage`-th stage (one-based, as in the logs).
+    pub fn report(&self, stage: usize) -> StageReport {
+        let tally = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let processed = tally.successes + tally.failures.values().sum::<usize>();
+        let ms = |duration: Duration| duration.as_nanos() as f64 / 1e6;
+        StageReport {
+            stage,
+            successes: tally.successes,
+            failures: tally.failures.clone(),
+            min_ms: tally.min.map(ms),
+            mean_ms: (processed > 0).then(|| ms(tally.total) / processed as f64),
+            max_ms: (processed > 0).then(|| ms(tally.max)),
+        }
+    }
+}
+
+/// What happened on a stage. Processing times are `null` if it processed nothing.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct StageReport {
+    pub stage: usize,
+    pub successes: usize,
+    /// Contexts given up on, by class of the error.
+    pub failures: BTreeMap<String, usize>,
+    pub min_ms: Option<f64>,
+    pub mean_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RunReport {
+    /// RFC 3339 UTC timestamp.
+    pub started_at: String,
+    /// RFC 3339 UTC timestamp.
+    pub finished_at: String,
+    pub generated: usize,
+    /// Contexts that made it through every stage.
+    pub completed: usize,
+    /// Contexts skipped as completed by the interrupted run, see `--resume`.
+    pub skipped: usize,
+    /// Contexts given up on, see [`super::failure::FAILED_CONTEXTS_FILE_NAME`].
+    pub failed: usize,
+    pub stages: Vec<StageReport>,
+}
+
+impl RunReport {
+    /// Writes the report as [`RUN_REPORT_FILE_NAME`] into `workdir`.
+    pub fn write(&self, workdir: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let path = workdir.join(RUN_REPORT_FILE_NAME);
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stage_stats() {
+        let stats = StageStats::default();
+        assert_eq!(stats.report(1).mean_ms, None);
+
+        stats.record(Ok(()), Duration::from_millis(10));
+        stats.record(Ok(()), Duration::from_millis(30));
+        stats.record(Err("weather"), Duration::from_millis(50));
+        stats.record(Err("weather"), Duration::from_millis(30));
+
+        let report = stats.report(2);
+        assert_eq!(report.stage, 2);
+        assert_eq!(report.successes, 2);
+        assert_eq!(
+            report.failures,
+            BTreeMap::from([("weather".to_string(), 2)])
+        );
+        assert_eq!(report.min_ms, Some(10.0));
+        assert_eq!(report.mean_ms, Some(30.0));
+        assert_eq!(report.max_ms, Some(50.0));
+    }
+}
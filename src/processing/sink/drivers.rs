@@ -0,0 +1,141 @@
+use super::{Sink, SinkDriver, SinkEnvironment};
+use crate::processing::context::Context;
+use serde::Deserialize;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::path::PathBuf;
+use std::sync::{Arc, LazyLock};
+
+/// Options of [`DRIVER_CSV_SINK`].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CsvSinkConfig {
+    /// CSV file, relative to the run directory. Defaults to `contexts.csv`.
+    #[serde(default = "default_csv_file")]
+    pub file: PathBuf,
+    /// Context variables written after the site ID and coordinates, one column each (e.g. `["wsta", "pdate"]`).
+    /// Variables a context doesn't have are left empty.
+    #[serde(default)]
+    pub fields: Vec<String>,
+}
+
+fn default_csv_file() -> PathBuf {
+    PathBuf::from("contexts.csv")
+}
+
+/// Writes a row per completed context of a run, with the site and the variables picked in [`CsvSinkConfig`].
+pub struct CsvSink {
+    writer: csv::Writer<File>,
+    fields: Vec<String>,
+}
+
+impl CsvSink {
+    pub fn new(c: CsvSinkConfig, env: &SinkEnvironment) -> Result<Self, Box<dyn Error>> {
+        let dir = env.workdir.join(&env.run.name);
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(&c.file);
+
+        // Resuming appends to the rows of the interrupted run, which already has the header.
+        let append = env.resume && path.exists();
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(!append)
+            .append(append)
+            .open(&path)
+            .map_err(|e| format!("Unable to open {}: {}", path.display(), e))?;
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(file);
+        if !append {
+            let header = ["site", "lon", "lat"]
+                .into_iter()
+                .chain(c.fields.iter().map(String::as_str));
+            writer.write_record(header)?;
+        }
+
+        Ok(Self {
+            writer,
+            fields: c.fields,
+        })
+    }
+}
+
+impl Sink for CsvSink {
+    fn accept(&mut self, ctx: &Context) -> Result<(), Box<dyn Error>> {
+        let mut record = vec![
+            ctx.site.id.to_string(),
+            ctx.site.lon.as_f64().to_string(),
+            ctx.site.lat.as_f64().to_string(),
+        ];
+        for field in &self.fields {
+            record.push(match ctx.get(field) {
+                Some(value) => value.to_prim(ctx)?.as_string(),
+                None => String::new(),
+            });
+        }
+        self.writer.write_record(&record)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes the completed contexts of a run to a CSV file, see [`CsvSink`].
+pub const DRIVER_CSV_SINK: LazyLock<SinkDriver<CsvSink, CsvSinkConfig>> =
+    LazyLock::new(|| SinkDriver {
+        create: Arc::new(CsvSink::new),
+        config_deserializer: Arc::new(serde_json::from_value),
+    });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::runs::RunConfig;
+    use crate::data::GeoDeg;
+    use crate::processing::context::{ContextValue, PrimitiveContextValue};
+    use crate::sites::Site;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_csv_sink() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = RunConfig {
+            name: "r1".to_string(),
+            extra: HashMap::from([(
+                "crop".to_string(),
+                ContextValue::Prim(PrimitiveContextValue::String("MZ".to_string())),
+            )]),
+            ..Default::default()
+        };
+        let env = SinkEnvironment {
+            workdir: dir.path(),
+            run: &run,
+            resume: false,
+        };
+        let config: CsvSinkConfig =
+            serde_json::from_value(serde_json::json!({ "fields": ["crop", "unknown"] })).unwrap();
+
+        let mut sink = CsvSink::new(config, &env).unwrap();
+        let ctx = Context {
+            site: Site {
+                id: 7,
+                lon: GeoDeg::from(1.5),
+                lat: GeoDeg::from(-2.0),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
+            },
+            run: run.clone(),
+            provided: Default::default(),
+        };
+        sink.accept(&ctx).unwrap();
+        sink.finish().unwrap();
+
+        let rows = std::fs::read_to_string(dir.path().join("r1").join("contexts.csv")).unwrap();
+        assert_eq!(rows, "site,lon,lat,crop,unknown\n7,1.5,-2,MZ,\n");
+    }
+}
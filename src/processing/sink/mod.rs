@@ -0,0 +1,138 @@
+//! Terminal stage of the processing: the contexts that made it through every stage of the pipeline are passed to the
+//! [`Sink`]s, the checkpoint (see [`super::checkpoint`]) along with the ones configured on their run.
+
+pub mod drivers;
+
+use super::context::Context;
+use crate::config::runs::RunConfig;
+use std::any::Any;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+use std::sync::mpmc::Receiver;
+use std::sync::Arc;
+
+/// Takes the contexts that made it through the pipeline, one at a time, on a single thread.
+pub trait Sink: Send {
+    fn accept(&mut self, ctx: &Context) -> Result<(), Box<dyn Error>>;
+
+    /// Called once every context was accepted (e.g. to flush what was written).
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+impl<S: Sink + ?Sized> Sink for Box<S> {
+    fn accept(&mut self, ctx: &Context) -> Result<(), Box<dyn Error>> {
+        (**self).accept(ctx)
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        (**self).finish()
+    }
+}
+
+/// What a [`Sink`] is built from, besides its own config.
+pub struct SinkEnvironment<'a> {
+    pub workdir: &'a Path,
+    /// Run the sink takes the contexts of.
+    pub run: &'a RunConfig,
+    /// Whether the run resumes an interrupted one, see [`super::checkpoint`].
+    pub resume: bool,
+}
+
+/// Constructs a new [`Sink`] of type [`S`] from the config [`C`].
+#[allow(type_alias_bounds)] // Same as the site generator drivers, see [`crate::sites::SiteGeneratorDriver`].
+type SinkFactory<S: Sink, C> = Arc<dyn Fn(C, &SinkEnvironment) -> Result<S, Box<dyn Error>>>;
+
+/// Deserializes a config of type [`C`] from a [`serde_json::Value`].
+type SinkConfigDeserializer<C> =
+    Arc<dyn Fn(serde_json::Value) -> Result<C, serde_json::error::Error>>;
+
+/// A [`Sink`] addressable by identifier from the `sinks` of a run.
+pub struct SinkDriver<S: Sink, C> {
+    pub create: SinkFactory<S, C>,
+    pub config_deserializer: SinkConfigDeserializer<C>,
+}
+
+impl<S: Sink, C> Clone for SinkDriver<S, C> {
+    fn clone(&self) -> Self {
+        SinkDriver {
+            create: self.create.clone(),
+            config_deserializer: self.config_deserializer.clone(),
+        }
+    }
+}
+
+impl<S: Sink, C> SinkDriver<S, C> {
+    pub fn coerce_to_dynamic(self) -> SinkDriver<Box<dyn Sink>, Box<dyn Any>>
+    where
+        S: 'static,
+        C: Any + 'static,
+    {
+        SinkDriver {
+            create: Arc::new(move |c: Box<dyn Any>, env: &SinkEnvironment| {
+                let config = c
+                    .downcast::<C>()
+                    .map_err(|_| Box::<dyn Error>::from("Failed to downcast config"))?;
+                let concrete_sink = (self.create)(*config, env)?;
+                Ok(Box::new(concrete_sink) as Box<dyn Sink>)
+            }),
+            config_deserializer: Arc::new(move |v| {
+                let concrete_config = (self.config_deserializer)(v)?;
+                Ok(Box::new(concrete_config) as Box<dyn Any>)
+            }),
+        }
+    }
+}
+
+/// A [`Sink`] along with how it's referred to in the logs.
+pub struct NamedSink {
+    pub name: String,
+    pub sink: Box<dyn Sink>,
+}
+
+/// Every [`Sink`] of the processing.
+#[derive(Default)]
+pub struct Sinks {
+    /// Sinks taking every context.
+    pub all: Vec<NamedSink>,
+    /// Sinks taking the contexts of a run, by run name.
+    pub by_run: HashMap<String, Vec<NamedSink>>,
+}
+
+/// Passes `ctx` to every sink of `sinks`, dropping the ones that fail so the others carry on.
+fn offer(sinks: &mut Vec<NamedSink>, ctx: &Context) {
+    sinks.retain_mut(|s| match s.sink.accept(ctx) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("Sink {} failed, giving up on it: {}", s.name, e);
+            false
+        }
+    });
+}
+
+/// Passes the contexts received on `rx` (the ones that made it out of the last stage) to `sinks` until every sender
+/// hangs up, then finishes them. Returns how many contexts were received.
+pub fn drain(rx: Receiver<Context>, mut sinks: Sinks) -> usize {
+    let mut completed = 0;
+    // The contexts are drained even if every sink failed, so the pipeline isn't held up.
+    for ctx in rx {
+        completed += 1;
+        offer(&mut sinks.all, &ctx);
+        if let Some(run_sinks) = sinks.by_run.get_mut(&ctx.run.name) {
+            offer(run_sinks, &ctx);
+        }
+    }
+
+    for s in sinks
+        .all
+        .iter_mut()
+        .chain(sinks.by_run.values_mut().flatten())
+    {
+        if let Err(e) = s.sink.finish() {
+            eprintln!("Sink {} failed: {}", s.name, e);
+        }
+    }
+    completed
+}
@@ -0,0 +1,281 @@
+//! Spill-to-disk queue between the context generator and the first stage (see `--spill-to-disk`), so the contexts
+//! the stages can't keep up with are parked in a file of the working directory rather than in memory.
+
+use super::context::{
+    Context, ContextValue, ListItem, PrimitiveContextValue, ProviderValue, RandomValue,
+    RasterValue, TemplateString, VectorValue,
+};
+use crate::config::runs::RunConfig;
+use crate::data::GeoDeg;
+use crate::sites::Site;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::mpmc::{Receiver, RecvTimeoutError, Sender, TrySendError};
+use std::time::Duration;
+use tempfile::NamedTempFile;
+
+/// How long to wait for the generator before checking whether the first stage has room again.
+const IDLE_WAIT: Duration = Duration::from_millis(10);
+
+/// A [`ContextValue`], tagged so it reads back as the same variant.
+#[derive(Serialize, Deserialize)]
+enum SpilledValue {
+    TemplateString(TemplateString),
+    Prim(PrimitiveContextValue),
+    List(Vec<ListItem>),
+    Raster(RasterValue),
+    Vector(VectorValue),
+    Random(RandomValue),
+    Provider(ProviderValue),
+    Records(Vec<HashMap<String, PrimitiveContextValue>>),
+}
+
+/// A [`Context`] as written to the queue file. The run is only referred to by name.
+#[derive(Serialize, Deserialize)]
+struct SpilledContext {
+    run: String,
+    site: i32,
+    lon: f64,
+    lat: f64,
+    covariates: HashMap<String, f64>,
+    attributes: HashMap<String, String>,
+    weight: Option<f64>,
+    provided: HashMap<String, SpilledValue>,
+}
+
+impl From<Context> for SpilledContext {
+    fn from(ctx: Context) -> Self {
+        let provided = ctx.provided.into_iter().map(|(key, value)| {
+            let value = match value {
+                ContextValue::TemplateString(s) => SpilledValue::TemplateString(s),
+                ContextValue::Prim(p) => SpilledValue::Prim(p),
+                ContextValue::List(l) => SpilledValue::List(l),
+                ContextValue::Raster(r) => SpilledValue::Raster(r),
+                ContextValue::Vector(v) => SpilledValue::Vector(v),
+                ContextValue::Random(r) => SpilledValue::Random(r),
+                ContextValue::Provider(p) => SpilledValue::Provider(p),
+                ContextValue::Records(r) => SpilledValue::Records(r),
+            };
+            (key, value)
+        });
+        Self {
+            run: ctx.run.name,
+            site: ctx.site.id,
+            lon: ctx.site.lon.as_f64(),
+            lat: ctx.site.lat.as_f64(),
+            covariates: ctx.site.covariates,
+            attributes: ctx.site.attributes,
+            weight: ctx.site.weight,
+            provided: provided.collect(),
+        }
+    }
+}
+
+/// FIFO queue of contexts backed by a temporary file, emptied (and truncated) as they are read back.
+pub struct SpillQueue {
+    /// Runs the contexts are read back with.
+    runs: Vec<RunConfig>,
+    file: NamedTempFile,
+    writer: BufWriter<File>,
+    reader: BufReader<File>,
+    /// Contexts written but not read back yet.
+    pending: usize,
+    /// Contexts written overall.
+    spilled: usize,
+}
+
+impl SpillQueue {
+    /// Creates the queue file in `dir`. It is removed once the queue is dropped.
+    pub fn new(dir: &Path, runs: Vec<RunConfig>) -> io::Result<Self> {
+        let file = NamedTempFile::with_prefix_in(".pythia-spill-", dir)?;
+        Ok(Self {
+            runs,
+            writer: BufWriter::new(file.reopen()?),
+            reader: BufReader::new(file.reopen()?),
+            file,
+            pending: 0,
+            spilled: 0,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending == 0
+    }
+
+    pub fn push(&mut self, ctx: Context) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, &SpilledContext::from(ctx))?;
+        self.writer.write_all(b"\n")?;
+        self.pending += 1;
+        self.spilled += 1;
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> io::Result<Option<Context>> {
+        if self.pending == 0 {
+            return Ok(None);
+        }
+        self.writer.flush()?;
+        let mut line = String::new();
+        self.reader.read_line(&mut line)?;
+        let spilled: SpilledContext = serde_json::from_str(&line)?;
+        self.pending -= 1;
+        if self.pending == 0 {
+            // Everything was read back, so the file starts over instead of growing for the whole run.
+            self.file.as_file().set_len(0)?;
+            self.writer.seek(SeekFrom::Start(0))?;
+            self.reader.seek(SeekFrom::Start(0))?;
+        }
+
+        let run = self
+            .runs
+            .iter()
+            .find(|run| run.name == spilled.run)
+            .ok_or_else(|| io::Error::other(format!("Unknown run \"{}\"", spilled.run)))?;
+        let provided = spilled.provided.into_iter().map(|(key, value)| {
+            let value = match value {
+                SpilledValue::TemplateString(s) => ContextValue::TemplateString(s),
+                SpilledValue::Prim(p) => ContextValue::Prim(p),
+                SpilledValue::List(l) => ContextValue::List(l),
+                SpilledValue::Raster(r) => ContextValue::Raster(r),
+                SpilledValue::Vector(v) => ContextValue::Vector(v),
+                SpilledValue::Random(r) => ContextValue::Random(r),
+                SpilledValue::Provider(p) => ContextValue::Provider(p),
+                SpilledValue::Records(r) => ContextValue::Records(r),
+            };
+            (key, value)
+        });
+        Ok(Some(Context {
+            site: Site {
+                id: spilled.site,
+                lon: GeoDeg::from(spilled.lon),
+                lat: GeoDeg::from(spilled.lat),
+                covariates: spilled.covariates,
+                attributes: spilled.attributes,
+                weight: spilled.weight,
+            },
+            run: run.clone(),
+            provided: provided.collect(),
+        }))
+    }
+}
+
+/// Passes the contexts received on `rx` on to `tx` in order, parking them in `queue` whenever `tx` is full, until `rx`
+/// hangs up and every parked context is passed on, or `tx` hangs up. Returns how many contexts were spilled.
+pub fn spool(
+    rx: Receiver<Context>,
+    tx: Sender<Context>,
+    mut queue: SpillQueue,
+) -> io::Result<usize> {
+    // Next context to pass on, ahead of the ones in the queue.
+    let mut front: Option<Context> = None;
+    let mut open = true;
+    loop {
+        loop {
+            if front.is_none() {
+                front = queue.pop()?;
+            }
+            let Some(ctx) = front.take() else {
+                break;
+            };
+            match tx.try_send(ctx) {
+                Ok(()) => {}
+                Err(TrySendError::Full(ctx)) => {
+                    front = Some(ctx);
+                    break;
+                }
+                Err(TrySendError::Disconnected(_)) => return Ok(queue.spilled),
+            }
+        }
+
+        let backed_up = front.is_some() || !queue.is_empty();
+        if !open {
+            // Nothing left to receive, so wait on the first stage instead.
+            while let Some(ctx) = front
+                .take()
+                .map_or_else(|| queue.pop(), |ctx| Ok(Some(ctx)))?
+            {
+                if tx.send(ctx).is_err() {
+                    break;
+                }
+            }
+            return Ok(queue.spilled);
+        }
+
+        match backed_up {
+            // Nothing to pass on, so wait for the generator.
+            false => match rx.recv() {
+                Ok(ctx) => front = Some(ctx),
+                Err(_) => open = false,
+            },
+            true => match rx.recv_timeout(IDLE_WAIT) {
+                Ok(ctx) => queue.push(ctx)?,
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => open = false,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpmc::{channel, sync_channel};
+
+    fn context(run: &RunConfig, site: i32) -> Context {
+        Context {
+            site: Site {
+                id: site,
+                lon: GeoDeg::from(1.5),
+                lat: GeoDeg::from(-2.5),
+                covariates: HashMap::from([("elev".to_string(), 300.0)]),
+                attributes: HashMap::from([("region".to_string(), "Sahel".to_string())]),
+                weight: Some(0.5),
+            },
+            run: run.clone(),
+            provided: HashMap::from([(
+                "soil_profile".to_string(),
+                ContextValue::Prim(PrimitiveContextValue::String("*IB00000001".to_string())),
+            )]),
+        }
+    }
+
+    #[test]
+    fn test_spool() {
+        let runs = vec![RunConfig {
+            name: "maize".to_string(),
+            ..Default::default()
+        }];
+        let dir = tempfile::tempdir().unwrap();
+
+        let (tx_gen, rx_gen) = channel();
+        for site in 0..50 {
+            tx_gen.send(context(&runs[0], site)).unwrap();
+        }
+        drop(tx_gen);
+
+        // The first stage doesn't take anything until the spooler received every context.
+        let (tx, rx) = sync_channel(1);
+        let queue = SpillQueue::new(dir.path(), runs.clone()).unwrap();
+        let generated = rx_gen.clone();
+        let spilled = std::thread::scope(|s| {
+            let spooler = s.spawn(|| spool(rx_gen, tx, queue));
+            while !generated.is_empty() {
+                std::thread::yield_now();
+            }
+            let contexts: Vec<Context> = rx.iter().collect();
+            let sites: Vec<i32> = contexts.iter().map(|ctx| ctx.site.id).collect();
+            assert_eq!(sites, (0..50).collect::<Vec<_>>());
+            assert_eq!(contexts[49].site, context(&runs[0], 49).site);
+            assert!(matches!(
+                &contexts[49].provided["soil_profile"],
+                ContextValue::Prim(PrimitiveContextValue::String(p)) if p == "*IB00000001"
+            ));
+            spooler.join().unwrap().unwrap()
+        });
+        assert!(spilled > 0);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,145 @@
+//! Template functions locating the site being rendered on a grid, so outputs can be rasterized again and joined back
+//! to the grid they come from:
+//!
+//! - `grid_cell(arcmin=5)` (or `degrees=0.5`): the cell of a global lon/lat grid holding the site, counted from its
+//!   north-west corner like the CELL5M grid. See [`GridCell::global`].
+//! - `raster_cell(path="soils.tif")`: the pixel of the raster at `path` holding the site, null if outside of it. The
+//!   raster is expected to be in lon/lat, and only its size and geotransform are read, once, on the first use.
+//!
+//! Both are objects with the zero-based `col` and `row` of the cell, its number `cell` (`row * cols + col`) and the
+//! `cols` and `rows` of the grid, e.g. `{% set cell = grid_cell(arcmin=5) %}{{ cell.cell }}`.
+
+use super::nearest::site;
+use crate::data::GridCell;
+use crate::utils::raster::RasterGrid;
+use gdal::Dataset;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use tera::{Error, Function, Result, Value};
+
+fn cell_value(cell: GridCell) -> Value {
+    serde_json::json!({
+        "col": cell.col,
+        "row": cell.row,
+        "cell": cell.index(),
+        "cols": cell.cols,
+        "rows": cell.rows,
+    })
+}
+
+/// The `grid_cell` function.
+pub struct GlobalCell;
+
+impl Function for GlobalCell {
+    fn call(&self, args: &HashMap<String, Value>) -> Result<Value> {
+        let positive = |name: &str| {
+            args.get(name)
+                .map(|v| {
+                    v.as_f64().filter(|n| *n > 0.0).ok_or_else(|| {
+                        Error::msg(format!(
+                            "Function `grid_cell` expects `{}` to be a positive number, got {}",
+                            name, v
+                        ))
+                    })
+                })
+                .transpose()
+        };
+        let resolution = match (positive("arcmin")?, positive("degrees")?) {
+            (Some(arcmin), None) => arcmin / 60.0,
+            (None, Some(degrees)) => degrees,
+            _ => return Err(Error::msg(
+                "Function `grid_cell` expects either the resolution in `arcmin` or in `degrees`",
+            )),
+        };
+        let (lon, lat) = site("grid_cell")?;
+        Ok(cell_value(GridCell::global(lon, lat, resolution)))
+    }
+}
+
+/// Grids of the rasters read so far by path, shared by the templates of every run.
+static GRIDS: LazyLock<Mutex<HashMap<String, RasterGrid>>> = LazyLock::new(Default::default);
+
+/// The `raster_cell` function.
+pub struct RasterCell;
+
+impl RasterCell {
+    fn grid(&self, path: &str) -> Result<RasterGrid> {
+        let mut grids = GRIDS.lock().unwrap_or_else(|e| e.into_inner());
+        match grids.entry(path.to_string()) {
+            Entry::Occupied(entry) => Ok(*entry.get()),
+            Entry::Vacant(entry) => {
+                let grid = Dataset::open(path)
+                    .and_then(|ds| RasterGrid::of(&ds))
+                    .map_err(|e| Error::msg(format!("Failed to read raster {}: {}", path, e)))?;
+                Ok(*entry.insert(grid))
+            }
+        }
+    }
+}
+
+impl Function for RasterCell {
+    fn call(&self, args: &HashMap<String, Value>) -> Result<Value> {
+        let path = args
+            .get("path")
+            .and_then(Value::as_str)
+            .ok_or_else(|| Error::msg("Function `raster_cell` expects a string `path`"))?;
+        let (lon, lat) = site("raster_cell")?;
+
+        let grid = self.grid(path)?;
+        let (cols, rows) = grid.size();
+        Ok(match grid.pixel(lon, lat) {
+            Some((col, row)) => cell_value(GridCell {
+                col: col as u64,
+                row: row as u64,
+                cols: cols as u64,
+                rows: rows as u64,
+            }),
+            None => Value::Null,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::nearest::with_site;
+    use super::*;
+    use tera::Tera;
+
+    fn render(lon: f64, lat: f64, template: &str) -> Result<String> {
+        let mut tera = Tera::default();
+        tera.register_function("grid_cell", GlobalCell);
+        tera.register_function("raster_cell", RasterCell);
+        tera.add_raw_template("t", template)?;
+        with_site(lon, lat, || tera.render("t", &tera::Context::new()))
+    }
+
+    #[test]
+    fn test_cells() {
+        let cell5m =
+            "{% set c = grid_cell(arcmin=5) %}{{ c.cell }} {{ c.col }} {{ c.row }} {{ c.cols }}";
+        assert_eq!(
+            render(12.2919, 14.7917, cell5m).unwrap(),
+            "3898947 2307 902 4320"
+        );
+        assert_eq!(
+            render(
+                -179.9,
+                89.9,
+                "{% set c = grid_cell(degrees=0.5) %}{{ c.cell }}"
+            )
+            .unwrap(),
+            "0"
+        );
+        assert!(render(0.0, 0.0, "{{ grid_cell() }}").is_err());
+        assert!(render(0.0, 0.0, "{{ grid_cell(arcmin=5, degrees=1) }}").is_err());
+        assert!(render(0.0, 0.0, "{{ grid_cell(arcmin=0) }}").is_err());
+
+        let pixel = r#"{% set c = raster_cell(path="testdata/DSSAT-Soils.tif") %}"#;
+        let inside = render(12.2919, 14.7917, &format!("{}{{{{ c.col }}}}", pixel)).unwrap();
+        assert!(inside.parse::<u64>().is_ok());
+        let outside = format!("{}{{{{ c is object }}}}", pixel);
+        assert_eq!(render(-50.0, -50.0, &outside).unwrap(), "false");
+        assert!(render(0.0, 0.0, r#"{{ raster_cell(path="missing.tif") }}"#).is_err());
+    }
+}
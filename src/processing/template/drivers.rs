@@ -0,0 +1,185 @@
+use super::{
+    cells, extensions, filters, nearest, variables, Engine, EngineDriver, TemplateSources,
+    UndefinedPolicy,
+};
+use crate::processing::context::Context;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeSet, HashSet};
+use std::error::Error;
+use std::sync::{Arc, LazyLock};
+
+/// Options of [`DRIVER_TERA`]. It has none for now.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct TeraEngineConfig {}
+
+/// Renders with [Tera](https://keats.github.io/tera/), along with the DSSAT filters (see [`filters`]), the `nearest`
+/// function (see [`nearest`]), the `grid_cell` and `raster_cell` functions (see [`cells`]) and the ones registered by
+/// plugins (see [`extensions`]).
+pub struct TeraEngine {
+    tera: tera::Tera,
+    run: String,
+    undefined: UndefinedPolicy,
+}
+
+impl TeraEngine {
+    pub fn new(
+        _: TeraEngineConfig,
+        sources: &TemplateSources,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut tera = tera::Tera::default();
+        filters::register(&mut tera);
+        tera.register_function("nearest", nearest::Nearest);
+        tera.register_function("grid_cell", cells::GlobalCell);
+        tera.register_function("raster_cell", cells::RasterCell);
+        extensions::register(&mut tera, sources.extensions);
+
+        // Added first, as Tera resolves the templates the run template extends when it is added.
+        let partials = sources.partials.iter().map(|(name, source)| (name, source));
+        tera.add_raw_templates(partials)?;
+        tera.add_raw_template(sources.run, sources.template)?;
+        Ok(Self {
+            tera,
+            run: sources.run.to_string(),
+            undefined: sources.undefined,
+        })
+    }
+}
+
+/// Name of the variable Tera failed to render on for not being in the context, if that's why it failed.
+fn missing_variable(err: &tera::Error) -> Option<String> {
+    let mut source: Option<&dyn Error> = Some(err);
+    while let Some(err) = source {
+        let msg = err.to_string();
+        let name = msg
+            .strip_prefix("Variable `")
+            .and_then(|rest| rest.split_once("` not found in context"));
+        if let Some((name, _)) = name {
+            return Some(name.to_string());
+        }
+        source = err.source();
+    }
+    None
+}
+
+/// Sets the variable at `path` (e.g. `soil.depth`) to `value`, along with the objects on the way to it. False if one
+/// of them is already set to something else than an object.
+fn fill(vars: &mut tera::Context, path: &str, value: Value) -> bool {
+    let Some((root, rest)) = path.split_once('.') else {
+        vars.insert(path, &value);
+        return true;
+    };
+
+    let mut top = vars
+        .get(root)
+        .cloned()
+        .unwrap_or_else(|| Value::Object(Map::new()));
+    let mut node = &mut top;
+    for key in rest.split('.') {
+        let Value::Object(map) = node else {
+            return false;
+        };
+        node = map.entry(key).or_insert_with(|| Value::Object(Map::new()));
+    }
+    *node = value;
+    vars.insert(root, &top);
+    true
+}
+
+impl Engine for TeraEngine {
+    fn render(
+        &self,
+        ctx: &Context,
+        vars: serde_json::Value,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let mut vars = tera::Context::from_value(vars)?;
+        let (lon, lat) = (ctx.site.lon.as_f64(), ctx.site.lat.as_f64());
+
+        // Tera stops at the first missing variable, so they are filled in one render at a time.
+        let mut filled = HashSet::new();
+        loop {
+            let result = nearest::with_site(lon, lat, || self.tera.render(&self.run, &vars));
+            let missing = match (&result, self.undefined) {
+                (_, UndefinedPolicy::Strict) | (Ok(_), _) => None,
+                (Err(e), _) => missing_variable(e).filter(|name| !filled.contains(name)),
+            };
+            let Some(name) = missing else {
+                return Ok(result?);
+            };
+
+            let value = match self.undefined {
+                UndefinedPolicy::Keep => Value::String(format!("{{{{ {} }}}}", name)),
+                _ => Value::String(String::new()),
+            };
+            if !fill(&mut vars, &name, value) {
+                return Ok(result?);
+            }
+            filled.insert(name);
+        }
+    }
+
+    fn variables(&self) -> Option<BTreeSet<String>> {
+        match self.undefined {
+            UndefinedPolicy::Strict => Some(variables::variables(&self.tera, &self.run)),
+            // The missing ones are rendered anyway.
+            _ => Some(BTreeSet::new()),
+        }
+    }
+}
+
+/// The default template engine of the runs, see [`TeraEngine`].
+pub const DRIVER_TERA: LazyLock<EngineDriver<TeraEngine, TeraEngineConfig>> =
+    LazyLock::new(|| EngineDriver {
+        create: Arc::new(TeraEngine::new),
+        config_deserializer: Arc::new(serde_json::from_value),
+    });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::runs::RunConfig;
+    use crate::data::GeoDeg;
+    use crate::sites::Site;
+    use serde_json::json;
+
+    fn render(
+        template: &str,
+        undefined: UndefinedPolicy,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let ctx = Context {
+            site: Site {
+                id: 1,
+                lon: GeoDeg::from(0.0),
+                lat: GeoDeg::from(0.0),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
+            },
+            run: RunConfig::default(),
+            provided: Default::default(),
+        };
+        let sources = TemplateSources {
+            run: "r1",
+            template,
+            partials: &[],
+            undefined,
+            extensions: &[],
+        };
+        let engine = TeraEngine::new(TeraEngineConfig::default(), &sources)?;
+        Ok(engine.render(&ctx, json!({ "crop": "MZ" }))?)
+    }
+
+    #[test]
+    fn test_undefined() {
+        let template = "{{ crop }}|{{ pdate }}|{{ soil.depth }}";
+        assert!(render(template, UndefinedPolicy::Strict).is_err());
+        assert_eq!(render(template, UndefinedPolicy::Empty).unwrap(), "MZ||");
+        assert_eq!(
+            render(template, UndefinedPolicy::Keep).unwrap(),
+            "MZ|{{ pdate }}|{{ soil.depth }}"
+        );
+        // `crop` is a string, it can't have an `id`.
+        assert!(render("{{ crop.id }}", UndefinedPolicy::Empty).is_err());
+    }
+}
@@ -0,0 +1,112 @@
+//! Functions and filters of the templates registered by plugins (e.g. ICASA codes or unit conversions), installed into
+//! the template engines of every run along with their own ones. A resource registered as `acme:icasa-code` is named
+//! `acme_icasa_code` in the templates, e.g. `{{ crop | acme_icasa_code }}`.
+
+use crate::registry::PublicIdentifier;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tera::{Filter, Function, Result, Value};
+
+/// A function or a filter of the templates, see [`crate::registry::Registries::reg_template_extensions`].
+#[allow(dead_code)] // Only plugins register these.
+#[derive(Clone)]
+pub enum TemplateExtension {
+    Function(Arc<dyn Function>),
+    Filter(Arc<dyn Filter>),
+}
+
+/// Name of the extension registered as `id` in the templates: its namespace and ID joined by `_`, with the `-` of
+/// either replaced by `_` (which Tera doesn't allow in names).
+pub fn template_name(id: &PublicIdentifier) -> String {
+    format!("{}_{}", id.namespace, id.id).replace('-', "_")
+}
+
+struct SharedFunction(Arc<dyn Function>);
+
+impl Function for SharedFunction {
+    fn call(&self, args: &HashMap<String, Value>) -> Result<Value> {
+        self.0.call(args)
+    }
+
+    fn is_safe(&self) -> bool {
+        self.0.is_safe()
+    }
+}
+
+struct SharedFilter(Arc<dyn Filter>);
+
+impl Filter for SharedFilter {
+    fn filter(&self, value: &Value, args: &HashMap<String, Value>) -> Result<Value> {
+        self.0.filter(value, args)
+    }
+
+    fn is_safe(&self) -> bool {
+        self.0.is_safe()
+    }
+}
+
+/// Installs `extensions`, by their name in the templates, into `tera`.
+pub fn register(tera: &mut tera::Tera, extensions: &[(String, TemplateExtension)]) {
+    for (name, extension) in extensions {
+        match extension {
+            TemplateExtension::Function(f) => {
+                tera.register_function(name, SharedFunction(f.clone()))
+            }
+            TemplateExtension::Filter(f) => tera.register_filter(name, SharedFilter(f.clone())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::resources::TemplateExtensionResource;
+    use crate::registry::{Claimant, Registries};
+
+    #[test]
+    fn test_extensions() {
+        let mut registries = Registries::new();
+        let namespace = registries
+            .claim_namespace(
+                "acme",
+                Claimant::new("acme-plugin", "1.0.0", "Acme template extensions"),
+            )
+            .unwrap();
+        let icasa_crop = |value: &Value, _: &HashMap<String, Value>| {
+            let code = match value.as_str() {
+                Some("maize") => "MZ",
+                _ => "-99",
+            };
+            Ok(Value::from(code))
+        };
+        let answer = |_: &HashMap<String, Value>| Ok(Value::from(42));
+        registries
+            .regmut_template_extensions()
+            .register(
+                &namespace,
+                "icasa-crop",
+                TemplateExtensionResource(TemplateExtension::Filter(Arc::new(icasa_crop))),
+            )
+            .unwrap()
+            .register(
+                &namespace,
+                "answer",
+                TemplateExtensionResource(TemplateExtension::Function(Arc::new(answer))),
+            )
+            .unwrap();
+
+        let extensions: Vec<_> = registries
+            .reg_template_extensions()
+            .entries()
+            .into_iter()
+            .map(|(id, resource)| (template_name(&id), resource.0.clone()))
+            .collect();
+        let mut tera = tera::Tera::default();
+        register(&mut tera, &extensions);
+        let rendered = tera.render_str(
+            r#"{{ "maize" | acme_icasa_crop }} {{ "rice" | acme_icasa_crop }} {{ acme_answer() }}"#,
+            &tera::Context::new(),
+        );
+        assert_eq!(rendered.unwrap(), "MZ -99 42");
+    }
+}
@@ -0,0 +1,213 @@
+//! Tera filters for the fixed-width columns of the DSSAT files, so the templates don't have to hand-roll them with
+//! `format`:
+//!
+//! - `dssat_num(width=6, precision=?)`: the number right-justified in `width` columns, with `precision` decimals (or
+//!   as few as it takes otherwise). Decimals are dropped until the number fits, it is an error if it still doesn't.
+//! - `dssat_date`: the date (`YYYY-MM-DD` or `YYYYDDD`) in the DSSAT `YYDDD` format.
+//! - `dssat_missing`: `-99` if the value is missing, the value itself otherwise.
+//! - `dssat_trunc(width)`: the text cut to `width` columns, left-justified in them.
+//! - `date_offset(by)`: the date (`YYYY-MM-DD` or `YYYYDDD`) moved by the offset `by` (e.g. `"-15d"`, see
+//!   [`DateOffset`]) as `YYYY-MM-DD`, e.g. for the bounds of a window around the planting date.
+//!
+//! A missing value (null, blank or `-99`) is written as `-99` by every filter, justified the same as its column.
+
+use crate::processing::context::date::{parse_date, DateOffset};
+use crate::weather::wth::yyddd;
+use std::collections::HashMap;
+use tera::{Error, Result, Tera, Value};
+
+/// DSSAT code of a missing value.
+const MISSING: i64 = -99;
+
+/// Width of the columns of `dssat_num` if the template doesn't tell, the most common in the DSSAT files.
+const DEFAULT_NUM_WIDTH: usize = 6;
+
+pub fn register(tera: &mut Tera) {
+    tera.register_filter("dssat_num", dssat_num);
+    tera.register_filter("dssat_date", dssat_date);
+    tera.register_filter("dssat_missing", dssat_missing);
+    tera.register_filter("dssat_trunc", dssat_trunc);
+    tera.register_filter("date_offset", date_offset);
+}
+
+fn is_missing(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(s) => s.trim().is_empty() || s.trim().parse::<f64>() == Ok(MISSING as f64),
+        Value::Number(n) => n.as_f64() == Some(MISSING as f64),
+        _ => false,
+    }
+}
+
+fn usize_arg(filter: &str, args: &HashMap<String, Value>, name: &str) -> Result<Option<usize>> {
+    args.get(name)
+        .map(|v| {
+            v.as_u64().map(|n| n as usize).ok_or_else(|| {
+                Error::msg(format!(
+                    "Filter `{}` expects `{}` to be a non-negative integer, got {}",
+                    filter, name, v
+                ))
+            })
+        })
+        .transpose()
+}
+
+fn dssat_num(value: &Value, args: &HashMap<String, Value>) -> Result<Value> {
+    let width = usize_arg("dssat_num", args, "width")?.unwrap_or(DEFAULT_NUM_WIDTH);
+    let precision = usize_arg("dssat_num", args, "precision")?;
+    if is_missing(value) {
+        return Ok(Value::String(format!("{:>width$}", MISSING)));
+    }
+
+    let n = match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        Error::msg(format!(
+            "Filter `dssat_num` expects a number, got {}",
+            value
+        ))
+    })?;
+
+    let fits = |s: String| (s.len() <= width).then(|| format!("{:>width$}", s));
+    let formatted = match precision {
+        Some(p) => fits(format!("{:.p$}", n)),
+        None => fits(n.to_string()),
+    };
+    formatted
+        .or_else(|| {
+            (0..precision.unwrap_or(width))
+                .rev()
+                .find_map(|p| fits(format!("{:.p$}", n)))
+        })
+        .map(Value::String)
+        .ok_or_else(|| Error::msg(format!("{} does not fit in {} columns", n, width)))
+}
+
+fn dssat_date(value: &Value, _: &HashMap<String, Value>) -> Result<Value> {
+    if is_missing(value) {
+        return Ok(Value::String(MISSING.to_string()));
+    }
+
+    let text = match value {
+        Value::String(s) => s.trim().to_string(),
+        Value::Number(n) if n.is_u64() => n.to_string(),
+        _ => String::new(),
+    };
+    parse_date(&text)
+        .map(|date| Value::String(yyddd(date)))
+        .ok_or_else(|| {
+            Error::msg(format!(
+                "Filter `dssat_date` expects a YYYY-MM-DD or YYYYDDD date, got {}",
+                value
+            ))
+        })
+}
+
+fn dssat_missing(value: &Value, _: &HashMap<String, Value>) -> Result<Value> {
+    match is_missing(value) {
+        true => Ok(Value::from(MISSING)),
+        false => Ok(value.clone()),
+    }
+}
+
+fn dssat_trunc(value: &Value, args: &HashMap<String, Value>) -> Result<Value> {
+    let width = usize_arg("dssat_trunc", args, "width")?
+        .ok_or_else(|| Error::msg("Filter `dssat_trunc` expects a `width`"))?;
+    let text = match value {
+        _ if is_missing(value) => MISSING.to_string(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    let truncated: String = text.chars().take(width).collect();
+    Ok(Value::String(format!("{:<width$}", truncated)))
+}
+
+fn date_offset(value: &Value, args: &HashMap<String, Value>) -> Result<Value> {
+    let offset: DateOffset = args
+        .get("by")
+        .and_then(Value::as_str)
+        .ok_or_else(|| Error::msg("Filter `date_offset` expects an offset `by`, e.g. \"+30d\""))?
+        .parse()
+        .map_err(|e| Error::msg(format!("Filter `date_offset`: {}", e)))?;
+    let date = value.as_str().and_then(parse_date).ok_or_else(|| {
+        Error::msg(format!(
+            "Filter `date_offset` expects a YYYY-MM-DD or YYYYDDD date, got {}",
+            value
+        ))
+    })?;
+    offset
+        .apply(date)
+        .map(|date| Value::String(date.format("%Y-%m-%d").to_string()))
+        .ok_or_else(|| Error::msg(format!("{} moved by {} is out of range", date, offset)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str) -> Result<String> {
+        let mut tera = Tera::default();
+        register(&mut tera);
+        tera.add_raw_template("t", template)?;
+
+        let mut ctx = tera::Context::new();
+        ctx.insert("srad", &15.26);
+        ctx.insert("elev", &1234);
+        ctx.insert("pdate", "2024-02-01");
+        ctx.insert("unknown", &Value::Null);
+        ctx.insert("cultivar", "Pioneer 3394 Hybrid");
+        tera.render("t", &ctx)
+    }
+
+    #[test]
+    fn test_filters() {
+        assert_eq!(render("[{{ srad | dssat_num }}]").unwrap(), "[ 15.26]");
+        assert_eq!(
+            render("[{{ srad | dssat_num(width=5, precision=1) }}]").unwrap(),
+            "[ 15.3]"
+        );
+        assert_eq!(
+            render("[{{ srad | dssat_num(width=4) }}]").unwrap(),
+            "[15.3]"
+        );
+        assert_eq!(render("[{{ srad | dssat_num(width=2) }}]").unwrap(), "[15]");
+        assert!(render("{{ srad | dssat_num(width=1) }}").is_err());
+        assert_eq!(render("[{{ elev | dssat_num }}]").unwrap(), "[  1234]");
+        assert_eq!(
+            render("[{{ unknown | dssat_num(precision=1) }}]").unwrap(),
+            "[   -99]"
+        );
+
+        assert_eq!(render("{{ pdate | dssat_date }}").unwrap(), "24032");
+        assert_eq!(render("{{ '2024032' | dssat_date }}").unwrap(), "24032");
+        assert_eq!(render("{{ unknown | dssat_date }}").unwrap(), "-99");
+        assert!(render("{{ cultivar | dssat_date }}").is_err());
+
+        assert_eq!(render("{{ unknown | dssat_missing }}").unwrap(), "-99");
+        assert_eq!(render("{{ elev | dssat_missing }}").unwrap(), "1234");
+
+        assert_eq!(
+            render("[{{ cultivar | dssat_trunc(width=12) }}]").unwrap(),
+            "[Pioneer 3394]"
+        );
+        assert_eq!(
+            render("[{{ 'MZ' | dssat_trunc(width=4) }}]").unwrap(),
+            "[MZ  ]"
+        );
+        assert!(render("{{ cultivar | dssat_trunc }}").is_err());
+
+        assert_eq!(
+            render("{{ pdate | date_offset(by=\"+30d\") }}").unwrap(),
+            "2024-03-02"
+        );
+        assert_eq!(
+            render("{{ pdate | date_offset(by=\"-15d\") | dssat_date }}").unwrap(),
+            "24017"
+        );
+        assert!(render("{{ pdate | date_offset(by=\"15\") }}").is_err());
+        assert!(render("{{ cultivar | date_offset(by=\"+1m\") }}").is_err());
+    }
+}
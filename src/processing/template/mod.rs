@@ -0,0 +1,507 @@
+use super::context::{Context, ContextEvaluationError, ContextLocation};
+use crate::config::engines::TemplateEngineConfig;
+use crate::config::runs::RunConfig;
+use crate::utils::text::normalize;
+use extensions::TemplateExtension;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use thiserror::Error;
+
+mod cells;
+pub mod drivers;
+pub mod extensions;
+mod filters;
+mod nearest;
+mod variables;
+
+/// A template language the runs can be written in (e.g. [`drivers::DRIVER_TERA`]). Holds the template of a single run,
+/// along with the ones of the template directory it may include.
+pub trait Engine: Send + Sync {
+    /// Renders the template of the run for `ctx`, whose variables (see [`Context::tera`]) are `vars`.
+    fn render(
+        &self,
+        ctx: &Context,
+        vars: serde_json::Value,
+    ) -> Result<String, Box<dyn Error + Send + Sync>>;
+
+    /// Root names of the variables the template fails to render without, or `None` if the engine can't tell.
+    fn variables(&self) -> Option<BTreeSet<String>> {
+        None
+    }
+}
+
+impl<E: Engine + ?Sized> Engine for Box<E> {
+    fn render(
+        &self,
+        ctx: &Context,
+        vars: serde_json::Value,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        (**self).render(ctx, vars)
+    }
+
+    fn variables(&self) -> Option<BTreeSet<String>> {
+        (**self).variables()
+    }
+}
+
+/// How the variables a template uses but its context doesn't have are rendered.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UndefinedPolicy {
+    /// Fails the context.
+    #[default]
+    Strict,
+    /// As an empty string.
+    Empty,
+    /// As they are written in the template (e.g. `{{ pdate }}`), to be filled in by a later stage.
+    Keep,
+}
+
+/// The templates an [`Engine`] is built from, with their line endings normalized (see [`normalize`]).
+pub struct TemplateSources<'a> {
+    /// Name of the run the template is of, followed by the name of the output for the `outputs` of the run (e.g.
+    /// `run/soil`).
+    pub run: &'a str,
+    pub template: &'a str,
+    /// Templates of the template directory by their path relative to it, see [`TemplateEngine::register_dir`].
+    pub partials: &'a [(String, String)],
+    pub undefined: UndefinedPolicy,
+    /// Functions and filters registered by plugins, by their name in the templates, see [`extensions`].
+    pub extensions: &'a [(String, TemplateExtension)],
+}
+
+/// Constructs a new [`Engine`] of type [`E`] from the config [`C`].
+#[allow(type_alias_bounds)] // Same as the site generator drivers, see [`crate::sites::SiteGeneratorDriver`].
+type EngineFactory<E: Engine, C> =
+    Arc<dyn Fn(C, &TemplateSources) -> Result<E, Box<dyn Error + Send + Sync>>>;
+
+/// Deserializes a config of type [`C`] from a [`serde_json::Value`].
+type EngineConfigDeserializer<C> =
+    Arc<dyn Fn(serde_json::Value) -> Result<C, serde_json::error::Error>>;
+
+/// An [`Engine`] addressable by identifier from the `engine` of a run.
+pub struct EngineDriver<E: Engine, C> {
+    pub create: EngineFactory<E, C>,
+    pub config_deserializer: EngineConfigDeserializer<C>,
+}
+
+impl<E: Engine, C> Clone for EngineDriver<E, C> {
+    fn clone(&self) -> Self {
+        EngineDriver {
+            create: self.create.clone(),
+            config_deserializer: self.config_deserializer.clone(),
+        }
+    }
+}
+
+impl<E: Engine, C> EngineDriver<E, C> {
+    pub fn coerce_to_dynamic(self) -> EngineDriver<Box<dyn Engine>, Box<dyn Any>>
+    where
+        E: 'static,
+        C: Any + 'static,
+    {
+        EngineDriver {
+            create: Arc::new(move |c: Box<dyn Any>, sources: &TemplateSources| {
+                let config = c.downcast::<C>().map_err(|_| {
+                    Box::<dyn Error + Send + Sync>::from("Failed to downcast config")
+                })?;
+                let concrete_engine = (self.create)(*config, sources)?;
+                Ok(Box::new(concrete_engine) as Box<dyn Engine>)
+            }),
+            config_deserializer: Arc::new(move |v| {
+                let concrete_config = (self.config_deserializer)(v)?;
+                Ok(Box::new(concrete_config) as Box<dyn Any>)
+            }),
+        }
+    }
+}
+
+/// The templates of every run, each rendered by the [`Engine`] of its run.
+#[derive(Default)]
+pub struct TemplateEngine {
+    engines: HashMap<String, Box<dyn Engine>>,
+    filenames: HashMap<String, String>,
+    /// `output_filename` of the runs that set one, by run name.
+    output_filenames: tera::Tera,
+    /// Templates of the `raw` runs, copied as they are instead of rendered.
+    raw: HashMap<String, Arc<[u8]>>,
+    partials: Vec<(String, String)>,
+    extensions: Vec<(String, TemplateExtension)>,
+}
+
+#[derive(Debug, Error)]
+pub enum TemplateError {
+    #[error("Failed to read template {path} of run \"{run}\": {source}")]
+    IOError {
+        run: String,
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error(
+        "Failed to parse template {path} of run \"{run}\" with {engine}: {}",
+        error_chain(source.as_ref())
+    )]
+    Parse {
+        run: String,
+        path: PathBuf,
+        engine: String,
+        source: Box<dyn Error + Send + Sync>,
+    },
+    #[error(
+        "Failed to parse the output file name of run \"{run}\": {}",
+        error_chain(source)
+    )]
+    FileNameParse { run: String, source: tera::Error },
+    #[error("Output file name \"{name}\" of {location} is not a plain file name")]
+    InvalidFileName {
+        location: ContextLocation,
+        name: String,
+    },
+    #[error("Failed to read template {path} of the template directory: {source}")]
+    DirIOError {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Template path {0} is not a file")]
+    TemplateNotAFile(PathBuf),
+    #[error("Failed to render template for {location}: {}", error_chain(source.as_ref()))]
+    Render {
+        location: ContextLocation,
+        source: Box<dyn Error + Send + Sync>,
+    },
+    #[error("Context evaluation error for {location}: {source}")]
+    ContextEvaluation {
+        location: ContextLocation,
+        source: ContextEvaluationError,
+    },
+}
+
+/// Formats an error along with all of its sources. Tera only reports the outermost error in its
+/// [`std::fmt::Display`] implementation, which usually hides the actual cause (e.g. the missing variable).
+fn error_chain(err: &dyn Error) -> String {
+    let mut msg = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        msg.push_str(": ");
+        msg.push_str(&err.to_string());
+        source = err.source();
+    }
+    msg
+}
+
+/// Name the template of `run` (or its output named `output`) is registered under, e.g. `run/soil`. Run names can't
+/// have slashes, so the ones of the outputs don't clash with them.
+fn key(run: &str, output: Option<&str>) -> String {
+    match output {
+        Some(output) => format!("{}/{}", run, output),
+        None => run.to_string(),
+    }
+}
+
+/// Reads every file under `dir` as a template named by its path relative to `root`, with `/` separators.
+fn read_dir(
+    root: &Path,
+    dir: &Path,
+    templates: &mut Vec<(String, String)>,
+) -> Result<(), TemplateError> {
+    let io_err = |path: &Path, source| TemplateError::DirIOError {
+        path: path.to_path_buf(),
+        source,
+    };
+    for entry in std::fs::read_dir(dir).map_err(|e| io_err(dir, e))? {
+        let path = entry.map_err(|e| io_err(dir, e))?.path();
+        if path.is_dir() {
+            read_dir(root, &path, templates)?;
+            continue;
+        }
+
+        let name = path.strip_prefix(root).unwrap_or(&path).components();
+        let name = name
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let contents = std::fs::read_to_string(&path).map_err(|e| io_err(&path, e))?;
+        templates.push((name, normalize(&contents)));
+    }
+    Ok(())
+}
+
+impl TemplateEngine {
+    /// Reads every file under `dir` as a template named by its path relative to it, for the templates of the runs
+    /// registered after to `{% include %}` or `{% extends %}`.
+    pub fn register_dir(&mut self, dir: &Path) -> Result<(), TemplateError> {
+        read_dir(dir, dir, &mut self.partials)
+    }
+
+    /// Adds the functions and filters of `extensions` (by their name in the templates) to the templates of the runs
+    /// registered after, see [`extensions`].
+    pub fn register_extensions(&mut self, extensions: &[(String, TemplateExtension)]) {
+        self.extensions.extend_from_slice(extensions);
+    }
+
+    /// Registers the template of `run` along with the ones of its `outputs`, see [`TemplateEngine::render`].
+    pub fn register(
+        &mut self,
+        run: &RunConfig,
+        engine: &TemplateEngineConfig,
+    ) -> Result<(), TemplateError> {
+        let main = (
+            run.name.clone(),
+            &run.template,
+            run.output_filename.as_deref(),
+        );
+        let outputs = run.outputs.iter().map(|output| {
+            let key = key(&run.name, Some(&output.name));
+            (key, &output.template, output.output_filename.as_deref())
+        });
+        for (key, file, output_filename) in std::iter::once(main).chain(outputs) {
+            let raw = run.raw && key == run.name;
+            self.register_template(run, &key, file, output_filename, raw, engine)?;
+        }
+        Ok(())
+    }
+
+    fn register_template(
+        &mut self,
+        run: &RunConfig,
+        key: &str,
+        file: &Path,
+        output_filename: Option<&str>,
+        raw: bool,
+        engine: &TemplateEngineConfig,
+    ) -> Result<(), TemplateError> {
+        let run_name = run.name.as_str();
+        let io_err = |source| TemplateError::IOError {
+            run: run_name.to_string(),
+            path: file.to_path_buf(),
+            source,
+        };
+
+        let full_path = file.canonicalize().map_err(io_err)?;
+        if raw {
+            let contents = std::fs::read(full_path).map_err(io_err)?;
+            self.raw.insert(key.to_string(), contents.into());
+        } else {
+            let contents = normalize(&std::fs::read_to_string(full_path).map_err(io_err)?);
+            let sources = TemplateSources {
+                run: key,
+                template: &contents,
+                partials: &self.partials,
+                undefined: run.undefined,
+                extensions: &self.extensions,
+            };
+            let engine = engine
+                .build(&sources)
+                .map_err(|source| TemplateError::Parse {
+                    run: run_name.to_string(),
+                    path: file.to_path_buf(),
+                    engine: engine.name.clone(),
+                    source,
+                })?;
+            self.engines.insert(key.to_string(), engine);
+        }
+        self.filenames.insert(
+            key.to_string(),
+            file.file_name()
+                .ok_or(TemplateError::TemplateNotAFile(file.to_path_buf()))?
+                .to_string_lossy()
+                .to_string(),
+        );
+        if let Some(output_filename) = output_filename {
+            self.output_filenames
+                .add_raw_template(key, output_filename)
+                .map_err(|source| TemplateError::FileNameParse {
+                    run: run_name.to_string(),
+                    source,
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Name of the file the template of the run of `ctx` (or its output named `output`) is rendered to: its
+    /// `output_filename` rendered for `ctx` if it sets one, the file name of the template otherwise. `None` if it isn't
+    /// registered.
+    pub fn file_name(
+        &self,
+        ctx: &Context,
+        output: Option<&str>,
+    ) -> Result<Option<String>, TemplateError> {
+        let key = key(&ctx.run.name, output);
+        if !self.output_filenames.templates.contains_key(&key) {
+            return Ok(self.filenames.get(&key).cloned());
+        }
+
+        let tera_ctx = ctx
+            .tera()
+            .map_err(|source| TemplateError::ContextEvaluation {
+                location: ctx.location(),
+                source,
+            })?;
+        let name = self
+            .output_filenames
+            .render(&key, &tera_ctx)
+            .map_err(|source| TemplateError::Render {
+                location: ctx.location(),
+                source: source.into(),
+            })?;
+
+        // Rendered files always go in the directory of their context.
+        let mut components = Path::new(&name).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(_)), None) => Ok(Some(name)),
+            _ => Err(TemplateError::InvalidFileName {
+                location: ctx.location(),
+                name,
+            }),
+        }
+    }
+
+    /// Root names of the variables the templates and the output file names of `run` fail to render without (see
+    /// [`Engine::variables`]). `None` if the run isn't registered or its engine can't tell.
+    pub fn variables(&self, run: &RunConfig) -> Option<BTreeSet<String>> {
+        let outputs = run.outputs.iter().map(|output| Some(output.name.as_str()));
+        let mut names = BTreeSet::new();
+        for output in std::iter::once(None).chain(outputs) {
+            let key = key(&run.name, output);
+            if !self.raw.contains_key(&key) {
+                names.extend(self.engines.get(&key)?.variables()?);
+            }
+            names.extend(variables::variables(&self.output_filenames, &key));
+        }
+        Some(names)
+    }
+
+    /// Contents of the template of `run_name` if the run is `raw`, to be copied as they are instead of rendered.
+    pub fn raw(&self, run_name: &str) -> Option<Arc<[u8]>> {
+        self.raw.get(run_name).cloned()
+    }
+
+    /// Renders the template of the run of `ctx`, or the one of its output named `output`.
+    pub fn render(&self, ctx: &Context, output: Option<&str>) -> Result<String, TemplateError> {
+        let tera_ctx = ctx
+            .tera()
+            .map_err(|source| TemplateError::ContextEvaluation {
+                location: ctx.location(),
+                source,
+            })?;
+
+        let render_err = |source: Box<dyn Error + Send + Sync>| TemplateError::Render {
+            location: ctx.location(),
+            source,
+        };
+        let engine = self
+            .engines
+            .get(&key(&ctx.run.name, output))
+            .ok_or_else(|| render_err("No template is registered for the run".into()))?;
+        engine.render(ctx, tera_ctx.into_json()).map_err(render_err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::runs::{RunConfig, RunOutputConfig};
+    use crate::data::GeoDeg;
+    use crate::processing::context::{ContextValue, PrimitiveContextValue};
+    use crate::sites::Site;
+
+    #[test]
+    fn test_register_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("common")).unwrap();
+        std::fs::write(
+            dir.path().join("base.tpl"),
+            "*HEADER\r\n{% block body %}{% endblock %}",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("common/crop.tpl"), "CR {{ crop }}").unwrap();
+        let soil_template = dir.path().join("soil.tpl");
+        std::fs::write(&soil_template, "SOIL {{ site_id }}").unwrap();
+        let run_template = dir.path().join("run.tpl");
+        std::fs::write(
+            &run_template,
+            r#"{% extends "base.tpl" %}{% block body %}{% include "common/crop.tpl" %}{% endblock %}"#,
+        )
+        .unwrap();
+
+        let mut templates = TemplateEngine::default();
+        templates.register_dir(dir.path()).unwrap();
+        let run = RunConfig {
+            name: "r1".to_string(),
+            template: run_template,
+            output_filename: Some("{{ crop }}{{ site_id }}.SNX".to_string()),
+            outputs: vec![RunOutputConfig {
+                name: "soil".to_string(),
+                template: soil_template,
+                output_filename: None,
+            }],
+            extra: HashMap::from([(
+                "crop".to_string(),
+                ContextValue::Prim(PrimitiveContextValue::String("MZ".to_string())),
+            )]),
+            ..Default::default()
+        };
+        let engine = TemplateEngineConfig::default();
+        templates.register(&run, &engine).unwrap();
+
+        let mut ctx = Context {
+            site: Site {
+                id: 1,
+                lon: GeoDeg::from(0.0),
+                lat: GeoDeg::from(0.0),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
+            },
+            run,
+            provided: Default::default(),
+        };
+        assert_eq!(templates.render(&ctx, None).unwrap(), "*HEADER\nCR MZ");
+        assert_eq!(templates.file_name(&ctx, None).unwrap().unwrap(), "MZ1.SNX");
+        assert_eq!(templates.render(&ctx, Some("soil")).unwrap(), "SOIL 1");
+        assert_eq!(
+            templates.file_name(&ctx, Some("soil")).unwrap().unwrap(),
+            "soil.tpl"
+        );
+        assert!(templates.render(&ctx, Some("missing")).is_err());
+
+        ctx.run.extra.insert(
+            "crop".to_string(),
+            ContextValue::Prim(PrimitiveContextValue::String("../MZ".to_string())),
+        );
+        assert!(matches!(
+            templates.file_name(&ctx, None),
+            Err(TemplateError::InvalidFileName { .. })
+        ));
+
+        assert!(matches!(
+            TemplateEngine::default().register_dir(&dir.path().join("missing")),
+            Err(TemplateError::DirIOError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_register_raw() {
+        let dir = tempfile::tempdir().unwrap();
+        let grid = dir.path().join("soil.grd");
+        let contents = [0x00, 0xff, 0xfe, b'{', b'{', b'\r', b'\n'];
+        std::fs::write(&grid, contents).unwrap();
+
+        let mut templates = TemplateEngine::default();
+        let run = RunConfig {
+            name: "r1".to_string(),
+            template: grid,
+            raw: true,
+            ..Default::default()
+        };
+        templates
+            .register(&run, &TemplateEngineConfig::default())
+            .unwrap();
+        assert_eq!(templates.raw("r1").as_deref(), Some(&contents[..]));
+        assert_eq!(templates.raw("r2"), None);
+    }
+}
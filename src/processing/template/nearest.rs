@@ -0,0 +1,150 @@
+//! The `nearest(path, field)` template function: `field` of the feature of the vector dataset at `path` nearest to
+//! the site being rendered, e.g. `{{ nearest(path="stations.gpkg", field="code") }}` for the weather station of the
+//! X-file. Null if the field of that feature is null.
+//!
+//! Distances are great-circle ones to the center of the extent of each feature (the point itself for point layers),
+//! so the dataset is expected to be in lon/lat. Only its first layer is read, once per field, on the first use.
+
+use crate::data::haversine_km;
+use gdal::errors::GdalError;
+use gdal::vector::LayerAccess;
+use gdal::Dataset;
+use std::cell::Cell;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+use tera::{Error, Function, Result, Value};
+
+thread_local! {
+    /// Site being rendered on the current thread, as `(lon, lat)`. Tera functions don't see the context of the
+    /// template, so [`super::drivers::TeraEngine`] sets it for them with [`with_site`].
+    static SITE: Cell<Option<(f64, f64)>> = const { Cell::new(None) };
+}
+
+/// Runs `render` with the site at `lon`, `lat` as the one [`Nearest`] (and the functions of [`super::cells`]) look up
+/// from.
+pub fn with_site<T>(lon: f64, lat: f64, render: impl FnOnce() -> T) -> T {
+    SITE.set(Some((lon, lat)));
+    let result = render();
+    SITE.set(None);
+    result
+}
+
+/// Site being rendered on the current thread, as `(lon, lat)`, or an error naming `function` if there is none.
+pub(super) fn site(function: &str) -> Result<(f64, f64)> {
+    SITE.get().ok_or_else(|| {
+        Error::msg(format!(
+            "Function `{}` needs a site to look up from",
+            function
+        ))
+    })
+}
+
+struct Feature {
+    lon: f64,
+    lat: f64,
+    value: Option<String>,
+}
+
+fn load(path: &str, field: &str) -> std::result::Result<Vec<Feature>, GdalError> {
+    let ds = Dataset::open(path)?;
+    let mut layer = ds.layer(0)?;
+    let mut features = Vec::new();
+    for feature in layer.features() {
+        let Some(geometry) = feature.geometry() else {
+            continue;
+        };
+        let envelope = geometry.envelope();
+        features.push(Feature {
+            lon: (envelope.MinX + envelope.MaxX) / 2.0,
+            lat: (envelope.MinY + envelope.MaxY) / 2.0,
+            value: feature.field_as_string_by_name(field)?,
+        });
+    }
+    Ok(features)
+}
+
+/// Features read so far by path and field, shared by the templates of every run.
+static LAYERS: LazyLock<Mutex<HashMap<(String, String), Arc<Vec<Feature>>>>> =
+    LazyLock::new(Default::default);
+
+/// The `nearest` function.
+pub struct Nearest;
+
+impl Nearest {
+    fn features(&self, path: &str, field: &str) -> Result<Arc<Vec<Feature>>> {
+        // Held while loading, so the workers rendering their first context don't all read the same dataset.
+        let mut layers = LAYERS.lock().unwrap_or_else(|e| e.into_inner());
+        match layers.entry((path.to_string(), field.to_string())) {
+            Entry::Occupied(entry) => Ok(entry.get().clone()),
+            Entry::Vacant(entry) => {
+                let features = load(path, field).map_err(|e| {
+                    Error::msg(format!("Failed to read field {} of {}: {}", field, path, e))
+                })?;
+                Ok(entry.insert(Arc::new(features)).clone())
+            }
+        }
+    }
+}
+
+impl Function for Nearest {
+    fn call(&self, args: &HashMap<String, Value>) -> Result<Value> {
+        let arg = |name: &str| {
+            args.get(name).and_then(Value::as_str).ok_or_else(|| {
+                Error::msg(format!("Function `nearest` expects a string `{}`", name))
+            })
+        };
+        let (path, field) = (arg("path")?, arg("field")?);
+        let (lon, lat) = site("nearest")?;
+
+        let features = self.features(path, field)?;
+        let distance = |f: &Feature| haversine_km(lat, lon, f.lat, f.lon);
+        let nearest = features
+            .iter()
+            .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+            .ok_or_else(|| Error::msg(format!("{} has no features", path)))?;
+        Ok(nearest.value.clone().map_or(Value::Null, Value::String))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn station(code: &str, coordinates: &str) -> String {
+        format!(
+            r#"{{ "type": "Feature", "properties": {{ "code": "{}" }}, "geometry": {{ "type": "Point", "coordinates": {} }} }}"#,
+            code, coordinates
+        )
+    }
+
+    #[test]
+    fn test_nearest() {
+        let mut file = tempfile::Builder::new()
+            .suffix(".geojson")
+            .tempfile()
+            .unwrap();
+        let stations = format!(
+            r#"{{ "type": "FeatureCollection", "features": [{}, {}, {}] }}"#,
+            station("ABCD", "[10.0, 10.0]"),
+            station("EFGH", "[12.0, 10.0]"),
+            station("IJKL", "[179.9, 0.0]"),
+        );
+        file.write_all(stations.as_bytes()).unwrap();
+
+        let mut tera = tera::Tera::default();
+        tera.register_function("nearest", Nearest);
+        let template = format!(
+            r#"{{{{ nearest(path="{}", field="code") }}}}"#,
+            file.path().display()
+        );
+        tera.add_raw_template("t", &template).unwrap();
+        let render = |lon, lat| with_site(lon, lat, || tera.render("t", &tera::Context::new()));
+
+        assert_eq!(render(10.9, 10.0).unwrap(), "ABCD");
+        assert_eq!(render(11.1, 9.0).unwrap(), "EFGH");
+        assert_eq!(render(-179.9, 0.5).unwrap(), "IJKL");
+        assert!(tera.render("t", &tera::Context::new()).is_err());
+    }
+}
@@ -0,0 +1,243 @@
+//! Finds the context variables a Tera template reads, so templates asking for variables their context never has can
+//! be reported before any context is rendered rather than at every one of them.
+//!
+//! Only the root of the variables is reported (`soil` for `{{ soil.depth }}`), and only those whose absence fails the
+//! render: the ones tested by an `{% if %}` or passed through the `default` filter are left out, as are the ones set
+//! by the template itself (`{% set %}`, `{% for %}`).
+
+use std::collections::BTreeSet;
+use tera::ast::{Block, Expr, ExprVal, Node};
+use tera::{Template, Tera};
+
+/// Root names of the context variables the template `name` of `tera` reads, including through the templates it
+/// extends and includes. Empty if `tera` has no such template.
+pub fn variables(tera: &Tera, name: &str) -> BTreeSet<String> {
+    let mut walker = Walker {
+        tera,
+        template: tera.templates.get(name),
+        blocks: Vec::new(),
+        includes: Vec::new(),
+        bound: Vec::new(),
+        found: BTreeSet::new(),
+    };
+    if let Some(template) = walker.template {
+        // Templates that extend another render the root of their hierarchy, with their own blocks.
+        let root = template.parents.last().and_then(|p| tera.templates.get(p));
+        walker.nodes(&root.unwrap_or(template).ast);
+    }
+    walker.found
+}
+
+struct Walker<'a> {
+    tera: &'a Tera,
+    template: Option<&'a Template>,
+    /// Blocks being walked, with how many levels of `{{ super() }}` up their hierarchy.
+    blocks: Vec<(&'a str, usize)>,
+    /// Templates being included, so a template including itself doesn't recurse forever.
+    includes: Vec<&'a str>,
+    /// Variables set by the template in the scopes being walked.
+    bound: Vec<&'a str>,
+    found: BTreeSet<String>,
+}
+
+impl<'a> Walker<'a> {
+    /// Definitions of the block `name`, from the template being rendered up to the root of its hierarchy.
+    fn definitions(&self, name: &str) -> Vec<&'a Block> {
+        let Some(template) = self.template else {
+            return Vec::new();
+        };
+        std::iter::once(template.name.as_str())
+            .chain(template.parents.iter().map(String::as_str))
+            .filter_map(|t| self.tera.templates.get(t)?.blocks.get(name))
+            .collect()
+    }
+
+    fn block(&mut self, name: &'a str, level: usize, fallback: &'a [Node]) {
+        let definitions = self.definitions(name);
+        let body = match definitions.get(level) {
+            Some(block) => block.body.as_slice(),
+            None if level == 0 => fallback,
+            None => return,
+        };
+        self.blocks.push((name, level));
+        self.nodes(body);
+        self.blocks.pop();
+    }
+
+    fn nodes(&mut self, nodes: &'a [Node]) {
+        let scope = self.bound.len();
+        for node in nodes {
+            self.node(node);
+        }
+        self.bound.truncate(scope);
+    }
+
+    fn node(&mut self, node: &'a Node) {
+        match node {
+            Node::VariableBlock(_, expr) => self.expr(expr),
+            Node::Set(_, set) => {
+                self.expr(&set.value);
+                self.bound.push(&set.key);
+            }
+            Node::Block(_, block, _) => self.block(&block.name, 0, &block.body),
+            Node::Super => {
+                if let Some(&(name, level)) = self.blocks.last() {
+                    self.block(name, level + 1, &[]);
+                }
+            }
+            Node::Include(_, names, _) => {
+                let included = names
+                    .iter()
+                    .find_map(|name| self.tera.templates.get_key_value(name));
+                if let Some((name, template)) = included {
+                    if !self.includes.contains(&name.as_str()) {
+                        self.includes.push(name);
+                        self.nodes(&template.ast);
+                        self.includes.pop();
+                    }
+                }
+            }
+            Node::FilterSection(_, section, _) => {
+                section.filter.args.values().for_each(|arg| self.expr(arg));
+                self.nodes(&section.body);
+            }
+            Node::Forloop(_, forloop, _) => {
+                self.expr(&forloop.container);
+                let scope = self.bound.len();
+                self.bound.extend(forloop.key.as_deref());
+                self.bound.extend([forloop.value.as_str(), "loop"]);
+                self.nodes(&forloop.body);
+                self.bound.truncate(scope);
+                if let Some(body) = &forloop.empty_body {
+                    self.nodes(body);
+                }
+            }
+            Node::If(conditions, _) => {
+                for (_, condition, body) in &conditions.conditions {
+                    // Tera takes an undefined variable as false there.
+                    let bare =
+                        matches!(condition.val, ExprVal::Ident(_)) && condition.filters.is_empty();
+                    if !bare {
+                        self.expr(condition);
+                    }
+                    self.nodes(body);
+                }
+                if let Some((_, body)) = &conditions.otherwise {
+                    self.nodes(body);
+                }
+            }
+            // Macros can't see the context, only their arguments.
+            Node::MacroDefinition(..)
+            | Node::Extends(..)
+            | Node::ImportMacro(..)
+            | Node::Text(_)
+            | Node::Raw(..)
+            | Node::Break(_)
+            | Node::Continue(_)
+            | Node::Comment(..) => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &'a Expr) {
+        if !expr.has_default_filter() {
+            self.val(&expr.val);
+        }
+        for filter in &expr.filters {
+            filter.args.values().for_each(|arg| self.expr(arg));
+        }
+    }
+
+    fn val(&mut self, val: &'a ExprVal) {
+        match val {
+            ExprVal::Ident(ident) => self.ident(ident),
+            ExprVal::Math(math) => {
+                self.expr(&math.lhs);
+                self.expr(&math.rhs);
+            }
+            ExprVal::Logic(logic) => {
+                self.expr(&logic.lhs);
+                self.expr(&logic.rhs);
+            }
+            ExprVal::In(within) => {
+                self.expr(&within.lhs);
+                self.expr(&within.rhs);
+            }
+            // The tested variable may well be undefined, e.g. `foo is defined`.
+            ExprVal::Test(test) => test.args.iter().for_each(|arg| self.expr(arg)),
+            ExprVal::MacroCall(call) => call.args.values().for_each(|arg| self.expr(arg)),
+            ExprVal::FunctionCall(call) => call.args.values().for_each(|arg| self.expr(arg)),
+            ExprVal::Array(values) => values.iter().for_each(|value| self.expr(value)),
+            ExprVal::StringConcat(concat) => {
+                for value in &concat.values {
+                    self.val(value);
+                }
+            }
+            ExprVal::String(_) | ExprVal::Int(_) | ExprVal::Float(_) | ExprVal::Bool(_) => {}
+        }
+    }
+
+    /// Records the root of `ident` (e.g. `soil.layers[n]`), along with the ones of the variables it is indexed with.
+    fn ident(&mut self, ident: &str) {
+        let mut parts = ident.split(['[', ']']);
+        let root = parts.next().unwrap_or_default();
+        for (i, part) in parts.enumerate() {
+            // Odd parts are the path after a closing bracket, e.g. `.depth` of `layers[n].depth`.
+            let part = part.trim();
+            let literal = part.starts_with(['"', '\'', '`']) || part.parse::<i64>().is_ok();
+            if i % 2 == 0 && !part.is_empty() && !literal {
+                self.ident(part);
+            }
+        }
+
+        let root = root.split('.').next().unwrap_or_default();
+        if !root.is_empty() && root != "__tera_context" && !self.bound.contains(&root) {
+            self.found.insert(root.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_variables() {
+        let mut tera = Tera::default();
+        tera.add_raw_templates([
+            (
+                "base.tpl",
+                "{{ site_id }}{% block body %}{{ overridden }}{% endblock %}",
+            ),
+            ("crop.tpl", "{{ crop }}{% include \"crop.tpl\" %}"),
+            (
+                "run.tpl",
+                r#"{% extends "base.tpl" %}
+{% block body %}{{ super() }}{% include "crop.tpl" %}
+{% set depth = soil.depth %}{{ depth }}
+{% for layer in layers %}{{ layer.sllb }}{{ loop.index }}{{ values[key] }}{% endfor %}
+{% if irrigated %}{{ pdate | default(value=sdate) }}{% elif n > 1 %}{% endif %}
+{{ nearest(path=stations, field="code") ~ wsta }}{% endblock %}"#,
+            ),
+        ])
+        .unwrap();
+
+        let expected = [
+            "crop",
+            "key",
+            "layers",
+            "n",
+            "overridden",
+            "sdate",
+            "site_id",
+            "soil",
+            "stations",
+            "values",
+            "wsta",
+        ];
+        assert_eq!(
+            variables(&tera, "run.tpl"),
+            expected.iter().map(|v| v.to_string()).collect()
+        );
+        assert!(variables(&tera, "missing.tpl").is_empty());
+    }
+}
@@ -0,0 +1,295 @@
+//! Module _provenance_ keeps track of what went into a campaign, so its outputs can be traced back to the exact inputs that produced them.
+
+use crate::config::inputs::InputConfig;
+use crate::sites::Site;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Name of the manifest file written to the root of the working directory.
+pub const MANIFEST_FILE_NAME: &str = "pythia-manifest.json";
+
+/// Name of the metadata file written to context (or run) directories, see [`DirectoryMetadata`].
+pub const METADATA_FILE_NAME: &str = "pythia-metadata.json";
+
+/// The identity of an input dataset at the moment it was used.
+#[derive(Serialize, Debug, Clone)]
+pub struct InputDigest {
+    pub path: PathBuf,
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl InputDigest {
+    /// Computes the size and SHA-256 digest of the file at `path`.
+    pub fn of(path: &Path) -> std::io::Result<Self> {
+        let mut file = File::open(path)?;
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0u8; 1 << 16];
+        let mut size = 0u64;
+
+        loop {
+            let read = file.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buf[..read]);
+            size += read as u64;
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            size,
+            sha256: hex(&hasher.finalize()),
+        })
+    }
+}
+
+/// Hex-encodes `bytes` (e.g. a digest) in lowercase.
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[derive(Debug, Error)]
+pub enum InputVerificationError {
+    #[error("Failed to read input {0}: {1}")]
+    Read(PathBuf, std::io::Error),
+    #[error("Input {path} has size {actual} bytes, expected {expected} bytes")]
+    SizeMismatch {
+        path: PathBuf,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("Input {path} has SHA-256 {actual}, expected {expected}")]
+    ChecksumMismatch {
+        path: PathBuf,
+        expected: String,
+        actual: String,
+    },
+}
+
+/// Digests every declared input and checks it against its expectations.
+/// All the mismatches are reported at once, instead of failing on the first one.
+pub fn verify_inputs(
+    inputs: &HashMap<PathBuf, InputConfig>,
+) -> Result<Vec<InputDigest>, Vec<InputVerificationError>> {
+    let mut digests = Vec::new();
+    let mut errors = Vec::new();
+
+    for (path, expected) in inputs {
+        let digest = match InputDigest::of(path) {
+            Ok(digest) => digest,
+            Err(err) => {
+                errors.push(InputVerificationError::Read(path.clone(), err));
+                continue;
+            }
+        };
+
+        if let Some(size) = expected.size {
+            if size != digest.size {
+                errors.push(InputVerificationError::SizeMismatch {
+                    path: path.clone(),
+                    expected: size,
+                    actual: digest.size,
+                });
+            }
+        }
+
+        if let Some(sha256) = &expected.sha256 {
+            if !sha256.eq_ignore_ascii_case(&digest.sha256) {
+                errors.push(InputVerificationError::ChecksumMismatch {
+                    path: path.clone(),
+                    expected: sha256.to_lowercase(),
+                    actual: digest.sha256.clone(),
+                });
+            }
+        }
+
+        digests.push(digest);
+    }
+
+    if errors.is_empty() {
+        digests.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(digests)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Describes how the contents of a working directory were produced.
+#[derive(Serialize, Debug, Clone)]
+pub struct Manifest {
+    pub pythia_version: &'static str,
+    pub config: InputDigest,
+    /// RFC 3339 UTC timestamp.
+    pub created_at: String,
+    pub workdir: PathBuf,
+    pub temporary_workdir: bool,
+    pub inputs: Vec<InputDigest>,
+}
+
+impl Manifest {
+    pub fn new(
+        config: InputDigest,
+        workdir: PathBuf,
+        temporary_workdir: bool,
+        inputs: Vec<InputDigest>,
+    ) -> Self {
+        Self {
+            pythia_version: env!("CARGO_PKG_VERSION"),
+            config,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            workdir,
+            temporary_workdir,
+            inputs,
+        }
+    }
+
+    /// Writes the manifest as [`MANIFEST_FILE_NAME`] into `workdir`.
+    pub fn write(&self, workdir: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let path = workdir.join(MANIFEST_FILE_NAME);
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+
+    /// The metadata of the directory of `run`, or of the context of `run` on `site`.
+    pub fn directory_metadata<'a>(
+        &'a self,
+        run: &'a str,
+        site: Option<&Site>,
+    ) -> DirectoryMetadata<'a> {
+        DirectoryMetadata {
+            pythia_version: self.pythia_version,
+            config_sha256: &self.config.sha256,
+            campaign_created_at: &self.created_at,
+            written_at: chrono::Utc::now().to_rfc3339(),
+            run,
+            site: site.map(|site| SiteMetadata {
+                id: site.id,
+                lon: site.lon.as_f64(),
+                lat: site.lat.as_f64(),
+                weight: site.weight,
+            }),
+            inputs: &self.inputs,
+        }
+    }
+}
+
+/// Which directories of a run get a [`DirectoryMetadata`] file.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataScope {
+    /// Every context directory, describing how its files were produced.
+    Context,
+    /// The run directory only.
+    Run,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SiteMetadata {
+    pub id: i32,
+    pub lon: f64,
+    pub lat: f64,
+    /// See [`Site::weight`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight: Option<f64>,
+}
+
+/// A small summary of the [`Manifest`], written as [`METADATA_FILE_NAME`] next to the files it describes, so a single
+/// context (or run) directory can be traced back to the campaign that produced it even when copied elsewhere.
+#[derive(Serialize, Debug)]
+pub struct DirectoryMetadata<'a> {
+    pub pythia_version: &'static str,
+    pub config_sha256: &'a str,
+    /// RFC 3339 UTC timestamps of the start of the campaign and of the metadata file itself.
+    pub campaign_created_at: &'a str,
+    pub written_at: String,
+    pub run: &'a str,
+    /// Not set in run directories.
+    pub site: Option<SiteMetadata>,
+    pub inputs: &'a [InputDigest],
+}
+
+impl DirectoryMetadata<'_> {
+    /// Writes the metadata as [`METADATA_FILE_NAME`] into `dir`.
+    pub fn write(&self, dir: &Path) -> std::io::Result<PathBuf> {
+        let path = dir.join(METADATA_FILE_NAME);
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_verify_inputs() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"pythia").unwrap();
+        let path = file.path().to_path_buf();
+
+        let digest = InputDigest::of(&path).unwrap();
+        assert_eq!(digest.size, 6);
+        assert_eq!(digest.sha256.len(), 64);
+
+        let ok = HashMap::from([(
+            path.clone(),
+            InputConfig {
+                sha256: Some(digest.sha256.to_uppercase()),
+                size: Some(6),
+                ..Default::default()
+            },
+        )]);
+        assert!(verify_inputs(&ok).is_ok());
+
+        let bad = HashMap::from([(
+            path.clone(),
+            InputConfig {
+                sha256: Some("0".repeat(64)),
+                size: Some(7),
+                ..Default::default()
+            },
+        )]);
+        assert_eq!(verify_inputs(&bad).unwrap_err().len(), 2);
+    }
+
+    #[test]
+    fn test_directory_metadata() {
+        let config = InputDigest {
+            path: PathBuf::from("config.json"),
+            size: 2,
+            sha256: "ab".repeat(32),
+        };
+        let manifest = Manifest::new(config, PathBuf::from("/tmp/wd"), false, vec![]);
+        let site = Site {
+            id: 3,
+            lon: crate::data::GeoDeg::from(1.5),
+            lat: crate::data::GeoDeg::from(-2.5),
+            covariates: Default::default(),
+            attributes: Default::default(),
+            weight: Some(0.75),
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = manifest
+            .directory_metadata("maize", Some(&site))
+            .write(dir.path())
+            .unwrap();
+        let written: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(written["run"], "maize");
+        assert_eq!(written["config_sha256"], "ab".repeat(32));
+        assert_eq!(written["site"]["id"], 3);
+        assert_eq!(written["site"]["lat"], -2.5);
+        assert_eq!(written["site"]["weight"], 0.75);
+
+        let run = manifest.directory_metadata("maize", None);
+        assert!(run.site.is_none());
+    }
+}
@@ -3,10 +3,12 @@ use thiserror::Error;
 
 #[derive(Debug, Clone, Error)]
 pub enum RegistryError {
-    #[error("Identifier {0} is already registered.")]
-    AlreadyRegistered(PublicIdentifier),
-    #[error("Namespace {0} is already claimed.")]
-    NamespaceAlreadyClaimed(Namespace),
+    /// The identifier, along with the claimant of its namespace.
+    #[error("Identifier {0} is already registered by {1}.")]
+    AlreadyRegistered(PublicIdentifier, Arc<Claimant>),
+    /// The namespace already claimed, along with the claimant that attempted to claim it again.
+    #[error("Namespace {0} is already claimed by {claimed}, {1} can't claim it.", claimed = .0.claimant())]
+    NamespaceAlreadyClaimed(Namespace, Arc<Claimant>),
     #[error("The provided name is empty or contains illegal characters. Only lowercase alphanumeric and dash characters are allowed.")]
     IllegalName(String),
 }
@@ -1,11 +1,26 @@
 use super::resources::*;
-use super::{Namespace, Registry};
+use super::{Claimant, Namespace, Registry};
+use crate::config::pipeline::StageDriverKind;
+use crate::harvest::collect::DRIVER_COLLECT;
+use crate::processing::context::Context;
+use crate::processing::pipeline::{Executed, Rendered, StageTypes};
+use crate::processing::processor::drivers::*;
+use crate::processing::sink::drivers::*;
+use crate::processing::template::drivers::*;
 use crate::sites::drivers::*;
 use std::error::Error;
 
 pub fn init_itself(registries: &mut super::Registries) -> Result<Namespace, Box<dyn Error>> {
-    let namespace = registries.claim_namespace("std")?;
+    let claimant = Claimant::new(
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        "Standard library of drivers embedded in the application",
+    );
+    let namespace = registries.claim_namespace("std", claimant)?;
     register_sitegen_drivers(&namespace, registries.regmut_sitegen_drivers())?;
+    register_pipeline_stages(&namespace, registries.regmut_pipeline_stages())?;
+    register_sinks(&namespace, registries.regmut_sinks())?;
+    register_template_engines(&namespace, registries.regmut_template_engines())?;
     Ok(namespace)
 }
 
@@ -13,17 +28,125 @@ fn register_sitegen_drivers(
     namespace: &Namespace,
     registry: &mut Registry<SiteGeneratorDriverResource>,
 ) -> Result<(), Box<dyn Error>> {
-    registry.register(
+    registry.register_described(
         &namespace,
         "vector",
+        "Sites at the features of a vector dataset read with GDAL (e.g. a shapefile or a GeoPackage)",
         SiteGeneratorDriverResource(DRIVER_VECTOR.clone().coerce_to_dynamic()),
     )?;
 
-    registry.register(
+    registry.register_described(
         &namespace,
         "raster",
+        "Sites at the pixels of a raster",
         SiteGeneratorDriverResource(DRIVER_RASTER.clone().coerce_to_dynamic()),
     )?;
 
+    registry.register_described(
+        &namespace,
+        "csv",
+        "Sites at the rows of a CSV file",
+        SiteGeneratorDriverResource(DRIVER_CSV.clone().coerce_to_dynamic()),
+    )?;
+
+    registry.register_described(
+        &namespace,
+        "geojson",
+        "Sites at the features of a GeoJSON file",
+        SiteGeneratorDriverResource(DRIVER_GEOJSON.clone().coerce_to_dynamic()),
+    )?;
+
+    Ok(())
+}
+
+fn register_pipeline_stages(
+    namespace: &Namespace,
+    registry: &mut Registry<StageDriverResource>,
+) -> Result<(), Box<dyn Error>> {
+    registry.register_described(
+        &namespace,
+        "render",
+        "Renders the templates of the runs into the context directories",
+        StageDriverResource(StageDriverKind::Processor(
+            DRIVER_RENDER.clone().coerce_to_dynamic(),
+            StageTypes::of::<Context, Rendered>(),
+        )),
+    )?;
+
+    registry.register_described(
+        &namespace,
+        "render-batched",
+        "Same as std:render, handling the runs of a site together",
+        StageDriverResource(StageDriverKind::Processor(
+            DRIVER_RENDER_BATCHED.clone().coerce_to_dynamic(),
+            StageTypes::of::<Context, Rendered>(),
+        )),
+    )?;
+
+    registry.register_described(
+        &namespace,
+        "rate-limit",
+        "Limits the rate contexts are passed on to the next stage at",
+        StageDriverResource(StageDriverKind::Processor(
+            DRIVER_RATE_LIMIT.clone().coerce_to_dynamic(),
+            StageTypes::Passthrough,
+        )),
+    )?;
+
+    registry.register_described(
+        &namespace,
+        "exec",
+        "Runs DSSAT in the context directories",
+        StageDriverResource(StageDriverKind::Processor(
+            DRIVER_EXEC.clone().coerce_to_dynamic(),
+            StageTypes::of::<Rendered, Executed>(),
+        )),
+    )?;
+
+    registry.register_described(
+        &namespace,
+        "copy",
+        "Copies the files of the context directories elsewhere, e.g. to a network filesystem",
+        StageDriverResource(StageDriverKind::Processor(
+            DRIVER_COPY.clone().coerce_to_dynamic(),
+            StageTypes::Passthrough,
+        )),
+    )?;
+
+    registry.register_described(
+        &namespace,
+        "collect",
+        "Harvests the outputs DSSAT wrote into the context directories into tables",
+        StageDriverResource(StageDriverKind::stage(DRIVER_COLLECT.clone())),
+    )?;
+
+    Ok(())
+}
+
+fn register_sinks(
+    namespace: &Namespace,
+    registry: &mut Registry<SinkDriverResource>,
+) -> Result<(), Box<dyn Error>> {
+    registry.register_described(
+        &namespace,
+        "csv",
+        "Writes the completed contexts of a run to a CSV file",
+        SinkDriverResource(DRIVER_CSV_SINK.clone().coerce_to_dynamic()),
+    )?;
+
+    Ok(())
+}
+
+fn register_template_engines(
+    namespace: &Namespace,
+    registry: &mut Registry<TemplateEngineDriverResource>,
+) -> Result<(), Box<dyn Error>> {
+    registry.register_described(
+        &namespace,
+        "tera",
+        "Renders the templates with Tera, along with the DSSAT filters and functions",
+        TemplateEngineDriverResource(DRIVER_TERA.clone().coerce_to_dynamic()),
+    )?;
+
     Ok(())
 }
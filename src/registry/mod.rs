@@ -18,8 +18,8 @@ use error::*;
 pub use identifier::{PublicIdentifier, PublicIdentifierSeed};
 pub use serialize::{ResourceSeed};
 use resources::*;
-use std::collections::HashSet;
-use std::sync::LazyLock;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock};
 
 /// Validates if the given string is a valid name/id for a [`Namespace`] or [`Identifier`].
 static RE_VALID_NAMESPACE_OR_ID: LazyLock<regex::Regex> =
@@ -49,6 +49,32 @@ pub static RE_VALID_NAMESPACE_AND_ID: LazyLock<regex::Regex> =
 #[derive(Debug, Hash, Clone, Eq, PartialEq)]
 pub struct Namespace {
     namespace: String,
+    claimant: Arc<Claimant>,
+}
+
+/// The plugin/extension that claimed a [`Namespace`], so the resources under it (and conflicts between them) can be
+/// traced back to it.
+#[derive(Debug, Hash, Clone, Eq, PartialEq)]
+pub struct Claimant {
+    pub name: String,
+    pub version: String,
+    pub description: String,
+}
+
+impl Claimant {
+    pub fn new(name: &str, version: &str, description: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            version: version.to_string(),
+            description: description.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for Claimant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.name, self.version)
+    }
 }
 
 impl Namespace {
@@ -62,6 +88,11 @@ impl Namespace {
     pub fn namespace(&self) -> &str {
         &self.namespace
     }
+
+    /// Gets the plugin/extension that claimed the namespace.
+    pub fn claimant(&self) -> &Claimant {
+        &self.claimant
+    }
 }
 
 impl std::fmt::Display for Namespace {
@@ -71,12 +102,14 @@ impl std::fmt::Display for Namespace {
 }
 
 /// Used to define valid resources that can be registered on the [`Registry`].
-/// Resources must be safe-[`Clone`]able.
-pub trait Resource: Sized + Clone {}
+/// Resources are stored behind an [`Arc`], so they are shared rather than cloned when looked up.
+pub trait Resource: Sized {}
 
 /// Stores [`Resource`]s, identified by [`Identifier`], and provides basic operations on them.
 pub struct Registry<T: Resource> {
-    map: K2HashMap<String, String, T>,
+    map: K2HashMap<String, String, Arc<T>>,
+    /// Short descriptions of the resources registered with one, see [`Registry::register_described`].
+    descriptions: HashMap<PublicIdentifier, String>,
 }
 
 impl<T: Resource> Registry<T> {
@@ -84,6 +117,7 @@ impl<T: Resource> Registry<T> {
     fn new() -> Self {
         Self {
             map: K2HashMap::new(),
+            descriptions: HashMap::new(),
         }
     }
 
@@ -106,17 +140,36 @@ impl<T: Resource> Registry<T> {
 
         let identifier = PublicIdentifier::new(namespace.namespace().to_string(), id.to_string());
         if self.is_registered(&identifier) {
-            return Err(RegistryError::AlreadyRegistered(identifier));
+            return Err(RegistryError::AlreadyRegistered(
+                identifier,
+                namespace.claimant.clone(),
+            ));
         }
 
         self.map.insert(
             namespace.namespace.clone(),
             id.to_string().clone(),
-            resource,
+            Arc::new(resource),
         );
         Ok(self)
     }
 
+    /// Same as [`Registry::register`], along with a short description of the resource for the config authors (see the
+    /// `list` subcommand).
+    #[allow(dead_code)]
+    pub fn register_described(
+        &mut self,
+        namespace: &Namespace,
+        id: &str,
+        description: &str,
+        resource: T,
+    ) -> Result<&mut Self, RegistryError> {
+        self.register(namespace, id, resource)?;
+        self.descriptions
+            .insert(namespace.id(id), description.to_string());
+        Ok(self)
+    }
+
     /// Checks if there is something registered under the given namespace and id.
     #[allow(dead_code)]
     pub fn is_registered(&self, identifier: &PublicIdentifier) -> bool {
@@ -125,8 +178,14 @@ impl<T: Resource> Registry<T> {
 
     /// Returns the [`Resource`] registered under the given namespace and id, if any.
     #[allow(dead_code)]
-    pub fn get(&self, identifier: &PublicIdentifier) -> Option<&T> {
-        self.map.get(&identifier.namespace, &identifier.id)
+    pub fn get(&self, identifier: &PublicIdentifier) -> Option<Arc<T>> {
+        self.map.get(&identifier.namespace, &identifier.id).cloned()
+    }
+
+    /// Returns the description the [`Resource`] under the given namespace and id was registered with, if any.
+    #[allow(dead_code)]
+    pub fn description(&self, identifier: &PublicIdentifier) -> Option<&str> {
+        self.descriptions.get(identifier).map(String::as_str)
     }
 
     /// Returns the [`Identifier`] of all registered [`Resource`]s.
@@ -141,7 +200,7 @@ impl<T: Resource> Registry<T> {
     /// Returns all registered [`Resource`]s.
     #[allow(dead_code)]
     pub fn resources(&self) -> Vec<&T> {
-        self.map.values().collect()
+        self.map.values().map(Arc::as_ref).collect()
     }
 
     /// Returns all registered [`Resource`]s and their [`Identifier`]s.
@@ -149,7 +208,7 @@ impl<T: Resource> Registry<T> {
     pub fn entries(&self) -> Vec<(PublicIdentifier, &T)> {
         self.map
             .iter()
-            .map(|(k1, k2, v)| (PublicIdentifier::new(k1.clone(), k2.clone()), v))
+            .map(|(k1, k2, v)| (PublicIdentifier::new(k1.clone(), k2.clone()), v.as_ref()))
             .collect()
     }
 
@@ -166,41 +225,69 @@ impl<T: Resource> Registry<T> {
 /// [`Registries`] must expose mutable and non-mutable access to the [`Registry`]s inside it via
 ///   functions like [`Registries::regmut_sitegen_drivers`] (``&mut``) and [`Registries::reg_sitegen_drivers`] (``&``).
 pub struct Registries {
-    namespaces: HashSet<Namespace>,
+    namespaces: HashMap<String, Namespace>,
     reg_sitegen_drivers: Registry<SiteGeneratorDriverResource>,
+    reg_processor_drivers: Registry<ProcessorDriverResource>,
+    reg_pipeline_stages: Registry<StageDriverResource>,
+    reg_sinks: Registry<SinkDriverResource>,
+    reg_template_engines: Registry<TemplateEngineDriverResource>,
+    reg_context_providers: Registry<ContextProviderResource>,
+    reg_template_extensions: Registry<TemplateExtensionResource>,
 }
 
 impl Registries {
     /// Creates a new instance.
     pub fn new() -> Self {
         Self {
-            namespaces: HashSet::new(),
+            namespaces: HashMap::new(),
             reg_sitegen_drivers: Registry::new(),
+            reg_processor_drivers: Registry::new(),
+            reg_pipeline_stages: Registry::new(),
+            reg_sinks: Registry::new(),
+            reg_template_engines: Registry::new(),
+            reg_context_providers: Registry::new(),
+            reg_template_extensions: Registry::new(),
         }
     }
 
-    /// Claims a [`Namespace`] for the given `namespace` string.
+    /// Claims a [`Namespace`] for the given `namespace` string on behalf of `claimant`.
     ///
     /// Namespaces are supposed to be claimed only once per plugin/extension.
     /// For instance, the embedded module will claim the `std` namespace upon application startup.
     /// Plugins that wish to extend the functionality and register their own [`Resource`]s will be provided with a namespace for themselves
     /// and shall it to register all of their [`Resource`]s.
-    pub fn claim_namespace(&mut self, namespace: &'static str) -> Result<Namespace, RegistryError> {
+    pub fn claim_namespace(
+        &mut self,
+        namespace: &'static str,
+        claimant: Claimant,
+    ) -> Result<Namespace, RegistryError> {
         if !RE_VALID_NAMESPACE_OR_ID.is_match(namespace) {
             return Err(RegistryError::IllegalName(namespace.to_string()));
         }
 
+        if let Some(claimed) = self.namespaces.get(namespace) {
+            return Err(RegistryError::NamespaceAlreadyClaimed(
+                claimed.clone(),
+                Arc::new(claimant),
+            ));
+        }
+
         let namespace = Namespace {
             namespace: namespace.to_string(),
+            claimant: Arc::new(claimant),
         };
-        if self.namespaces.contains(&namespace) {
-            return Err(RegistryError::NamespaceAlreadyClaimed(namespace));
-        }
-
-        self.namespaces.insert(namespace.clone());
+        self.namespaces
+            .insert(namespace.namespace.clone(), namespace.clone());
         Ok(namespace)
     }
 
+    /// Returns the claimed [`Namespace`]s, sorted by name.
+    pub fn namespaces(&self) -> Vec<&Namespace> {
+        let mut namespaces: Vec<_> = self.namespaces.values().collect();
+        namespaces.sort_by(|a, b| a.namespace.cmp(&b.namespace));
+        namespaces
+    }
+
     pub fn reg_sitegen_drivers(&self) -> &Registry<SiteGeneratorDriverResource> {
         &self.reg_sitegen_drivers
     }
@@ -208,15 +295,70 @@ impl Registries {
     pub fn regmut_sitegen_drivers(&mut self) -> &mut Registry<SiteGeneratorDriverResource> {
         &mut self.reg_sitegen_drivers
     }
+
+    pub fn reg_processor_drivers(&self) -> &Registry<ProcessorDriverResource> {
+        &self.reg_processor_drivers
+    }
+
+    #[allow(dead_code)] // Only plugins register these, the standard ones are pipeline stages.
+    pub fn regmut_processor_drivers(&mut self) -> &mut Registry<ProcessorDriverResource> {
+        &mut self.reg_processor_drivers
+    }
+
+    pub fn reg_pipeline_stages(&self) -> &Registry<StageDriverResource> {
+        &self.reg_pipeline_stages
+    }
+
+    pub fn regmut_pipeline_stages(&mut self) -> &mut Registry<StageDriverResource> {
+        &mut self.reg_pipeline_stages
+    }
+
+    pub fn reg_sinks(&self) -> &Registry<SinkDriverResource> {
+        &self.reg_sinks
+    }
+
+    pub fn regmut_sinks(&mut self) -> &mut Registry<SinkDriverResource> {
+        &mut self.reg_sinks
+    }
+
+    pub fn reg_template_engines(&self) -> &Registry<TemplateEngineDriverResource> {
+        &self.reg_template_engines
+    }
+
+    pub fn regmut_template_engines(&mut self) -> &mut Registry<TemplateEngineDriverResource> {
+        &mut self.reg_template_engines
+    }
+
+    pub fn reg_context_providers(&self) -> &Registry<ContextProviderResource> {
+        &self.reg_context_providers
+    }
+
+    #[allow(dead_code)] // Only plugins register these.
+    pub fn regmut_context_providers(&mut self) -> &mut Registry<ContextProviderResource> {
+        &mut self.reg_context_providers
+    }
+
+    pub fn reg_template_extensions(&self) -> &Registry<TemplateExtensionResource> {
+        &self.reg_template_extensions
+    }
+
+    #[allow(dead_code)] // Only plugins register these.
+    pub fn regmut_template_extensions(&mut self) -> &mut Registry<TemplateExtensionResource> {
+        &mut self.reg_template_extensions
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn claimant() -> Claimant {
+        Claimant::new("foo-plugin", "1.0.0", "Foo resources")
+    }
+
     #[test]
     fn namespace() {
-        match Registries::new().claim_namespace("foo") {
+        match Registries::new().claim_namespace("foo", claimant()) {
             Ok(ns) => assert_eq!(ns.namespace, "foo"),
             Err(_) => panic!("Expected to claim the namespace"),
         }
@@ -224,7 +366,7 @@ mod tests {
 
     #[test]
     fn invalid_namespace() {
-        match Registries::new().claim_namespace("inv@lid") {
+        match Registries::new().claim_namespace("inv@lid", claimant()) {
             Ok(_) => panic!("Expected to disallow namespaces with invalid characters"),
             Err(_) => {}
         }
@@ -233,19 +375,24 @@ mod tests {
     #[test]
     fn dupe_namespace() {
         let mut registries = Registries::new();
-        let namespace = registries.claim_namespace("foo").unwrap();
+        let namespace = registries.claim_namespace("foo", claimant()).unwrap();
         assert_eq!(namespace.namespace, "foo");
 
-        match registries.claim_namespace("foo") {
+        let bar = Claimant::new("bar-plugin", "0.2.0", "Bar resources");
+        match registries.claim_namespace("foo", bar) {
             Ok(_) => panic!("Expected to disallow claiming duplicate namespace"),
-            Err(_) => {}
+            Err(err) => assert_eq!(
+                err.to_string(),
+                "Namespace foo is already claimed by foo-plugin 1.0.0, bar-plugin 0.2.0 can't claim it."
+            ),
         }
+        assert_eq!(registries.namespaces(), vec![&namespace]);
     }
 
     #[test]
     fn identifier() {
         let mut registries = Registries::new();
-        let namespace = registries.claim_namespace("foo").unwrap();
+        let namespace = registries.claim_namespace("foo", claimant()).unwrap();
         assert_eq!(namespace.id("bar").to_string(), "foo:bar");
     }
 
@@ -257,6 +404,7 @@ mod tests {
     fn registry_invalid_id() {
         let namespace = Namespace {
             namespace: "foo".to_string(),
+            claimant: Arc::new(claimant()),
         };
         let mut reg: Registry<DummyResource> = Registry::new();
         match reg.register(&namespace, "inv@lid", DummyResource.into()) {
@@ -269,6 +417,7 @@ mod tests {
     fn register() {
         let namespace = Namespace {
             namespace: "foo".to_string(),
+            claimant: Arc::new(claimant()),
         };
         let mut reg: Registry<DummyResource> = Registry::new();
         let id = namespace.id("bar");
@@ -277,8 +426,7 @@ mod tests {
 
         match reg.get(&id) {
             Some(res) => assert_eq!(
-                res,
-                &DummyResource.into(),
+                *res, DummyResource,
                 "Registered and retrieved resources do not match"
             ),
             None => panic!("Expected to find resource"),
@@ -292,5 +440,13 @@ mod tests {
         assert_eq!(reg.resources(), vec![&DummyResource.into()]);
         assert_eq!(reg.entries(), vec![(id, &DummyResource.into())]);
         assert_eq!(reg.len(), 1);
+
+        match reg.register(&namespace, "bar", DummyResource) {
+            Ok(_) => panic!("Expected to disallow registering a duplicate id"),
+            Err(err) => assert_eq!(
+                err.to_string(),
+                "Identifier foo:bar is already registered by foo-plugin 1.0.0."
+            ),
+        }
     }
 }
@@ -1,3 +1,10 @@
+use crate::config::pipeline::StageDriverKind;
+use crate::processing::context::provider::{ContextProvider, ContextProviderDriver};
+use crate::processing::context::Context;
+use crate::processing::processor::{Processor, ProcessorDriver};
+use crate::processing::sink::{Sink, SinkDriver};
+use crate::processing::template::extensions::TemplateExtension;
+use crate::processing::template::{Engine, EngineDriver};
 use crate::registry::Resource;
 use crate::sites::SiteGenerator;
 use crate::sites::SiteGeneratorDriver;
@@ -9,3 +16,37 @@ pub struct SiteGeneratorDriverResource(
 );
 
 impl Resource for SiteGeneratorDriverResource {}
+
+#[derive(Clone)]
+pub struct ProcessorDriverResource(
+    pub ProcessorDriver<Box<dyn Processor<Output = Context>>, Box<dyn Any>>,
+);
+
+impl Resource for ProcessorDriverResource {}
+
+#[derive(Clone)]
+pub struct StageDriverResource(pub StageDriverKind);
+
+impl Resource for StageDriverResource {}
+
+#[derive(Clone)]
+pub struct SinkDriverResource(pub SinkDriver<Box<dyn Sink>, Box<dyn Any>>);
+
+impl Resource for SinkDriverResource {}
+
+#[derive(Clone)]
+pub struct TemplateEngineDriverResource(pub EngineDriver<Box<dyn Engine>, Box<dyn Any>>);
+
+impl Resource for TemplateEngineDriverResource {}
+
+#[derive(Clone)]
+pub struct ContextProviderResource(
+    pub ContextProviderDriver<Box<dyn ContextProvider>, Box<dyn Any>>,
+);
+
+impl Resource for ContextProviderResource {}
+
+#[derive(Clone)]
+pub struct TemplateExtensionResource(pub TemplateExtension);
+
+impl Resource for TemplateExtensionResource {}
@@ -1,29 +1,36 @@
 use super::{PublicIdentifierSeed, Registry, Resource};
 use serde::de::DeserializeSeed;
 use serde::Deserializer;
+use std::sync::Arc;
 
 /// Used to deserialize a [`Resource`] from a string.
 /// This deserializer delegates the deserialization of registry identifiers to
 ///  the [`PublicIdentifierSeed`] deserializer.
-#[derive(Clone)]
 pub struct ResourceSeed<'a, T: Resource> {
     pub registry: &'a Registry<T>,
     pub id_seed: PublicIdentifierSeed,
 }
 
+// Not derived, as that would require the resources to be `Clone` too.
+impl<T: Resource> Clone for ResourceSeed<'_, T> {
+    fn clone(&self) -> Self {
+        Self {
+            registry: self.registry,
+            id_seed: self.id_seed.clone(),
+        }
+    }
+}
+
 impl<'de, T: Resource + 'de> DeserializeSeed<'de> for ResourceSeed<'de, T> {
-    type Value = T;
+    type Value = Arc<T>;
 
     /// Deserializes a [`Resource`] from a string.
     /// The string is expected to be a valid [`super::PublicIdentifier`] under the given [`Registry`].
     ///
-    /// The deserialized value is a **clone** of the [`Resource`] under the given [`Registry`].
+    /// The deserialized value is the [`Resource`] under the given [`Registry`], shared with it.
     ///
     /// # Errors
     /// This function fails if the string is not a valid [`PublicIdentifier`] or if the [`Resource`] is not registered under the given [`Registry`].
-    ///
-    /// # TODO
-    /// Maybe it's a good idea to make the Registry store ``Rc<T>`` (or ``Arc<T>``) instead of ``T``, so we avoid cloning the resource.
     fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
     where
         D: Deserializer<'de>,
@@ -35,6 +42,6 @@ impl<'de, T: Resource + 'de> DeserializeSeed<'de> for ResourceSeed<'de, T> {
                 id
             ))
         })?;
-        Ok(res.clone())
+        Ok(res)
     }
 }
@@ -0,0 +1,271 @@
+//! Seasonal and sequential (crop rotation) DSSAT simulation setups.
+
+use crate::processing::context::{ContextValue, PrimitiveContextValue};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use validator::ValidationError;
+
+static ERRCODE_SIMULATION_INVALID: &str = "ERRCODE_SIMULATION_INVALID";
+
+/// Factor level columns of the `*TREATMENTS` section, in order, with the level used when a step doesn't set them.
+const FACTORS: [(&str, i64); 13] = [
+    ("cu", 1),
+    ("fl", 1),
+    ("sa", 0),
+    ("ic", 1),
+    ("mp", 1),
+    ("mi", 0),
+    ("mf", 0),
+    ("mr", 0),
+    ("mc", 0),
+    ("mt", 0),
+    ("me", 0),
+    ("mh", 0),
+    ("sm", 1),
+];
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SimulationMode {
+    /// Every step is an independent treatment, simulated from its own initial conditions.
+    #[default]
+    Seasonal,
+    /// The steps are the components of a crop rotation, simulated one after the other.
+    Sequential,
+}
+
+/// A treatment of the simulation: a season in seasonal mode, or a component of the rotation in sequential mode.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SimulationStep {
+    /// Treatment name (`TNAME`), at most 25 characters.
+    pub name: String,
+
+    /// Factor levels (`cu`, `fl`, `sa`, `ic`, `mp`, `mi`, `mf`, `mr`, `mc`, `mt`, `me`, `mh`, `sm`) of the step.
+    /// Unset levels default to 1 for `cu`, `fl`, `mp` and `sm`, 1 for `ic` unless the soil state is carried over, and 0 otherwise.
+    #[serde(default)]
+    pub factors: HashMap<String, i64>,
+
+    /// Any other field of the step (e.g. `crop`, `ingeno`, `pdate`), passed to templates as is.
+    #[serde(flatten)]
+    pub extra: HashMap<String, PrimitiveContextValue>,
+}
+
+/// Simulation setup of a run, exposed to templates as `simulation_mode` (`seasonal` or `sequential`), `nyers`,
+/// the list `treatments` (one record per step, with the fields `n`, `r`, `o`, `c`, `tname`, every factor level and
+/// the extra fields of the step) and `treatments_block`, the whole `*TREATMENTS` section ready to be pasted in the template.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SimulationConfig {
+    #[serde(default)]
+    pub mode: SimulationMode,
+
+    /// Number of years simulated (`NYERS`). In sequential mode, the rotation is repeated over them.
+    #[serde(default = "default_years")]
+    pub years: u32,
+
+    /// In sequential mode, every component after the first starts from the soil state left by the previous one
+    /// (initial conditions level `ic` 0) instead of being reinitialized.
+    #[serde(default = "default_carry_soil_state")]
+    pub carry_soil_state: bool,
+
+    pub steps: Vec<SimulationStep>,
+}
+
+fn default_years() -> u32 {
+    1
+}
+
+fn default_carry_soil_state() -> bool {
+    true
+}
+
+pub fn validate_simulation(simulation: &SimulationConfig) -> Result<(), ValidationError> {
+    let invalid = |msg: String| {
+        Err(ValidationError::new(ERRCODE_SIMULATION_INVALID).with_message(Cow::from(msg)))
+    };
+
+    if simulation.steps.is_empty() {
+        return invalid("Simulation has no steps".to_string());
+    }
+    if simulation.years == 0 {
+        return invalid("Simulation must last at least one year".to_string());
+    }
+    for step in &simulation.steps {
+        if step.name.len() > 25 {
+            return invalid(format!(
+                "Simulation step name {} is longer than 25 characters",
+                step.name
+            ));
+        }
+        if let Some(factor) = step
+            .factors
+            .keys()
+            .find(|k| !FACTORS.iter().any(|(f, _)| *f == k.as_str()))
+        {
+            return invalid(format!(
+                "Simulation step {} has an unknown factor {}",
+                step.name, factor
+            ));
+        }
+    }
+    Ok(())
+}
+
+impl SimulationConfig {
+    pub fn is_sequential(&self) -> bool {
+        self.mode == SimulationMode::Sequential
+    }
+
+    /// One record per step, as listed in the `*TREATMENTS` section.
+    fn treatments(&self) -> Vec<HashMap<String, PrimitiveContextValue>> {
+        use PrimitiveContextValue::{Int, String as Str};
+
+        self.steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| {
+                let n = i as i64 + 1;
+                let carried = self.is_sequential() && self.carry_soil_state && i > 0;
+
+                let mut record = step.extra.clone();
+                for (factor, default) in FACTORS {
+                    let default = if factor == "ic" && carried {
+                        0
+                    } else {
+                        default
+                    };
+                    let level = step.factors.get(factor).copied().unwrap_or(default);
+                    record.insert(factor.to_string(), Int(level));
+                }
+                record.insert("n".to_string(), Int(n));
+                record.insert(
+                    "r".to_string(),
+                    Int(if self.is_sequential() { n } else { 1 }),
+                );
+                record.insert("o".to_string(), Int(0));
+                record.insert("c".to_string(), Int(0));
+                record.insert("tname".to_string(), Str(step.name.clone()));
+                record
+            })
+            .collect()
+    }
+
+    /// The variables of the simulation, which are the same for every site.
+    pub fn values(&self) -> Vec<(String, ContextValue)> {
+        let mode = match self.mode {
+            SimulationMode::Seasonal => "seasonal",
+            SimulationMode::Sequential => "sequential",
+        };
+        let treatments = self.treatments();
+        vec![
+            (
+                "simulation_mode".to_string(),
+                ContextValue::Prim(PrimitiveContextValue::String(mode.to_string())),
+            ),
+            (
+                "nyers".to_string(),
+                ContextValue::Prim(PrimitiveContextValue::Int(self.years as i64)),
+            ),
+            (
+                "treatments_block".to_string(),
+                ContextValue::Prim(PrimitiveContextValue::String(treatments_block(&treatments))),
+            ),
+            ("treatments".to_string(), ContextValue::Records(treatments)),
+        ]
+    }
+}
+
+/// Formats the `*TREATMENTS` section of `treatments` (see [`SimulationConfig::treatments`]).
+fn treatments_block(treatments: &[HashMap<String, PrimitiveContextValue>]) -> String {
+    let int = |record: &HashMap<String, PrimitiveContextValue>, k: &str| match record.get(k) {
+        Some(PrimitiveContextValue::Int(i)) => *i,
+        _ => 0,
+    };
+
+    let mut block = String::from(
+        "*TREATMENTS                        -------------FACTOR LEVELS------------\n\
+         @N R O C TNAME.................... CU FL SA IC MP MI MF MR MC MT ME MH SM\n",
+    );
+    for record in treatments {
+        let tname = record
+            .get("tname")
+            .map(|t| t.as_string())
+            .unwrap_or_default();
+        block.push_str(&format!(
+            "{:>2}{:>2}{:>2}{:>2} {:<25}",
+            int(record, "n"),
+            int(record, "r"),
+            int(record, "o"),
+            int(record, "c"),
+            tname
+        ));
+        for (factor, _) in FACTORS {
+            block.push_str(&format!("{:>3}", int(record, factor)));
+        }
+        block.push('\n');
+    }
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(mode: &str) -> SimulationConfig {
+        serde_json::from_str(&format!(
+            r#"{{
+                "mode": "{}",
+                "years": 4,
+                "steps": [
+                    {{ "name": "Maize", "crop": "MZ", "factors": {{ "mp": 1 }} }},
+                    {{ "name": "Soybean", "crop": "SB", "factors": {{ "cu": 2, "mp": 2 }} }}
+                ]
+            }}"#,
+            mode
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_treatments() {
+        let seasonal = config("seasonal").treatments();
+        assert_eq!(seasonal[1]["r"], PrimitiveContextValue::Int(1));
+        assert_eq!(seasonal[1]["ic"], PrimitiveContextValue::Int(1));
+        assert_eq!(seasonal[1]["cu"], PrimitiveContextValue::Int(2));
+        assert_eq!(
+            seasonal[1]["crop"],
+            PrimitiveContextValue::String("SB".to_string())
+        );
+
+        let sequential = config("sequential").treatments();
+        assert_eq!(sequential[0]["ic"], PrimitiveContextValue::Int(1));
+        assert_eq!(sequential[1]["r"], PrimitiveContextValue::Int(2));
+        assert_eq!(sequential[1]["ic"], PrimitiveContextValue::Int(0));
+    }
+
+    #[test]
+    fn test_treatments_block() {
+        let block = treatments_block(&config("sequential").treatments());
+        let lines: Vec<&str> = block.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[3],
+            " 2 2 0 0 Soybean                    2  1  0  0  2  0  0  0  0  0  0  0  1"
+        );
+        assert_eq!(crate::batch::treatments(&block).len(), 2);
+    }
+
+    #[test]
+    fn test_validate_simulation() {
+        assert!(validate_simulation(&config("seasonal")).is_ok());
+
+        let mut invalid = config("seasonal");
+        invalid.steps[0].factors.insert("xx".to_string(), 1);
+        assert!(validate_simulation(&invalid).is_err());
+
+        let mut invalid = config("seasonal");
+        invalid.steps.clear();
+        assert!(validate_simulation(&invalid).is_err());
+    }
+}
@@ -0,0 +1,253 @@
+//! Caching of the realized site list of a site source, so later runs don't have to read (and filter, and sample) the
+//! datasets again.
+//!
+//! The cache is a CSV file with the columns `id`, `lon`, `lat`, `weight` (empty if the site has none), `covariates`,
+//! holding the covariates of the site as `name=value` pairs separated by `;`, and `attributes`, holding the attributes
+//! of the site as a JSON object (empty if the site has none). It is only written once the whole site source has been iterated,
+//! so an interrupted run never leaves a partial cache behind. Delete the file to refresh it.
+
+use super::{Site, SiteGenError, SiteGenerator};
+use crate::data::GeoDeg;
+use csv::{StringRecord, StringRecordsIntoIter, Writer};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+
+const HEADER: [&str; 6] = ["id", "lon", "lat", "weight", "covariates", "attributes"];
+
+/// Streams the sites of a cache file.
+pub struct CachedSiteGenerator {
+    records: StringRecordsIntoIter<File>,
+    row: usize,
+}
+
+impl CachedSiteGenerator {
+    pub fn open(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut reader = csv::Reader::from_path(path)?;
+        if reader.headers()? != &StringRecord::from(HEADER.to_vec()) {
+            return Err(format!(
+                "{} is not a site cache (expected the columns {})",
+                path.display(),
+                HEADER.join(", ")
+            )
+            .into());
+        }
+        Ok(CachedSiteGenerator {
+            records: reader.into_records(),
+            row: 0,
+        })
+    }
+
+    /// Counts the sites of the cache file at `path`.
+    pub fn count_sites(path: &Path) -> Option<usize> {
+        Some(csv::Reader::from_path(path).ok()?.into_records().count())
+    }
+}
+
+fn record_to_site(record: &StringRecord, row: usize) -> Result<Site, SiteGenError> {
+    let invalid = |what: &str, value: &str| SiteGenError::Row {
+        row,
+        message: format!("Invalid {} \"{}\" in the site cache", what, value),
+    };
+    let get = |idx: usize| record.get(idx).unwrap_or_default();
+
+    let id = get(0).parse().map_err(|_| invalid("site ID", get(0)))?;
+    let lon: f64 = get(1).parse().map_err(|_| invalid("longitude", get(1)))?;
+    let lat: f64 = get(2).parse().map_err(|_| invalid("latitude", get(2)))?;
+    let weight = match get(3) {
+        "" => None,
+        weight => Some(weight.parse().map_err(|_| invalid("weight", weight))?),
+    };
+    let covariates = get(4)
+        .split(';')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair
+                .split_once('=')
+                .ok_or_else(|| invalid("covariate", pair))?;
+            let value = value.parse().map_err(|_| invalid("covariate", pair))?;
+            Ok((name.to_string(), value))
+        })
+        .collect::<Result<HashMap<_, _>, SiteGenError>>()?;
+    let attributes = match get(5) {
+        "" => HashMap::new(),
+        attributes => {
+            serde_json::from_str(attributes).map_err(|_| invalid("attributes", attributes))?
+        }
+    };
+
+    Ok(Site {
+        id,
+        lon: GeoDeg::from(lon),
+        lat: GeoDeg::from(lat),
+        covariates,
+        attributes,
+        weight,
+    })
+}
+
+impl Iterator for CachedSiteGenerator {
+    type Item = Result<Site, SiteGenError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = match self.records.next()? {
+            Ok(record) => record,
+            Err(e) => return Some(Err(e.into())),
+        };
+        let row = self.row;
+        self.row += 1;
+        Some(record_to_site(&record, row))
+    }
+
+    /// Skips `n` rows without parsing their fields.
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        for _ in 0..n {
+            if self.records.next()?.is_ok() {
+                self.row += 1;
+            }
+        }
+        self.next()
+    }
+}
+
+/// Passes the sites of a source through, writing them to a cache file that is put in place once the source is
+/// exhausted. If writing fails, the error is yielded once and the sites keep flowing without being cached.
+pub struct CachingSiteGenerator {
+    inner: Box<dyn SiteGenerator>,
+    path: PathBuf,
+    writer: Option<Writer<NamedTempFile>>,
+}
+
+impl CachingSiteGenerator {
+    pub fn new(inner: Box<dyn SiteGenerator>, path: &Path) -> Result<Self, std::io::Error> {
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        std::fs::create_dir_all(dir)?;
+        let mut writer = Writer::from_writer(NamedTempFile::new_in(dir)?);
+        writer.write_record(HEADER)?;
+        Ok(CachingSiteGenerator {
+            inner,
+            path: path.to_path_buf(),
+            writer: Some(writer),
+        })
+    }
+
+    fn write(&mut self, site: &Site) -> Result<(), csv::Error> {
+        let Some(writer) = self.writer.as_mut() else {
+            return Ok(());
+        };
+
+        let mut covariates: Vec<_> = site.covariates.iter().collect();
+        covariates.sort_by(|a, b| a.0.cmp(b.0));
+        let covariates = covariates
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join(";");
+        let attributes = if site.attributes.is_empty() {
+            String::new()
+        } else {
+            let attributes: BTreeMap<_, _> = site.attributes.iter().collect();
+            serde_json::to_string(&attributes).unwrap()
+        };
+        writer.write_record([
+            site.id.to_string(),
+            site.lon.to_string(),
+            site.lat.to_string(),
+            site.weight.map(|w| w.to_string()).unwrap_or_default(),
+            covariates,
+            attributes,
+        ])
+    }
+
+    /// Moves the complete cache into place.
+    fn persist(&mut self) -> Result<(), csv::Error> {
+        let Some(writer) = self.writer.take() else {
+            return Ok(());
+        };
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        file.persist(&self.path).map_err(|e| e.error)?;
+        Ok(())
+    }
+
+    fn cache_error(&mut self, e: csv::Error) -> SiteGenError {
+        self.writer = None;
+        SiteGenError::Cache(self.path.clone(), e)
+    }
+}
+
+impl Iterator for CachingSiteGenerator {
+    type Item = Result<Site, SiteGenError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.inner.next() {
+            Some(Ok(site)) => match self.write(&site) {
+                Ok(()) => Some(Ok(site)),
+                Err(e) => {
+                    // The site itself is fine, so it is yielded right after the error.
+                    let error = self.cache_error(e);
+                    self.inner = Box::new(std::iter::once(Ok(site)).chain(std::mem::replace(
+                        &mut self.inner,
+                        Box::new(std::iter::empty()),
+                    )));
+                    Some(Err(error))
+                }
+            },
+            Some(Err(e)) => Some(Err(e)),
+            None => match self.persist() {
+                Ok(()) => None,
+                Err(e) => Some(Err(self.cache_error(e))),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_site_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache").join("sites.csv");
+        let sites = vec![
+            Site {
+                id: 1,
+                lon: GeoDeg::from(12.5418),
+                lat: GeoDeg::from(-14.875),
+                covariates: [("soil_depth".to_string(), 1.5), ("zone".to_string(), 2.0)].into(),
+                attributes: [("region".to_string(), "Sahel; \"north\"".to_string())].into(),
+                weight: Some(12.5),
+            },
+            Site {
+                id: 2,
+                lon: GeoDeg::from(12.2919),
+                lat: GeoDeg::from(14.7917),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
+            },
+        ];
+
+        let source: Box<dyn SiteGenerator> = Box::new(sites.clone().into_iter().map(Ok));
+        let mut caching = CachingSiteGenerator::new(source, &path).unwrap();
+        assert!(caching.next().unwrap().is_ok());
+        // Nothing is written until the source is exhausted.
+        assert!(!path.exists());
+        assert!(caching.all(|site| site.is_ok()));
+        assert!(path.exists());
+        // The coordinates are written with the full precision of GeoDeg.
+        assert!(std::fs::read_to_string(&path)
+            .unwrap()
+            .contains("1,12.54180,-14.87500,12.5,"));
+
+        let cached: Vec<Site> = CachedSiteGenerator::open(&path)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(cached, sites);
+    }
+}
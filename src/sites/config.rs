@@ -1,4 +1,4 @@
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
 use serde_inline_default::serde_inline_default;
 use std::fmt::Debug;
 use validator::Validate;
@@ -12,9 +12,51 @@ pub struct VectorSiteGeneratorConfig {
     #[validate(length(min = 1, message = "Vector file path cannot be empty"))]
     pub file: String,
 
+    /// Name of the only layer to read sites from. If not set, sites are read from every layer.
+    #[serde(default)]
+    pub layer: Option<String>,
+
     #[serde_inline_default("ID".to_string())]
     #[validate(length(min = 1, message = "Site ID key cannot be empty"))]
     pub site_id_key: String,
+
+    /// Numeric field the weight of every site (e.g. harvested area) is read from. See [`crate::sites::Site::weight`].
+    #[serde(default)]
+    pub weight_key: Option<String>,
+}
+
+#[serde_inline_default]
+#[derive(Validate, Deserialize, Clone, Debug)]
+pub struct CsvSiteGeneratorConfig {
+    #[validate(length(min = 1, message = "CSV file path cannot be empty"))]
+    pub file: String,
+
+    #[serde_inline_default("lat".to_string())]
+    #[validate(length(min = 1, message = "Latitude column cannot be empty"))]
+    pub lat_column: String,
+
+    #[serde_inline_default("lon".to_string())]
+    #[validate(length(min = 1, message = "Longitude column cannot be empty"))]
+    pub lon_column: String,
+
+    #[serde_inline_default("id".to_string())]
+    #[validate(length(min = 1, message = "Site ID column cannot be empty"))]
+    pub site_id_column: String,
+
+    /// Columns read as text attributes of the sites. See [`crate::sites::Site::attributes`].
+    #[serde(default)]
+    pub attribute_columns: Vec<String>,
+}
+
+#[serde_inline_default]
+#[derive(Validate, Deserialize, Clone, Debug)]
+pub struct GeoJsonSiteGeneratorConfig {
+    #[validate(length(min = 1, message = "GeoJSON file path cannot be empty"))]
+    pub file: String,
+
+    #[serde_inline_default("id".to_string())]
+    #[validate(length(min = 1, message = "Site ID property cannot be empty"))]
+    pub site_id_property: String,
 }
 
 #[serde_inline_default]
@@ -24,4 +66,74 @@ pub struct RasterSiteGeneratorConfig {
 
     #[serde_inline_default(0)]
     pub layer_index: usize,
+
+    /// Pixel values to be skipped in addition to the band's own nodata value. Accepts numbers and `"nan"`.
+    #[serde(default, deserialize_with = "deserialize_nodata_values")]
+    pub nodata: Vec<f64>,
+
+    /// Explicitly treats (or never treats) zero as nodata. If not set, zero is only treated as nodata when
+    /// the band has no nodata value and no `nodata` values are given.
+    #[serde(default)]
+    pub treat_zero_as_nodata: Option<bool>,
+
+    /// Extra bands whose values are attached to every site, and exposed to templates by name (e.g. `${soil_depth}`).
+    #[serde(default)]
+    #[validate(nested)]
+    pub covariates: Vec<RasterCovariateConfig>,
+
+    /// Band the weight of every site (e.g. harvested area) is read from. See [`crate::sites::Site::weight`].
+    #[serde(default)]
+    pub weight: Option<RasterWeightConfig>,
+}
+
+#[derive(Validate, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RasterCovariateConfig {
+    #[validate(length(min = 1, message = "Covariate name cannot be empty"))]
+    pub name: String,
+
+    /// Raster to read the band from. If not set, it is read from the raster sites are read from.
+    #[serde(default)]
+    pub file: Option<String>,
+
+    /// **ZERO-BASED** index of the band, like `layer_index`.
+    #[serde(default)]
+    pub layer_index: usize,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RasterWeightConfig {
+    /// Raster to read the band from. If not set, it is read from the raster sites are read from.
+    #[serde(default)]
+    pub file: Option<String>,
+
+    /// **ZERO-BASED** index of the band, like `layer_index`.
+    #[serde(default)]
+    pub layer_index: usize,
+}
+
+/// JSON has no representation for NaN, so it is accepted as the string `"nan"` (case-insensitive).
+fn deserialize_nodata_values<'de, D>(deserializer: D) -> Result<Vec<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NoDataValue {
+        Number(f64),
+        Keyword(String),
+    }
+
+    Vec::<NoDataValue>::deserialize(deserializer)?
+        .into_iter()
+        .map(|v| match v {
+            NoDataValue::Number(n) => Ok(n),
+            NoDataValue::Keyword(k) if k.eq_ignore_ascii_case("nan") => Ok(f64::NAN),
+            NoDataValue::Keyword(k) => Err(serde::de::Error::custom(format!(
+                "Invalid nodata value \"{}\". Expected a number or \"nan\".",
+                k
+            ))),
+        })
+        .collect()
 }
@@ -7,16 +7,110 @@ pub const DRIVER_VECTOR: LazyLock<
     SiteGeneratorDriver<VectorSiteGenerator, VectorSiteGeneratorConfig>,
 > = LazyLock::new(|| SiteGeneratorDriver {
     create: Arc::new(|c: VectorSiteGeneratorConfig| {
-        VectorSiteGenerator::new(c.file.as_str(), c.site_id_key)
+        Ok(VectorSiteGenerator::new(c.file.as_str(), c.site_id_key)?
+            .with_layer(c.layer.as_deref())?
+            .with_weight_key(c.weight_key))
     }),
     config_deserializer: Arc::new(serde_json::from_value),
+    preflight: Arc::new(|c: &VectorSiteGeneratorConfig| {
+        VectorSiteGenerator::preflight(
+            c.file.as_str(),
+            c.layer.as_deref(),
+            c.site_id_key.as_str(),
+            c.weight_key.as_deref(),
+        )
+    }),
+    count: Arc::new(|c: &VectorSiteGeneratorConfig| {
+        VectorSiteGenerator::count_sites(c.file.as_str(), c.layer.as_deref())
+    }),
 });
 
 pub const DRIVER_RASTER: LazyLock<
     SiteGeneratorDriver<RasterSiteGenerator, RasterSiteGeneratorConfig>,
 > = LazyLock::new(|| SiteGeneratorDriver {
     create: Arc::new(|c: RasterSiteGeneratorConfig| {
-        RasterSiteGenerator::new(c.file.as_str(), c.layer_index)
+        let covariates = raster_covariates(&c);
+        let weight = raster_weight(&c);
+        let gen = RasterSiteGenerator::new(c.file.as_str(), c.layer_index, no_data_policy(&c))?;
+        Ok(gen.with_covariates(covariates)?.with_weight(weight)?)
     }),
     config_deserializer: Arc::new(serde_json::from_value),
+    preflight: Arc::new(|c: &RasterSiteGeneratorConfig| {
+        let mut issues = RasterSiteGenerator::preflight(c.file.as_str(), c.layer_index);
+        for covariate in raster_covariates(c).into_iter().chain(raster_weight(c)) {
+            issues.extend(covariate.preflight());
+        }
+        issues
+    }),
+    count: Arc::new(|c: &RasterSiteGeneratorConfig| {
+        RasterSiteGenerator::count_sites(c.file.as_str(), c.layer_index, &no_data_policy(c)).ok()
+    }),
+});
+
+fn no_data_policy(c: &RasterSiteGeneratorConfig) -> NoDataPolicy {
+    NoDataPolicy {
+        values: c.nodata.clone(),
+        treat_zero_as_nodata: c.treat_zero_as_nodata,
+    }
+}
+
+fn raster_covariates(c: &RasterSiteGeneratorConfig) -> Vec<RasterCovariate> {
+    c.covariates
+        .iter()
+        .map(|covariate| RasterCovariate {
+            name: covariate.name.clone(),
+            file: covariate.file.clone().unwrap_or_else(|| c.file.clone()),
+            band_index: covariate.layer_index,
+        })
+        .collect()
+}
+
+/// The weight band, read like a covariate named "weight".
+fn raster_weight(c: &RasterSiteGeneratorConfig) -> Option<RasterCovariate> {
+    c.weight.as_ref().map(|weight| RasterCovariate {
+        name: "weight".to_string(),
+        file: weight.file.clone().unwrap_or_else(|| c.file.clone()),
+        band_index: weight.layer_index,
+    })
+}
+
+pub const DRIVER_CSV: LazyLock<SiteGeneratorDriver<CsvSiteGenerator, CsvSiteGeneratorConfig>> =
+    LazyLock::new(|| SiteGeneratorDriver {
+        create: Arc::new(|c: CsvSiteGeneratorConfig| {
+            CsvSiteGenerator::new(
+                c.file.as_str(),
+                c.lat_column.as_str(),
+                c.lon_column.as_str(),
+                c.site_id_column.as_str(),
+            )?
+            .with_attributes(&c.attribute_columns)
+        }),
+        config_deserializer: Arc::new(serde_json::from_value),
+        preflight: Arc::new(|c: &CsvSiteGeneratorConfig| {
+            CsvSiteGenerator::preflight(
+                c.file.as_str(),
+                c.lat_column.as_str(),
+                c.lon_column.as_str(),
+                c.site_id_column.as_str(),
+                &c.attribute_columns,
+            )
+        }),
+        count: Arc::new(|c: &CsvSiteGeneratorConfig| {
+            CsvSiteGenerator::count_sites(c.file.as_str())
+        }),
+    });
+
+pub const DRIVER_GEOJSON: LazyLock<
+    SiteGeneratorDriver<GeoJsonSiteGenerator, GeoJsonSiteGeneratorConfig>,
+> = LazyLock::new(|| SiteGeneratorDriver {
+    create: Arc::new(|c: GeoJsonSiteGeneratorConfig| {
+        GeoJsonSiteGenerator::new(c.file.as_str(), c.site_id_property.as_str())
+    }),
+    config_deserializer: Arc::new(serde_json::from_value),
+    preflight: Arc::new(|c: &GeoJsonSiteGeneratorConfig| {
+        GeoJsonSiteGenerator::preflight(c.file.as_str(), c.site_id_property.as_str())
+    }),
+    count: Arc::new(|c: &GeoJsonSiteGeneratorConfig| {
+        GeoJsonSiteGenerator::count_sites(c.file.as_str(), c.site_id_property.as_str())
+    }),
 });
@@ -0,0 +1,262 @@
+//! Values attached to the sites of a [`super::SiteGenerator`] after its filters, whatever the driver.
+
+use super::Site;
+use crate::utils::polygons::{PolygonIndex, PolygonLayer};
+use crate::utils::raster::RasterSampler;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+/// Name of the covariate (thus of the template variable) holding the elevation of a site.
+pub const ELEVATION_COVARIATE: &str = "elev";
+
+/// Names of the attributes (thus of the template variables) holding the country, first and second level
+/// administrative region codes of a site.
+pub const REGION_ATTRIBUTES: [&str; 3] = ["country", "adm1", "adm2"];
+
+/// Samples a DEM raster at every site, exposing the value as [`ELEVATION_COVARIATE`]. Sites outside the raster or on
+/// nodata pixels are left without it.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Elevation {
+    pub file: PathBuf,
+
+    /// Band to read from (**ONE-BASED**).
+    #[serde(default = "default_band")]
+    pub band: usize,
+}
+
+fn default_band() -> usize {
+    1
+}
+
+impl Elevation {
+    pub fn open(&self) -> Result<RasterSampler, gdal::errors::GdalError> {
+        RasterSampler::open(&self.file.to_string_lossy())
+    }
+
+    /// Checks that the DEM can be opened and has the band. Returns every problem found.
+    pub fn preflight(&self) -> Vec<String> {
+        match self.open() {
+            Ok(sampler) if self.band == 0 || self.band > sampler.band_count() => vec![format!(
+                "DEM {} has {} band(s), band {} (one-based) does not exist",
+                self.file.display(),
+                sampler.band_count(),
+                self.band
+            )],
+            Ok(_) => Vec::new(),
+            Err(e) => vec![format!("Unable to open DEM {}: {}", self.file.display(), e)],
+        }
+    }
+
+    /// Attaches the elevation at `site` to it.
+    pub fn enrich(&self, sampler: &RasterSampler, mut site: Site) -> Site {
+        if let Ok(Some(elevation)) = sampler.sample(
+            self.band.saturating_sub(1),
+            site.lon.as_f64(),
+            site.lat.as_f64(),
+        ) {
+            site.covariates
+                .insert(ELEVATION_COVARIATE.to_string(), elevation);
+        }
+        site
+    }
+}
+
+/// Tags every site with the codes of the administrative regions containing it, read from a boundaries dataset
+/// (e.g. the ADM_2 layer of GADM, whose features also carry the codes of the levels above) and exposed as the
+/// [`REGION_ATTRIBUTES`]. Sites outside every boundary, or in one whose field is null, are left without the code.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AdminRegions {
+    pub file: PathBuf,
+
+    /// Name of the layer. If not set, the first layer is used.
+    #[serde(default)]
+    pub layer: Option<String>,
+
+    /// Field holding the country code, `null` to leave it out.
+    #[serde(default = "default_country_field")]
+    pub country_field: Option<String>,
+
+    /// Field holding the code of the first level region, `null` to leave it out.
+    #[serde(default = "default_adm1_field")]
+    pub adm1_field: Option<String>,
+
+    /// Field holding the code of the second level region, `null` to leave it out.
+    #[serde(default = "default_adm2_field")]
+    pub adm2_field: Option<String>,
+}
+
+fn default_country_field() -> Option<String> {
+    Some("GID_0".to_string())
+}
+
+fn default_adm1_field() -> Option<String> {
+    Some("GID_1".to_string())
+}
+
+fn default_adm2_field() -> Option<String> {
+    Some("GID_2".to_string())
+}
+
+/// The boundaries of [`AdminRegions`], read into memory.
+pub struct RegionIndex {
+    index: PolygonIndex,
+    /// Attribute of every field read, in the order of the fields of `index`.
+    attributes: Vec<&'static str>,
+}
+
+impl AdminRegions {
+    /// Attributes tagged and the fields they are read from.
+    fn fields(&self) -> Vec<(&'static str, &str)> {
+        let fields = [&self.country_field, &self.adm1_field, &self.adm2_field];
+        REGION_ATTRIBUTES
+            .into_iter()
+            .zip(fields)
+            .filter_map(|(attribute, field)| Some((attribute, field.as_deref()?)))
+            .collect()
+    }
+
+    pub fn open(&self) -> Result<RegionIndex, gdal::errors::GdalError> {
+        let (attributes, fields): (Vec<_>, Vec<_>) = self.fields().into_iter().unzip();
+        let index =
+            PolygonIndex::load_fields(&self.file.to_string_lossy(), self.layer.clone(), &fields)?;
+        Ok(RegionIndex { index, attributes })
+    }
+
+    /// Checks that the boundaries can be opened and have the fields. Returns every problem found.
+    pub fn preflight(&self) -> Vec<String> {
+        let fields = PolygonLayer::open(&self.file.to_string_lossy(), self.layer.clone())
+            .and_then(|layer| layer.fields());
+        match fields {
+            Ok(fields) => self
+                .fields()
+                .into_iter()
+                .filter(|(_, field)| !fields.iter().any(|f| f == field))
+                .map(|(_, field)| {
+                    format!(
+                        "Boundaries {} have no field named \"{}\"",
+                        self.file.display(),
+                        field
+                    )
+                })
+                .collect(),
+            Err(e) => vec![format!(
+                "Unable to open boundaries {}: {}",
+                self.file.display(),
+                e
+            )],
+        }
+    }
+
+    /// Attaches the codes of the regions containing `site` to it.
+    pub fn enrich(&self, regions: &RegionIndex, mut site: Site) -> Site {
+        let codes = regions
+            .index
+            .attributes_at(site.lon.as_f64(), site.lat.as_f64())
+            .unwrap_or_default();
+        for (attribute, code) in regions.attributes.iter().zip(codes) {
+            if let Some(code) = code {
+                site.attributes.insert(attribute.to_string(), code.clone());
+            }
+        }
+        site
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::GeoDeg;
+
+    #[test]
+    fn test_elevation() {
+        // Any single band raster will do, the test raster holds site IDs.
+        let elevation: Elevation =
+            serde_json::from_str(r#"{ "file": "testdata/DSSAT-Soils.tif" }"#).unwrap();
+        assert!(elevation.preflight().is_empty());
+        let sampler = elevation.open().unwrap();
+
+        let site = |lon: f64, lat: f64| Site {
+            id: 1,
+            lon: GeoDeg::from(lon),
+            lat: GeoDeg::from(lat),
+            covariates: Default::default(),
+            attributes: Default::default(),
+            weight: None,
+        };
+        let enriched = elevation.enrich(&sampler, site(12.2919, 14.7917));
+        assert_eq!(
+            enriched.covariates.get(ELEVATION_COVARIATE),
+            Some(&3898947.0)
+        );
+        let outside = elevation.enrich(&sampler, site(-50.0, -50.0));
+        assert!(outside.covariates.is_empty());
+
+        let missing_band = Elevation {
+            band: 2,
+            ..elevation
+        };
+        assert_eq!(missing_band.preflight().len(), 1);
+    }
+
+    #[test]
+    fn test_admin_regions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("boundaries.geojson");
+        let feature = |gid_1: &str, gid_2: Option<&str>, x: f64| {
+            serde_json::json!({
+                "type": "Feature",
+                "properties": { "GID_0": "NER", "GID_1": gid_1, "GID_2": gid_2 },
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [[[x, 10.0], [x + 5.0, 10.0], [x + 5.0, 15.0], [x, 15.0], [x, 10.0]]]
+                }
+            })
+        };
+        let boundaries = serde_json::json!({
+            "type": "FeatureCollection",
+            "features": [feature("NER.1_1", Some("NER.1.1_1"), 0.0), feature("NER.2_1", None, 5.0)]
+        });
+        std::fs::write(&path, boundaries.to_string()).unwrap();
+
+        let regions: AdminRegions =
+            serde_json::from_value(serde_json::json!({ "file": path })).unwrap();
+        assert!(regions.preflight().is_empty());
+        let index = regions.open().unwrap();
+
+        let site = |lon: f64, lat: f64| Site {
+            id: 1,
+            lon: GeoDeg::from(lon),
+            lat: GeoDeg::from(lat),
+            covariates: Default::default(),
+            attributes: Default::default(),
+            weight: None,
+        };
+        let tagged = regions.enrich(&index, site(2.5, 12.5));
+        assert_eq!(tagged.attributes["country"], "NER");
+        assert_eq!(tagged.attributes["adm1"], "NER.1_1");
+        assert_eq!(tagged.attributes["adm2"], "NER.1.1_1");
+        let tagged = regions.enrich(&index, site(7.5, 12.5));
+        assert_eq!(tagged.attributes["adm1"], "NER.2_1");
+        assert!(!tagged.attributes.contains_key("adm2"));
+        assert!(regions
+            .enrich(&index, site(-5.0, 12.5))
+            .attributes
+            .is_empty());
+
+        let without_adm2 = AdminRegions {
+            adm2_field: None,
+            ..regions.clone()
+        };
+        assert!(!without_adm2
+            .enrich(&without_adm2.open().unwrap(), site(2.5, 12.5))
+            .attributes
+            .contains_key("adm2"));
+        let missing_field = AdminRegions {
+            adm1_field: Some("NAME_1".to_string()),
+            ..regions
+        };
+        assert_eq!(missing_field.preflight().len(), 1);
+    }
+}
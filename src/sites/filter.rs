@@ -0,0 +1,229 @@
+//! Filters applied on top of whatever [`super::SiteGenerator`] a driver builds.
+
+use super::Site;
+use crate::utils::raster::RasterSampler;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+/// Sites inside a longitude/latitude box, edges included. Configured as `[min_lon, min_lat, max_lon, max_lat]`.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(try_from = "[f64; 4]")]
+pub struct BoundingBox {
+    pub min_lon: f64,
+    pub min_lat: f64,
+    pub max_lon: f64,
+    pub max_lat: f64,
+}
+
+impl TryFrom<[f64; 4]> for BoundingBox {
+    type Error = String;
+
+    fn try_from([min_lon, min_lat, max_lon, max_lat]: [f64; 4]) -> Result<Self, Self::Error> {
+        if !(-180.0..=180.0).contains(&min_lon) || !(-180.0..=180.0).contains(&max_lon) {
+            return Err("Bounding box longitudes must be between -180 and 180".to_string());
+        }
+        if !(-90.0..=90.0).contains(&min_lat) || !(-90.0..=90.0).contains(&max_lat) {
+            return Err("Bounding box latitudes must be between -90 and 90".to_string());
+        }
+        if min_lon > max_lon || min_lat > max_lat {
+            return Err(format!(
+                "Bounding box [{}, {}, {}, {}] must be [min_lon, min_lat, max_lon, max_lat]",
+                min_lon, min_lat, max_lon, max_lat
+            ));
+        }
+        Ok(BoundingBox {
+            min_lon,
+            min_lat,
+            max_lon,
+            max_lat,
+        })
+    }
+}
+
+impl BoundingBox {
+    pub fn contains(&self, site: &Site) -> bool {
+        // Compared at the precision sites are stored with, so sites exactly on an edge are kept.
+        let (lon, lat) = (site.lon.as_f32(), site.lat.as_f32());
+        (self.min_lon as f32..=self.max_lon as f32).contains(&lon)
+            && (self.min_lat as f32..=self.max_lat as f32).contains(&lat)
+    }
+}
+
+/// Sites where a secondary raster (e.g. harvested area) is below a threshold. Sites outside the raster or on nodata
+/// pixels are dropped as well.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RasterThreshold {
+    pub file: PathBuf,
+
+    /// Band to read from (**ONE-BASED**).
+    #[serde(default = "default_band")]
+    pub band: usize,
+
+    /// Sites where the raster is below this value are dropped.
+    pub min: f64,
+}
+
+fn default_band() -> usize {
+    1
+}
+
+impl RasterThreshold {
+    pub fn open(&self) -> Result<RasterSampler, gdal::errors::GdalError> {
+        RasterSampler::open(&self.file.to_string_lossy())
+    }
+
+    /// Checks that the raster can be opened and has the band. Returns every problem found.
+    pub fn preflight(&self) -> Vec<String> {
+        match self.open() {
+            Ok(sampler) if self.band == 0 || self.band > sampler.band_count() => vec![format!(
+                "Threshold raster {} has {} band(s), band {} (one-based) does not exist",
+                self.file.display(),
+                sampler.band_count(),
+                self.band
+            )],
+            Ok(_) => Vec::new(),
+            Err(e) => vec![format!(
+                "Unable to open threshold raster {}: {}",
+                self.file.display(),
+                e
+            )],
+        }
+    }
+
+    /// Whether the value of the raster at `site` is at least [`RasterThreshold::min`].
+    pub fn accepts(&self, sampler: &RasterSampler, site: &Site) -> bool {
+        match sampler.sample(
+            self.band.saturating_sub(1),
+            site.lon.as_f64(),
+            site.lat.as_f64(),
+        ) {
+            Ok(Some(value)) => value >= self.min,
+            _ => false,
+        }
+    }
+}
+
+/// How sites of different sources are matched against each other by `include` and `exclude`.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SiteMatch {
+    #[default]
+    Id,
+    /// Longitude and latitude, at the precision sites are stored with.
+    Coordinates,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum SiteKey {
+    Id(i32),
+    Coordinates(u32, u32),
+}
+
+impl SiteMatch {
+    fn key(&self, site: &Site) -> SiteKey {
+        match self {
+            SiteMatch::Id => SiteKey::Id(site.id),
+            SiteMatch::Coordinates => {
+                SiteKey::Coordinates(site.lon.as_f32().to_bits(), site.lat.as_f32().to_bits())
+            }
+        }
+    }
+}
+
+/// The sites of another source, to intersect with (`include`) or subtract from (`exclude`) a source.
+pub struct SiteSet {
+    on: SiteMatch,
+    keys: HashSet<SiteKey>,
+}
+
+impl SiteSet {
+    pub fn new<'a>(on: SiteMatch, sites: impl IntoIterator<Item = &'a Site>) -> Self {
+        SiteSet {
+            on,
+            keys: sites.into_iter().map(|site| on.key(site)).collect(),
+        }
+    }
+
+    pub fn contains(&self, site: &Site) -> bool {
+        self.keys.contains(&self.on.key(site))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::GeoDeg;
+
+    #[test]
+    fn test_bounding_box() {
+        let bbox: BoundingBox = serde_json::from_str("[14.125, 12.875, 14.375, 13.042]").unwrap();
+        let site = |lon: f64, lat: f64| Site {
+            id: 1,
+            lon: GeoDeg::from(lon),
+            lat: GeoDeg::from(lat),
+            covariates: Default::default(),
+            attributes: Default::default(),
+            weight: None,
+        };
+
+        assert!(bbox.contains(&site(14.125, 13.042)));
+        assert!(bbox.contains(&site(14.2, 12.9)));
+        assert!(!bbox.contains(&site(14.458, 13.042)));
+        assert!(!bbox.contains(&site(14.2, 12.792)));
+
+        assert!(serde_json::from_str::<BoundingBox>("[14.375, 12.875, 14.125, 13.042]").is_err());
+        assert!(serde_json::from_str::<BoundingBox>("[0, 0, 190, 10]").is_err());
+        assert!(serde_json::from_str::<BoundingBox>("[0, 0, 10]").is_err());
+    }
+
+    #[test]
+    fn test_site_set() {
+        let site = |id, lon: f64, lat: f64| Site {
+            id,
+            lon: GeoDeg::from(lon),
+            lat: GeoDeg::from(lat),
+            covariates: Default::default(),
+            attributes: Default::default(),
+            weight: None,
+        };
+        let done = [site(1, 10.0, 20.0), site(2, 10.5, 20.0)];
+
+        let by_id = SiteSet::new(SiteMatch::Id, &done);
+        assert!(by_id.contains(&site(1, 0.0, 0.0)));
+        assert!(!by_id.contains(&site(3, 10.0, 20.0)));
+
+        let by_coordinates = SiteSet::new(SiteMatch::Coordinates, &done);
+        assert!(by_coordinates.contains(&site(3, 10.5, 20.0)));
+        assert!(!by_coordinates.contains(&site(1, 10.0, 20.5)));
+    }
+
+    #[test]
+    fn test_raster_threshold() {
+        // The test raster holds site IDs, which makes for an easy to check threshold.
+        let threshold: RasterThreshold =
+            serde_json::from_str(r#"{ "file": "testdata/DSSAT-Soils.tif", "min": 3898948 }"#)
+                .unwrap();
+        assert!(threshold.preflight().is_empty());
+        let sampler = threshold.open().unwrap();
+
+        let site = |id, lon: f64, lat: f64| Site {
+            id,
+            lon: GeoDeg::from(lon),
+            lat: GeoDeg::from(lat),
+            covariates: Default::default(),
+            attributes: Default::default(),
+            weight: None,
+        };
+        assert!(!threshold.accepts(&sampler, &site(3898947, 12.2919, 14.7917)));
+        assert!(threshold.accepts(&sampler, &site(3898948, 12.3752, 14.7917)));
+        assert!(!threshold.accepts(&sampler, &site(0, -50.0, -50.0)));
+
+        let missing_band = RasterThreshold {
+            band: 2,
+            ..threshold
+        };
+        assert_eq!(missing_band.preflight().len(), 1);
+    }
+}
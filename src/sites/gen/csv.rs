@@ -0,0 +1,276 @@
+use super::super::{Site, SiteGenError};
+use crate::data::GeoDeg;
+use csv::{Reader, StringRecord, StringRecordsIntoIter};
+use std::fs::File;
+use std::str::FromStr;
+
+/// Implementation of SiteGenerator that streams sites from a CSV file, one per row.
+/// Rows with an invalid ID or coordinates are reported as errors.
+/// ```rs
+/// match CsvSiteGenerator::new("sites.csv", "lat", "lon", "id") {
+///     Ok(gen) => for site in gen {
+///         println!("{:?}", site);
+///     },
+///     Err(e) => println!("{}", e),
+/// }
+/// ```
+pub struct CsvSiteGenerator {
+    records: StringRecordsIntoIter<File>,
+    /// Index of the next row, not counting the header.
+    row: usize,
+    lat_idx: usize,
+    lon_idx: usize,
+    site_id_idx: usize,
+    headers: StringRecord,
+    /// Names and positions of the columns read as attributes of the sites.
+    attributes: Vec<(String, usize)>,
+}
+
+/// Positions of the columns named `lat_column`, `lon_column` and `site_id_column`, or the names of the missing ones.
+fn column_positions(
+    headers: &StringRecord,
+    lat_column: &str,
+    lon_column: &str,
+    site_id_column: &str,
+) -> Result<[usize; 3], Vec<String>> {
+    let mut positions = [0; 3];
+    let mut missing = Vec::new();
+    for (i, name) in [lat_column, lon_column, site_id_column].iter().enumerate() {
+        match headers.iter().position(|h| h.trim() == *name) {
+            Some(idx) => positions[i] = idx,
+            None => missing.push(name.to_string()),
+        }
+    }
+
+    if missing.is_empty() {
+        Ok(positions)
+    } else {
+        Err(missing)
+    }
+}
+
+impl CsvSiteGenerator {
+    /// Constructs a new CsvSiteGenerator from the CSV file at `path`, which must have a header row.
+    /// Parameters "lat_column" and "lon_column" name the columns holding the coordinates, in decimal degrees.
+    /// Parameter "site_id_column" names the column holding the site ID. Must be an int32, otherwise the row is reported as an error.
+    pub fn new(
+        path: &str,
+        lat_column: &str,
+        lon_column: &str,
+        site_id_column: &str,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut reader = Reader::from_path(path)?;
+        let headers = reader.headers()?.clone();
+        let [lat_idx, lon_idx, site_id_idx] =
+            column_positions(&headers, lat_column, lon_column, site_id_column).map_err(
+                |missing| format!("CSV file {} has no column(s) {}", path, missing.join(", ")),
+            )?;
+
+        Ok(CsvSiteGenerator {
+            records: reader.into_records(),
+            row: 0,
+            lat_idx,
+            lon_idx,
+            site_id_idx,
+            headers,
+            attributes: Vec::new(),
+        })
+    }
+
+    /// Attaches the text of the `columns` of every row to its site, as attributes named after the columns (see
+    /// [`Site::attributes`]). Empty values are left out.
+    pub fn with_attributes(
+        mut self,
+        columns: &[String],
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let missing = missing_columns(&self.headers, columns);
+        if !missing.is_empty() {
+            return Err(format!("CSV file has no column(s) {}", missing.join(", ")).into());
+        }
+        self.attributes = columns
+            .iter()
+            .filter_map(|name| {
+                let idx = self.headers.iter().position(|h| h.trim() == name)?;
+                Some((name.clone(), idx))
+            })
+            .collect();
+        Ok(self)
+    }
+
+    fn record_to_site(&self, record: &StringRecord, row: usize) -> Result<Site, SiteGenError> {
+        Ok(Site {
+            id: field(record, self.site_id_idx, row, "site ID")?,
+            lon: GeoDeg::from(field::<f64>(record, self.lon_idx, row, "longitude")?),
+            lat: GeoDeg::from(field::<f64>(record, self.lat_idx, row, "latitude")?),
+            covariates: Default::default(),
+            attributes: self
+                .attributes
+                .iter()
+                .filter_map(|(name, idx)| {
+                    let value = record.get(*idx)?.trim();
+                    (!value.is_empty()).then(|| (name.clone(), value.to_string()))
+                })
+                .collect(),
+            weight: None,
+        })
+    }
+
+    /// Counts the rows of the CSV file at `path`, not counting the header.
+    pub fn count_sites(path: &str) -> Option<usize> {
+        Some(Reader::from_path(path).ok()?.into_records().count())
+    }
+
+    /// Checks that the CSV file at `path` can be read and has the `lat_column`, `lon_column`, `site_id_column` and
+    /// `attribute_columns` columns. Returns every problem found.
+    pub fn preflight(
+        path: &str,
+        lat_column: &str,
+        lon_column: &str,
+        site_id_column: &str,
+        attribute_columns: &[String],
+    ) -> Vec<String> {
+        let mut reader = match Reader::from_path(path) {
+            Ok(reader) => reader,
+            Err(e) => return vec![format!("Unable to open CSV file {}: {}", path, e)],
+        };
+
+        let headers = match reader.headers() {
+            Ok(headers) => headers,
+            Err(e) => {
+                return vec![format!(
+                    "Unable to read the header of CSV file {}: {}",
+                    path, e
+                )]
+            }
+        };
+
+        let mut missing = column_positions(headers, lat_column, lon_column, site_id_column)
+            .err()
+            .unwrap_or_default();
+        missing.extend(missing_columns(headers, attribute_columns));
+        missing
+            .iter()
+            .map(|name| format!("CSV file {} has no column named \"{}\"", path, name))
+            .collect()
+    }
+}
+
+/// Names of the `columns` missing from `headers`.
+fn missing_columns(headers: &StringRecord, columns: &[String]) -> Vec<String> {
+    columns
+        .iter()
+        .filter(|name| !headers.iter().any(|h| h.trim() == name.as_str()))
+        .cloned()
+        .collect()
+}
+
+/// Parses the value of the column at `idx` of the `row`th record.
+fn field<T: FromStr>(
+    record: &StringRecord,
+    idx: usize,
+    row: usize,
+    what: &str,
+) -> Result<T, SiteGenError> {
+    let value = record.get(idx).unwrap_or_default().trim();
+    value.parse().map_err(|_| SiteGenError::Row {
+        row,
+        message: format!("Invalid {} \"{}\"", what, value),
+    })
+}
+
+impl Iterator for CsvSiteGenerator {
+    type Item = Result<Site, SiteGenError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = match self.records.next()? {
+            Ok(record) => record,
+            Err(e) => return Some(Err(e.into())),
+        };
+        let row = self.row;
+        self.row += 1;
+
+        Some(self.record_to_site(&record, row))
+    }
+
+    /// Skips `n` rows without parsing their fields.
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        for _ in 0..n {
+            if self.records.next()?.is_ok() {
+                self.row += 1;
+            }
+        }
+        self.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn sites_file(contents: &str) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_csv_site_generator() {
+        let file =
+            sites_file("name,y,x,cell\nA,13.042,14.125,1\nB,oops,14.208,2\nC,12.958,14.292,3\n");
+        let path = file.path().to_str().unwrap();
+
+        let (sites, errors): (Vec<_>, Vec<_>) = CsvSiteGenerator::new(path, "y", "x", "cell")
+            .unwrap()
+            .partition(Result::is_ok);
+        assert_eq!(
+            errors[0].as_ref().unwrap_err().to_string(),
+            "Row 1: Invalid latitude \"oops\""
+        );
+        assert_eq!(
+            sites.into_iter().map(Result::unwrap).collect::<Vec<_>>(),
+            vec![
+                Site {
+                    id: 1,
+                    lon: GeoDeg::from(14.125),
+                    lat: GeoDeg::from(13.042),
+                    covariates: Default::default(),
+                    attributes: Default::default(),
+                    weight: None,
+                },
+                Site {
+                    id: 3,
+                    lon: GeoDeg::from(14.292),
+                    lat: GeoDeg::from(12.958),
+                    covariates: Default::default(),
+                    attributes: Default::default(),
+                    weight: None,
+                },
+            ]
+        );
+
+        assert!(CsvSiteGenerator::preflight(path, "y", "x", "cell", &[]).is_empty());
+        assert_eq!(
+            CsvSiteGenerator::preflight(path, "lat", "x", "cell", &["zone".to_string()]),
+            vec![
+                format!("CSV file {} has no column named \"lat\"", path),
+                format!("CSV file {} has no column named \"zone\"", path),
+            ]
+        );
+        assert!(CsvSiteGenerator::new(path, "lat", "lon", "cell").is_err());
+
+        let named = CsvSiteGenerator::new(path, "y", "x", "cell")
+            .unwrap()
+            .with_attributes(&["name".to_string()])
+            .unwrap();
+        let names: Vec<_> = named
+            .filter_map(Result::ok)
+            .map(|site| site.attributes["name"].clone())
+            .collect();
+        assert_eq!(names, vec!["A", "C"]);
+        assert!(CsvSiteGenerator::new(path, "y", "x", "cell")
+            .unwrap()
+            .with_attributes(&["zone".to_string()])
+            .is_err());
+    }
+}
@@ -0,0 +1,251 @@
+use super::super::{Site, SiteGenError};
+use crate::data::GeoDeg;
+use serde_json::Value;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum GeoJsonSiteError {
+    #[error("Failed to read GeoJSON file {0}: {1}")]
+    Io(String, std::io::Error),
+    #[error("Failed to parse GeoJSON file {0}: {1}")]
+    Json(String, serde_json::Error),
+    #[error("GeoJSON file {0} is neither a FeatureCollection nor a Feature")]
+    NotFeatures(String),
+    #[error("Feature {index} of GeoJSON file {path}: {message}")]
+    InvalidFeature {
+        path: String,
+        index: usize,
+        message: String,
+    },
+}
+
+/// Implementation of SiteGenerator that reads point features from a GeoJSON file, without going through GDAL.
+/// Unlike [`super::VectorSiteGenerator`], every feature is validated when the generator is constructed, and any
+/// invalid feature fails it with its index instead of being skipped.
+/// ```rs
+/// match GeoJsonSiteGenerator::new("sites.geojson", "CELL5M") {
+///     Ok(gen) => for site in gen {
+///         println!("{:?}", site);
+///     },
+///     Err(e) => println!("{}", e),
+/// }
+/// ```
+pub struct GeoJsonSiteGenerator {
+    sites: std::vec::IntoIter<Site>,
+}
+
+impl GeoJsonSiteGenerator {
+    /// Constructs a new GeoJsonSiteGenerator from the GeoJSON file at `path`.
+    /// Parameter "site_id_property" is the name of the feature property that contains the site ID. Must be an int32.
+    pub fn new(path: &str, site_id_property: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        match read_sites(path, site_id_property) {
+            Ok(sites) => Ok(GeoJsonSiteGenerator {
+                sites: sites.into_iter(),
+            }),
+            Err(errors) => Err(errors
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+                .into()),
+        }
+    }
+
+    /// Counts the features of the GeoJSON file at `path`, if every one of them is valid.
+    pub fn count_sites(path: &str, site_id_property: &str) -> Option<usize> {
+        read_sites(path, site_id_property)
+            .ok()
+            .map(|sites| sites.len())
+    }
+
+    /// Checks every feature of the GeoJSON file at `path`. Returns every problem found.
+    pub fn preflight(path: &str, site_id_property: &str) -> Vec<String> {
+        match read_sites(path, site_id_property) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors.iter().map(|e| e.to_string()).collect(),
+        }
+    }
+}
+
+impl Iterator for GeoJsonSiteGenerator {
+    type Item = Result<Site, SiteGenError>;
+
+    /// Never fails, as every feature was already validated.
+    fn next(&mut self) -> Option<Self::Item> {
+        self.sites.next().map(Ok)
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.sites.nth(n).map(Ok)
+    }
+}
+
+/// Reads the sites of every feature of the GeoJSON file at `path`, or every problem found with it.
+fn read_sites(path: &str, site_id_property: &str) -> Result<Vec<Site>, Vec<GeoJsonSiteError>> {
+    let contents =
+        std::fs::read_to_string(path).map_err(|e| vec![GeoJsonSiteError::Io(path.into(), e)])?;
+    let document: Value = serde_json::from_str(&contents)
+        .map_err(|e| vec![GeoJsonSiteError::Json(path.into(), e)])?;
+
+    let features: Vec<&Value> = match document.get("type").and_then(Value::as_str) {
+        Some("FeatureCollection") => match document.get("features").and_then(Value::as_array) {
+            Some(features) => features.iter().collect(),
+            None => return Err(vec![GeoJsonSiteError::NotFeatures(path.into())]),
+        },
+        Some("Feature") => vec![&document],
+        _ => return Err(vec![GeoJsonSiteError::NotFeatures(path.into())]),
+    };
+
+    let mut sites = Vec::with_capacity(features.len());
+    let mut errors = Vec::new();
+    for (index, feature) in features.into_iter().enumerate() {
+        match feature_to_site(feature, site_id_property) {
+            Ok(site) => sites.push(site),
+            Err(message) => errors.push(GeoJsonSiteError::InvalidFeature {
+                path: path.into(),
+                index,
+                message,
+            }),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(sites)
+    } else {
+        Err(errors)
+    }
+}
+
+fn feature_to_site(feature: &Value, site_id_property: &str) -> Result<Site, String> {
+    let geometry = feature
+        .get("geometry")
+        .filter(|g| !g.is_null())
+        .ok_or("Feature has no geometry")?;
+
+    match geometry.get("type").and_then(Value::as_str) {
+        Some("Point") => {}
+        Some(other) => return Err(format!("Expected a Point geometry, found {}", other)),
+        None => return Err("Geometry has no type".to_string()),
+    }
+
+    let coordinates = geometry
+        .get("coordinates")
+        .and_then(Value::as_array)
+        .map(|c| c.iter().map(Value::as_f64).collect::<Vec<_>>());
+    let (lon, lat) = match coordinates.as_deref() {
+        Some([Some(lon), Some(lat), ..]) => (*lon, *lat),
+        _ => return Err("Point coordinates must be an array of numbers [lon, lat]".to_string()),
+    };
+
+    let id = match feature
+        .get("properties")
+        .and_then(|p| p.get(site_id_property))
+    {
+        None | Some(Value::Null) => {
+            return Err(format!("Feature has no \"{}\" property", site_id_property))
+        }
+        Some(value) => value
+            .as_i64()
+            .and_then(|id| i32::try_from(id).ok())
+            .ok_or_else(|| {
+                format!(
+                    "Property \"{}\" is not an int32 (found {})",
+                    site_id_property, value
+                )
+            })?,
+    };
+
+    Ok(Site {
+        id,
+        lon: GeoDeg::from(lon),
+        lat: GeoDeg::from(lat),
+        covariates: Default::default(),
+        attributes: Default::default(),
+        weight: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn geojson_file(contents: &str) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        file
+    }
+
+    fn point(id: &str, coordinates: &str) -> String {
+        format!(
+            r#"{{ "type": "Feature", "properties": {{ "CELL5M": {} }}, "geometry": {{ "type": "Point", "coordinates": {} }} }}"#,
+            id, coordinates
+        )
+    }
+
+    #[test]
+    fn test_geojson_site_generator() {
+        let file = geojson_file(&format!(
+            r#"{{ "type": "FeatureCollection", "features": [{}, {}] }}"#,
+            point("3989689", "[14.125, 13.042]"),
+            point("3989690", "[14.208, 13.042, 350.0]"),
+        ));
+        let path = file.path().to_str().unwrap();
+
+        let sites: Vec<Site> = GeoJsonSiteGenerator::new(path, "CELL5M")
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            sites,
+            vec![
+                Site {
+                    id: 3989689,
+                    lon: GeoDeg::from(14.125),
+                    lat: GeoDeg::from(13.042),
+                    covariates: Default::default(),
+                    attributes: Default::default(),
+                    weight: None,
+                },
+                Site {
+                    id: 3989690,
+                    lon: GeoDeg::from(14.208),
+                    lat: GeoDeg::from(13.042),
+                    covariates: Default::default(),
+                    attributes: Default::default(),
+                    weight: None,
+                },
+            ]
+        );
+        assert_eq!(
+            GeoJsonSiteGenerator::preflight(path, "ID"),
+            vec![
+                format!(
+                    "Feature 0 of GeoJSON file {}: Feature has no \"ID\" property",
+                    path
+                ),
+                format!(
+                    "Feature 1 of GeoJSON file {}: Feature has no \"ID\" property",
+                    path
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_geojson_invalid_features() {
+        let file = geojson_file(&format!(
+            r#"{{ "type": "FeatureCollection", "features": [{}, {}, {}] }}"#,
+            point("1", "[14.125, 13.042]"),
+            point("\"a\"", "[14.208, 13.042]"),
+            r#"{ "type": "Feature", "properties": { "CELL5M": 3 }, "geometry": { "type": "LineString", "coordinates": [] } }"#,
+        ));
+        let path = file.path().to_str().unwrap();
+
+        let issues = GeoJsonSiteGenerator::preflight(path, "CELL5M");
+        assert_eq!(issues.len(), 2);
+        assert!(issues[0].starts_with("Feature 1 "));
+        assert!(issues[1].ends_with("Expected a Point geometry, found LineString"));
+        assert!(GeoJsonSiteGenerator::new(path, "CELL5M").is_err());
+    }
+}
@@ -1,5 +1,10 @@
+mod csv;
+mod geojson;
 mod raster;
 mod vector;
 
+// `self::` disambiguates the module from the `csv` crate.
+pub use self::csv::*;
+pub use geojson::*;
 pub use raster::*;
 pub use vector::*;
@@ -1,23 +1,22 @@
-use super::super::Site;
+use super::super::{Site, SiteGenError};
 use crate::data::GeoDeg;
+use crate::utils::raster::RasterSampler;
+use gdal::errors::GdalError;
 use gdal::raster::{Buffer, GdalDataType};
 use gdal::{Dataset, GeoTransformEx};
+use std::collections::HashMap;
 use std::fmt;
 use std::rc::Rc;
 
 /// Represents an error meaning that the desired data type of the raster band is not supported.
 #[derive(Debug, Clone)]
 struct InvalidRasterDataTypeError {
-    expected: GdalDataType,
     actual: GdalDataType,
 }
 
 impl InvalidRasterDataTypeError {
     fn new(actual: GdalDataType) -> Self {
-        InvalidRasterDataTypeError {
-            expected: GdalDataType::Int32,
-            actual,
-        }
+        InvalidRasterDataTypeError { actual }
     }
 }
 
@@ -25,8 +24,8 @@ impl fmt::Display for InvalidRasterDataTypeError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "Invalid raster data type. Expected {}, got {}.",
-            self.expected, self.actual
+            "Invalid raster data type. Expected an integer type of up to 32 bits or a floating point type, got {}.",
+            self.actual
         )
     }
 }
@@ -37,15 +36,81 @@ impl std::error::Error for InvalidRasterDataTypeError {
     }
 }
 
+/// Whether site IDs can be read out of bands of type `band_type`.
+fn is_supported_band_type(band_type: GdalDataType) -> bool {
+    band_type.is_floating() || (band_type.is_integer() && band_type.bits() <= 32)
+}
+
+/// Decides which pixel values of a raster are nodata (thus skipped) instead of site IDs.
+///
+/// NaN is always treated as nodata, as it can't possibly be a site ID.
+#[derive(Debug, Clone, Default)]
+pub struct NoDataPolicy {
+    /// Values treated as nodata in addition to the band's own nodata value.
+    pub values: Vec<f64>,
+
+    /// Explicitly treats (`Some(true)`) or never treats (`Some(false)`) zero as nodata, even if it is the band's nodata value.
+    /// If `None`, zero is treated as nodata only if the band has no nodata value and no [`NoDataPolicy::values`] are given.
+    pub treat_zero_as_nodata: Option<bool>,
+}
+
+impl NoDataPolicy {
+    /// Resolves the final set of (non-NaN) nodata values given the band's own nodata value.
+    fn resolve(&self, band_no_data: Option<f64>) -> Vec<f64> {
+        let mut values = self.values.clone();
+        values.extend(band_no_data);
+
+        match self.treat_zero_as_nodata {
+            Some(true) => values.push(0.0),
+            Some(false) => values.retain(|v| *v != 0.0),
+            None if values.is_empty() => values.push(0.0),
+            None => {}
+        }
+
+        values.retain(|v| !v.is_nan());
+        values
+    }
+}
+
+/// A band whose value at every site is attached to it as a covariate named `name`.
+#[derive(Debug, Clone)]
+pub struct RasterCovariate {
+    pub name: String,
+    /// GDAL-valid path to the raster dataset, which may or may not be the one sites are read from.
+    pub file: String,
+    /// **ZERO-BASED** index of the band.
+    pub band_index: usize,
+}
+
+impl RasterCovariate {
+    /// Checks that the dataset can be opened and that the band exists. Returns every problem found.
+    pub fn preflight(&self) -> Vec<String> {
+        match RasterSampler::open(&self.file) {
+            Ok(sampler) if self.band_index >= sampler.band_count() => vec![format!(
+                "Covariate \"{}\": {} has {} band(s), band index {} (zero-based) does not exist",
+                self.name,
+                self.file,
+                sampler.band_count(),
+                self.band_index
+            )],
+            Ok(_) => Vec::new(),
+            Err(e) => vec![format!(
+                "Covariate \"{}\": unable to open raster dataset {}: {}",
+                self.name, self.file, e
+            )],
+        }
+    }
+}
+
 /// Implementation of SiteGenerator that allows streaming from a GDAL raster dataset.
-/// Works only on bands of data type Int32.
+/// Works on integer bands of up to 32 bits and floating point bands. Pixels of floating point bands must hold whole numbers to be used as site IDs, otherwise they are reported as errors.
 ///
 /// Example usage with https://dataverse.harvard.edu/dataset.xhtml?persistentId=doi:10.7910/DVN/1PEEY0:
 ///
 /// Take a raster dataset. Instructions on how to rasterize can be found at [testdata/DSSAT-Soils.tif](testdata/README.md#dssat-soilstif).
 ///
 /// ```rs
-/// match RasterSiteGenerator::new("Point5m_SoilGrids-for-DSSAT-10km_v1.tif", 0) {
+/// match RasterSiteGenerator::new("Point5m_SoilGrids-for-DSSAT-10km_v1.tif", 0, NoDataPolicy::default()) {
 ///     Ok(gen) => for site in gen {
 ///         println!("{:?}", site);
 ///     },
@@ -54,7 +119,7 @@ impl std::error::Error for InvalidRasterDataTypeError {
 /// ```
 pub struct RasterSiteGenerator {
     ds: Rc<Dataset>,
-    no_data_value: i32,
+    no_data_values: Vec<f64>,
     band_index: usize,
     px_size_x: f64,
     px_size_y: f64,
@@ -64,28 +129,35 @@ pub struct RasterSiteGenerator {
     block_y_size: usize,
     curr_block_x: usize,
     curr_block_y: usize,
-    buffer: Option<Buffer<i32>>,
+    buffer: Option<Buffer<f64>>,
     buffer_x_size: usize,
     buffer_y_size: usize,
     px_idx: usize,
+    covariates: Vec<(RasterCovariate, RasterSampler)>,
+    weight: Option<(RasterCovariate, RasterSampler)>,
 }
 
 impl RasterSiteGenerator {
     /// Constructs a new RasterSiteGenerator.
     /// Parameter "path" is the GDAL-valid path to the raster dataset.
     /// Parameter "band_index" is the **ZERO-BASED** index of the band to use.
-    pub fn new(path: &str, band_index: usize) -> Result<Self, Box<dyn std::error::Error>> {
+    /// Parameter "no_data" decides which pixel values are skipped.
+    pub fn new(
+        path: &str,
+        band_index: usize,
+        no_data: NoDataPolicy,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         let ds = Rc::new(Dataset::open(path)?);
         let band = ds.rasterband(band_index + 1)?;
         let (x_size, y_size) = band.size();
 
         let band_type = band.band_type();
-        if band_type != GdalDataType::Int32 {
+        if !is_supported_band_type(band_type) {
             return Err(Box::new(InvalidRasterDataTypeError::new(band_type)));
         }
 
         let (block_x_size, block_y_size) = band.block_size();
-        let no_data_value = band.no_data_value().unwrap_or(0.0) as i32;
+        let no_data_values = no_data.resolve(band.no_data_value());
 
         // https://gdal.org/en/stable/tutorials/geotransforms_tut.html
         let geo_transform = ds.geo_transform()?;
@@ -94,7 +166,7 @@ impl RasterSiteGenerator {
 
         let mut gen = Self {
             ds,
-            no_data_value,
+            no_data_values,
             band_index: band_index + 1,
             px_size_x,
             px_size_y,
@@ -108,45 +180,160 @@ impl RasterSiteGenerator {
             buffer_x_size: 0,
             buffer_y_size: 0,
             px_idx: 0,
+            covariates: Vec::new(),
+            weight: None,
         };
 
-        gen.load_next_block();
+        gen.load_next_block()?;
         Ok(gen)
     }
 
-    fn load_next_block(&mut self) -> bool {
+    /// Attaches the value of every band of `covariates` to the generated sites. Covariates on nodata pixels, or
+    /// outside their raster, are left out of the site.
+    pub fn with_covariates(mut self, covariates: Vec<RasterCovariate>) -> Result<Self, GdalError> {
+        self.covariates = covariates
+            .into_iter()
+            .map(|c| RasterSampler::open(&c.file).map(|sampler| (c, sampler)))
+            .collect::<Result<_, _>>()?;
+        Ok(self)
+    }
+
+    /// Reads the [`Site::weight`] of the generated sites from the band of `weight`. Sites on nodata pixels, or outside
+    /// its raster, have no weight.
+    pub fn with_weight(mut self, weight: Option<RasterCovariate>) -> Result<Self, GdalError> {
+        self.weight = weight
+            .map(|w| RasterSampler::open(&w.file).map(|sampler| (w, sampler)))
+            .transpose()?;
+        Ok(self)
+    }
+
+    /// Samples every covariate at the given point.
+    fn sample_covariates(&self, lon: f64, lat: f64) -> HashMap<String, f64> {
+        self.covariates
+            .iter()
+            .filter_map(|(covariate, sampler)| {
+                let value = sampler.sample(covariate.band_index, lon, lat).ok()??;
+                Some((covariate.name.clone(), value))
+            })
+            .collect()
+    }
+
+    /// Checks that the dataset at `path` can be opened, has a geotransform and that its band `band_index` (**ZERO-BASED**) exists and has a supported data type.
+    /// Returns every problem found.
+    pub fn preflight(path: &str, band_index: usize) -> Vec<String> {
+        let ds = match Dataset::open(path) {
+            Ok(ds) => ds,
+            Err(e) => return vec![format!("Unable to open raster dataset {}: {}", path, e)],
+        };
+
+        let mut issues = Vec::new();
+        if let Err(e) = ds.geo_transform() {
+            issues.push(format!(
+                "Raster dataset {} has no geotransform: {}",
+                path, e
+            ));
+        }
+
+        if band_index >= ds.raster_count() {
+            issues.push(format!(
+                "Raster dataset {} has {} band(s), band index {} (zero-based) does not exist",
+                path,
+                ds.raster_count(),
+                band_index
+            ));
+            return issues;
+        }
+
+        match ds.rasterband(band_index + 1) {
+            Ok(band) if !is_supported_band_type(band.band_type()) => issues.push(format!(
+                "Band {} of {}: {}",
+                band_index,
+                path,
+                InvalidRasterDataTypeError::new(band.band_type())
+            )),
+            Ok(_) => {}
+            Err(e) => issues.push(format!(
+                "Unable to read band {} of {}: {}",
+                band_index, path, e
+            )),
+        }
+        issues
+    }
+
+    /// Counts the pixels of the band `band_index` (**ZERO-BASED**) of the dataset at `path` that are not nodata under
+    /// `no_data`, reading whole blocks instead of building sites out of them.
+    pub fn count_sites(
+        path: &str,
+        band_index: usize,
+        no_data: &NoDataPolicy,
+    ) -> Result<usize, GdalError> {
+        let ds = Dataset::open(path)?;
+        let band = ds.rasterband(band_index + 1)?;
+        let no_data_values = no_data.resolve(band.no_data_value());
+        let (x_size, y_size) = band.size();
+        let (block_x_size, block_y_size) = band.block_size();
+
+        let mut count = 0;
+        for y_offset in (0..y_size).step_by(block_y_size.max(1)) {
+            for x_offset in (0..x_size).step_by(block_x_size.max(1)) {
+                let size = (
+                    block_x_size.min(x_size - x_offset),
+                    block_y_size.min(y_size - y_offset),
+                );
+                let buffer =
+                    band.read_as::<f64>((x_offset as isize, y_offset as isize), size, size, None)?;
+                count += buffer
+                    .data()
+                    .iter()
+                    .filter(|value| !value.is_nan() && !no_data_values.contains(value))
+                    .count();
+            }
+        }
+        Ok(count)
+    }
+
+    /// Reads the current block into the buffer. Returns `false` once there are no blocks left.
+    fn load_next_block(&mut self) -> Result<bool, GdalError> {
         if (self.curr_block_y * self.block_y_size) >= self.y_size
             || (self.curr_block_x * self.block_x_size) >= self.x_size
         {
-            return false;
+            return Ok(false);
         }
 
-        match self
-            .ds
-            .rasterband(self.band_index)
-            .unwrap()
-            .read_block((self.curr_block_x, self.curr_block_y))
-        {
-            Ok(buffer) => {
-                self.buffer_x_size = self
-                    .block_x_size
-                    .min(self.x_size - self.curr_block_x * self.block_x_size);
-                self.buffer_y_size = self
-                    .block_y_size
-                    .min(self.y_size - self.curr_block_y * self.block_y_size);
-                self.buffer = Some(buffer);
-                self.px_idx = 0;
-                true
-            }
-            Err(_) => false,
-        }
+        let x_offset = self.curr_block_x * self.block_x_size;
+        let y_offset = self.curr_block_y * self.block_y_size;
+        let buffer_x_size = self.block_x_size.min(self.x_size - x_offset);
+        let buffer_y_size = self.block_y_size.min(self.y_size - y_offset);
+
+        // Reads as f64 regardless of the band type, so integer and floating point bands share the same code path.
+        // Every i32 is exactly representable in f64.
+        let buffer = self.ds.rasterband(self.band_index)?.read_as::<f64>(
+            (x_offset as isize, y_offset as isize),
+            (buffer_x_size, buffer_y_size),
+            (buffer_x_size, buffer_y_size),
+            None,
+        )?;
+        self.buffer_x_size = buffer_x_size;
+        self.buffer_y_size = buffer_y_size;
+        self.buffer = Some(buffer);
+        self.px_idx = 0;
+        Ok(true)
     }
 }
 
-impl Iterator for RasterSiteGenerator {
-    type Item = Site;
+impl RasterSiteGenerator {
+    fn is_no_data(&self, value: f64) -> bool {
+        value.is_nan() || self.no_data_values.contains(&value)
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Indices of the pixels of the current block that are not nodata, from the current one on.
+    fn block_sites(&self) -> impl Iterator<Item = usize> + '_ {
+        let data = self.buffer.as_ref().map_or(&[][..], |buffer| buffer.data());
+        (self.px_idx..data.len()).filter(move |&i| !self.is_no_data(data[i]))
+    }
+
+    /// Moves to the next pixel that is not nodata, returning its position and value.
+    fn next_pixel(&mut self) -> Option<Result<(usize, usize, f64), GdalError>> {
         loop {
             if let Some(ref buffer) = self.buffer {
                 if self.px_idx < self.buffer_x_size * self.buffer_y_size {
@@ -154,20 +341,13 @@ impl Iterator for RasterSiteGenerator {
                     let y_offset = self.px_idx / self.buffer_x_size;
                     let value = buffer.data()[self.px_idx];
                     self.px_idx += 1;
-                    if value == self.no_data_value {
+                    if self.is_no_data(value) {
                         continue;
                     }
 
-                    let x = (self.curr_block_x * self.block_x_size + x_offset) as f64;
-                    let y = (self.curr_block_y * self.block_y_size + y_offset) as f64;
-                    let gt = self.ds.geo_transform().unwrap();
-                    let (lon, lat) = gt.apply(x, y);
-
-                    return Some(Site {
-                        id: value,
-                        lon: GeoDeg::from(lon + (self.px_size_x / 2.0)),
-                        lat: GeoDeg::from(lat - (self.px_size_y / 2.0)),
-                    });
+                    let x = self.curr_block_x * self.block_x_size + x_offset;
+                    let y = self.curr_block_y * self.block_y_size + y_offset;
+                    return Some(Ok((x, y, value)));
                 }
             }
 
@@ -177,10 +357,68 @@ impl Iterator for RasterSiteGenerator {
                 self.curr_block_y += 1;
             }
 
-            if !self.load_next_block() {
-                return None;
+            // A block that can't be read is reported once, and skipped on the next call.
+            match self.load_next_block() {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+
+    fn pixel_to_site(&self, x: usize, y: usize, value: f64) -> Result<Site, SiteGenError> {
+        if value.fract() != 0.0 || value < i32::MIN as f64 || value > i32::MAX as f64 {
+            return Err(SiteGenError::Pixel { x, y, value });
+        }
+
+        let gt = self.ds.geo_transform()?;
+        let (lon, lat) = gt.apply(x as f64, y as f64);
+        let (lon, lat) = (lon + (self.px_size_x / 2.0), lat - (self.px_size_y / 2.0));
+
+        Ok(Site {
+            id: value as i32,
+            lon: GeoDeg::from(lon),
+            lat: GeoDeg::from(lat),
+            covariates: self.sample_covariates(lon, lat),
+            attributes: Default::default(),
+            weight: self
+                .weight
+                .as_ref()
+                .and_then(|(weight, sampler)| sampler.sample(weight.band_index, lon, lat).ok()?),
+        })
+    }
+}
+
+impl Iterator for RasterSiteGenerator {
+    type Item = Result<Site, SiteGenError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(match self.next_pixel()? {
+            Ok((x, y, value)) => self.pixel_to_site(x, y, value),
+            Err(e) => Err(e.into()),
+        })
+    }
+
+    /// Skips `n` entries block by block, without building sites (nor sampling covariates) out of the skipped pixels.
+    /// A block whose sites are all skipped is only counted, and a block that can't be read counts as an entry, as it
+    /// would with [`Iterator::next`].
+    fn nth(&mut self, mut n: usize) -> Option<Self::Item> {
+        while n > 0 {
+            let (skipped, last) = self
+                .block_sites()
+                .take(n)
+                .fold((0, None), |(count, _), i| (count + 1, Some(i)));
+            n -= skipped;
+            if n == 0 {
+                self.px_idx = last.map_or(self.px_idx, |i| i + 1);
+                break;
             }
+            // The entry the next block starts with, or the error reading it, is skipped as well.
+            self.px_idx = self.buffer_x_size * self.buffer_y_size;
+            let _ = self.next_pixel()?;
+            n -= 1;
         }
+        self.next()
     }
 }
 
@@ -190,108 +428,169 @@ mod tests {
 
     #[test]
     fn test_raster_site_generator() {
-        let gen = RasterSiteGenerator::new("testdata/DSSAT-Soils.tif", 0).unwrap();
+        let gen = RasterSiteGenerator::new("testdata/DSSAT-Soils.tif", 0, NoDataPolicy::default())
+            .unwrap();
 
         let expected = vec![
             Site {
                 id: 3894630,
                 lon: GeoDeg::from(12.5418),
                 lat: GeoDeg::from(14.875),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3898947,
                 lon: GeoDeg::from(12.2919),
                 lat: GeoDeg::from(14.7917),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3898948,
                 lon: GeoDeg::from(12.3752),
                 lat: GeoDeg::from(14.7917),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3898949,
                 lon: GeoDeg::from(12.4585),
                 lat: GeoDeg::from(14.7917),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3898975,
                 lon: GeoDeg::from(14.6243),
                 lat: GeoDeg::from(14.7917),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3898976,
                 lon: GeoDeg::from(14.7076),
                 lat: GeoDeg::from(14.7917),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3903264,
                 lon: GeoDeg::from(12.042),
                 lat: GeoDeg::from(14.7084),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3903265,
                 lon: GeoDeg::from(12.1253),
                 lat: GeoDeg::from(14.7084),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3903266,
                 lon: GeoDeg::from(12.2086),
                 lat: GeoDeg::from(14.7084),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3903267,
                 lon: GeoDeg::from(12.2919),
                 lat: GeoDeg::from(14.7084),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3903268,
                 lon: GeoDeg::from(12.3752),
                 lat: GeoDeg::from(14.7084),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3903269,
                 lon: GeoDeg::from(12.4585),
                 lat: GeoDeg::from(14.7084),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3903271,
                 lon: GeoDeg::from(12.6251),
                 lat: GeoDeg::from(14.7084),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3903273,
                 lon: GeoDeg::from(12.7917),
                 lat: GeoDeg::from(14.7084),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3903274,
                 lon: GeoDeg::from(12.875),
                 lat: GeoDeg::from(14.7084),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3903279,
                 lon: GeoDeg::from(13.2915),
                 lat: GeoDeg::from(14.7084),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3903280,
                 lon: GeoDeg::from(13.3748),
                 lat: GeoDeg::from(14.7084),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3903284,
                 lon: GeoDeg::from(13.708),
                 lat: GeoDeg::from(14.7084),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3903286,
                 lon: GeoDeg::from(13.8746),
                 lat: GeoDeg::from(14.7084),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3903293,
                 lon: GeoDeg::from(14.4577),
                 lat: GeoDeg::from(14.7084),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
         ];
 
@@ -304,6 +603,7 @@ mod tests {
 
         let mut i = 0;
         for site in gen {
+            let site = site.unwrap();
             if i < len {
                 assert_eq!(site, expected[i]);
             }
@@ -321,4 +621,109 @@ mod tests {
         assert_eq!(min_lat, 12.0428);
         assert_eq!(max_lat, 14.875);
     }
+
+    #[test]
+    fn test_raster_skip() {
+        let open = || {
+            RasterSiteGenerator::new("testdata/DSSAT-Soils.tif", 0, NoDataPolicy::default())
+                .unwrap()
+        };
+        let all: Vec<Site> = open().map(Result::unwrap).collect();
+        let skipped: Vec<Site> = open().skip(1000).map(Result::unwrap).collect();
+        assert_eq!(skipped, all[1000..]);
+        for n in [0, 1, 999, all.len() - 1] {
+            assert_eq!(open().nth(n).unwrap().unwrap(), all[n]);
+        }
+        assert!(open().nth(all.len()).is_none());
+    }
+
+    #[test]
+    fn test_raster_count_sites() {
+        let count = RasterSiteGenerator::count_sites(
+            "testdata/DSSAT-Soils.tif",
+            0,
+            &NoDataPolicy::default(),
+        )
+        .unwrap();
+        assert_eq!(count, 1157);
+    }
+
+    #[test]
+    fn test_raster_covariates() {
+        let covariates = vec![
+            RasterCovariate {
+                name: "cell".to_string(),
+                file: "testdata/DSSAT-Soils.tif".to_string(),
+                band_index: 0,
+            },
+            RasterCovariate {
+                name: "missing".to_string(),
+                file: "testdata/DSSAT-Soils.tif".to_string(),
+                band_index: 1,
+            },
+        ];
+        assert!(covariates[0].preflight().is_empty());
+        assert_eq!(covariates[1].preflight().len(), 1);
+
+        let gen = RasterSiteGenerator::new("testdata/DSSAT-Soils.tif", 0, NoDataPolicy::default())
+            .unwrap()
+            .with_covariates(covariates)
+            .unwrap();
+
+        // The covariate is read from the very band sites are read from, so it must match the site ID.
+        let mut count = 0;
+        for site in gen.take(50) {
+            let site = site.unwrap();
+            assert_eq!(site.covariates.get("cell"), Some(&(site.id as f64)));
+            assert!(!site.covariates.contains_key("missing"));
+            count += 1;
+        }
+        assert_eq!(count, 50);
+    }
+
+    #[test]
+    fn test_raster_weight() {
+        let weight = RasterCovariate {
+            name: "weight".to_string(),
+            file: "testdata/DSSAT-Soils.tif".to_string(),
+            band_index: 0,
+        };
+        let gen = RasterSiteGenerator::new("testdata/DSSAT-Soils.tif", 0, NoDataPolicy::default())
+            .unwrap()
+            .with_weight(Some(weight))
+            .unwrap();
+
+        for site in gen.take(10) {
+            let site = site.unwrap();
+            assert_eq!(site.weight, Some(site.id as f64));
+            assert!(site.covariates.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_no_data_policy() {
+        let legacy = NoDataPolicy::default();
+        assert_eq!(legacy.resolve(None), vec![0.0]);
+        assert_eq!(legacy.resolve(Some(-9999.0)), vec![-9999.0]);
+        assert_eq!(legacy.resolve(Some(f64::NAN)), Vec::<f64>::new());
+
+        let multiple = NoDataPolicy {
+            values: vec![-1.0, -2.0],
+            treat_zero_as_nodata: None,
+        };
+        assert_eq!(multiple.resolve(Some(-9999.0)), vec![-1.0, -2.0, -9999.0]);
+
+        let zero = NoDataPolicy {
+            values: vec![],
+            treat_zero_as_nodata: Some(true),
+        };
+        assert_eq!(zero.resolve(Some(-9999.0)), vec![-9999.0, 0.0]);
+
+        let not_zero = NoDataPolicy {
+            values: vec![],
+            treat_zero_as_nodata: Some(false),
+        };
+        assert_eq!(not_zero.resolve(Some(0.0)), Vec::<f64>::new());
+        assert_eq!(not_zero.resolve(None), Vec::<f64>::new());
+    }
 }
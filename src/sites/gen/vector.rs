@@ -1,7 +1,8 @@
-use super::super::Site;
+use super::super::{Site, SiteGenError};
 use crate::data::GeoDeg;
-use gdal::errors::GdalError;
-use gdal::vector::{Feature, FeatureIterator, Layer, LayerAccess};
+use gdal::vector::{
+    Feature, FeatureIterator, FieldValue, Layer, LayerAccess, OGRFieldType, OGRwkbGeometryType,
+};
 use gdal::Dataset;
 use std::rc::Rc;
 
@@ -17,37 +18,175 @@ use std::rc::Rc;
 /// ```
 pub struct VectorSiteGenerator {
     site_id_key: String,
+    weight_key: Option<String>,
     ds: Rc<Dataset>,
+    /// Indexes of the layers to go through, in order.
+    layers: Vec<usize>,
+    /// Position in `layers` of the current layer.
     curr_layer: usize,
     layer: Option<Layer<'static>>,
     feat_iter: Box<Option<FeatureIterator<'static>>>,
+    /// Index of the next feature, counted across layers. Used to locate errors.
+    feature_idx: usize,
 }
 
 impl VectorSiteGenerator {
     /// Constructs a new VectorSiteGenerator from a GDAL vector dataset.
     /// Parameter "path" is the GDAL-valid path to the dataset.
-    /// Parameter "site_id_key" is the name of the field in the dataset that contains the site ID. Must be an int32, otherwise the feature is skipped.
+    /// Parameter "site_id_key" is the name of the field in the dataset that contains the site ID. Must be an int32, otherwise the feature is reported as an error.
     pub fn new(path: &str, site_id_key: String) -> Result<Self, Box<dyn std::error::Error>> {
         let ds = Rc::new(Dataset::open(path)?);
         Ok(VectorSiteGenerator {
             site_id_key,
+            weight_key: None,
+            layers: (0..ds.layer_count()).collect(),
             ds,
             curr_layer: 0,
             layer: None,
             feat_iter: Box::new(None),
+            feature_idx: 0,
         })
     }
+
+    /// Reads the [`Site::weight`] of the generated sites from the numeric field named `weight_key`. Sites where the field
+    /// is null have no weight.
+    pub fn with_weight_key(mut self, weight_key: Option<String>) -> Self {
+        self.weight_key = weight_key;
+        self
+    }
+
+    /// Only reads sites from the layer named `layer` (if any), instead of every layer of the dataset.
+    pub fn with_layer(mut self, layer: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
+        if let Some(name) = layer {
+            self.layers = vec![layer_index(&self.ds, name)?];
+        }
+        Ok(self)
+    }
+
+    /// Checks that the dataset at `path` can be opened, that it has a layer named `layer` (if any), and that every layer
+    /// (or just that one) has an Int32 field named `site_id_key`, a numeric field named `weight_key` (if any) and point
+    /// geometries.
+    /// Returns every problem found.
+    pub fn preflight(
+        path: &str,
+        layer: Option<&str>,
+        site_id_key: &str,
+        weight_key: Option<&str>,
+    ) -> Vec<String> {
+        let ds = match Dataset::open(path) {
+            Ok(ds) => ds,
+            Err(e) => return vec![format!("Unable to open vector dataset {}: {}", path, e)],
+        };
+
+        if ds.layer_count() == 0 {
+            return vec![format!("Vector dataset {} has no layers", path)];
+        }
+
+        let layers = match layer.map(|name| layer_index(&ds, name)) {
+            Some(Ok(index)) => vec![index],
+            Some(Err(e)) => return vec![format!("Vector dataset {}: {}", path, e)],
+            None => (0..ds.layer_count()).collect(),
+        };
+
+        let mut issues = Vec::new();
+        for layer in layers.into_iter().filter_map(|i| ds.layer(i).ok()) {
+            let defn = layer.defn();
+            match defn.fields().find(|f| f.name() == site_id_key) {
+                None => issues.push(format!(
+                    "Layer \"{}\" of {} has no field named \"{}\"",
+                    layer.name(),
+                    path,
+                    site_id_key
+                )),
+                Some(field) if field.field_type() != OGRFieldType::OFTInteger => {
+                    issues.push(format!(
+                        "Field \"{}\" of layer \"{}\" of {} is not of type Int32",
+                        site_id_key,
+                        layer.name(),
+                        path
+                    ))
+                }
+                Some(_) => {}
+            }
+
+            if let Some(weight_key) = weight_key {
+                match defn.fields().find(|f| f.name() == weight_key) {
+                    None => issues.push(format!(
+                        "Layer \"{}\" of {} has no field named \"{}\"",
+                        layer.name(),
+                        path,
+                        weight_key
+                    )),
+                    Some(field)
+                        if !matches!(
+                            field.field_type(),
+                            OGRFieldType::OFTInteger
+                                | OGRFieldType::OFTInteger64
+                                | OGRFieldType::OFTReal
+                        ) =>
+                    {
+                        issues.push(format!(
+                            "Field \"{}\" of layer \"{}\" of {} is not numeric",
+                            weight_key,
+                            layer.name(),
+                            path
+                        ))
+                    }
+                    Some(_) => {}
+                }
+            }
+
+            let geometry_type = defn
+                .geom_fields()
+                .next()
+                .map(|g| g.field_type())
+                .unwrap_or(OGRwkbGeometryType::wkbNone);
+            if geometry_type != OGRwkbGeometryType::wkbPoint
+                && geometry_type != OGRwkbGeometryType::wkbUnknown
+            {
+                issues.push(format!(
+                    "Layer \"{}\" of {} does not have point geometries (found {})",
+                    layer.name(),
+                    path,
+                    gdal::vector::geometry_type_to_name(geometry_type)
+                ));
+            }
+        }
+        issues
+    }
+
+    /// Counts the features of every layer (or just the one named `layer`) of the dataset at `path`, if GDAL can do it
+    /// without reading them all.
+    pub fn count_sites(path: &str, layer: Option<&str>) -> Option<usize> {
+        let ds = Dataset::open(path).ok()?;
+        let count = match layer {
+            Some(name) => ds.layer_by_name(name).ok()?.try_feature_count()?,
+            None => ds
+                .layers()
+                .map(|layer| layer.try_feature_count())
+                .sum::<Option<u64>>()?,
+        };
+        Some(count as usize)
+    }
 }
 
-impl Iterator for VectorSiteGenerator {
-    type Item = Site;
+/// Index of the layer named `name`, or an error listing the layers of the dataset.
+fn layer_index(ds: &Dataset, name: &str) -> Result<usize, String> {
+    let names: Vec<String> = ds.layers().map(|layer| layer.name()).collect();
+    names
+        .iter()
+        .position(|n| n == name)
+        .ok_or_else(|| format!("No layer named \"{}\" (layers: {})", name, names.join(", ")))
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
+impl VectorSiteGenerator {
+    /// Moves to the next feature, going through the layers in order.
+    fn next_feature(&mut self) -> Option<Feature<'static>> {
         if self.feat_iter.is_none() {
             self.layer = self
-                .ds
-                .layer(self.curr_layer)
-                .ok()
+                .layers
+                .get(self.curr_layer)
+                .and_then(|&index| self.ds.layer(index).ok())
                 .map(|l| unsafe { std::mem::transmute::<Layer, Layer<'static>>(l) });
 
             if let Some(layer) = self.layer.as_mut() {
@@ -56,18 +195,21 @@ impl Iterator for VectorSiteGenerator {
                         layer.features(),
                     )
                 }));
-                return self.next();
+                return self.next_feature();
             }
             return None;
         }
 
         match self.feat_iter.as_mut() {
             Some(feat_iter) => match feat_iter.next() {
-                Some(feat) => feature_to_site(&feat, &self.site_id_key),
+                Some(feat) => {
+                    self.feature_idx += 1;
+                    Some(feat)
+                }
                 None => {
                     self.curr_layer += 1;
                     self.feat_iter = Box::new(None);
-                    self.next()
+                    self.next_feature()
                 }
             },
             None => None,
@@ -75,39 +217,66 @@ impl Iterator for VectorSiteGenerator {
     }
 }
 
-fn feature_to_site(feature: &Feature, site_id_key: &str) -> Option<Site> {
-    if let Some(geometry) = feature.geometry() {
-        if geometry.geometry_type() != gdal::vector::OGRwkbGeometryType::wkbPoint {
-            return None;
-        }
+impl Iterator for VectorSiteGenerator {
+    type Item = Result<Site, SiteGenError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let feat = self.next_feature()?;
+        let index = self.feature_idx - 1;
+        Some(
+            feature_to_site(&feat, &self.site_id_key, self.weight_key.as_deref())
+                .map_err(|message| SiteGenError::Feature { index, message }),
+        )
+    }
 
-        // TODO better error handling. At least expose something in the interface to let consumers know if something went wrong.
-        let id_result = feature
-            .field(site_id_key)
-            .and_then(|id| {
-                id.ok_or(GdalError::NullPointer {
-                    method_name: "dummy",
-                    msg: "Feature has no id".to_string(),
-                })
-            })
-            .map(|id| id.into_int())
-            .and_then(|id| {
-                id.ok_or(GdalError::NullPointer {
-                    method_name: "dummy",
-                    msg: "Feature ID is not i32".to_string(),
-                })
-            });
-
-        if let Ok(id) = id_result {
-            let (lon, lat, _) = geometry.get_point(0);
-            return Some(Site {
-                id,
-                lon: GeoDeg::from(lon),
-                lat: GeoDeg::from(lat),
-            });
+    /// Skips `n` features without turning them into sites.
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        for _ in 0..n {
+            self.next_feature()?;
         }
+        self.next()
     }
-    None
+}
+
+fn feature_to_site(
+    feature: &Feature,
+    site_id_key: &str,
+    weight_key: Option<&str>,
+) -> Result<Site, String> {
+    let geometry = feature.geometry().ok_or("Feature has no geometry")?;
+    if geometry.geometry_type() != OGRwkbGeometryType::wkbPoint {
+        return Err(format!(
+            "Expected a Point geometry, found {}",
+            gdal::vector::geometry_type_to_name(geometry.geometry_type())
+        ));
+    }
+
+    let id = match feature.field(site_id_key) {
+        Ok(Some(value)) => value
+            .into_int()
+            .ok_or_else(|| format!("Field \"{}\" is not an int32", site_id_key))?,
+        Ok(None) => return Err(format!("Field \"{}\" is null", site_id_key)),
+        Err(e) => return Err(format!("Unable to read field \"{}\": {}", site_id_key, e)),
+    };
+
+    let weight = match weight_key.map(|key| (key, feature.field(key))) {
+        None | Some((_, Ok(None))) => None,
+        Some((_, Ok(Some(FieldValue::RealValue(value))))) => Some(value),
+        Some((_, Ok(Some(FieldValue::IntegerValue(value))))) => Some(value as f64),
+        Some((_, Ok(Some(FieldValue::Integer64Value(value))))) => Some(value as f64),
+        Some((key, Ok(Some(_)))) => return Err(format!("Field \"{}\" is not numeric", key)),
+        Some((key, Err(e))) => return Err(format!("Unable to read field \"{}\": {}", key, e)),
+    };
+
+    let (lon, lat, _) = geometry.get_point(0);
+    Ok(Site {
+        id,
+        lon: GeoDeg::from(lon),
+        lat: GeoDeg::from(lat),
+        covariates: Default::default(),
+        attributes: Default::default(),
+        weight,
+    })
 }
 
 #[cfg(test)]
@@ -124,101 +293,161 @@ mod tests {
                 id: 3989689,
                 lon: GeoDeg::from(14.125),
                 lat: GeoDeg::from(13.042),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3989690,
                 lon: GeoDeg::from(14.208),
                 lat: GeoDeg::from(13.042),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3989691,
                 lon: GeoDeg::from(14.292),
                 lat: GeoDeg::from(13.042),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3989692,
                 lon: GeoDeg::from(14.375),
                 lat: GeoDeg::from(13.042),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3989693,
                 lon: GeoDeg::from(14.458),
                 lat: GeoDeg::from(13.042),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3994009,
                 lon: GeoDeg::from(14.125),
                 lat: GeoDeg::from(12.958),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3994010,
                 lon: GeoDeg::from(14.208),
                 lat: GeoDeg::from(12.958),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3994011,
                 lon: GeoDeg::from(14.292),
                 lat: GeoDeg::from(12.958),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3994012,
                 lon: GeoDeg::from(14.375),
                 lat: GeoDeg::from(12.958),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3994013,
                 lon: GeoDeg::from(14.458),
                 lat: GeoDeg::from(12.958),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3998329,
                 lon: GeoDeg::from(14.125),
                 lat: GeoDeg::from(12.875),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3998330,
                 lon: GeoDeg::from(14.208),
                 lat: GeoDeg::from(12.875),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3998331,
                 lon: GeoDeg::from(14.292),
                 lat: GeoDeg::from(12.875),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3998332,
                 lon: GeoDeg::from(14.375),
                 lat: GeoDeg::from(12.875),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3998333,
                 lon: GeoDeg::from(14.458),
                 lat: GeoDeg::from(12.875),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 3998334,
                 lon: GeoDeg::from(14.542),
                 lat: GeoDeg::from(12.875),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 4002650,
                 lon: GeoDeg::from(14.208),
                 lat: GeoDeg::from(12.792),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 4002651,
                 lon: GeoDeg::from(14.292),
                 lat: GeoDeg::from(12.792),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 4002652,
                 lon: GeoDeg::from(14.375),
                 lat: GeoDeg::from(12.792),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
             Site {
                 id: 4002653,
                 lon: GeoDeg::from(14.458),
                 lat: GeoDeg::from(12.792),
+                covariates: Default::default(),
+                attributes: Default::default(),
+                weight: None,
             },
         ];
 
@@ -231,6 +460,7 @@ mod tests {
 
         let mut i = 0;
         for site in gen {
+            let site = site.unwrap();
             if i < len {
                 assert_eq!(site, expected[i]);
             }
@@ -248,4 +478,38 @@ mod tests {
         assert_eq!(min_lat, 12.042);
         assert_eq!(max_lat, 14.875);
     }
+
+    #[test]
+    fn test_vector_layer() {
+        let path = "testdata/DSSAT-Soils.shp.zip";
+        let name = Dataset::open(path).unwrap().layer(0).unwrap().name();
+        assert!(VectorSiteGenerator::preflight(path, Some(&name), "CELL5M", None).is_empty());
+        assert_eq!(
+            VectorSiteGenerator::preflight(path, Some("nope"), "CELL5M", None),
+            vec![format!(
+                "Vector dataset {}: No layer named \"nope\" (layers: {})",
+                path, name
+            )]
+        );
+
+        let gen = VectorSiteGenerator::new(path, "CELL5M".to_string()).unwrap();
+        assert_eq!(gen.with_layer(Some(&name)).unwrap().count(), 1157);
+        let gen = VectorSiteGenerator::new(path, "CELL5M".to_string()).unwrap();
+        assert!(gen.with_layer(Some("nope")).is_err());
+    }
+
+    #[test]
+    fn test_vector_weight() {
+        let path = "testdata/DSSAT-Soils.shp.zip";
+        assert!(VectorSiteGenerator::preflight(path, None, "CELL5M", Some("CELL5M")).is_empty());
+        assert!(!VectorSiteGenerator::preflight(path, None, "CELL5M", Some("AREA")).is_empty());
+
+        let gen = VectorSiteGenerator::new(path, "CELL5M".to_string())
+            .unwrap()
+            .with_weight_key(Some("CELL5M".to_string()));
+        for site in gen.take(10) {
+            let site = site.unwrap();
+            assert_eq!(site.weight, Some(site.id as f64));
+        }
+    }
 }
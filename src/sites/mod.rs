@@ -1,10 +1,18 @@
+pub mod cache;
 pub mod config;
 pub mod drivers;
+pub mod enrich;
+pub mod filter;
 pub mod gen;
+pub mod sample;
+pub mod transform;
 
 use crate::data::GeoDeg;
+use gdal::errors::GdalError;
 use std::any::Any;
+use std::collections::HashMap;
 use std::error::Error;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 /// Constructs a new [`SiteGenerator`] of type [`G`] from the config [`C`].
@@ -15,14 +23,45 @@ type SitegenFactory<G: SiteGenerator, C> = Arc<dyn Fn(C) -> Result<G, Box<dyn Er
 type SitegenConfigDeserializer<C> =
     Arc<dyn Fn(serde_json::Value) -> Result<C, serde_json::error::Error>>;
 
+/// Checks a config of type [`C`] without building the [`SiteGenerator`], returning every problem found
+/// (e.g. missing files, bands, layers or fields). Used to report problems before any processing starts.
+type SitegenPreflight<C> = Arc<dyn Fn(&C) -> Vec<String>>;
+
+/// Counts the entries a config of type [`C`] yields without building the [`SiteGenerator`], faster than iterating it
+/// (e.g. from the feature count of a layer). Entries that turn out to be errors are counted too. `None` if the count
+/// is unknown. Used to report progress.
+type SitegenCount<C> = Arc<dyn Fn(&C) -> Option<usize>>;
+
+/// An entry of a site source that could not be turned into a [`Site`]. The generator goes on with the next entry.
+#[derive(Debug, thiserror::Error)]
+pub enum SiteGenError {
+    #[error("Feature {index}: {message}")]
+    Feature { index: usize, message: String },
+    #[error("Row {row}: {message}")]
+    Row { row: usize, message: String },
+    #[error("Pixel ({x}, {y}): value {value} is not a valid site ID")]
+    Pixel { x: usize, y: usize, value: f64 },
+    #[error(transparent)]
+    Gdal(#[from] GdalError),
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[error("Site {id}: unable to transform its ID: {message}")]
+    IdTransform { id: i32, message: String },
+    #[error("Unable to write the site cache {}: {}", .0.display(), .1)]
+    Cache(PathBuf, csv::Error),
+}
+
 /// SiteGenerator allows for streaming Sites from an undetermined source.
 /// The order of the sites is not guaranteed, as different file formats may index their data differently, and pre-sorting is not possible.
-pub trait SiteGenerator: Iterator<Item = Site> {}
-impl<T: Iterator<Item = Site>> SiteGenerator for T {}
+/// Entries of the source that can't be read or turned into a site are yielded as errors, so they can be reported.
+pub trait SiteGenerator: Iterator<Item = Result<Site, SiteGenError>> {}
+impl<T: Iterator<Item = Result<Site, SiteGenError>>> SiteGenerator for T {}
 
 pub struct SiteGeneratorDriver<G: SiteGenerator, C> {
     pub create: SitegenFactory<G, C>,
     pub config_deserializer: SitegenConfigDeserializer<C>,
+    pub preflight: SitegenPreflight<C>,
+    pub count: SitegenCount<C>,
 }
 
 impl<G: SiteGenerator, C> Clone for SiteGeneratorDriver<G, C> {
@@ -30,6 +69,8 @@ impl<G: SiteGenerator, C> Clone for SiteGeneratorDriver<G, C> {
         SiteGeneratorDriver {
             create: self.create.clone(),
             config_deserializer: self.config_deserializer.clone(),
+            preflight: self.preflight.clone(),
+            count: self.count.clone(),
         }
     }
 }
@@ -40,6 +81,8 @@ impl<G: SiteGenerator, C> SiteGeneratorDriver<G, C> {
         G: SiteGenerator + 'static,
         C: Any + 'static,
     {
+        let preflight = self.preflight.clone();
+        let count = self.count.clone();
         SiteGeneratorDriver {
             create: Arc::new(move |c: Box<dyn Any>| {
                 let config = c
@@ -52,6 +95,13 @@ impl<G: SiteGenerator, C> SiteGeneratorDriver<G, C> {
                 let concrete_config = (self.config_deserializer)(v)?;
                 Ok(Box::new(concrete_config) as Box<dyn Any>)
             }),
+            preflight: Arc::new(
+                move |c: &Box<dyn Any>| match c.as_ref().downcast_ref::<C>() {
+                    Some(config) => preflight(config),
+                    None => vec!["Failed to downcast config".to_string()],
+                },
+            ),
+            count: Arc::new(move |c: &Box<dyn Any>| count(c.as_ref().downcast_ref::<C>()?)),
         }
     }
 }
@@ -61,4 +111,11 @@ pub struct Site {
     pub id: i32,
     pub lon: GeoDeg,
     pub lat: GeoDeg,
+    /// Values read along with the site by its generator (e.g. extra raster bands), exposed to templates by name.
+    pub covariates: HashMap<String, f64>,
+    /// Text values read along with the site by its generator (e.g. the region of a feature), exposed to templates by
+    /// name like the covariates.
+    pub attributes: HashMap<String, String>,
+    /// Weight of the site in aggregations (e.g. its harvested area), if its generator reads one.
+    pub weight: Option<f64>,
 }
@@ -0,0 +1,246 @@
+//! Sampling of the sites of a [`super::SiteGenerator`], applied after its filters.
+
+use super::Site;
+use crate::utils::rng::Rng;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(tag = "method", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SampleConfig {
+    /// `size` sites drawn uniformly from every site of the source (reservoir sampling), so the sample covers the whole
+    /// extent instead of the first rows or blocks of the dataset. The same seed always draws the same sites.
+    Random {
+        size: usize,
+        #[serde(default)]
+        seed: u64,
+    },
+    /// `per_stratum` sites drawn uniformly from each stratum, so every part of the extent (or every class of an
+    /// attribute) is covered. Strata are either the cells of a `[columns, rows]` grid laid over the extent of the
+    /// sites, or the distinct values of a site `attribute` (a covariate, see [`Site::covariates`]). Sites without the
+    /// attribute are left out.
+    Stratified {
+        #[serde(default)]
+        grid: Option<[usize; 2]>,
+        #[serde(default)]
+        attribute: Option<String>,
+        per_stratum: usize,
+        #[serde(default)]
+        seed: u64,
+    },
+}
+
+impl SampleConfig {
+    /// Checks that stratified samples have exactly one way of telling strata apart.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            SampleConfig::Stratified {
+                grid, attribute, ..
+            } => match (grid, attribute) {
+                (Some([columns, rows]), None) if *columns == 0 || *rows == 0 => {
+                    Err("Stratification grid must have at least one column and one row".to_string())
+                }
+                (Some(_), None) | (None, Some(_)) => Ok(()),
+                _ => Err(
+                    "Stratified samples need exactly one of \"grid\" or \"attribute\"".to_string(),
+                ),
+            },
+            SampleConfig::Random { .. } => Ok(()),
+        }
+    }
+
+    /// Consumes `sites`, returning the sample in the order the sites were generated.
+    pub fn sample(&self, sites: impl Iterator<Item = Site>) -> Vec<Site> {
+        match self {
+            SampleConfig::Random { size, seed } => {
+                let mut rng = Rng::new(*seed);
+                let mut reservoir = Reservoir::new(*size);
+                for (i, site) in sites.enumerate() {
+                    reservoir.offer(i, site, &mut rng);
+                }
+                reservoir.into_sites()
+            }
+            SampleConfig::Stratified {
+                grid,
+                attribute,
+                per_stratum,
+                seed,
+            } => {
+                let mut rng = Rng::new(*seed);
+                let sites: Vec<Site> = sites.collect();
+                let strata: Vec<Option<u64>> = match (grid, attribute) {
+                    (Some(grid), _) => grid_strata(&sites, *grid),
+                    (None, Some(attribute)) => sites
+                        .iter()
+                        .map(|site| site.covariates.get(attribute).map(|v| v.to_bits()))
+                        .collect(),
+                    (None, None) => vec![None; sites.len()],
+                };
+
+                let mut reservoirs: HashMap<u64, Reservoir> = HashMap::new();
+                for (i, (site, stratum)) in sites.into_iter().zip(strata).enumerate() {
+                    if let Some(stratum) = stratum {
+                        reservoirs
+                            .entry(stratum)
+                            .or_insert_with(|| Reservoir::new(*per_stratum))
+                            .offer(i, site, &mut rng);
+                    }
+                }
+
+                let mut sample: Vec<(usize, Site)> =
+                    reservoirs.into_values().flat_map(|r| r.items).collect();
+                sample.sort_by_key(|(i, _)| *i);
+                sample.into_iter().map(|(_, site)| site).collect()
+            }
+        }
+    }
+}
+
+/// Index of the cell of a `[columns, rows]` grid laid over the extent of `sites` each site falls in.
+fn grid_strata(sites: &[Site], [columns, rows]: [usize; 2]) -> Vec<Option<u64>> {
+    let (mut min_lon, mut min_lat) = (f64::MAX, f64::MAX);
+    let (mut max_lon, mut max_lat) = (f64::MIN, f64::MIN);
+    for site in sites {
+        min_lon = min_lon.min(site.lon.as_f64());
+        max_lon = max_lon.max(site.lon.as_f64());
+        min_lat = min_lat.min(site.lat.as_f64());
+        max_lat = max_lat.max(site.lat.as_f64());
+    }
+
+    // Sites on the maximum edge belong to the last cell, not to one past it.
+    let cell = |value: f64, min: f64, max: f64, cells: usize| {
+        if max > min {
+            (((value - min) / (max - min) * cells as f64) as usize).min(cells - 1)
+        } else {
+            0
+        }
+    };
+    sites
+        .iter()
+        .map(|site| {
+            let column = cell(site.lon.as_f64(), min_lon, max_lon, columns);
+            let row = cell(site.lat.as_f64(), min_lat, max_lat, rows);
+            Some((row * columns + column) as u64)
+        })
+        .collect()
+}
+
+/// Algorithm R: every site offered ends up in the reservoir with probability `size / n`, in a single pass.
+struct Reservoir {
+    size: usize,
+    seen: usize,
+    items: Vec<(usize, Site)>,
+}
+
+impl Reservoir {
+    fn new(size: usize) -> Self {
+        Reservoir {
+            size,
+            seen: 0,
+            items: Vec::new(),
+        }
+    }
+
+    /// Offers the `i`th site generated.
+    fn offer(&mut self, i: usize, site: Site, rng: &mut Rng) {
+        self.seen += 1;
+        if self.items.len() < self.size {
+            self.items.push((i, site));
+        } else if self.size > 0 {
+            let j = rng.below(self.seen);
+            if j < self.size {
+                self.items[j] = (i, site);
+            }
+        }
+    }
+
+    /// The sites kept, in the order they were generated.
+    fn into_sites(mut self) -> Vec<Site> {
+        self.items.sort_by_key(|(i, _)| *i);
+        self.items.into_iter().map(|(_, site)| site).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::GeoDeg;
+
+    fn sites(n: i32) -> impl Iterator<Item = Site> {
+        (0..n).map(|id| Site {
+            id,
+            lon: GeoDeg::from(0.0),
+            lat: GeoDeg::from(0.0),
+            covariates: Default::default(),
+            attributes: Default::default(),
+            weight: None,
+        })
+    }
+
+    /// A 10x10 grid of sites spaced 1 degree apart, with the covariate `zone` set to 0 on the western half and 1 on the
+    /// eastern half.
+    fn grid() -> impl Iterator<Item = Site> {
+        (0..100).map(|id| Site {
+            id,
+            lon: GeoDeg::from((id % 10) as f64),
+            lat: GeoDeg::from((id / 10) as f64),
+            covariates: [("zone".to_string(), ((id % 10) / 5) as f64)].into(),
+            attributes: Default::default(),
+            weight: None,
+        })
+    }
+
+    #[test]
+    fn test_random_sample() {
+        let config: SampleConfig =
+            serde_json::from_str(r#"{ "method": "random", "size": 10, "seed": 42 }"#).unwrap();
+
+        let sample = config.sample(sites(1000));
+        assert_eq!(sample.len(), 10);
+        assert!(sample.windows(2).all(|w| w[0].id < w[1].id));
+        // Not simply the first sites of the source.
+        assert!(sample.last().unwrap().id >= 10);
+        assert_eq!(sample, config.sample(sites(1000)));
+
+        assert_eq!(config.sample(sites(5)).len(), 5);
+    }
+
+    #[test]
+    fn test_stratified_sample() {
+        let by_grid: SampleConfig = serde_json::from_str(
+            r#"{ "method": "stratified", "grid": [2, 2], "per_stratum": 3, "seed": 7 }"#,
+        )
+        .unwrap();
+        assert!(by_grid.validate().is_ok());
+        let sample = by_grid.sample(grid());
+        assert_eq!(sample.len(), 12);
+        for (west, south) in [(true, true), (true, false), (false, true), (false, false)] {
+            let count = sample
+                .iter()
+                .filter(|s| (s.lon.as_f64() < 5.0) == west && (s.lat.as_f64() < 5.0) == south)
+                .count();
+            assert_eq!(count, 3);
+        }
+        assert_eq!(sample, by_grid.sample(grid()));
+
+        let by_attribute: SampleConfig = serde_json::from_str(
+            r#"{ "method": "stratified", "attribute": "zone", "per_stratum": 4 }"#,
+        )
+        .unwrap();
+        let sample = by_attribute.sample(grid());
+        assert_eq!(sample.len(), 8);
+        assert_eq!(
+            sample
+                .iter()
+                .filter(|s| s.covariates["zone"] == 0.0)
+                .count(),
+            4
+        );
+
+        let both: SampleConfig = serde_json::from_str(
+            r#"{ "method": "stratified", "grid": [2, 2], "attribute": "zone", "per_stratum": 4 }"#,
+        )
+        .unwrap();
+        assert!(both.validate().is_err());
+    }
+}
@@ -0,0 +1,148 @@
+//! Rewrites of the sites of a [`super::SiteGenerator`], applied before its filters.
+
+use super::{Site, SiteGenError};
+use serde::Deserialize;
+use std::error::Error;
+
+/// Name the expression of [`IdTransform::Template`] is registered under.
+const TEMPLATE_NAME: &str = "id_transform";
+
+/// Rewrites the ID of every site, so the IDs of different sources (e.g. CELL5M IDs of different products) can be told
+/// apart when the sources are combined. Configured as `{ "offset": 1000000 }`, `{ "modulo": 100000 }` or
+/// `{ "template": "{{ id * 10 + 1 }}" }`.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum IdTransform {
+    /// Adds the offset to every ID.
+    Offset(i64),
+    /// Keeps the (non-negative) remainder of every ID divided by the modulo.
+    Modulo(i64),
+    /// Renders a Tera template with the `id`, `lon`, `lat`, covariates and attributes of the site. Must render to an integer.
+    Template(String),
+}
+
+/// An [`IdTransform`] ready to be applied, with its template (if any) parsed.
+pub struct IdTransformer {
+    transform: IdTransform,
+    tera: tera::Tera,
+}
+
+impl IdTransform {
+    /// Checks the modulo and parses the template.
+    pub fn build(&self) -> Result<IdTransformer, Box<dyn Error>> {
+        let mut tera = tera::Tera::default();
+        match self {
+            IdTransform::Modulo(modulo) if *modulo <= 0 => {
+                return Err(format!("ID modulo must be positive, got {}", modulo).into())
+            }
+            IdTransform::Template(template) => tera
+                .add_raw_template(TEMPLATE_NAME, template)
+                .map_err(|e| format!("Invalid ID template: {}", error_chain(&e)))?,
+            _ => {}
+        }
+        Ok(IdTransformer {
+            transform: self.clone(),
+            tera,
+        })
+    }
+}
+
+impl IdTransformer {
+    pub fn apply(&self, mut site: Site) -> Result<Site, SiteGenError> {
+        let original = site.id;
+        let error = |message: String| SiteGenError::IdTransform {
+            id: original,
+            message,
+        };
+        let id = match &self.transform {
+            IdTransform::Offset(offset) => site.id as i64 + offset,
+            IdTransform::Modulo(modulo) => (site.id as i64).rem_euclid(*modulo),
+            IdTransform::Template(_) => {
+                let mut ctx = tera::Context::new();
+                for (k, v) in &site.attributes {
+                    ctx.insert(k, v);
+                }
+                for (k, v) in &site.covariates {
+                    ctx.insert(k, v);
+                }
+                ctx.insert("id", &site.id);
+                ctx.insert("lon", &site.lon.as_f32());
+                ctx.insert("lat", &site.lat.as_f32());
+
+                let rendered = self
+                    .tera
+                    .render(TEMPLATE_NAME, &ctx)
+                    .map_err(|e| error(error_chain(&e)))?;
+                let rendered = rendered.trim();
+                rendered
+                    .parse()
+                    .map_err(|_| error(format!("\"{}\" is not an integer", rendered)))?
+            }
+        };
+
+        site.id = i32::try_from(id).map_err(|_| error(format!("{} is not an int32", id)))?;
+        Ok(site)
+    }
+}
+
+/// Tera only reports the outermost error in its [`std::fmt::Display`] implementation, which usually hides the cause.
+fn error_chain(err: &dyn Error) -> String {
+    let mut msg = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        msg.push_str(": ");
+        msg.push_str(&err.to_string());
+        source = err.source();
+    }
+    msg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::GeoDeg;
+
+    fn site(id: i32) -> Site {
+        Site {
+            id,
+            lon: GeoDeg::from(12.5),
+            lat: GeoDeg::from(-3.25),
+            covariates: Default::default(),
+            attributes: Default::default(),
+            weight: None,
+        }
+    }
+
+    fn transform(json: &str) -> IdTransformer {
+        serde_json::from_str::<IdTransform>(json)
+            .unwrap()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_id_transform() {
+        let offset = transform(r#"{ "offset": 10000000 }"#);
+        assert_eq!(offset.apply(site(3989689)).unwrap().id, 13989689);
+        assert!(offset.apply(site(i32::MAX)).is_err());
+
+        let modulo = transform(r#"{ "modulo": 1000 }"#);
+        assert_eq!(modulo.apply(site(3989689)).unwrap().id, 689);
+        assert_eq!(modulo.apply(site(-1)).unwrap().id, 999);
+        assert!(serde_json::from_str::<IdTransform>(r#"{ "modulo": 0 }"#)
+            .unwrap()
+            .build()
+            .is_err());
+
+        let template = transform(r#"{ "template": "{{ id * 10 + 1 }}" }"#);
+        assert_eq!(template.apply(site(3989689)).unwrap().id, 39896891);
+        let not_an_integer = transform(r#"{ "template": "{{ lat }}" }"#);
+        assert!(not_an_integer.apply(site(1)).is_err());
+        assert!(
+            serde_json::from_str::<IdTransform>(r#"{ "template": "{{ id" }"#)
+                .unwrap()
+                .build()
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,122 @@
+//! Module _soil_ looks up DSSAT soil profiles by soil ID, either to copy them into context directories or to expose
+//! their fields to templates.
+
+pub mod sol;
+
+use crate::processing::context::{Context, ContextEvaluationError, ContextValue};
+use serde::{Deserialize, Serialize};
+use sol::SoilProfile;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use validator::ValidationError;
+
+static ERRCODE_SOIL_FILE_NOT_FOUND: &str = "ERRCODE_SOIL_FILE_NOT_FOUND";
+
+#[derive(Debug, Error)]
+pub enum SoilError {
+    #[error("Failed to read soil file {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("Soil directory {0} has no .SOL files")]
+    Empty(PathBuf),
+}
+
+/// Where the soil profiles of a run come from and what to do with them.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SoilConfig {
+    /// A master `.SOL` file, or a directory of `.SOL` archives (e.g. one per region) which are all loaded.
+    pub file: PathBuf,
+
+    /// Soil ID of each site. May interpolate context variables (e.g. `"IB${soil_code}"`).
+    /// If not set, the site ID is used, as in the original Pythia.
+    #[serde(default)]
+    pub id: Option<ContextValue>,
+
+    /// Copies the profile into every context directory, as [`SoilConfig::output_file`].
+    #[serde(default = "default_copy")]
+    pub copy: bool,
+
+    #[serde(default = "default_output_file")]
+    pub output_file: String,
+}
+
+fn default_copy() -> bool {
+    true
+}
+
+fn default_output_file() -> String {
+    "SOIL.SOL".to_string()
+}
+
+pub fn validate_soil(soil: &SoilConfig) -> Result<(), ValidationError> {
+    if !soil.file.exists() {
+        let msg = format!("Soil file {} does not exist", soil.file.display());
+        return Err(ValidationError::new(ERRCODE_SOIL_FILE_NOT_FOUND).with_message(Cow::from(msg)));
+    }
+    Ok(())
+}
+
+impl SoilConfig {
+    /// Resolves the soil ID of the site of `ctx`.
+    pub fn resolve_id(&self, ctx: &Context) -> Result<String, ContextEvaluationError> {
+        match &self.id {
+            Some(id) => Ok(id.to_prim(ctx)?.as_string().trim().to_string()),
+            None => Ok(ctx.site.id.to_string()),
+        }
+    }
+}
+
+/// Every soil profile of the `.SOL` file(s) of a [`SoilConfig`], indexed by ID.
+#[derive(Debug)]
+pub struct SoilLibrary {
+    profiles: HashMap<String, SoilProfile>,
+}
+
+impl SoilLibrary {
+    pub fn load(config: &SoilConfig) -> Result<Self, SoilError> {
+        let files = if config.file.is_dir() {
+            let mut files: Vec<PathBuf> = std::fs::read_dir(&config.file)
+                .map_err(|e| SoilError::Io(config.file.clone(), e))?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| {
+                    p.extension()
+                        .is_some_and(|ext| ext.eq_ignore_ascii_case("sol"))
+                })
+                .collect();
+            if files.is_empty() {
+                return Err(SoilError::Empty(config.file.clone()));
+            }
+            files.sort();
+            files
+        } else {
+            vec![config.file.clone()]
+        };
+
+        let mut profiles = HashMap::new();
+        for file in files {
+            for profile in Self::read(&file)? {
+                // The first occurrence wins, as DSSAT itself does when scanning a .SOL file.
+                profiles.entry(profile.id.clone()).or_insert(profile);
+            }
+        }
+        Ok(Self { profiles })
+    }
+
+    fn read(path: &Path) -> Result<Vec<SoilProfile>, SoilError> {
+        let bytes = std::fs::read(path).map_err(|e| SoilError::Io(path.to_path_buf(), e))?;
+        // .SOL files are frequently Latin-1 encoded, so they are read lossily instead of failing on non UTF-8 bytes.
+        let contents = crate::utils::text::normalize(&String::from_utf8_lossy(&bytes));
+        Ok(sol::parse(&contents))
+    }
+
+    pub fn get(&self, id: &str) -> Option<&SoilProfile> {
+        self.profiles.get(id)
+    }
+}
+
+/// Wraps the text of a single profile (see [`SoilProfile::text`]) into a standalone `.SOL` file.
+pub fn standalone_sol(profile: &str) -> String {
+    format!("*SOILS: pythia-rs\n\n{}\n", profile)
+}
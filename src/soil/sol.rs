@@ -0,0 +1,173 @@
+//! Parser for DSSAT soil (`.SOL`) files.
+
+use std::collections::HashMap;
+
+/// A single soil profile of a `.SOL` file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoilProfile {
+    /// Profile ID, e.g. `IBMZ910014`.
+    pub id: String,
+    /// The profile exactly as it appears in the file, from its `*ID` line to the last line before the next profile.
+    pub text: String,
+    /// Single-row fields, keyed by their lowercase column name (e.g. `salb`, `slu1`), plus the header fields
+    /// `source`, `texture`, `depth` and `description`.
+    pub fields: HashMap<String, String>,
+    /// Layer tables, one map per layer (top to bottom), keyed by lowercase column name. Tables sharing the
+    /// layer depth column (`SLB`) are merged by layer order.
+    pub layers: Vec<HashMap<String, String>>,
+}
+
+/// Parses every profile in the contents of a `.SOL` file. Line endings are expected to be normalized to LF.
+pub fn parse(contents: &str) -> Vec<SoilProfile> {
+    let mut profiles = Vec::new();
+    let mut current: Option<Vec<&str>> = None;
+
+    for line in contents.lines() {
+        if line.starts_with('*') && !line.starts_with("*SOILS") {
+            if let Some(lines) = current.take() {
+                profiles.push(parse_profile(&lines));
+            }
+            current = Some(vec![line]);
+        } else if let Some(lines) = current.as_mut() {
+            lines.push(line);
+        }
+    }
+    if let Some(lines) = current {
+        profiles.push(parse_profile(&lines));
+    }
+
+    profiles
+}
+
+fn parse_profile(lines: &[&str]) -> SoilProfile {
+    let mut lines = lines.to_vec();
+    while lines.last().is_some_and(|l| l.trim().is_empty()) {
+        lines.pop();
+    }
+
+    let header = lines[0].trim_start_matches('*');
+    let (id, rest) = header
+        .split_once(char::is_whitespace)
+        .unwrap_or((header, ""));
+    let mut fields = HashMap::new();
+    let mut rest = rest.split_whitespace();
+    for key in ["source", "texture", "depth"] {
+        if let Some(value) = rest.next() {
+            fields.insert(key.to_string(), value.to_string());
+        }
+    }
+    fields.insert(
+        "description".to_string(),
+        rest.collect::<Vec<_>>().join(" "),
+    );
+
+    let mut layers: Vec<HashMap<String, String>> = Vec::new();
+    let mut i = 1;
+    while i < lines.len() {
+        let Some(columns) = lines[i].strip_prefix('@') else {
+            i += 1;
+            continue;
+        };
+        let columns: Vec<String> = columns
+            .replace("SCS FAMILY", "SCS_FAMILY")
+            .split_whitespace()
+            .map(str::to_lowercase)
+            .collect();
+
+        let mut rows = Vec::new();
+        i += 1;
+        while i < lines.len() && !lines[i].starts_with('@') {
+            let line = lines[i];
+            if !line.trim().is_empty() && !line.trim_start().starts_with('!') {
+                rows.push(parse_row(&columns, line));
+            }
+            i += 1;
+        }
+
+        match rows.len() {
+            0 => {}
+            1 if !columns.iter().any(|c| c == "slb") => fields.extend(rows.remove(0)),
+            _ => {
+                for (n, row) in rows.into_iter().enumerate() {
+                    match layers.get_mut(n) {
+                        Some(layer) => layer.extend(row),
+                        None => layers.push(row),
+                    }
+                }
+            }
+        }
+    }
+
+    SoilProfile {
+        id: id.to_string(),
+        text: lines.join("\n"),
+        fields,
+        layers,
+    }
+}
+
+/// Splits a data row by whitespace. Extra values are joined into the last column, as free-text columns
+/// (e.g. `SCS FAMILY`) may contain spaces.
+fn parse_row(columns: &[String], line: &str) -> HashMap<String, String> {
+    let values: Vec<&str> = line.split_whitespace().collect();
+    let mut row = HashMap::new();
+    for (n, column) in columns.iter().enumerate() {
+        let value = if n + 1 == columns.len() {
+            values.get(n..).map(|v| v.join(" "))
+        } else {
+            values.get(n).map(|v| v.to_string())
+        };
+        if let Some(value) = value.filter(|v| !v.is_empty()) {
+            row.insert(column.clone(), value);
+        }
+    }
+    row
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOL: &str = "*SOILS: General DSSAT Soil Input File
+
+*IBMZ910014  IBSNAT      SCL     140  Generic medium silty loam
+@SITE        COUNTRY          LAT     LONG SCS FAMILY
+ Generic     Generic      -99      -99     Generic
+@ SCOM  SALB  SLU1  SLDR  SLRO  SLNF  SLPF  SMHB  SMPX  SMKE
+    BN  0.13   6.0  0.40  75.0  1.00  1.00 IB001 IB001 IB001
+@  SLB  SLMH  SLLL  SDUL  SSAT  SRGF
+     5   -99 0.110 0.280 0.380 1.000
+    15   -99 0.110 0.280 0.380 1.000
+@  SLB  SLPX  SLPT
+     5   -99   -99
+    15   -99   -99
+
+*IBMZ910015  IBSNAT      S       100  Generic sand
+@ SCOM  SALB
+    BN  0.15
+";
+
+    #[test]
+    fn test_parse() {
+        let profiles = parse(SOL);
+        assert_eq!(profiles.len(), 2);
+
+        let p = &profiles[0];
+        assert_eq!(p.id, "IBMZ910014");
+        assert_eq!(p.fields["texture"], "SCL");
+        assert_eq!(p.fields["depth"], "140");
+        assert_eq!(p.fields["description"], "Generic medium silty loam");
+        assert_eq!(p.fields["scs_family"], "Generic");
+        assert_eq!(p.fields["salb"], "0.13");
+        assert_eq!(p.layers.len(), 2);
+        assert_eq!(p.layers[1]["slb"], "15");
+        assert_eq!(p.layers[1]["sdul"], "0.280");
+        assert_eq!(p.layers[1]["slpx"], "-99");
+        assert!(p.text.starts_with("*IBMZ910014"));
+        assert!(p.text.ends_with("15   -99   -99"));
+
+        assert_eq!(profiles[1].id, "IBMZ910015");
+        assert_eq!(profiles[1].fields["salb"], "0.15");
+        assert!(profiles[1].layers.is_empty());
+    }
+}
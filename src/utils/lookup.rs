@@ -0,0 +1,115 @@
+//! Keyed CSV lookup tables, e.g. per-region values selected by a context variable.
+
+use crate::processing::context::{Context, ContextEvaluationError, ContextValue};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum LookupError {
+    #[error("Failed to read lookup table {0}: {1}")]
+    Csv(PathBuf, csv::Error),
+    #[error("Lookup table {0} has no column named \"{1}\"")]
+    MissingColumn(PathBuf, String),
+}
+
+/// A CSV file and the context value used to select one of its rows.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct LookupConfig {
+    pub file: PathBuf,
+
+    /// Value matched against [`LookupConfig::key_column`], usually interpolating a context variable (e.g. `"${region}"`).
+    pub key: ContextValue,
+
+    #[serde(default = "default_key_column")]
+    pub key_column: String,
+}
+
+fn default_key_column() -> String {
+    "id".to_string()
+}
+
+impl LookupConfig {
+    pub fn resolve_key(&self, ctx: &Context) -> Result<String, ContextEvaluationError> {
+        Ok(self.key.to_prim(ctx)?.as_string().trim().to_string())
+    }
+
+    pub fn load(&self) -> Result<LookupTable, LookupError> {
+        LookupTable::load(&self.file, &self.key_column)
+    }
+}
+
+/// The rows of a CSV file indexed by the value of a key column. The first row of a repeated key wins.
+#[derive(Debug)]
+pub struct LookupTable {
+    path: PathBuf,
+    headers: Vec<String>,
+    rows: HashMap<String, Vec<String>>,
+}
+
+impl LookupTable {
+    pub fn load(path: &PathBuf, key_column: &str) -> Result<Self, LookupError> {
+        let csv_err = |e| LookupError::Csv(path.clone(), e);
+        let mut reader = csv::Reader::from_path(path).map_err(csv_err)?;
+        let headers: Vec<String> = reader
+            .headers()
+            .map_err(csv_err)?
+            .iter()
+            .map(|h| h.trim().to_string())
+            .collect();
+
+        let key_idx = headers
+            .iter()
+            .position(|h| h == key_column)
+            .ok_or_else(|| LookupError::MissingColumn(path.clone(), key_column.to_string()))?;
+
+        let mut rows = HashMap::new();
+        for record in reader.records() {
+            let record = record.map_err(csv_err)?;
+            let values: Vec<String> = record.iter().map(|v| v.trim().to_string()).collect();
+            let key = values.get(key_idx).cloned().unwrap_or_default();
+            rows.entry(key).or_insert(values);
+        }
+
+        Ok(Self {
+            path: path.clone(),
+            headers,
+            rows,
+        })
+    }
+
+    /// Index of `column`, to be used with [`LookupTable::row`].
+    pub fn column(&self, column: &str) -> Result<usize, LookupError> {
+        self.headers
+            .iter()
+            .position(|h| h == column)
+            .ok_or_else(|| LookupError::MissingColumn(self.path.clone(), column.to_string()))
+    }
+
+    pub fn row(&self, key: &str) -> Option<&[String]> {
+        self.rows.get(key).map(Vec::as_slice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let path = std::env::temp_dir().join(format!("pythia-lookup-{}.csv", std::process::id()));
+        std::fs::write(&path, "id, pdate\nA, 120\nB, 135\nA, 999\n").unwrap();
+
+        let table = LookupTable::load(&path, "id").unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let pdate = table.column("pdate").unwrap();
+        assert_eq!(table.row("A").unwrap()[pdate], "120");
+        assert_eq!(table.row("B").unwrap()[pdate], "135");
+        assert!(table.row("C").is_none());
+        assert!(table.column("missing").is_err());
+        assert!(LookupTable::load(&path, "id").is_err());
+    }
+}
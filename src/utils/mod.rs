@@ -1 +1,8 @@
+pub mod lookup;
+pub mod polygons;
+pub mod portable;
+pub mod raster;
+pub mod rng;
+pub mod rtree;
+pub mod text;
 pub mod threehashmap;
@@ -0,0 +1,195 @@
+//! Point-in-polygon lookups on GDAL vector datasets.
+
+use super::rtree::{Polygon, RTree};
+use gdal::errors::GdalError;
+use gdal::vector::{Geometry, Layer, LayerAccess, OGRwkbGeometryType};
+use gdal::Dataset;
+
+/// A polygon layer whose attributes are looked up at points. The points are expected to be in the same CRS as the layer.
+pub struct PolygonLayer {
+    ds: Dataset,
+    layer: Option<String>,
+}
+
+impl PolygonLayer {
+    /// Opens the layer named `layer` of the dataset at `path`, or its first layer if `None`.
+    pub fn open(path: &str, layer: Option<String>) -> Result<Self, GdalError> {
+        let polygons = Self {
+            ds: Dataset::open(path)?,
+            layer,
+        };
+        polygons.layer()?;
+        Ok(polygons)
+    }
+
+    fn layer(&self) -> Result<Layer<'_>, GdalError> {
+        match &self.layer {
+            Some(name) => self.ds.layer_by_name(name),
+            None => self.ds.layer(0),
+        }
+    }
+
+    /// Names of the fields of the layer.
+    pub fn fields(&self) -> Result<Vec<String>, GdalError> {
+        Ok(self.layer()?.defn().fields().map(|f| f.name()).collect())
+    }
+
+    /// Value of `field` of the first polygon containing the point, if any contains it and the field is not null.
+    pub fn attribute_at(
+        &self,
+        lon: f64,
+        lat: f64,
+        field: &str,
+    ) -> Result<Option<String>, GdalError> {
+        let mut point = Geometry::empty(OGRwkbGeometryType::wkbPoint)?;
+        point.add_point_2d((lon, lat));
+
+        let mut layer = self.layer()?;
+        layer.set_spatial_filter(&point);
+        let feature = layer
+            .features()
+            .find(|f| f.geometry().is_some_and(|g| g.contains(&point)));
+
+        match feature {
+            Some(feature) => feature.field_as_string_by_name(field),
+            None => Ok(None),
+        }
+    }
+}
+
+/// The polygons of a layer with the values of some of their fields, read once into memory and indexed by an
+/// [`RTree`]. Unlike [`PolygonLayer`], it holds no GDAL handle, so it can be shared between threads.
+pub struct PolygonIndex {
+    /// Polygons (the parts of multipolygons on their own) along with the index of their feature in `values`.
+    polygons: Vec<(Polygon, usize)>,
+    /// Values of the fields of every feature, in the order of the fields.
+    values: Vec<Vec<Option<String>>>,
+    tree: RTree,
+}
+
+impl PolygonIndex {
+    /// Reads the polygons of the layer named `layer` of the dataset at `path` (or of its first layer if `None`) and the
+    /// values of their `field`.
+    pub fn load(path: &str, layer: Option<String>, field: &str) -> Result<Self, GdalError> {
+        Self::load_fields(path, layer, &[field])
+    }
+
+    /// Like [`PolygonIndex::load`], with the values of every one of `fields`.
+    pub fn load_fields(
+        path: &str,
+        layer: Option<String>,
+        fields: &[&str],
+    ) -> Result<Self, GdalError> {
+        let source = PolygonLayer::open(path, layer)?;
+        let mut layer = source.layer()?;
+        let mut polygons = Vec::new();
+        let mut values = Vec::new();
+        for feature in layer.features() {
+            let Some(geometry) = feature.geometry() else {
+                continue;
+            };
+            let mut parts = Vec::new();
+            polygon_parts(geometry, &mut parts);
+            polygons.extend(parts.into_iter().map(|part| (part, values.len())));
+            values.push(
+                fields
+                    .iter()
+                    .map(|field| feature.field_as_string_by_name(field))
+                    .collect::<Result<_, _>>()?,
+            );
+        }
+        Ok(Self::new(polygons, values))
+    }
+
+    fn new(polygons: Vec<(Polygon, usize)>, values: Vec<Vec<Option<String>>>) -> Self {
+        let bboxes: Vec<_> = polygons.iter().map(|(p, _)| p.bbox()).collect();
+        Self {
+            tree: RTree::new(&bboxes),
+            polygons,
+            values,
+        }
+    }
+
+    /// Values of the fields of the first polygon containing the point, if any contains it.
+    pub fn attributes_at(&self, lon: f64, lat: f64) -> Option<&[Option<String>]> {
+        self.tree
+            .query(lon, lat)
+            .into_iter()
+            .map(|i| &self.polygons[i])
+            .find(|(polygon, _)| polygon.contains(lon, lat))
+            .map(|(_, feature)| self.values[*feature].as_slice())
+    }
+
+    /// Value of the (first) field of the first polygon containing the point, if any contains it and the field is not
+    /// null.
+    pub fn attribute_at(&self, lon: f64, lat: f64) -> Option<&str> {
+        self.attributes_at(lon, lat)?.first()?.as_deref()
+    }
+}
+
+/// Collects the polygons of `geometry`: itself if it is a polygon (a geometry of rings), or the ones of its parts.
+fn polygon_parts(geometry: &Geometry, polygons: &mut Vec<Polygon>) {
+    let parts: Vec<_> = (0..geometry.geometry_count())
+        .map(|i| geometry.get_geometry(i))
+        .collect();
+    if parts.iter().any(|part| part.geometry_count() > 0) {
+        for part in &parts {
+            polygon_parts(part, polygons);
+        }
+        return;
+    }
+
+    let rings = parts
+        .iter()
+        .map(|ring| {
+            ring.get_point_vec()
+                .into_iter()
+                .map(|(x, y, _)| (x, y))
+                .collect()
+        })
+        .collect();
+    polygons.extend(Polygon::new(rings));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(x: f64, y: f64, size: f64) -> Polygon {
+        Polygon::new(vec![vec![
+            (x, y),
+            (x + size, y),
+            (x + size, y + size),
+            (x, y + size),
+        ]])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_polygon_index() {
+        let value = |s: &str| Some(s.to_string());
+        let index = PolygonIndex::new(
+            vec![
+                (square(0.0, 0.0, 10.0), 0),
+                (square(5.0, 5.0, 10.0), 1),
+                (square(20.0, 20.0, 10.0), 2),
+                (square(40.0, 40.0, 10.0), 1),
+            ],
+            vec![
+                vec![value("north"), value("N1")],
+                vec![value("east"), None],
+                vec![None, value("S1")],
+            ],
+        );
+        assert_eq!(index.attribute_at(6.0, 6.0), Some("north"));
+        assert_eq!(index.attribute_at(12.0, 12.0), Some("east"));
+        assert_eq!(index.attribute_at(45.0, 45.0), Some("east"));
+        assert_eq!(index.attribute_at(25.0, 25.0), None);
+        assert_eq!(index.attribute_at(-1.0, 0.0), None);
+        assert_eq!(
+            index.attributes_at(25.0, 25.0),
+            Some([None, value("S1")].as_slice())
+        );
+        assert_eq!(index.attributes_at(-1.0, 0.0), None);
+    }
+}
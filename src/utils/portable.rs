@@ -0,0 +1,67 @@
+//! Checks for names that end up as file or directory names, so they are valid on every common filesystem and not only on the one the config was written on.
+
+/// Names reserved by Windows, regardless of extension (e.g. `con.txt` is as invalid as `con`).
+const WINDOWS_RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Characters forbidden in file names on Windows. `/` is forbidden everywhere.
+const FORBIDDEN_CHARS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// Checks whether `name` is usable as a file or directory name on Windows, macOS and Linux alike.
+///
+/// Returns a description of the problem if it isn't, e.g. it is a reserved Windows name (`CON`, `lpt1.txt`),
+/// ends with a dot or space, or contains forbidden or control characters.
+pub fn portable_filename_issue(name: &str) -> Option<String> {
+    if name.is_empty() {
+        return Some("it is empty".to_string());
+    }
+
+    if name == "." || name == ".." {
+        return Some(format!("\"{}\" is a special directory name", name));
+    }
+
+    if let Some(c) = name
+        .chars()
+        .find(|c| FORBIDDEN_CHARS.contains(c) || c.is_control())
+    {
+        return Some(format!("it contains the forbidden character {:?}", c));
+    }
+
+    if name.ends_with('.') || name.ends_with(' ') {
+        return Some("it ends with a dot or a space".to_string());
+    }
+
+    let stem = name.split('.').next().unwrap_or(name).trim_end();
+    if WINDOWS_RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+    {
+        return Some(format!("\"{}\" is a reserved name on Windows", stem));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_portable_filename() {
+        assert_eq!(portable_filename_issue("run-1"), None);
+        assert_eq!(portable_filename_issue("MAIZE.SNX"), None);
+        assert_eq!(portable_filename_issue("console"), None);
+
+        assert!(portable_filename_issue("").is_some());
+        assert!(portable_filename_issue("..").is_some());
+        assert!(portable_filename_issue("CON").is_some());
+        assert!(portable_filename_issue("con").is_some());
+        assert!(portable_filename_issue("Lpt1.txt").is_some());
+        assert!(portable_filename_issue("run.").is_some());
+        assert!(portable_filename_issue("run ").is_some());
+        assert!(portable_filename_issue("a:b").is_some());
+        assert!(portable_filename_issue("a\tb").is_some());
+    }
+}
@@ -0,0 +1,86 @@
+//! Point sampling of GDAL rasters.
+
+use gdal::errors::GdalError;
+use gdal::{Dataset, GeoTransform, GeoTransformEx};
+
+/// Pixel grid of a raster dataset, without a handle on it (so it can be shared between threads).
+#[derive(Clone, Copy, Debug)]
+pub struct RasterGrid {
+    inv_geo_transform: GeoTransform,
+    size: (usize, usize),
+}
+
+impl RasterGrid {
+    pub fn of(ds: &Dataset) -> Result<Self, GdalError> {
+        Ok(Self {
+            inv_geo_transform: ds.geo_transform()?.invert()?,
+            size: ds.raster_size(),
+        })
+    }
+
+    /// Columns and rows of the raster.
+    pub fn size(&self) -> (usize, usize) {
+        self.size
+    }
+
+    /// Pixel (column, row) containing the given point, if it falls inside the raster.
+    pub fn pixel(&self, lon: f64, lat: f64) -> Option<(usize, usize)> {
+        let (x, y) = self.inv_geo_transform.apply(lon, lat);
+        if x < 0.0 || y < 0.0 || x >= self.size.0 as f64 || y >= self.size.1 as f64 {
+            return None;
+        }
+        Some((x as usize, y as usize))
+    }
+}
+
+/// Reads single pixel values out of a raster dataset at geographic coordinates.
+/// The coordinates are expected to be in the same CRS as the dataset.
+pub struct RasterSampler {
+    ds: Dataset,
+    grid: RasterGrid,
+}
+
+impl RasterSampler {
+    pub fn open(path: &str) -> Result<Self, GdalError> {
+        let ds = Dataset::open(path)?;
+        let grid = RasterGrid::of(&ds)?;
+        Ok(Self { ds, grid })
+    }
+
+    /// Number of bands in the dataset.
+    pub fn band_count(&self) -> usize {
+        self.ds.raster_count()
+    }
+
+    /// Pixel (column, row) containing the given point, if it falls inside the raster.
+    pub fn pixel(&self, lon: f64, lat: f64) -> Option<(usize, usize)> {
+        self.grid.pixel(lon, lat)
+    }
+
+    /// Samples the band `band_index` (**ZERO-BASED**) at the given point.
+    /// Returns `None` if the point is outside the raster or the pixel holds the band's nodata value (or NaN).
+    pub fn sample(&self, band_index: usize, lon: f64, lat: f64) -> Result<Option<f64>, GdalError> {
+        let Some((x, y)) = self.pixel(lon, lat) else {
+            return Ok(None);
+        };
+
+        let band = self.ds.rasterband(band_index + 1)?;
+        let value = band
+            .read_as::<f64>((x as isize, y as isize), (1, 1), (1, 1), None)?
+            .data()[0];
+        if value.is_nan() || band.no_data_value() == Some(value) {
+            return Ok(None);
+        }
+
+        Ok(Some(
+            value * band.scale().unwrap_or(1.0) + band.offset().unwrap_or(0.0),
+        ))
+    }
+
+    /// Samples every band at the given point, in band order. See [`RasterSampler::sample`].
+    pub fn sample_all(&self, lon: f64, lat: f64) -> Result<Vec<Option<f64>>, GdalError> {
+        (0..self.band_count())
+            .map(|band_index| self.sample(band_index, lon, lat))
+            .collect()
+    }
+}
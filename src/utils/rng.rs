@@ -0,0 +1,60 @@
+//! Small seeded pseudo-random number generator, so anything random (samples, searches) is reproducible from its seed.
+
+/// xorshift64* generator. Not suitable for anything security related.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // The state must never be zero.
+        Rng((seed ^ 0x9E37_79B9_7F4A_7C15).max(1))
+    }
+
+    /// Generator of the stream `stream` of `seed` (e.g. the one of a site), unrelated to the streams next to it.
+    pub fn with_stream(seed: u64, stream: u64) -> Self {
+        Rng::new(splitmix64(seed ^ splitmix64(stream)))
+    }
+
+    /// Uniform in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform in `[0, n)`. `n` must not be zero.
+    pub fn below(&mut self, n: usize) -> usize {
+        ((self.next_f64() * n as f64) as usize).min(n - 1)
+    }
+}
+
+/// Finalizer of SplitMix64, scrambling nearby values into unrelated ones.
+fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rng() {
+        let mut a = Rng::new(0);
+        let mut b = Rng::new(0);
+        for _ in 0..1000 {
+            let value = a.next_f64();
+            assert!((0.0..1.0).contains(&value));
+            assert_eq!(value, b.next_f64());
+            assert!(a.below(7) < 7);
+            b.below(7);
+        }
+
+        let first = |seed, stream| Rng::with_stream(seed, stream).next_f64();
+        assert_eq!(first(1, 2), first(1, 2));
+        assert_ne!(first(1, 2), first(1, 3));
+        assert_ne!(first(1, 2), first(2, 2));
+    }
+}
@@ -0,0 +1,192 @@
+//! Packed R-tree of polygons, answering which polygons contain a point without testing all of them.
+
+use std::ops::Range;
+
+/// Children of every node of the tree.
+const NODE_CAPACITY: usize = 16;
+
+/// Axis-aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BBox {
+    pub min: (f64, f64),
+    pub max: (f64, f64),
+}
+
+impl BBox {
+    pub fn of(points: &[(f64, f64)]) -> Option<Self> {
+        let (first, rest) = points.split_first()?;
+        Some(rest.iter().fold(
+            BBox {
+                min: *first,
+                max: *first,
+            },
+            |bbox, (x, y)| {
+                bbox.union(&BBox {
+                    min: (*x, *y),
+                    max: (*x, *y),
+                })
+            },
+        ))
+    }
+
+    pub fn union(&self, other: &BBox) -> BBox {
+        BBox {
+            min: (self.min.0.min(other.min.0), self.min.1.min(other.min.1)),
+            max: (self.max.0.max(other.max.0), self.max.1.max(other.max.1)),
+        }
+    }
+
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.min.0 && x <= self.max.0 && y >= self.min.1 && y <= self.max.1
+    }
+
+    fn center(&self) -> (f64, f64) {
+        (
+            (self.min.0 + self.max.0) / 2.0,
+            (self.min.1 + self.max.1) / 2.0,
+        )
+    }
+}
+
+/// A polygon as its rings, the exterior and the holes.
+#[derive(Clone, Debug)]
+pub struct Polygon {
+    bbox: BBox,
+    rings: Vec<Vec<(f64, f64)>>,
+}
+
+impl Polygon {
+    /// `None` if the polygon has no points.
+    pub fn new(rings: Vec<Vec<(f64, f64)>>) -> Option<Self> {
+        let bbox = BBox::of(rings.first()?)?;
+        Some(Self { bbox, rings })
+    }
+
+    pub fn bbox(&self) -> BBox {
+        self.bbox
+    }
+
+    /// Whether the point is inside the polygon (even-odd rule, so holes are left out). Points on the edges may fall on
+    /// either side.
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        if !self.bbox.contains(x, y) {
+            return false;
+        }
+        let mut inside = false;
+        for ring in &self.rings {
+            let edges = ring.iter().zip(ring.iter().cycle().skip(1));
+            for (&(x1, y1), &(x2, y2)) in edges {
+                if (y1 > y) != (y2 > y) && x < x1 + (y - y1) / (y2 - y1) * (x2 - x1) {
+                    inside = !inside;
+                }
+            }
+        }
+        inside
+    }
+}
+
+/// Bounding boxes of items packed into a tree by sort-tile-recursive, built once and read-only afterwards (so it can be
+/// shared between threads).
+#[derive(Debug)]
+pub struct RTree {
+    /// Items and their bounding boxes, in the order of the leaves.
+    items: Vec<(BBox, usize)>,
+    /// Nodes of every level, from the leaves up, each with its bounding box and the range of its children (items for
+    /// the leaves, nodes of the level below for the others).
+    levels: Vec<Vec<(BBox, Range<usize>)>>,
+}
+
+impl RTree {
+    /// Tree of the items `0..bboxes.len()`.
+    pub fn new(bboxes: &[BBox]) -> Self {
+        let mut items: Vec<usize> = (0..bboxes.len()).collect();
+        let leaves = bboxes.len().div_ceil(NODE_CAPACITY);
+        let slice = (leaves as f64).sqrt().ceil() as usize * NODE_CAPACITY;
+        let center = |i: &usize| bboxes[*i].center();
+        items.sort_by(|a, b| center(a).0.total_cmp(&center(b).0));
+        for slice in items.chunks_mut(slice.max(1)) {
+            slice.sort_by(|a, b| center(a).1.total_cmp(&center(b).1));
+        }
+
+        let bbox = |range: &Range<usize>, of: &dyn Fn(usize) -> BBox| {
+            range.clone().map(of).reduce(|a, b| a.union(&b)).unwrap()
+        };
+        let group = |count: usize, of: &dyn Fn(usize) -> BBox| {
+            (0..count)
+                .step_by(NODE_CAPACITY)
+                .map(|start| start..(start + NODE_CAPACITY).min(count))
+                .map(|range| (bbox(&range, of), range))
+                .collect::<Vec<_>>()
+        };
+
+        let items: Vec<(BBox, usize)> = items.into_iter().map(|i| (bboxes[i], i)).collect();
+        let mut levels = vec![group(items.len(), &|i| items[i].0)];
+        while levels.last().unwrap().len() > 1 {
+            let below = levels.last().unwrap();
+            let level = group(below.len(), &|i| below[i].0);
+            levels.push(level);
+        }
+        Self { items, levels }
+    }
+
+    /// Items whose bounding box contains the point, in ascending order.
+    pub fn query(&self, x: f64, y: f64) -> Vec<usize> {
+        let Some(top) = self.levels.last() else {
+            return Vec::new();
+        };
+        let mut nodes: Vec<(usize, usize)> =
+            (0..top.len()).map(|i| (self.levels.len() - 1, i)).collect();
+        let mut found = Vec::new();
+        while let Some((level, i)) = nodes.pop() {
+            let (bbox, children) = &self.levels[level][i];
+            if !bbox.contains(x, y) {
+                continue;
+            }
+            match level {
+                0 => found.extend(
+                    self.items[children.clone()]
+                        .iter()
+                        .filter(|(bbox, _)| bbox.contains(x, y))
+                        .map(|(_, item)| *item),
+                ),
+                _ => nodes.extend(children.clone().map(|child| (level - 1, child))),
+            }
+        }
+        found.sort_unstable();
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(x: f64, y: f64, size: f64) -> Vec<(f64, f64)> {
+        vec![(x, y), (x + size, y), (x + size, y + size), (x, y + size)]
+    }
+
+    #[test]
+    fn test_polygon() {
+        let donut = Polygon::new(vec![square(0.0, 0.0, 10.0), square(4.0, 4.0, 2.0)]).unwrap();
+        assert!(donut.contains(1.0, 1.0));
+        assert!(!donut.contains(5.0, 5.0));
+        assert!(!donut.contains(11.0, 5.0));
+        assert!(Polygon::new(vec![]).is_none());
+    }
+
+    #[test]
+    fn test_rtree() {
+        // A 40x40 grid of unit squares, plus one covering all of them.
+        let mut bboxes: Vec<BBox> = (0..1600)
+            .map(|i| BBox::of(&square((i % 40) as f64, (i / 40) as f64, 1.0)).unwrap())
+            .collect();
+        bboxes.push(BBox::of(&square(0.0, 0.0, 40.0)).unwrap());
+        let tree = RTree::new(&bboxes);
+        assert!(tree.levels.len() > 2);
+
+        assert_eq!(tree.query(12.5, 3.5), vec![3 * 40 + 12, 1600]);
+        assert_eq!(tree.query(1.0, 1.5), vec![40, 41, 1600]);
+        assert!(tree.query(-1.0, 3.0).is_empty());
+        assert!(RTree::new(&[]).query(0.0, 0.0).is_empty());
+    }
+}
@@ -0,0 +1,155 @@
+//! Text normalization for templates and rendered files.
+//! DSSAT (especially on Windows) is picky about line endings and byte order marks, so both are handled explicitly instead of being inherited from whatever editor produced the template.
+
+use serde::{Deserialize, Serialize};
+
+const BOM: char = '\u{FEFF}';
+
+/// Line ending style of rendered files.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+/// What to do with the blank (empty or whitespace only) lines of rendered files.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BlankLines {
+    #[default]
+    Keep,
+    /// Runs of blank lines are reduced to a single one.
+    Collapse,
+    Remove,
+}
+
+/// Clean-up of the whitespace of rendered files. Tera control blocks (`{% if %}`, `{% for %}`) tend to leave blank lines
+/// and trailing spaces behind, which the fixed-format parsers of DSSAT don't always tolerate.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Whitespace {
+    /// Strips the whitespace at the end of every line.
+    pub trim_trailing: bool,
+    pub blank_lines: BlankLines,
+    /// Ends the file with exactly one newline, dropping the blank lines after the last one with text.
+    pub final_newline: bool,
+}
+
+/// Strips a leading byte order mark and converts every line ending (CRLF, CR) to LF.
+pub fn normalize(text: &str) -> String {
+    text.strip_prefix(BOM)
+        .unwrap_or(text)
+        .replace("\r\n", "\n")
+        .replace('\r', "\n")
+}
+
+/// Cleans up the whitespace of LF-normalized `text` (see [`normalize`]) as told by `whitespace`.
+pub fn tidy(text: String, whitespace: Whitespace) -> String {
+    if whitespace == Whitespace::default() {
+        return text;
+    }
+
+    let ends_with_newline = text.ends_with('\n');
+    let mut lines: Vec<&str> = Vec::new();
+    for line in text.strip_suffix('\n').unwrap_or(&text).split('\n') {
+        let line = match whitespace.trim_trailing {
+            true => line.trim_end(),
+            false => line,
+        };
+        let blank = line.trim().is_empty();
+        let skip = match whitespace.blank_lines {
+            BlankLines::Keep => false,
+            BlankLines::Collapse => blank && lines.last().is_some_and(|l| l.trim().is_empty()),
+            BlankLines::Remove => blank,
+        };
+        if !skip {
+            lines.push(line);
+        }
+    }
+
+    if whitespace.final_newline {
+        while lines.last().is_some_and(|l| l.trim().is_empty()) {
+            lines.pop();
+        }
+        return lines.iter().map(|l| format!("{}\n", l)).collect();
+    }
+    let mut tidied = lines.join("\n");
+    if ends_with_newline {
+        tidied.push('\n');
+    }
+    tidied
+}
+
+/// Converts LF-normalized `text` (see [`normalize`]) to the given line ending, optionally prefixing it with a byte order mark.
+pub fn finalize(text: String, line_ending: LineEnding, bom: bool) -> String {
+    let text = match line_ending {
+        LineEnding::Lf => text,
+        LineEnding::Crlf => text.replace('\n', "\r\n"),
+    };
+
+    if bom {
+        format!("{}{}", BOM, text)
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("\u{FEFF}a\r\nb\rc\nd"), "a\nb\nc\nd");
+        assert_eq!(normalize("a\n\r\nb"), "a\n\nb");
+    }
+
+    #[test]
+    fn test_tidy() {
+        let text = "*GENERAL  \n\n\n  \n*FIELDS\n\n".to_string();
+        assert_eq!(tidy(text.clone(), Whitespace::default()), text);
+
+        let whitespace = |trim_trailing, blank_lines, final_newline| Whitespace {
+            trim_trailing,
+            blank_lines,
+            final_newline,
+        };
+        assert_eq!(
+            tidy(text.clone(), whitespace(true, BlankLines::Keep, false)),
+            "*GENERAL\n\n\n\n*FIELDS\n\n"
+        );
+        assert_eq!(
+            tidy(text.clone(), whitespace(false, BlankLines::Collapse, false)),
+            "*GENERAL  \n\n*FIELDS\n\n"
+        );
+        assert_eq!(
+            tidy(text.clone(), whitespace(true, BlankLines::Remove, true)),
+            "*GENERAL\n*FIELDS\n"
+        );
+        assert_eq!(
+            tidy(
+                "*FIELDS".to_string(),
+                whitespace(false, BlankLines::Keep, true)
+            ),
+            "*FIELDS\n"
+        );
+    }
+
+    #[test]
+    fn test_finalize() {
+        assert_eq!(
+            finalize("a\nb\n".to_string(), LineEnding::Lf, false),
+            "a\nb\n"
+        );
+        assert_eq!(
+            finalize("a\nb\n".to_string(), LineEnding::Crlf, false),
+            "a\r\nb\r\n"
+        );
+        assert_eq!(
+            finalize("a\n".to_string(), LineEnding::Crlf, true),
+            "\u{FEFF}a\r\n"
+        );
+    }
+}
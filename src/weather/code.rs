@@ -0,0 +1,149 @@
+//! DSSAT weather station codes derived from the coordinates (or the ID) of the sites, so the templates naming `.WTH`
+//! files don't each encode them with string slicing (e.g. `{{ wth_code }}2401.WTH`).
+
+use crate::data::GridCell;
+use crate::sites::Site;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use validator::ValidationError;
+
+static ERRCODE_STATION_CODE_INVALID: &str = "ERRCODE_STATION_CODE_INVALID";
+
+/// Characters of a station code.
+const CODE_LENGTH: usize = 4;
+
+const DIGITS: &[u8; 36] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// How the 4-characters station code of every site is derived, exposed to templates as `wth_code`. Codes are made of
+/// the `prefix` followed by a number in base 36 (digits, then uppercase letters), zero-padded to 4 characters.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "scheme", rename_all = "snake_case", deny_unknown_fields)]
+pub enum StationCodeConfig {
+    /// Index of the cell of a global grid of `resolution` degrees holding the site, counted row by row from the
+    /// north-west corner. Sites in the same cell share their code.
+    Grid {
+        #[serde(default = "default_resolution")]
+        resolution: f64,
+        #[serde(default)]
+        prefix: String,
+    },
+    /// ID of the site. Sites whose ID is negative or doesn't fit in the code get none.
+    SiteId {
+        #[serde(default)]
+        prefix: String,
+    },
+}
+
+fn default_resolution() -> f64 {
+    0.25
+}
+
+pub fn validate_station_code(config: &StationCodeConfig) -> Result<(), ValidationError> {
+    let invalid = |msg: String| {
+        Err(ValidationError::new(ERRCODE_STATION_CODE_INVALID).with_message(Cow::from(msg)))
+    };
+
+    let prefix = config.prefix();
+    if prefix.len() >= CODE_LENGTH || !prefix.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return invalid(format!(
+            "Station code prefix {} must be at most {} alphanumeric characters",
+            prefix,
+            CODE_LENGTH - 1
+        ));
+    }
+    if let StationCodeConfig::Grid { resolution, .. } = config {
+        if !resolution.is_finite() || *resolution <= 0.0 {
+            return invalid("Station code grid resolution must be positive".to_string());
+        }
+        let (cols, rows) = GridCell::global_size(*resolution);
+        let codes = 36u64.pow((CODE_LENGTH - prefix.len()) as u32);
+        if cols * rows > codes {
+            return invalid(format!(
+                "A grid of {} degrees has {} cells, more than the {} codes after the prefix {}",
+                resolution,
+                cols * rows,
+                codes,
+                prefix
+            ));
+        }
+    }
+    Ok(())
+}
+
+impl StationCodeConfig {
+    fn prefix(&self) -> &str {
+        match self {
+            StationCodeConfig::Grid { prefix, .. } | StationCodeConfig::SiteId { prefix } => prefix,
+        }
+    }
+
+    /// Station code of `site`, if it has one.
+    pub fn code(&self, site: &Site) -> Option<String> {
+        let number = match self {
+            StationCodeConfig::Grid { resolution, .. } => {
+                GridCell::global(site.lon.as_f64(), site.lat.as_f64(), *resolution).index()
+            }
+            StationCodeConfig::SiteId { .. } => u64::try_from(site.id).ok()?,
+        };
+
+        let prefix = self.prefix().to_uppercase();
+        let mut digits = Vec::new();
+        let mut rest = number;
+        for _ in prefix.len()..CODE_LENGTH {
+            digits.push(DIGITS[(rest % 36) as usize] as char);
+            rest /= 36;
+        }
+        (rest == 0).then(|| prefix + &digits.iter().rev().collect::<String>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::GeoDeg;
+
+    fn site(id: i32, lon: f64, lat: f64) -> Site {
+        Site {
+            id,
+            lon: GeoDeg::from(lon),
+            lat: GeoDeg::from(lat),
+            covariates: Default::default(),
+            attributes: Default::default(),
+            weight: None,
+        }
+    }
+
+    #[test]
+    fn test_station_code() {
+        let config = |json| serde_json::from_value::<StationCodeConfig>(json).unwrap();
+
+        let grid = config(serde_json::json!({ "scheme": "grid" }));
+        assert!(validate_station_code(&grid).is_ok());
+        assert_eq!(grid.code(&site(1, -180.0, 90.0)).as_deref(), Some("0000"));
+        assert_eq!(grid.code(&site(1, -179.9, 89.9)).as_deref(), Some("0000"));
+        assert_eq!(grid.code(&site(1, -179.7, 89.9)).as_deref(), Some("0001"));
+        // Row 1 starts after the 1440 cells of row 0, i.e. 1440 = 1 * 36^2 + 4 * 36 + 0.
+        assert_eq!(grid.code(&site(1, -180.0, 89.7)).as_deref(), Some("0140"));
+        assert_eq!(grid.code(&site(1, 180.0, -90.0)).as_deref(), Some("M7ZZ"));
+        assert_eq!(
+            grid.code(&site(1, 14.125, 13.042)),
+            grid.code(&site(2, 14.2, 13.1))
+        );
+
+        let coarse =
+            config(serde_json::json!({ "scheme": "grid", "resolution": 2, "prefix": "g" }));
+        assert!(validate_station_code(&coarse).is_ok());
+        // Row 43 and column 5 of the 180 columns of the grid, i.e. 7745 = 5 * 36^2 + 35 * 36 + 5.
+        assert_eq!(coarse.code(&site(1, -170.0, 3.0)).as_deref(), Some("G5Z5"));
+        let fine = config(serde_json::json!({ "scheme": "grid", "prefix": "G" }));
+        assert!(validate_station_code(&fine).is_err());
+
+        let ids = config(serde_json::json!({ "scheme": "site_id", "prefix": "S" }));
+        assert_eq!(ids.code(&site(71, 0.0, 0.0)).as_deref(), Some("S01Z"));
+        assert_eq!(ids.code(&site(46655, 0.0, 0.0)).as_deref(), Some("SZZZ"));
+        assert_eq!(ids.code(&site(46656, 0.0, 0.0)), None);
+        assert_eq!(ids.code(&site(-1, 0.0, 0.0)), None);
+        let long_prefix = config(serde_json::json!({ "scheme": "site_id", "prefix": "WXYZ" }));
+        assert!(validate_station_code(&long_prefix).is_err());
+    }
+}
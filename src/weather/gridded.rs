@@ -0,0 +1,206 @@
+//! Extraction of daily weather series out of gridded climate datasets.
+
+use super::wth::{WeatherDay, WeatherSeries};
+use super::WeatherError;
+use crate::utils::raster::RasterSampler;
+use chrono::{Days, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// A gridded dataset holding a single variable. Sampled values are converted with `value * scale + offset`,
+/// on top of any scale and offset declared by the dataset itself.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct GriddedVariable {
+    /// Path of the dataset, or any GDAL connection string (e.g. `NETCDF:"file.nc":tmax`, `ZARR:"store.zarr":/srad`).
+    pub file: PathBuf,
+
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+
+    #[serde(default)]
+    pub offset: f64,
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+/// The gridded datasets of each weather variable, with one band per day. Units are the ones of [`WeatherDay`].
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct GriddedVariables {
+    pub srad: GriddedVariable,
+    pub tmax: GriddedVariable,
+    pub tmin: GriddedVariable,
+    pub rain: GriddedVariable,
+    #[serde(default)]
+    pub dewp: Option<GriddedVariable>,
+    #[serde(default)]
+    pub wind: Option<GriddedVariable>,
+    #[serde(default)]
+    pub rhum: Option<GriddedVariable>,
+}
+
+impl GriddedVariable {
+    fn open(&self) -> Result<RasterSampler, WeatherError> {
+        RasterSampler::open(&self.file.to_string_lossy()).map_err(|source| WeatherError::Gdal {
+            path: self.file.clone(),
+            source,
+        })
+    }
+
+    fn convert(&self, value: Option<f64>) -> Option<f64> {
+        value.map(|v| v * self.scale + self.offset)
+    }
+
+    /// Samples the first band of the dataset at the given point.
+    pub fn sample(&self, lon: f64, lat: f64) -> Result<Option<f64>, WeatherError> {
+        let value = self
+            .open()?
+            .sample(0, lon, lat)
+            .map_err(|source| WeatherError::Gdal {
+                path: self.file.clone(),
+                source,
+            })?;
+        Ok(self.convert(value))
+    }
+
+    /// Samples every band (day) of the dataset at the given point.
+    fn series(&self, lon: f64, lat: f64) -> Result<Vec<Option<f64>>, WeatherError> {
+        let values = self
+            .open()?
+            .sample_all(lon, lat)
+            .map_err(|source| WeatherError::Gdal {
+                path: self.file.clone(),
+                source,
+            })?;
+        Ok(values.into_iter().map(|v| self.convert(v)).collect())
+    }
+}
+
+impl GriddedVariables {
+    /// Extracts the daily weather series at the given point. The first band of every dataset is the day `start`.
+    pub fn extract(
+        &self,
+        lon: f64,
+        lat: f64,
+        start: NaiveDate,
+    ) -> Result<WeatherSeries, WeatherError> {
+        let required = [
+            ("srad", &self.srad),
+            ("tmax", &self.tmax),
+            ("tmin", &self.tmin),
+            ("rain", &self.rain),
+        ];
+        let optional = [
+            ("dewp", &self.dewp),
+            ("wind", &self.wind),
+            ("rhum", &self.rhum),
+        ];
+
+        let mut columns = Vec::with_capacity(required.len() + optional.len());
+        for (name, variable) in required {
+            columns.push((name, Some(variable.series(lon, lat)?)));
+        }
+        for (name, variable) in optional {
+            columns.push((
+                name,
+                variable.as_ref().map(|v| v.series(lon, lat)).transpose()?,
+            ));
+        }
+
+        let expected = columns[0].1.as_ref().map_or(0, Vec::len);
+        for (name, column) in &columns {
+            if let Some(column) = column.as_ref().filter(|c| c.len() != expected) {
+                return Err(WeatherError::BandCount {
+                    variable: name.to_string(),
+                    expected,
+                    actual: column.len(),
+                });
+            }
+        }
+
+        let columns: Vec<Option<Vec<Option<f64>>>> = columns.into_iter().map(|(_, c)| c).collect();
+        Ok(WeatherSeries {
+            station: String::new(),
+            source: "Gridded".to_string(),
+            lat,
+            lon,
+            elev: None,
+            days: assemble_days(start, expected, &columns)?,
+        })
+    }
+}
+
+/// Builds the days of a series out of its variable columns, in the order srad, tmax, tmin, rain, dewp, wind, rhum.
+/// Absent columns are treated as missing for every day.
+fn assemble_days(
+    start: NaiveDate,
+    len: usize,
+    columns: &[Option<Vec<Option<f64>>>],
+) -> Result<Vec<WeatherDay>, WeatherError> {
+    let value = |col: usize, day: usize| -> Option<f64> {
+        columns
+            .get(col)
+            .and_then(|c| c.as_ref())
+            .and_then(|c| c[day])
+    };
+
+    (0..len)
+        .map(|day| {
+            let date = start
+                .checked_add_days(Days::new(day as u64))
+                .ok_or_else(|| WeatherError::InvalidDate(format!("{} + {} days", start, day)))?;
+            Ok(WeatherDay {
+                date,
+                srad: value(0, day),
+                tmax: value(1, day),
+                tmin: value(2, day),
+                rain: value(3, day),
+                dewp: value(4, day),
+                wind: value(5, day),
+                rhum: value(6, day),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assemble_days() {
+        let start = NaiveDate::from_ymd_opt(2020, 12, 31).unwrap();
+        let columns = vec![
+            Some(vec![Some(20.0), Some(21.0)]),
+            Some(vec![Some(30.0), None]),
+            Some(vec![Some(18.0), Some(17.0)]),
+            Some(vec![Some(0.0), Some(5.5)]),
+            None,
+            None,
+            None,
+        ];
+
+        let days = assemble_days(start, 2, &columns).unwrap();
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].date, start);
+        assert_eq!(days[1].date, NaiveDate::from_ymd_opt(2021, 1, 1).unwrap());
+        assert_eq!(days[0].tmax, Some(30.0));
+        assert_eq!(days[1].tmax, None);
+        assert_eq!(days[1].rain, Some(5.5));
+        assert_eq!(days[1].dewp, None);
+    }
+
+    #[test]
+    fn test_convert() {
+        let variable = GriddedVariable {
+            file: PathBuf::from("tmax.nc"),
+            scale: 1.0,
+            offset: -273.15,
+        };
+        assert_eq!(variable.convert(Some(300.0)), Some(300.0 - 273.15));
+        assert_eq!(variable.convert(None), None);
+    }
+}
@@ -0,0 +1,189 @@
+//! Module _weather_ acquires daily weather for sites and writes it as DSSAT `.WTH` files into context directories.
+
+pub mod code;
+pub mod gridded;
+pub mod power;
+pub mod stations;
+pub mod wth;
+
+use crate::sites::Site;
+use chrono::NaiveDate;
+use gridded::GriddedVariables;
+use power::PowerClient;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::path::PathBuf;
+use thiserror::Error;
+use validator::ValidationError;
+use wth::WeatherSeries;
+
+static ERRCODE_WEATHER_INVALID: &str = "ERRCODE_WEATHER_INVALID";
+
+#[derive(Debug, Error)]
+pub enum WeatherError {
+    #[error("HTTP error: {0}")]
+    Http(Box<ureq::Error>),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Malformed response: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Response is missing the parameter {0}")]
+    MissingParameter(String),
+    #[error("Invalid date {0} in response")]
+    InvalidDate(String),
+    #[error("GDAL error reading {path}: {source}")]
+    Gdal {
+        path: PathBuf,
+        source: gdal::errors::GdalError,
+    },
+    #[error("Gridded variable {variable} has {actual} bands, expected {expected}")]
+    BandCount {
+        variable: String,
+        expected: usize,
+        actual: usize,
+    },
+}
+
+/// Where the weather of a run comes from.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "source", rename_all = "snake_case", deny_unknown_fields)]
+pub enum WeatherConfig {
+    /// Daily weather from the NASA POWER API.
+    NasaPower {
+        start: NaiveDate,
+        end: NaiveDate,
+
+        /// 4-characters institute and site code, used in the `.WTH` header and file name.
+        #[serde(default = "default_station")]
+        station: String,
+
+        /// Directory where API responses are cached.
+        #[serde(default = "default_cache_dir")]
+        cache_dir: PathBuf,
+
+        /// If set, snaps sites to the center of a grid with cells of the given size (in degrees),
+        /// so all the sites within the same cell share a single download.
+        #[serde(default)]
+        grid: Option<f64>,
+    },
+    /// Daily weather extracted from gridded datasets (NetCDF, Zarr or anything else GDAL can open),
+    /// one dataset per variable and one band per day, starting at `start`.
+    Gridded {
+        start: NaiveDate,
+
+        /// 4-characters institute and site code, used in the `.WTH` header and file name.
+        #[serde(default = "default_gridded_station")]
+        station: String,
+
+        variables: Box<GriddedVariables>,
+
+        /// Raster with the terrain elevation (meters), used for the `ELEV` header field.
+        #[serde(default)]
+        elevation: Option<gridded::GriddedVariable>,
+    },
+}
+
+fn default_gridded_station() -> String {
+    "GRID".to_string()
+}
+
+fn default_station() -> String {
+    "NASA".to_string()
+}
+
+fn default_cache_dir() -> PathBuf {
+    PathBuf::from(".pythia-cache/power")
+}
+
+pub fn validate_weather(weather: &WeatherConfig) -> Result<(), ValidationError> {
+    let invalid = |msg: String| {
+        Err(ValidationError::new(ERRCODE_WEATHER_INVALID).with_message(Cow::from(msg)))
+    };
+
+    match weather {
+        WeatherConfig::NasaPower {
+            start,
+            end,
+            station,
+            grid,
+            ..
+        } => {
+            if start > end {
+                return invalid(format!("Weather start {} is after end {}", start, end));
+            }
+            validate_station(station)?;
+            if grid.is_some_and(|g| g <= 0.0) {
+                return invalid("Weather grid cell size must be positive".to_string());
+            }
+        }
+        WeatherConfig::Gridded { station, .. } => validate_station(station)?,
+    }
+    Ok(())
+}
+
+fn validate_station(station: &str) -> Result<(), ValidationError> {
+    if station.len() != 4 || !station.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(
+            ValidationError::new(ERRCODE_WEATHER_INVALID).with_message(Cow::from(format!(
+                "Weather station code {} must be 4 alphanumeric characters",
+                station
+            ))),
+        );
+    }
+    Ok(())
+}
+
+/// Snaps `value` to the center of the grid cell it falls in.
+fn snap(value: f64, cell: f64) -> f64 {
+    (value / cell).floor() * cell + cell / 2.0
+}
+
+impl WeatherConfig {
+    /// Acquires the weather for `site`.
+    pub fn fetch(&self, site: &Site) -> Result<WeatherSeries, WeatherError> {
+        Ok(match self {
+            WeatherConfig::NasaPower {
+                start,
+                end,
+                station,
+                cache_dir,
+                grid,
+            } => {
+                let (mut lat, mut lon) = (site.lat.as_f64(), site.lon.as_f64());
+                if let Some(cell) = grid {
+                    lat = snap(lat, *cell);
+                    lon = snap(lon, *cell);
+                }
+
+                let mut series = PowerClient { cache_dir }.fetch(lat, lon, *start, *end)?;
+                series.station = station.clone();
+                series
+            }
+            WeatherConfig::Gridded {
+                start,
+                station,
+                variables,
+                elevation,
+            } => {
+                let mut series = variables.extract(site.lon.as_f64(), site.lat.as_f64(), *start)?;
+                series.station = station.clone();
+                if let Some(elevation) = elevation {
+                    series.elev = elevation.sample(site.lon.as_f64(), site.lat.as_f64())?;
+                }
+                series
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snap() {
+        assert_eq!(snap(0.1, 0.5), 0.25);
+        assert_eq!(snap(-0.1, 0.5), -0.25);
+        assert_eq!(snap(12.74, 0.5), 12.75);
+    }
+}
@@ -0,0 +1,213 @@
+//! Client for the [NASA POWER](https://power.larc.nasa.gov/) daily point API.
+
+use super::wth::{WeatherDay, WeatherSeries};
+use super::WeatherError;
+use chrono::NaiveDate;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const API_URL: &str = "https://power.larc.nasa.gov/api/temporal/daily/point";
+
+/// Parameters requested to the API, in the order they are mapped into [`WeatherDay`].
+const PARAMETERS: [&str; 7] = [
+    "ALLSKY_SFC_SW_DWN",
+    "T2M_MAX",
+    "T2M_MIN",
+    "PRECTOTCORR",
+    "T2M_DEW",
+    "WS2M",
+    "RH2M",
+];
+
+/// Seconds in a day divided by meters in a kilometer, converts m/s into km/day.
+const MS_TO_KM_DAY: f64 = 86.4;
+
+#[derive(Deserialize)]
+struct PowerResponse {
+    geometry: PowerGeometry,
+    properties: PowerProperties,
+    #[serde(default)]
+    header: PowerHeader,
+}
+
+#[derive(Deserialize)]
+struct PowerGeometry {
+    coordinates: Vec<f64>,
+}
+
+#[derive(Deserialize)]
+struct PowerProperties {
+    parameter: HashMap<String, HashMap<String, f64>>,
+}
+
+#[derive(Deserialize)]
+struct PowerHeader {
+    fill_value: f64,
+}
+
+impl Default for PowerHeader {
+    fn default() -> Self {
+        Self { fill_value: -999.0 }
+    }
+}
+
+/// Downloads (or reads from the cache) daily weather for a single point.
+pub struct PowerClient<'a> {
+    pub cache_dir: &'a Path,
+}
+
+impl PowerClient<'_> {
+    /// Fetches the weather series at `lat`/`lon` between `start` and `end` (inclusive).
+    /// Responses are cached in `self.cache_dir`, keyed by coordinates and dates, so each location is only downloaded once.
+    pub fn fetch(
+        &self,
+        lat: f64,
+        lon: f64,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<WeatherSeries, WeatherError> {
+        let cache_file = self.cache_file(lat, lon, start, end);
+        let body = match std::fs::read_to_string(&cache_file) {
+            Ok(body) => body,
+            Err(_) => {
+                let body = download(lat, lon, start, end)?;
+                store(&cache_file, &body)?;
+                body
+            }
+        };
+
+        parse(&body, lat, lon)
+    }
+
+    fn cache_file(&self, lat: f64, lon: f64, start: NaiveDate, end: NaiveDate) -> PathBuf {
+        self.cache_dir.join(format!(
+            "power_{:.4}_{:.4}_{}_{}.json",
+            lat,
+            lon,
+            start.format("%Y%m%d"),
+            end.format("%Y%m%d")
+        ))
+    }
+}
+
+fn download(lat: f64, lon: f64, start: NaiveDate, end: NaiveDate) -> Result<String, WeatherError> {
+    ureq::get(API_URL)
+        .query("parameters", &PARAMETERS.join(","))
+        .query("community", "AG")
+        .query("latitude", &format!("{:.4}", lat))
+        .query("longitude", &format!("{:.4}", lon))
+        .query("start", &start.format("%Y%m%d").to_string())
+        .query("end", &end.format("%Y%m%d").to_string())
+        .query("format", "JSON")
+        .call()
+        .map_err(|e| WeatherError::Http(Box::new(e)))?
+        .into_string()
+        .map_err(WeatherError::Io)
+}
+
+/// Writes the cache file atomically, so concurrent workers never read a partially written response.
+fn store(path: &Path, body: &str) -> Result<(), WeatherError> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    std::fs::create_dir_all(dir)?;
+    let tmp = tempfile::NamedTempFile::new_in(dir)?;
+    std::fs::write(tmp.path(), body)?;
+    tmp.persist(path).map_err(|e| WeatherError::Io(e.error))?;
+    Ok(())
+}
+
+fn parse(body: &str, lat: f64, lon: f64) -> Result<WeatherSeries, WeatherError> {
+    let response: PowerResponse = serde_json::from_str(body)?;
+    let fill_value = response.header.fill_value;
+    let parameter = |name: &str| {
+        response
+            .properties
+            .parameter
+            .get(name)
+            .ok_or_else(|| WeatherError::MissingParameter(name.to_string()))
+    };
+
+    let srad = parameter("ALLSKY_SFC_SW_DWN")?;
+    let tmax = parameter("T2M_MAX")?;
+    let tmin = parameter("T2M_MIN")?;
+    let rain = parameter("PRECTOTCORR")?;
+    let dewp = parameter("T2M_DEW")?;
+    let wind = parameter("WS2M")?;
+    let rhum = parameter("RH2M")?;
+
+    let value = |series: &HashMap<String, f64>, key: &str| {
+        series.get(key).cloned().filter(|v| *v != fill_value)
+    };
+
+    let mut dates: Vec<&String> = tmax.keys().collect();
+    dates.sort();
+
+    let days = dates
+        .into_iter()
+        .map(|key| {
+            let date = NaiveDate::parse_from_str(key, "%Y%m%d")
+                .map_err(|_| WeatherError::InvalidDate(key.clone()))?;
+            Ok(WeatherDay {
+                date,
+                srad: value(srad, key),
+                tmax: value(tmax, key),
+                tmin: value(tmin, key),
+                rain: value(rain, key),
+                dewp: value(dewp, key),
+                wind: value(wind, key).map(|v| v * MS_TO_KM_DAY),
+                rhum: value(rhum, key),
+            })
+        })
+        .collect::<Result<Vec<_>, WeatherError>>()?;
+
+    Ok(WeatherSeries {
+        station: String::new(),
+        source: "NASA POWER".to_string(),
+        lat,
+        lon,
+        elev: response.geometry.coordinates.get(2).cloned(),
+        days,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let body = r#"{
+            "geometry": {"type": "Point", "coordinates": [36.25, -1.5, 1650.3]},
+            "header": {"fill_value": -999.0},
+            "properties": {"parameter": {
+                "ALLSKY_SFC_SW_DWN": {"20200102": 21.0, "20200101": 20.0},
+                "T2M_MAX": {"20200102": 26.0, "20200101": 25.0},
+                "T2M_MIN": {"20200102": 13.0, "20200101": 12.0},
+                "PRECTOTCORR": {"20200102": 0.0, "20200101": 3.5},
+                "T2M_DEW": {"20200102": 9.0, "20200101": 8.0},
+                "WS2M": {"20200102": 1.0, "20200101": -999.0},
+                "RH2M": {"20200102": 60.0, "20200101": 70.0}
+            }}
+        }"#;
+
+        let series = parse(body, -1.5, 36.25).unwrap();
+        assert_eq!(series.elev, Some(1650.3));
+        assert_eq!(series.days.len(), 2);
+        assert_eq!(
+            series.days[0].date,
+            NaiveDate::from_ymd_opt(2020, 1, 1).unwrap()
+        );
+        assert_eq!(series.days[0].rain, Some(3.5));
+        assert_eq!(series.days[0].wind, None);
+        assert_eq!(series.days[1].wind, Some(86.4));
+    }
+
+    #[test]
+    fn test_parse_missing_parameter() {
+        let body = r#"{"geometry": {"coordinates": [0, 0]}, "properties": {"parameter": {}}}"#;
+        assert!(matches!(
+            parse(body, 0.0, 0.0),
+            Err(WeatherError::MissingParameter(_))
+        ));
+    }
+}
@@ -0,0 +1,245 @@
+//! Assignment of sites to the nearest weather station (or weather grid cell) of an index.
+
+use crate::data::{haversine_km, EARTH_RADIUS_KM};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum StationIndexError {
+    #[error("Failed to read station index {0}: {1}")]
+    Csv(PathBuf, csv::Error),
+    #[error("Station index {0} has no column named \"{1}\"")]
+    MissingColumn(PathBuf, String),
+    #[error("Station index {path}, row {row}: invalid {column} \"{value}\"")]
+    InvalidValue {
+        path: PathBuf,
+        row: usize,
+        column: String,
+        value: String,
+    },
+}
+
+/// A CSV file listing weather stations (or grid cells), one per row.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct StationIndexConfig {
+    pub file: PathBuf,
+
+    #[serde(default = "default_id_column")]
+    pub id_column: String,
+
+    #[serde(default = "default_lat_column")]
+    pub lat_column: String,
+
+    #[serde(default = "default_lon_column")]
+    pub lon_column: String,
+
+    /// Column holding the weather file name of the station. If not set, the file name is `<id>.WTH`.
+    #[serde(default)]
+    pub file_column: Option<String>,
+
+    /// Sites further than this from every station are left unassigned.
+    #[serde(default)]
+    pub max_distance_km: Option<f64>,
+}
+
+fn default_id_column() -> String {
+    "id".to_string()
+}
+
+fn default_lat_column() -> String {
+    "lat".to_string()
+}
+
+fn default_lon_column() -> String {
+    "lon".to_string()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Station {
+    pub id: String,
+    pub lat: f64,
+    pub lon: f64,
+    pub file: String,
+}
+
+/// The station nearest to a site.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StationMatch<'a> {
+    pub station: &'a Station,
+    pub distance_km: f64,
+}
+
+/// Stations sorted by latitude, so the search can stop as soon as the latitude difference alone exceeds the best distance found.
+#[derive(Debug)]
+pub struct StationIndex {
+    stations: Vec<Station>,
+    max_distance_km: Option<f64>,
+}
+
+impl StationIndex {
+    pub fn new(mut stations: Vec<Station>, max_distance_km: Option<f64>) -> Self {
+        stations.sort_by(|a, b| a.lat.total_cmp(&b.lat));
+        Self {
+            stations,
+            max_distance_km,
+        }
+    }
+
+    pub fn load(config: &StationIndexConfig) -> Result<Self, StationIndexError> {
+        let path = config.file.as_path();
+        let csv_err = |e| StationIndexError::Csv(path.to_path_buf(), e);
+        let mut reader = csv::Reader::from_path(path).map_err(csv_err)?;
+
+        let headers = reader.headers().map_err(csv_err)?.clone();
+        let column = |name: &str| {
+            headers.iter().position(|h| h == name).ok_or_else(|| {
+                StationIndexError::MissingColumn(path.to_path_buf(), name.to_string())
+            })
+        };
+
+        let id_idx = column(&config.id_column)?;
+        let lat_idx = column(&config.lat_column)?;
+        let lon_idx = column(&config.lon_column)?;
+        let file_idx = config.file_column.as_deref().map(column).transpose()?;
+
+        let mut stations = Vec::new();
+        for (row, record) in reader.records().enumerate() {
+            let record = record.map_err(csv_err)?;
+            let id = record.get(id_idx).unwrap_or_default().to_string();
+            stations.push(Station {
+                lat: parse_coord(path, row, &config.lat_column, record.get(lat_idx))?,
+                lon: parse_coord(path, row, &config.lon_column, record.get(lon_idx))?,
+                file: match file_idx {
+                    Some(idx) => record.get(idx).unwrap_or_default().to_string(),
+                    None => format!("{}.WTH", id),
+                },
+                id,
+            });
+        }
+
+        Ok(Self::new(stations, config.max_distance_km))
+    }
+
+    /// Finds the station nearest to the given point, if any is within the maximum distance.
+    pub fn nearest(&self, lat: f64, lon: f64) -> Option<StationMatch<'_>> {
+        let start = self.stations.partition_point(|s| s.lat < lat);
+        let mut best = None;
+
+        // Walks away from the site latitude in both directions.
+        for station in self.stations[start..].iter() {
+            if !consider(&mut best, station, lat, lon) {
+                break;
+            }
+        }
+        for station in self.stations[..start].iter().rev() {
+            if !consider(&mut best, station, lat, lon) {
+                break;
+            }
+        }
+
+        best.filter(|b| self.max_distance_km.is_none_or(|max| b.distance_km <= max))
+    }
+}
+
+/// Updates `best` if `station` is nearer to the point. Returns false once `station` (and thus every station further away in latitude)
+/// can no longer be nearer than `best`, as the distance is never shorter than the latitude difference alone.
+fn consider<'a>(
+    best: &mut Option<StationMatch<'a>>,
+    station: &'a Station,
+    lat: f64,
+    lon: f64,
+) -> bool {
+    let best_distance = best.as_ref().map(|b| b.distance_km).unwrap_or(f64::MAX);
+    if (station.lat - lat).abs().to_radians() * EARTH_RADIUS_KM > best_distance {
+        return false;
+    }
+
+    let distance_km = haversine_km(lat, lon, station.lat, station.lon);
+    if distance_km < best_distance {
+        *best = Some(StationMatch {
+            station,
+            distance_km,
+        });
+    }
+    true
+}
+
+fn parse_coord(
+    path: &Path,
+    row: usize,
+    column: &str,
+    value: Option<&str>,
+) -> Result<f64, StationIndexError> {
+    let value = value.unwrap_or_default();
+    value
+        .trim()
+        .parse()
+        .map_err(|_| StationIndexError::InvalidValue {
+            path: path.to_path_buf(),
+            row: row + 1,
+            column: column.to_string(),
+            value: value.to_string(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn station(id: &str, lat: f64, lon: f64) -> Station {
+        Station {
+            id: id.to_string(),
+            lat,
+            lon,
+            file: format!("{}.WTH", id),
+        }
+    }
+
+    #[test]
+    fn test_nearest() {
+        let index = StationIndex::new(
+            vec![
+                station("AAAA", 0.0, 0.0),
+                station("BBBB", 1.0, 1.0),
+                station("CCCC", -5.0, 0.0),
+                station("DDDD", 0.2, 10.0),
+            ],
+            None,
+        );
+
+        assert_eq!(index.nearest(0.1, 0.1).unwrap().station.id, "AAAA");
+        assert_eq!(index.nearest(0.8, 0.9).unwrap().station.id, "BBBB");
+        assert_eq!(index.nearest(-3.0, 0.0).unwrap().station.id, "CCCC");
+        assert_eq!(index.nearest(0.0, 9.0).unwrap().station.id, "DDDD");
+    }
+
+    #[test]
+    fn test_nearest_max_distance() {
+        let index = StationIndex::new(vec![station("AAAA", 0.0, 0.0)], Some(50.0));
+        assert!(index.nearest(0.1, 0.1).is_some());
+        assert!(index.nearest(1.0, 1.0).is_none());
+    }
+
+    #[test]
+    fn test_load() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, b"code,y,x\nAAAA,1.5,2.5\nBBBB,-1,-2\n").unwrap();
+
+        let index = StationIndex::load(&StationIndexConfig {
+            file: file.path().to_path_buf(),
+            id_column: "code".to_string(),
+            lat_column: "y".to_string(),
+            lon_column: "x".to_string(),
+            file_column: None,
+            max_distance_km: None,
+        })
+        .unwrap();
+
+        assert_eq!(
+            index.nearest(1.0, 2.0).unwrap().station,
+            &station("AAAA", 1.5, 2.5)
+        );
+    }
+}
@@ -0,0 +1,202 @@
+//! Writer for DSSAT weather (`.WTH`) files.
+
+use crate::utils::text::{finalize, LineEnding};
+use chrono::{Datelike, NaiveDate};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// DSSAT missing value.
+const MISSING: f64 = -99.0;
+
+/// One day of weather. Missing values are represented as `None` and written as `-99`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeatherDay {
+    pub date: NaiveDate,
+    /// Solar radiation, MJ/m²/day.
+    pub srad: Option<f64>,
+    /// Maximum temperature, °C.
+    pub tmax: Option<f64>,
+    /// Minimum temperature, °C.
+    pub tmin: Option<f64>,
+    /// Precipitation, mm/day.
+    pub rain: Option<f64>,
+    /// Dew point temperature, °C.
+    pub dewp: Option<f64>,
+    /// Wind run, km/day.
+    pub wind: Option<f64>,
+    /// Relative humidity, %.
+    pub rhum: Option<f64>,
+}
+
+/// A daily weather series for a single location, ready to be written as a DSSAT `.WTH` file.
+#[derive(Debug, Clone)]
+pub struct WeatherSeries {
+    /// Institute and site code (`INSI`), 4 characters.
+    pub station: String,
+    pub source: String,
+    pub lat: f64,
+    pub lon: f64,
+    /// Elevation, meters.
+    pub elev: Option<f64>,
+    pub days: Vec<WeatherDay>,
+}
+
+impl WeatherSeries {
+    /// Annual average ambient temperature (`TAV`), °C.
+    pub fn tav(&self) -> Option<f64> {
+        let temps: Vec<f64> = self.days.iter().filter_map(day_mean_temp).collect();
+        if temps.is_empty() {
+            return None;
+        }
+        Some(temps.iter().sum::<f64>() / temps.len() as f64)
+    }
+
+    /// Annual amplitude of the monthly mean temperatures (`AMP`), °C. Half the difference between the warmest and coldest months.
+    pub fn amp(&self) -> Option<f64> {
+        let mut sums = [(0.0, 0usize); 12];
+        for day in &self.days {
+            if let Some(t) = day_mean_temp(day) {
+                let month = day.date.month0() as usize;
+                sums[month].0 += t;
+                sums[month].1 += 1;
+            }
+        }
+
+        let means: Vec<f64> = sums
+            .iter()
+            .filter(|(_, n)| *n > 0)
+            .map(|(sum, n)| sum / *n as f64)
+            .collect();
+        if means.is_empty() {
+            return None;
+        }
+
+        let max = means.iter().cloned().fold(f64::MIN, f64::max);
+        let min = means.iter().cloned().fold(f64::MAX, f64::min);
+        Some((max - min) / 2.0)
+    }
+
+    /// DSSAT file name for the series, in the format `SSSSYYNN.WTH` where `SSSS` is the station code,
+    /// `YY` the first year and `NN` the number of years covered.
+    pub fn file_name(&self) -> String {
+        let (first, last) = match (self.days.first(), self.days.last()) {
+            (Some(first), Some(last)) => (first.date.year(), last.date.year()),
+            _ => (0, 0),
+        };
+        format!(
+            "{}{:02}{:02}.WTH",
+            self.station,
+            first.rem_euclid(100),
+            (last - first + 1).min(99)
+        )
+    }
+
+    /// Writes the series into `dir` as a `.WTH` file named after [`WeatherSeries::file_name`] with the given line
+    /// endings, returning its path.
+    pub fn write(&self, dir: &Path, line_endings: LineEnding) -> std::io::Result<PathBuf> {
+        let path = dir.join(self.file_name());
+        std::fs::write(&path, finalize(self.to_wth(), line_endings, false))?;
+        Ok(path)
+    }
+
+    /// Renders the series in the DSSAT `.WTH` format.
+    pub fn to_wth(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "*WEATHER DATA : {}", self.source);
+        out.push('\n');
+        let _ = writeln!(
+            out,
+            "@ INSI      LAT     LONG  ELEV   TAV   AMP REFHT WNDHT"
+        );
+        let _ = writeln!(
+            out,
+            "  {:>4} {:>8.3} {:>8.3} {:>5.0} {:>5.1} {:>5.1} {:>5.1} {:>5.1}",
+            self.station,
+            self.lat,
+            self.lon,
+            self.elev.unwrap_or(MISSING),
+            self.tav().unwrap_or(MISSING),
+            self.amp().unwrap_or(MISSING),
+            2.0,
+            2.0
+        );
+        let _ = writeln!(out, "@DATE  SRAD  TMAX  TMIN  RAIN  DEWP  WIND  RHUM");
+        for day in &self.days {
+            let _ = writeln!(
+                out,
+                "{} {:>5.1} {:>5.1} {:>5.1} {:>5.1} {:>5.1} {:>5.0} {:>5.1}",
+                yyddd(day.date),
+                day.srad.unwrap_or(MISSING),
+                day.tmax.unwrap_or(MISSING),
+                day.tmin.unwrap_or(MISSING),
+                day.rain.unwrap_or(MISSING),
+                day.dewp.unwrap_or(MISSING),
+                day.wind.unwrap_or(MISSING),
+                day.rhum.unwrap_or(MISSING),
+            );
+        }
+        out
+    }
+}
+
+fn day_mean_temp(day: &WeatherDay) -> Option<f64> {
+    Some((day.tmax? + day.tmin?) / 2.0)
+}
+
+/// Formats a date in the DSSAT `YYDDD` format (two-digit year and day of year).
+pub fn yyddd(date: NaiveDate) -> String {
+    format!("{:02}{:03}", date.year().rem_euclid(100), date.ordinal())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(date: NaiveDate, tmax: f64, tmin: f64) -> WeatherDay {
+        WeatherDay {
+            date,
+            srad: Some(20.0),
+            tmax: Some(tmax),
+            tmin: Some(tmin),
+            rain: Some(0.0),
+            dewp: None,
+            wind: Some(172.8),
+            rhum: None,
+        }
+    }
+
+    #[test]
+    fn test_wth() {
+        let series = WeatherSeries {
+            station: "NASA".to_string(),
+            source: "NASA POWER".to_string(),
+            lat: -1.5,
+            lon: 36.25,
+            elev: Some(1234.0),
+            days: vec![
+                day(NaiveDate::from_ymd_opt(2000, 1, 1).unwrap(), 30.0, 20.0),
+                day(NaiveDate::from_ymd_opt(2001, 7, 1).unwrap(), 20.0, 10.0),
+            ],
+        };
+
+        assert_eq!(series.tav(), Some(20.0));
+        assert_eq!(series.amp(), Some(5.0));
+        assert_eq!(series.file_name(), "NASA0002.WTH");
+
+        let wth = series.to_wth();
+        let lines: Vec<&str> = wth.lines().collect();
+        assert_eq!(lines[0], "*WEATHER DATA : NASA POWER");
+        assert_eq!(
+            lines[3],
+            "  NASA   -1.500   36.250  1234  20.0   5.0   2.0   2.0"
+        );
+        assert_eq!(lines[5], "00001  20.0  30.0  20.0   0.0 -99.0   173 -99.0");
+        assert_eq!(lines[6], "01182  20.0  20.0  10.0   0.0 -99.0   173 -99.0");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = series.write(dir.path(), LineEnding::Crlf).unwrap();
+        assert_eq!(path, dir.path().join("NASA0002.WTH"));
+        let written = std::fs::read_to_string(path).unwrap();
+        assert_eq!(written, wth.replace('\n', "\r\n"));
+    }
+}
@@ -2,17 +2,29 @@ use std::error::Error;
 use std::fs::create_dir_all;
 use std::path::PathBuf;
 
+/// Prefix of temporary working directories, in the format `pythia-<cfg8>-<ts>-`, where `cfg8` are the first 8 characters of the
+/// configuration file hash and `ts` is the UTC timestamp. Makes temporary outputs of concurrent campaigns distinguishable.
+pub fn temp_workdir_prefix(config_hash: &str) -> String {
+    format!(
+        "pythia-{}-{}-",
+        &config_hash[..config_hash.len().min(8)],
+        chrono::Utc::now().format("%Y%m%dT%H%M%SZ")
+    )
+}
+
 pub fn make_workdir(
     workdir: &Option<PathBuf>,
     keep: &Option<bool>,
     overwrite: bool,
+    temp_prefix: &str,
 ) -> Result<(PathBuf, bool), Box<dyn Error>> {
     let keep = workdir.is_some() || keep.unwrap_or(false);
 
     let new_workdir = match workdir {
         Some(workdir) => create_dir_all(workdir).map(|_| workdir.to_path_buf())?,
         None => tempfile::Builder::new()
-            .prefix("pythia-workdir")
+            .prefix(temp_prefix)
+            .rand_bytes(4)
             .keep(keep)
             .tempdir()?
             .into_path(),
@@ -32,3 +44,16 @@ pub fn make_workdir(
 
     Ok((new_workdir, !keep))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_temp_workdir_prefix() {
+        let prefix = temp_workdir_prefix("0123456789abcdef");
+        assert!(prefix.starts_with("pythia-01234567-"));
+        assert!(prefix.ends_with("Z-"));
+        assert_eq!(prefix.len(), "pythia-01234567-20250101T000000Z-".len());
+    }
+}